@@ -1,635 +1,13029 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use chrono::Utc;
+use notify::Watcher;
 use portable_pty::{native_pty_system, CommandBuilder, PtySize};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs;
-use std::fs::Metadata;
-use std::io::{ErrorKind, Read, Write};
+use std::fs::{Metadata, OpenOptions};
+use std::io::{BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::panic::AssertUnwindSafe;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
-use std::time::UNIX_EPOCH;
-use tauri::{AppHandle, Emitter, Manager, State};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State, WebviewWindow};
 use uuid::Uuid;
 
 const TERMINAL_OUTPUT_EVENT: &str = "terminal:output";
 const TERMINAL_EXIT_EVENT: &str = "terminal:exit";
+const TERMINAL_RESIZE_EVENT: &str = "terminal:resize";
+const TERMINAL_LOG_ERROR_EVENT: &str = "terminal:log-error";
+const TERMINAL_COMMAND_START_EVENT: &str = "terminal:command-start";
+const TERMINAL_COMMAND_END_EVENT: &str = "terminal:command-end";
+const TERMINAL_CWD_CHANGED_EVENT: &str = "terminal:cwd-changed";
+const TERMINAL_RESTARTED_EVENT: &str = "terminal:restarted";
+const TERMINAL_IDLE_EVENT: &str = "terminal:idle";
+const OSC_PAYLOAD_MAX_BYTES: usize = 4096;
+const SHELL_INTEGRATION_DIR_NAME: &str = "shell-integration";
+const TERMINAL_SCROLLBACK_MAX_BYTES: usize = 1024 * 1024;
+const TERMINAL_CATCH_UP_MAX_BYTES: usize = 64 * 1024;
+const TERMINAL_BROADCAST_TIMEOUT: Duration = Duration::from_secs(2);
+const TERMINAL_LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const TERMINAL_RESTART_BACKOFF_DEFAULT: Duration = Duration::from_secs(1);
+const TERMINAL_REAP_POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// How long a write waits for the writer lock and for room in the PTY before giving up.
+const TERMINAL_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+const TERMINAL_WRITE_RETRY_INTERVAL: Duration = Duration::from_millis(5);
+const TERMINAL_EXITED_RETENTION: Duration = Duration::from_secs(30);
+/// Home the cursor, erase the screen and drop the scrollback, as `clear` does in xterm.
+const TERMINAL_CLEAR_SEQUENCE: &[u8] = b"\x1b[H\x1b[2J\x1b[3J";
+const TERMINAL_SEARCH_DEFAULT_RESULTS: usize = 500;
+const TERMINAL_SEARCH_MAX_RESULTS: usize = 5000;
+const TERMINAL_RESTART_BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// A process that ran this long before exiting counts as healthy, so the next restart starts
+/// again at attempt 1 with the shortest backoff.
+const TERMINAL_RESTART_STABLE_UPTIME: Duration = Duration::from_secs(60);
+const TERMINAL_IDLE_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_TERMINAL_IDLE_GRACE_MINUTES: u64 = 5;
+/// Control characters by symbolic name, with the usual defaults for PTYs that expose no termios.
+const TERMINAL_CONTROL_CHARACTERS: &[(&str, u8)] = &[
+  ("eof", 0x04),
+  ("interrupt", 0x03),
+  ("suspend", 0x1a),
+  ("quit", 0x1c),
+  ("erase", 0x7f)
+];
+const BRACKETED_PASTE_ENABLE: &[u8] = b"\x1b[?2004h";
+const BRACKETED_PASTE_DISABLE: &[u8] = b"\x1b[?2004l";
+const BRACKETED_PASTE_START: &str = "\x1b[200~";
+const BRACKETED_PASTE_END: &str = "\x1b[201~";
+const DEFAULT_TERM_NAME: &str = "xterm-256color";
+const FALLBACK_UTF8_LOCALE: &str = if cfg!(target_os = "macos") {
+  "en_US.UTF-8"
+} else {
+  "C.UTF-8"
+};
+const BASH_SHELL_INTEGRATION: &str = r#"# OpenSpace shell integration for bash.
+# Emits OSC 133 prompt/command markers and OSC 1337 CurrentDir for the terminal backend.
+if [ -n "${OPENSPACE_SHELL_INTEGRATION:-}" ]; then
+  return 0 2>/dev/null
+fi
+OPENSPACE_SHELL_INTEGRATION=1
+__openspace_at_prompt=0
+__openspace_command_running=0
+
+__openspace_prompt() {
+  local exit_status=$?
+  if [ "$__openspace_command_running" = 1 ]; then
+    printf '\033]133;D;%s\007' "$exit_status"
+  fi
+  __openspace_command_running=0
+  printf '\033]1337;CurrentDir=%s\007' "$PWD"
+  printf '\033]133;A\007'
+  return $exit_status
+}
+
+__openspace_prompt_ready() {
+  __openspace_at_prompt=1
+}
+
+__openspace_preexec() {
+  if [ "$__openspace_at_prompt" = 1 ] && [ "$BASH_COMMAND" != "__openspace_prompt" ]; then
+    __openspace_at_prompt=0
+    __openspace_command_running=1
+    printf '\033]133;C\007'
+  fi
+}
+
+PROMPT_COMMAND="__openspace_prompt${PROMPT_COMMAND:+;$PROMPT_COMMAND};__openspace_prompt_ready"
+trap '__openspace_preexec' DEBUG
+PS1="${PS1}\[\033]133;B\007\]"
+"#;
+const ZSH_SHELL_INTEGRATION: &str = r#"# OpenSpace shell integration for zsh.
+# Emits OSC 133 prompt/command markers and OSC 1337 CurrentDir for the terminal backend.
+if [[ -n "${OPENSPACE_SHELL_INTEGRATION:-}" ]]; then
+  return 0
+fi
+OPENSPACE_SHELL_INTEGRATION=1
+__openspace_command_running=0
+
+__openspace_precmd() {
+  local exit_status=$?
+  if [[ $__openspace_command_running == 1 ]]; then
+    printf '\033]133;D;%s\007' "$exit_status"
+  fi
+  __openspace_command_running=0
+  printf '\033]1337;CurrentDir=%s\007' "$PWD"
+  printf '\033]133;A\007'
+}
+
+__openspace_preexec() {
+  __openspace_command_running=1
+  printf '\033]133;C\007'
+}
+
+autoload -Uz add-zsh-hook
+add-zsh-hook precmd __openspace_precmd
+add-zsh-hook preexec __openspace_preexec
+PS1="${PS1}%{$(printf '\033]133;B\007')%}"
+"#;
+const PATH_ESCAPES_WORKSPACE_ERROR: &str = "access denied: path escapes workspace";
 const TASKS_FILE_NAME: &str = "tasks.json";
+/// Archived tasks, one compact JSON object per line, so archiving appends instead of rewriting.
+const TASKS_ARCHIVE_FILE_NAME: &str = "tasks-archive.ndjson";
 const WORKSPACE_FILE_NAME: &str = "workspace.json";
+const TERMINAL_PROFILES_FILE_NAME: &str = "terminal-profiles.json";
+const TERMINAL_LAYOUTS_FILE_NAME: &str = "terminal-layouts.json";
+const TERMINAL_DEFAULT_COLS: u16 = 120;
+const TERMINAL_DEFAULT_ROWS: u16 = 40;
+const TERMINAL_MAX_COLS: u16 = 1000;
+const TERMINAL_MAX_ROWS: u16 = 500;
+/// Smaller panes are usually a minimized or collapsed window, not a size worth remembering.
+const TERMINAL_REMEMBERED_MIN_COLS: u16 = 20;
+const TERMINAL_REMEMBERED_MIN_ROWS: u16 = 5;
+/// How long a remembered pane size waits in memory before it is written out, so dragging a
+/// split does not rewrite terminal-layouts.json on every resize step.
+const TERMINAL_LAYOUT_FLUSH_DELAY: Duration = Duration::from_secs(2);
+const SETTINGS_FILE_NAME: &str = "settings.json";
+const DEFAULT_RECENT_PATHS_MAX: usize = 10;
+const DEFAULT_JSON_PRETTY_MAX_BYTES: u64 = 1024 * 1024;
+const DEFAULT_FILE_READ_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const APP_READY_EVENT: &str = "app:ready";
+const APP_SAFE_MODE_EVENT: &str = "app:safe-mode";
+const WORKSPACE_ROOT_CHANGED_EVENT: &str = "workspace:root_changed";
+const SHUTDOWN_PROGRESS_EVENT: &str = "shutdown:progress";
+const APP_OPEN_PATH_EVENT: &str = "app:open-path";
+const FS_CHANGED_EVENT: &str = "fs:changed";
+/// Changes are collected for this long after the first one and then emitted together.
+const FS_WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+const FS_WATCH_MAX_PATHS_PER_EVENT: usize = 1000;
+const FS_WATCH_MAX_CHANGES_PER_BATCH: usize = 20_000;
+const FILESYSTEM_LIST_MAX_DEPTH: usize = 20;
+/// Files smaller than this are never reported as sparse.
+const SPARSE_MIN_SIZE: u64 = 64 * 1024;
+/// How long a sorted listing is kept for paging through it, while the directory is unchanged.
+const FILESYSTEM_LIST_CACHE_TTL: Duration = Duration::from_secs(5);
+/// How long a listing stays available to a caller paging through it by `snapshotId`, counted
+/// from the last page fetched.
+const FILESYSTEM_LIST_SNAPSHOT_TTL: Duration = Duration::from_secs(60);
+const FS_TREE_DEFAULT_DEPTH: usize = 3;
+const FS_TREE_DEFAULT_ENTRIES_PER_DIRECTORY: usize = 500;
+/// Entries in one `fs_tree` response; directories past it are left for lazy loading.
+const FS_TREE_MAX_ENTRIES: usize = 20_000;
+const FILESYSTEM_HASH_FINISHED_EVENT: &str = "filesystem:hash-finished";
+const FILESYSTEM_HASH_CHUNK_BYTES: usize = 1024 * 1024;
+const FILESYSTEM_LIST_RECURSIVE_FINISHED_EVENT: &str = "filesystem:list-recursive-finished";
+/// Entries a recursive listing returns before it stops and reports itself truncated.
+const FILESYSTEM_LIST_RECURSIVE_MAX_ENTRIES: usize = 100_000;
+const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+const FILESYSTEM_READ_BINARY_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const FILESYSTEM_EXPORT_PROGRESS_EVENT: &str = "filesystem:export-progress";
+const FILESYSTEM_EXPORT_FINISHED_EVENT: &str = "filesystem:export-finished";
+const FILESYSTEM_EXPORT_CHUNK_BYTES: usize = 1024 * 1024;
+/// How much is copied between fsyncs of the destination; each one advances the sidecar's
+/// verified offset, so this bounds the work redone when an interrupted export resumes.
+const FILESYSTEM_EXPORT_CHECKPOINT_BYTES: u64 = 16 * 1024 * 1024;
+const FILESYSTEM_EXPORT_PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+const FILESYSTEM_EXPORT_SIDECAR_SUFFIX: &str = ".openspace-export";
+const FS_COPY_PROGRESS_EVENT: &str = "fs:copy-progress";
+const FS_COPY_FINISHED_EVENT: &str = "fs:copy-finished";
+const FS_COPY_PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+const FS_COPY_CHUNK_BYTES: usize = 1024 * 1024;
+const FS_SEARCH_RESULTS_EVENT: &str = "fs:search-results";
+const FS_SEARCH_FINISHED_EVENT: &str = "fs:search-finished";
+const FS_SEARCH_RESULTS_INTERVAL: Duration = Duration::from_millis(100);
+const FS_SEARCH_DEFAULT_RESULTS: usize = 200;
+const FS_SEARCH_MAX_RESULTS: usize = 5000;
+/// Directories the filename and content searches skip unless the request names its own list.
+const FS_SEARCH_SKIPPED_DIRS: &[&str] = &[".git", "node_modules"];
+const FS_GREP_MATCHES_EVENT: &str = "fs:grep-matches";
+/// Matches are sent in batches at most this often, or sooner once `FS_GREP_MATCHES_PER_EVENT`
+/// have piled up.
+const FS_GREP_MATCHES_INTERVAL: Duration = Duration::from_millis(100);
+const FS_GREP_MATCHES_PER_EVENT: usize = 500;
+const FS_GREP_FINISHED_EVENT: &str = "fs:grep-finished";
+const FS_GREP_DEFAULT_MAX_MATCHES: usize = 2000;
+const FS_GREP_MAX_MATCHES: usize = 50_000;
+/// Longer lines (minified code, mostly) are cut to this many bytes in match events.
+const FS_GREP_LINE_MAX_BYTES: usize = 1024;
+/// How much of a single line content search looks at; the rest of a longer line is skipped
+/// unread so a minified or newline-free file can't be pulled into memory whole.
+const FS_GREP_SCAN_LINE_MAX_BYTES: u64 = 1024 * 1024;
+/// How many skipped binary files the finished event names; the count covers the rest.
+const FS_GREP_BINARY_FILES_LISTED: usize = 100;
+const FS_REPLACE_FINISHED_EVENT: &str = "fs:replace-finished";
+const FS_REPLACE_PREVIEWS_PER_FILE: usize = 20;
+const FILESYSTEM_ARCHIVE_PROGRESS_EVENT: &str = "filesystem:archive_progress";
+const FILESYSTEM_ARCHIVE_FINISHED_EVENT: &str = "filesystem:archive-finished";
+const FILESYSTEM_ARCHIVE_PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+const FILESYSTEM_EXTRACT_PROGRESS_EVENT: &str = "filesystem:extract_progress";
+const FILESYSTEM_EXTRACT_FINISHED_EVENT: &str = "filesystem:extract-finished";
+/// How many would-be-overwritten paths an extract conflict error names before summarizing.
+const FILESYSTEM_EXTRACT_CONFLICTS_LISTED: usize = 50;
+const EVENT_REPLAY_MAX_PER_TYPE: usize = 256;
+/// How long a `Latest` replay entry is kept, so state for terminals and windows long gone does
+/// not pile up.
+const EVENT_REPLAY_LATEST_TTL: Duration = Duration::from_secs(10 * 60);
+/// Watcher batches a window can still fetch after loading; older changes are covered by the
+/// listing it loads anyway.
+const FS_CHANGED_REPLAY_WINDOW: Duration = Duration::from_secs(10);
+/// How many finished operations `operation_result` remembers, and for how long.
+const OPERATION_RESULTS_MAX: usize = 64;
+const OPERATION_RESULT_RETENTION: Duration = Duration::from_secs(10 * 60);
+const STATE_OP_PROGRESS_EVENT: &str = "state:op-progress";
+const STATE_OP_FINISHED_EVENT: &str = "state:op-finished";
+/// The state files `workspace_export` bundles, when they exist.
+const STATE_EXPORT_FILE_NAMES: &[&str] = &[
+  TASKS_FILE_NAME,
+  TASKS_ARCHIVE_FILE_NAME,
+  WORKSPACE_FILE_NAME,
+  SETTINGS_FILE_NAME,
+  TERMINAL_PROFILES_FILE_NAME,
+  TERMINAL_LAYOUTS_FILE_NAME
+];
+/// English templates for user-facing messages, keyed by a stable id. `{name}` placeholders are
+/// filled in by `message`; `messages_catalog` hands the map to the frontend for translation.
+const MESSAGE_CATALOG: &[(&str, &str)] = &[
+  ("app.closing", "OpenSpace is shutting down"),
+  ("application.notFound", "Application \"{desktopId}\" was not found."),
+  ("file.deletedOnDisk", "{path} was deleted on disk since it was read"),
+  ("file.directoryNotEmpty", "{path} is a directory that is not empty"),
+  ("file.encodingUnknown", "unknown text encoding \"{encoding}\""),
+  ("file.encodingUnmappable", "the text cannot be represented in {encoding}"),
+  ("file.exists", "{path} already exists"),
+  ("file.extractConflicts", "extracting would overwrite {count} existing paths: {paths}"),
+  ("file.homeUnknown", "the home directory could not be determined"),
+  ("file.insufficientSpace", "not enough free space for {path}: {required} bytes needed, {available} available"),
+  ("file.modifiedOnDisk", "{path} was modified on disk since it was read (on-disk mtime {currentMtimeMs}, expected {expectedMtimeMs})"),
+  ("file.notFound", "{path} does not exist"),
+  ("file.notPreviewed", "{path} was not part of the dry run"),
+  ("file.sourceChanged", "{path} changed while it was being exported"),
+  ("file.tooLarge", "{path} is {size} bytes, more than the {max} byte limit for opening files"),
+  ("file.trashUnsupported", "Moving files to the trash is not supported on this platform"),
+  ("git.invalidRef", "\"{ref}\" is not a valid ref"),
+  ("git.notRepository", "{path} is not inside a git repository"),
+  ("listing.stale", "the listing of {path} changed; start again from the first page"),
+  ("workspace.pinnedNotRecent", "pinned path {path} is not one of the recent paths"),
+  ("workspace.recentDuplicate", "{path} appears more than once in the recent paths"),
+  ("workspace.recentsOverLimit", "{count} unpinned recent paths exceed the limit of {max}"),
+  ("watch.notFound", "filesystem watch \"{watchId}\" was not found"),
+  ("operation.notFound", "operation \"{operationId}\" was not found"),
+  ("process.notFound", "process {pid} does not exist"),
+  ("process.permissionDenied", "not allowed to signal process {pid}"),
+  ("state.revisionConflict", "{path} was changed elsewhere (revision {current}, expected {expected})"),
+  ("task.notFound", "task \"{taskId}\" was not found"),
+  ("terminal.closed", "the terminal process has exited"),
+  ("terminal.closedDuringWrite", "the terminal process exited after accepting {bytesWritten} of {totalBytes} bytes"),
+  ("terminal.exited", "the terminal process exited with code {exitCode}"),
+  ("terminal.notFound", "Terminal session \"{sessionId}\" was not found."),
+  ("terminal.openInOtherWindow", "terminal session \"{sessionId}\" is still open in window \"{windowLabel}\""),
+  ("terminal.spawnFailed", "failed to spawn terminal process: {error}"),
+  ("terminal.writeTimeout", "the terminal accepted only {bytesWritten} of {totalBytes} bytes within {timeoutMs} ms")
+];
+/// Events a window that loads late can still fetch through `events_replay`. Terminal output is
+/// left out on purpose; the scrollback replay in terminal_set_visibility covers it.
+const EVENT_REPLAY_POLICIES: &[(&str, EventReplayPolicy)] = &[
+  (APP_READY_EVENT, EventReplayPolicy::Latest),
+  (APP_SAFE_MODE_EVENT, EventReplayPolicy::Latest),
+  (WORKSPACE_ROOT_CHANGED_EVENT, EventReplayPolicy::Latest),
+  (TERMINAL_CWD_CHANGED_EVENT, EventReplayPolicy::Latest),
+  (TERMINAL_RESTARTED_EVENT, EventReplayPolicy::Latest),
+  (TERMINAL_EXIT_EVENT, EventReplayPolicy::Recent(TERMINAL_EXITED_RETENTION)),
+  (APP_OPEN_PATH_EVENT, EventReplayPolicy::Recent(Duration::from_secs(60))),
+  (FILESYSTEM_EXPORT_FINISHED_EVENT, EventReplayPolicy::Recent(Duration::from_secs(60))),
+  (FS_COPY_FINISHED_EVENT, EventReplayPolicy::Recent(Duration::from_secs(60))),
+  (FS_SEARCH_FINISHED_EVENT, EventReplayPolicy::Recent(Duration::from_secs(60))),
+  (FS_GREP_FINISHED_EVENT, EventReplayPolicy::Recent(Duration::from_secs(60))),
+  (FS_REPLACE_FINISHED_EVENT, EventReplayPolicy::Recent(Duration::from_secs(60))),
+  (FILESYSTEM_ARCHIVE_FINISHED_EVENT, EventReplayPolicy::Recent(Duration::from_secs(60))),
+  (FILESYSTEM_EXTRACT_FINISHED_EVENT, EventReplayPolicy::Recent(Duration::from_secs(60))),
+  (FILESYSTEM_LIST_RECURSIVE_FINISHED_EVENT, EventReplayPolicy::Recent(Duration::from_secs(60))),
+  (FILESYSTEM_HASH_FINISHED_EVENT, EventReplayPolicy::Recent(Duration::from_secs(60))),
+  (STATE_OP_FINISHED_EVENT, EventReplayPolicy::Recent(Duration::from_secs(60))),
+  (FS_CHANGED_EVENT, EventReplayPolicy::Recent(FS_CHANGED_REPLAY_WINDOW))
+];
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+const SHUTDOWN_FORCE_QUIT_TIMEOUT: Duration = Duration::from_secs(15);
+const DESKTOP_ENTRY_CACHE_TTL: Duration = Duration::from_secs(60);
+const GIT_STATUS_CACHE_TTL: Duration = Duration::from_secs(2);
+/// Hash, author name, author email, author date and subject, separated by unit separators.
+const GIT_LOG_FORMAT: &str = "--format=%H%x1f%an%x1f%ae%x1f%aI%x1f%s";
+/// Upper bound on `git_log`'s `maxCount`, so one call cannot pull in a whole large history.
+const GIT_LOG_MAX_COUNT: usize = 1000;
+const ACTIVITY_DEFAULT_DAYS: u32 = 30;
+const ACTIVITY_MAX_DAYS: u32 = 365;
+const ACTIVITY_MAX_COMMITS: usize = 2000;
+const ACTIVITY_MAX_WALKED_ENTRIES: usize = 20_000;
+/// Root and range combinations kept in the activity cache; the oldest is dropped beyond this.
+const ACTIVITY_CACHE_MAX_ENTRIES: usize = 16;
+const ACTIVITY_SKIPPED_DIRECTORIES: &[&str] = &[".git", "node_modules", "target", "dist", "build"];
+const SAFE_MODE_FLAG: &str = "--safe-mode";
+const ROOT_FLAG: &str = "--root";
+/// Files or directories that mark a project root when inferring one from a file argument.
+const WORKSPACE_ROOT_MARKERS: &[&str] = &[".git", "Cargo.toml", "package.json", "pyproject.toml"];
+const WORKSPACE_ROOT_SEARCH_MAX_LEVELS: usize = 8;
+const BUILTIN_SHELL_FLAG: &str = "--builtin-shell";
+const BUILTIN_SHELL_NAME: &str = "builtin";
+const SAFE_MODE_MARKER_FILE_NAME: &str = "SAFE_MODE";
+const STARTUP_MARKER_FILE_NAME: &str = "STARTUP_IN_PROGRESS";
+const SAFE_MODE_SUBSYSTEMS: &[&str] = &[
+  "watchers",
+  "indexer",
+  "git",
+  "shell-integration",
+  "terminal-restore"
+];
+
+const SAMPLE_WORKSPACE_DIRECTORIES: &[&str] = &["src", "docs", "scripts", ".openspace"];
+const SAMPLE_WORKSPACE_FILES: &[(&str, &str)] = &[
+  (
+    "README.md",
+    r#"# OpenSpace Sample Workspace
+
+This folder was generated by OpenSpace to show you around the app.
+
+- `src/` holds a tiny program you can open in the editor.
+- `docs/` holds notes.
+- `scripts/` holds a script you can run from a terminal.
+- `.openspace/` holds the seed tasks and terminal layout.
+
+Delete this folder at any time to remove the sample completely.
+"#
+  ),
+  (
+    "src/hello.sh",
+    r#"#!/usr/bin/env bash
+echo "Hello from OpenSpace"
+"#
+  ),
+  (
+    "docs/notes.md",
+    r#"# Notes
+
+Use this file to try out the editor.
+"#
+  ),
+  (
+    "scripts/list-files.sh",
+    r#"#!/usr/bin/env bash
+ls -la "$(dirname "$0")/.."
+"#
+  ),
+  (
+    ".openspace/tasks.json",
+    r#"{
+  "tasks": [
+    {
+      "id": "sample-1",
+      "title": "Open README.md",
+      "owner": "you",
+      "lane": "todo",
+      "tags": ["onboarding"],
+      "priority": "p2"
+    },
+    {
+      "id": "sample-2",
+      "title": "Run scripts/list-files.sh in a terminal",
+      "owner": "you",
+      "lane": "todo",
+      "tags": ["onboarding"],
+      "priority": "p3"
+    }
+  ]
+}
+"#
+  ),
+  (
+    ".openspace/terminals.json",
+    r#"{
+  "terminals": [
+    { "title": "shell", "cwd": "." },
+    { "title": "scripts", "cwd": "scripts" }
+  ]
+}
+"#
+  )
+];
 
 #[derive(Clone)]
 struct TerminalSession {
-  master: Arc<Mutex<Box<dyn portable_pty::MasterPty + Send>>>,
+  handles: TerminalSessionHandles,
+  bracketed_paste: Arc<AtomicBool>,
+  log: Arc<Mutex<Option<TerminalLog>>>,
+  bytes_written: Arc<AtomicU64>,
+  bytes_read: Arc<AtomicU64>,
+  output_events: Arc<AtomicU64>,
+  last_activity_ms: Arc<AtomicU64>,
+  created_at: Instant,
+  pid: Arc<AtomicU32>,
+  launch: Arc<TerminalLaunchConfig>,
+  restart_on_exit: Arc<AtomicBool>,
+  restart_policy: TerminalRestartPolicy,
+  keep_alive: bool,
+  idle_warned: Arc<AtomicBool>,
+  scrollback: Arc<Mutex<ScrollbackBuffer>>,
+  visible: Arc<AtomicBool>,
+  /// Set once the process has exited for good; the session lingers for
+  /// `TERMINAL_EXITED_RETENTION` or until `terminal_dispose`.
+  exit_code: Arc<Mutex<Option<i32>>>,
+  /// Set as soon as the PTY closes, before the process is reaped or restarted, so writes in
+  /// that window fail instead of queueing behind a reader that is gone.
+  pty_closed: Arc<AtomicBool>,
+  /// Label of the window that owns the session; only it sees the session and its events.
+  window_label: Arc<Mutex<String>>,
+  priority: Arc<Mutex<TerminalPriority>>,
+  /// Last working directory reported through shell integration.
+  cwd: Arc<Mutex<Option<String>>>
+}
+
+/// The PTY and process behind a session. Each part has its own lock, so a resize never waits
+/// behind a blocked write and a write never waits behind reaping. The reader is not kept here:
+/// the session's reader thread owns it.
+#[derive(Clone)]
+struct TerminalSessionHandles {
+  /// Resizes the PTY and reports its size and settings.
+  resize: Arc<PtyResizeHandle>,
+  /// Input side of the PTY.
   writer: Arc<Mutex<Box<dyn Write + Send>>>,
-  child: Arc<Mutex<Box<dyn portable_pty::Child + Send>>>
+  /// The shell process; reaped once the last handle to it is dropped.
+  child: Arc<Mutex<ChildReaper>>
+}
+
+/// What a session still needs of the PTY master once its reader and writer have been taken:
+/// resizing, its current size and its termios. `MasterPty` is not `Sync`, so the master stays
+/// behind a lock, but only resizes and size queries take it.
+struct PtyResizeHandle {
+  master: Mutex<Box<dyn portable_pty::MasterPty + Send>>,
+  /// Last pixel size the PTY was given, reused by resizes that only change cols/rows.
+  pixel_size: Mutex<(u16, u16)>
+}
+
+/// How a session's process was started, kept so it can be respawned the same way.
+#[derive(Clone, Debug)]
+struct TerminalLaunchConfig {
+  shell: String,
+  args: Vec<String>,
+  cwd: PathBuf,
+  term: String,
+  env: HashMap<String, String>,
+  startup_command: Option<String>,
+  builtin: bool
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TerminalRestartPolicy {
+  max_restarts: Option<u32>,
+  backoff: Duration
+}
+
+/// Per-session behaviour that is not part of how the process is launched.
+struct TerminalSessionOptions {
+  restart_on_exit: bool,
+  restart_policy: TerminalRestartPolicy,
+  keep_alive: bool,
+  priority: TerminalPriority
+}
+
+struct SpawnedTerminal {
+  master: Box<dyn portable_pty::MasterPty + Send>,
+  reader: Box<dyn Read + Send>,
+  writer: Box<dyn Write + Send>,
+  child: ChildReaper,
+  pid: u32
+}
+
+impl PtyResizeHandle {
+  fn new(master: Box<dyn portable_pty::MasterPty + Send>, size: PtySize) -> Self {
+    Self {
+      master: Mutex::new(master),
+      pixel_size: Mutex::new((size.pixel_width, size.pixel_height))
+    }
+  }
+
+  /// Resizes to `cols` x `rows`, keeping the previous pixel size for any dimension not given.
+  /// Returns the pixel size that was applied.
+  fn resize(
+    &self,
+    cols: u16,
+    rows: u16,
+    pixel_width: Option<u16>,
+    pixel_height: Option<u16>
+  ) -> Result<(u16, u16), String> {
+    let master = lock_recover(&self.master);
+    let mut pixel_size = lock_recover(&self.pixel_size);
+    let pixel_width = pixel_width.unwrap_or(pixel_size.0);
+    let pixel_height = pixel_height.unwrap_or(pixel_size.1);
+
+    master
+      .resize(PtySize {
+        rows,
+        cols,
+        pixel_width,
+        pixel_height
+      })
+      .map_err(|error| format!("failed to resize PTY: {error}"))?;
+    *pixel_size = (pixel_width, pixel_height);
+    Ok(*pixel_size)
+  }
+
+  /// The PTY size, with the pixel size it was last given.
+  fn size(&self) -> PtySize {
+    let mut size = lock_recover(&self.master).get_size().unwrap_or(PtySize {
+      rows: 40,
+      cols: 120,
+      pixel_width: 0,
+      pixel_height: 0
+    });
+    (size.pixel_width, size.pixel_height) = *lock_recover(&self.pixel_size);
+    size
+  }
+
+  fn control_character(&self, name: &str) -> Result<Option<u8>, String> {
+    configured_control_character(lock_recover(&self.master).as_ref(), name)
+  }
+
+  #[cfg(unix)]
+  fn raw_fd(&self) -> Option<i32> {
+    lock_recover(&self.master).as_raw_fd()
+  }
+
+  /// Swaps in the master of a respawned process; the pixel size carries over.
+  fn replace(&self, master: Box<dyn portable_pty::MasterPty + Send>) {
+    *lock_recover(&self.master) = master;
+  }
+}
+
+/// Owns a terminal's process and reaps it when dropped, so a path that forgets to `wait()`
+/// cannot leave a zombie behind. A process still running by then is killed first.
+struct ChildReaper(Box<dyn portable_pty::Child + Send>);
+
+impl std::ops::Deref for ChildReaper {
+  type Target = Box<dyn portable_pty::Child + Send>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+impl std::ops::DerefMut for ChildReaper {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.0
+  }
+}
+
+impl Drop for ChildReaper {
+  fn drop(&mut self) {
+    if matches!(self.0.try_wait(), Ok(None)) {
+      let _ = self.0.kill();
+    }
+    let _ = self.0.wait();
+  }
+}
+
+/// The most recent PTY output of a session. `total_bytes` counts everything ever appended,
+/// so offsets stay meaningful after older output has been evicted.
+struct ScrollbackBuffer {
+  data: VecDeque<u8>,
+  capacity: usize,
+  total_bytes: u64,
+  /// Newlines that have fallen off the front, so retained lines keep absolute numbers.
+  dropped_lines: u64,
+  /// Offset of the last `clear`, so output dropped on purpose is not mistaken for eviction.
+  cleared_at: u64,
+  hidden_at: Option<u64>
+}
+
+#[derive(Clone, Copy, Default)]
+enum AnsiState {
+  #[default]
+  Text,
+  Escape,
+  Csi,
+  Osc,
+  OscEscape
+}
+
+/// Removes ANSI escape sequences from PTY output. State carries across calls so a sequence
+/// split between two reads is still removed.
+#[derive(Default)]
+struct AnsiStripper {
+  state: AnsiState
+}
+
+#[derive(Clone, Copy, Default)]
+enum OscState {
+  #[default]
+  Text,
+  Escape,
+  Payload,
+  PayloadEscape
+}
+
+/// Collects OSC payloads (`ESC ] ... BEL` or `ESC ] ... ESC \`) from PTY output.
+/// Sequences split across reads are completed on a later call.
+#[derive(Default)]
+struct OscParser {
+  state: OscState,
+  payload: Vec<u8>,
+  overflowed: bool
+}
+
+enum ShellIntegrationEvent {
+  CommandStart,
+  CommandEnd(Option<i32>),
+  CwdChanged(String)
+}
+
+struct TerminalLog {
+  path: PathBuf,
+  writer: BufWriter<fs::File>,
+  stripper: Option<AnsiStripper>,
+  last_flush: Instant
 }
 
 #[derive(Clone, Default)]
 struct AppState {
-  sessions: Arc<Mutex<HashMap<String, TerminalSession>>>
+  sessions: Arc<Mutex<HashMap<String, TerminalSession>>>,
+  process_sample: Arc<Mutex<Option<ProcessSample>>>,
+  /// Keyed by canonical root; entries older than `GIT_STATUS_CACHE_TTL` are refreshed.
+  git_status_cache: Arc<Mutex<HashMap<String, GitStatusSnapshot>>>,
+  /// Sorted listings being paged through, keyed by path and listing options.
+  listing_cache: Arc<Mutex<HashMap<String, FsListingSnapshot>>>,
+  /// Active filesystem watches by id. Dropping one stops its watcher and its debounce thread.
+  watches: Arc<Mutex<HashMap<String, FsWatch>>>
 }
 
-#[derive(Clone, Default)]
-struct StartupContext {
-  root_path: Option<String>
+struct GitStatusSnapshot {
+  fetched_at: Instant,
+  entries: Vec<GitStatusEntry>
+}
+
+/// Reused while younger than `FILESYSTEM_LIST_CACHE_TTL` and the directory's mtime matches, so
+/// every page of a listing comes from the same sorted snapshot.
+struct FsListingSnapshot {
+  id: String,
+  fetched_at: Instant,
+  last_used: Instant,
+  directory_mtime_ms: u64,
+  entries: Arc<Vec<FilesystemEntry>>,
+  warnings: Arc<Vec<String>>
+}
+
+struct FsWatch {
+  window_label: String,
+  /// Held so the watch lasts exactly as long as this entry.
+  _watcher: Arc<Mutex<notify::RecommendedWatcher>>
+}
+
+/// Which paths under a watched root are reported. With a depth limit or ignore patterns each
+/// directory is watched on its own, so ignored trees such as node_modules cost no watches.
+#[derive(Clone)]
+struct FsWatchFilter {
+  root: PathBuf,
+  /// Directory levels below the root; 0 watches only the root itself.
+  max_depth: Option<usize>,
+  ignore: Vec<glob::Pattern>
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct TerminalCreateRequest {
-  cwd: Option<String>,
-  cols: Option<u16>,
-  rows: Option<u16>,
-  shell: Option<String>,
-  args: Option<Vec<String>>
+struct FsWatchStartRequest {
+  path: String,
+  #[serde(default)]
+  recursive: bool,
+  max_depth: Option<usize>,
+  /// Glob patterns matched against each path component and against the path relative to the
+  /// watched root, e.g. `node_modules` or `dist/**`.
+  #[serde(default)]
+  ignore: Vec<String>
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct TerminalCreateResponse {
-  session_id: String,
-  pid: u32
+struct FsWatchStartResponse {
+  watch_id: String
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct TerminalWriteRequest {
-  session_id: String,
-  data: String
+struct FsWatchStopRequest {
+  watch_id: String
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct TerminalResizeRequest {
-  session_id: String,
-  cols: u16,
-  rows: u16
+struct FsChangedEvent {
+  watch_id: String,
+  /// `created`, `modified`, `removed`, `renamed`, or `rescan` when events were lost and the
+  /// listener should re-read the watched root (`paths` holds just the root).
+  kind: &'static str,
+  paths: Vec<String>
 }
 
 #[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct TerminalKillRequest {
-  session_id: String,
-  signal: Option<String>
+struct GitStatusRequest {
+  root: String
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct TerminalOutputEvent {
-  session_id: String,
-  data: String
+struct GitLogRequest {
+  root: String,
+  max_count: usize,
+  /// Only commits touching this path, relative to `root` or absolute.
+  path: Option<String>
 }
 
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct TerminalExitEvent {
-  session_id: String,
-  exit_code: i32,
-  signal: Option<u32>
+struct GitCommit {
+  hash: String,
+  author_name: String,
+  author_email: String,
+  /// Author date in strict ISO 8601.
+  timestamp: String,
+  subject: String
 }
 
 #[derive(Debug, Deserialize)]
-struct FsListRequest {
-  path: String
+#[serde(rename_all = "camelCase")]
+struct GitDiffRequest {
+  root: String,
+  path: String,
+  #[serde(default)]
+  staged: bool
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct FilesystemEntry {
-  name: String,
+struct GitDiffResponse {
   path: String,
-  is_directory: bool,
-  size: u64,
-  mtime_ms: u64
+  /// Raw unified diff; empty when nothing changed or the file is untracked.
+  diff: String,
+  additions: u32,
+  deletions: u32,
+  untracked: bool
 }
 
 #[derive(Debug, Deserialize)]
-struct FsReadRequest {
-  path: String
+struct GitBranchesRequest {
+  root: String
 }
 
 #[derive(Debug, Serialize)]
-struct FsReadResponse {
-  path: String,
-  content: String
+#[serde(rename_all = "camelCase")]
+struct GitBranchList {
+  /// The checked-out branch, or the abbreviated commit when HEAD is detached.
+  current: String,
+  local: Vec<String>,
+  /// `remote/branch` names, without the remotes' symbolic HEAD entries.
+  remote: Vec<String>
 }
 
 #[derive(Debug, Deserialize)]
-struct FsWriteRequest {
-  path: String,
-  content: String
+#[serde(rename_all = "camelCase")]
+struct GitCheckoutRequest {
+  root: String,
+  #[serde(rename = "ref")]
+  ref_: String,
+  /// Restore only these files from `ref` instead of switching branches.
+  paths: Option<Vec<String>>,
+  /// Restore `paths` even when they have local changes.
+  #[serde(default)]
+  force: bool
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct TaskState {
-  tasks: Vec<serde_json::Value>,
-  updated_at: String
+struct GitCheckoutResult {
+  /// What HEAD names afterwards; unchanged when the checkout was refused.
+  switched_to: String,
+  /// Files whose local changes stopped the checkout. Nothing was changed when non-empty.
+  conflicts: Vec<String>
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// One line of `git status --porcelain=v1`. The status characters keep git's meaning, with `?`
+/// for untracked and a space for unchanged.
+#[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct WorkspaceState {
-  root_path: Option<String>,
-  recent_paths: Vec<String>,
-  updated_at: String
+struct GitStatusEntry {
+  path: String,
+  index_status: char,
+  worktree_status: char,
+  /// The source path of a rename or copy.
+  original_path: Option<String>
 }
 
-fn ensure_linux_runtime() -> Result<(), String> {
-  if std::env::consts::OS != "linux" {
-    return Err(format!(
-      "OpenSpace currently supports Linux only. Detected platform: {}.",
-      std::env::consts::OS
-    ));
-  }
-  Ok(())
+#[derive(Clone, Debug)]
+struct DesktopEntry {
+  id: String,
+  file_path: PathBuf,
+  name: String,
+  icon: Option<String>,
+  exec: String,
+  mime_types: Vec<String>
 }
 
-fn lock_error(name: &str) -> String {
-  format!("failed to lock {name}")
+#[derive(Default)]
+struct ShellCache {
+  shells: Mutex<Option<Vec<ShellInfo>>>
 }
 
-fn io_error(message: &str, error: std::io::Error) -> String {
-  format!("{message}: {error}")
+/// The OS clipboard, opened on first use. It is kept open because on X11 the contents the app
+/// copied are only served while the handle lives.
+#[derive(Default)]
+struct ClipboardState {
+  clipboard: Mutex<Option<arboard::Clipboard>>
 }
 
-fn expand_tilde(input: &str) -> String {
-  if input == "~" || input.starts_with("~/") {
-    if let Ok(home) = std::env::var("HOME") {
-      return input.replacen('~', &home, 1);
-    }
-  }
-  input.to_string()
+#[derive(Debug, Deserialize)]
+struct ClipboardWriteRequest {
+  text: String
 }
 
-fn resolve_path(input: &str) -> Result<PathBuf, String> {
-  let expanded = expand_tilde(input);
-  let candidate = PathBuf::from(&expanded);
-  if candidate.is_absolute() {
-    return Ok(candidate);
-  }
-  std::env::current_dir()
-    .map(|cwd| cwd.join(candidate))
-    .map_err(|error| io_error("failed to resolve relative path", error))
+#[derive(Debug, Serialize)]
+struct ClipboardReadResponse {
+  text: String
 }
 
-fn normalize_workspace_root(path: PathBuf) -> Result<PathBuf, String> {
-  if path.is_dir() {
-    return Ok(path);
-  }
-
-  if path.is_file() {
-    return path.parent().map(Path::to_path_buf).ok_or_else(|| {
-      format!(
-        "failed to determine workspace root for file path {}",
-        path.display()
-      )
-    });
-  }
-
-  Err(format!(
-    "startup path must be a file or directory: {}",
-    path.display()
-  ))
+/// Long-running background operations, such as exports, that the frontend can list and cancel,
+/// and the results of recently finished ones.
+#[derive(Default)]
+struct OperationRegistry {
+  operations: Mutex<HashMap<String, OperationHandle>>,
+  results: Mutex<VecDeque<FinishedOperation>>
 }
 
-fn resolve_startup_root_from_args() -> Result<Option<String>, String> {
-  let argument = match std::env::args().nth(1) {
-    Some(value) if !value.trim().is_empty() => value,
-    _ => return Ok(None)
-  };
+struct OperationHandle {
+  kind: &'static str,
+  cancelled: Arc<AtomicBool>
+}
 
-  let resolved = resolve_path(&argument)?;
-  if !resolved.exists() {
-    return Err(format!(
-      "startup path does not exist: {}",
-      resolved.display()
-    ));
-  }
+struct FinishedOperation {
+  operation_id: String,
+  event: &'static str,
+  finished_at: Instant,
+  payload: serde_json::Value
+}
 
-  let root = normalize_workspace_root(resolved)?;
-  let canonical_root = fs::canonicalize(&root)
-    .map_err(|error| io_error("failed to canonicalize startup path", error))?;
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OperationResultRequest {
+  operation_id: String
+}
 
-  Ok(Some(canonical_root.to_string_lossy().into_owned()))
+/// A running operation has no `event` or `payload` yet; a finished one has the event it
+/// reported through and that event's payload.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OperationResult {
+  operation_id: String,
+  running: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  event: Option<&'static str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  payload: Option<serde_json::Value>
 }
 
-fn modified_time_ms(metadata: &Metadata) -> u64 {
-  metadata
-    .modified()
-    .ok()
-    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
-    .and_then(|duration| u64::try_from(duration.as_millis()).ok())
-    .unwrap_or_default()
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkspaceExportRequest {
+  output_path: String,
+  #[serde(default)]
+  overwrite: bool
 }
 
-fn default_task_state() -> TaskState {
-  TaskState {
-    tasks: Vec::new(),
-    updated_at: Utc::now().to_rfc3339()
-  }
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkspaceExportStarted {
+  operation_id: String
 }
 
-fn default_workspace_state() -> WorkspaceState {
-  WorkspaceState {
-    root_path: None,
-    recent_paths: Vec::new(),
-    updated_at: Utc::now().to_rfc3339()
-  }
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StateOpProgressEvent {
+  operation_id: String,
+  /// `reading` while the state files are collected, then `writing`.
+  phase: &'static str,
+  items_processed: u64,
+  items_total: u64,
+  bytes: u64
 }
 
-fn persistence_file_path(app: &AppHandle, file_name: &str) -> Result<PathBuf, String> {
-  let mut path = app.path().app_data_dir().map_err(|error| error.to_string())?;
-  path.push("state");
-  fs::create_dir_all(&path).map_err(|error| io_error("failed to create app state directory", error))?;
-  path.push(file_name);
-  Ok(path)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkspaceExportFinishedEvent {
+  operation_id: String,
+  output_path: String,
+  bytes_written: u64,
+  /// The state files included; ones that do not exist yet are left out.
+  files: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
 }
 
-fn read_json_or_default<T>(file_path: &Path, fallback: T) -> Result<T, String>
-where
-  T: for<'de> Deserialize<'de>
-{
-  match fs::read_to_string(file_path) {
-    Ok(raw) => serde_json::from_str(&raw)
-      .map_err(|error| format!("failed to parse JSON at {}: {error}", file_path.display())),
-    Err(error) if error.kind() == ErrorKind::NotFound => Ok(fallback),
-    Err(error) => Err(io_error(
-      &format!("failed to read {}", file_path.display()),
-      error
-    ))
-  }
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OperationInfo {
+  operation_id: String,
+  kind: String
 }
 
-fn write_json<T>(file_path: &Path, value: &T) -> Result<(), String>
-where
-  T: Serialize
-{
-  let serialized = serde_json::to_string_pretty(value).map_err(|error| {
-    format!(
-      "failed to serialize JSON for {}: {error}",
-      file_path.display()
-    )
-  })?;
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OperationCancelRequest {
+  operation_id: String
+}
 
-  if let Some(parent) = file_path.parent() {
-    fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
-  }
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemExportRequest {
+  source_path: String,
+  destination_path: String,
+  #[serde(default)]
+  checksum: bool
+}
 
-  fs::write(file_path, serialized)
-    .map_err(|error| io_error(&format!("failed to write {}", file_path.display()), error))
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemExportStarted {
+  operation_id: String,
+  total_bytes: u64,
+  resumed_from: u64
 }
 
-fn get_terminal_session(state: &State<'_, AppState>, session_id: &str) -> Result<TerminalSession, String> {
-  let sessions = state
-    .sessions
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemExportProgressEvent {
+  operation_id: String,
+  copied_bytes: u64,
+  total_bytes: u64
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemExportFinishedEvent {
+  operation_id: String,
+  destination_path: String,
+  bytes_written: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  sha256: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsCopyRequest {
+  source_path: String,
+  destination_path: String,
+  #[serde(default)]
+  recursive: bool,
+  #[serde(default)]
+  overwrite: bool,
+  /// Copy what symlinks inside the tree point to instead of skipping them.
+  #[serde(default)]
+  follow_symlinks: bool
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsCopyStarted {
+  operation_id: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsCopyProgressEvent {
+  operation_id: String,
+  copied_bytes: u64,
+  copied_files: u64,
+  total_bytes: u64,
+  total_files: u64
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsCopyFailure {
+  path: String,
+  error: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsCopyFinishedEvent {
+  operation_id: String,
+  destination_path: String,
+  copied_bytes: u64,
+  copied_files: u64,
+  skipped_symlinks: u64,
+  /// Entries that could not be copied. The rest of the tree is still copied.
+  failures: Vec<FsCopyFailure>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsSearchFilenamesRequest {
+  root: String,
+  /// Matched as a case-insensitive subsequence of the path relative to `root`; whitespace is
+  /// ignored. An empty query matches every file.
+  query: String,
+  max_results: Option<usize>,
+  /// Defaults to true.
+  respect_gitignore: Option<bool>,
+  /// Directory names never descended into. Defaults to `.git` and `node_modules`.
+  skip_dirs: Option<Vec<String>>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsSearchStarted {
+  search_id: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsSearchMatch {
+  path: String,
+  relative_path: String,
+  score: i64
+}
+
+/// The best matches found so far, replacing those of the previous event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsSearchResultsEvent {
+  search_id: String,
+  matches: Vec<FsSearchMatch>,
+  scanned_files: u64
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsSearchFinishedEvent {
+  search_id: String,
+  matches: Vec<FsSearchMatch>,
+  scanned_files: u64,
+  /// Every file that matched, including those beyond `max_results`.
+  total_matches: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
+}
+
+/// What to look for and where; shared by content search and replace.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsContentQuery {
+  root: String,
+  query: String,
+  /// Treat `query` as a regular expression rather than literal text.
+  #[serde(default)]
+  is_regex: bool,
+  #[serde(default)]
+  case_sensitive: bool,
+  #[serde(default)]
+  whole_word: bool,
+  /// Only files whose path relative to `root` matches one of these are searched.
+  #[serde(default)]
+  include_globs: Vec<String>,
+  #[serde(default)]
+  exclude_globs: Vec<String>,
+  /// Defaults to true.
+  respect_gitignore: Option<bool>,
+  /// Directory names never descended into. Defaults to `.git` and `node_modules`.
+  skip_dirs: Option<Vec<String>>,
+  /// Directories or files under `root`, absolute or relative to it, to search instead of the
+  /// whole root. Matched as exact path prefixes, so names need no glob escaping. Empty means
+  /// the whole root.
+  #[serde(default)]
+  scope_paths: Vec<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsSearchContentRequest {
+  #[serde(flatten)]
+  query: FsContentQuery,
+  max_matches: Option<usize>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReplaceInFilesRequest {
+  #[serde(flatten)]
+  query: FsContentQuery,
+  /// In regex mode `$1` and `${name}` expand to capture groups; otherwise it is inserted as is.
+  replacement: String,
+  /// Report what would change without writing anything.
+  #[serde(default)]
+  dry_run: bool,
+  /// File mtimes from a dry run, by path. Only these files are rewritten; one whose mtime has
+  /// moved on since, or that the dry run did not report, is skipped.
+  #[serde(default)]
+  expected_mtimes: HashMap<String, u64>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReplaceStarted {
+  operation_id: String
+}
+
+/// The lines around one or more adjacent matches, before and after replacing them. Spans more
+/// than one line when a match does.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReplacePreview {
+  line_number: u64,
+  before: String,
+  after: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReplaceFileResult {
+  path: String,
+  relative_path: String,
+  matches: u64,
+  mtime_ms: u64,
+  /// The first `FS_REPLACE_PREVIEWS_PER_FILE` changed lines. Only filled in by a dry run.
+  previews: Vec<FsReplacePreview>
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReplaceSkippedFile {
+  path: String,
+  error: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReplaceFinishedEvent {
+  operation_id: String,
+  dry_run: bool,
+  /// Files that matched; on execution, only those that were rewritten.
+  files: Vec<FsReplaceFileResult>,
+  files_changed: u64,
+  /// On a dry run, how many matches would be replaced.
+  matches_replaced: u64,
+  /// Files that were left alone: too large, changed on disk, not in the dry run, or not
+  /// writable.
+  skipped: Vec<FsReplaceSkippedFile>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+struct FsGrepSubmatch {
+  start: usize,
+  end: usize
+}
+
+/// One matching line. Submatch ranges are byte offsets into the line's UTF-8 bytes.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsGrepMatch {
+  path: String,
+  relative_path: String,
+  /// 1-based.
+  line_number: u64,
+  line: String,
+  submatches: Vec<FsGrepSubmatch>,
+  /// The line was cut to `FS_GREP_LINE_MAX_BYTES`; submatches past the cut are left out.
+  truncated: bool,
+  /// The entry of `scopePaths` the file was found under, resolved to an absolute path.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  scope: Option<String>
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsGrepMatchesEvent {
+  search_id: String,
+  matches: Vec<FsGrepMatch>
+}
+
+/// Collects matches found by the search workers until a batch is due.
+struct GrepMatchBatch {
+  pending: Vec<FsGrepMatch>,
+  last_sent: Instant
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsGrepFinishedEvent {
+  search_id: String,
+  searched_files: u64,
+  matched_files: u64,
+  total_matches: u64,
+  /// `maxMatches` was reached and the search stopped early.
+  limit_reached: bool,
+  binary_files_skipped: u64,
+  binary_files: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct FilesystemArchiveRequest {
+  paths: Vec<String>,
+  output: String,
+  format: ArchiveFormat,
+  #[serde(default)]
+  overwrite: bool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum ArchiveFormat {
+  #[serde(rename = "zip")]
+  Zip,
+  #[serde(rename = "tar.gz")]
+  TarGz
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemArchiveStarted {
+  operation_id: String,
+  output: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemArchiveFinishedEvent {
+  operation_id: String,
+  output: String,
+  size_bytes: u64,
+  file_count: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemArchiveProgressEvent {
+  operation_id: String,
+  output: String,
+  files_done: u64,
+  files_total: u64
+}
+
+#[derive(Debug, Deserialize)]
+struct FilesystemExtractRequest {
+  archive: String,
+  destination: String,
+  #[serde(default)]
+  overwrite: bool
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemExtractStarted {
+  operation_id: String,
+  destination: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemExtractFinishedEvent {
+  operation_id: String,
+  archive: String,
+  destination: String,
+  file_count: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemExtractProgressEvent {
+  operation_id: String,
+  archive: String,
+  files_done: u64,
+  files_total: u64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExtractFormat {
+  Zip,
+  TarGz,
+  TarXz
+}
+
+/// One path going into an archive, named relative to the parent of the input it came from.
+struct ArchiveEntry {
+  source: PathBuf,
+  name: String,
+  metadata: Metadata
+}
+
+/// Written next to a partial export. `verified_offset` only advances after the destination has
+/// been synced, so everything before it is known to be on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportCheckpoint {
+  source_path: String,
+  source_size: u64,
+  source_mtime_ms: u64,
+  verified_offset: u64
+}
+
+#[derive(Clone, Copy, Debug)]
+enum EventReplayPolicy {
+  /// Only the newest event per terminal session, or per event type when the payload has no
+  /// `sessionId`, for up to `EVENT_REPLAY_LATEST_TTL`.
+  Latest,
+  /// Everything emitted within the window, up to `EVENT_REPLAY_MAX_PER_TYPE`.
+  Recent(Duration)
+}
+
+#[derive(Default)]
+struct EventReplayState {
+  buffer: Mutex<EventReplayBuffer>
+}
+
+#[derive(Default)]
+struct EventReplayBuffer {
+  next_sequence: u64,
+  events: VecDeque<ReplayedEvent>
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReplayedEvent {
+  sequence: u64,
+  event: String,
+  payload: serde_json::Value,
+  emitted_at_ms: u64,
+  replayed: bool,
+  /// Window label for events emitted to one window only.
+  #[serde(skip)]
+  target: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct EventsReplayRequest {
+  /// Only events with a larger sequence number; omit to get everything still buffered.
+  since: Option<u64>
+}
+
+#[derive(Default)]
+struct DesktopEntryCache {
+  scanned: Mutex<Option<(Instant, Arc<Vec<DesktopEntry>>)>>
+}
+
+#[derive(Default)]
+struct MimeAssociations {
+  defaults: HashMap<String, Vec<String>>,
+  added: HashMap<String, Vec<String>>,
+  removed: HashMap<String, Vec<String>>
+}
+
+/// Keyed by root, day range and the date computed on, with a sequence number ordering inserts.
+type ActivityCacheEntries = HashMap<(String, u32, String), (u64, WorkspaceActivity)>;
+
+#[derive(Default)]
+struct ActivityCache {
+  entries: Mutex<ActivityCacheEntries>
+}
+
+/// The active workspace root: the one passed on the command line until `workspace_set_root`
+/// switches it.
+#[derive(Default)]
+struct StartupContext {
+  root_path: Mutex<Option<String>>,
+  /// How the launch arguments picked the startup root, if they did.
+  root_provenance: Option<RootProvenance>,
+  /// A file named on the command line, opened once the frontend is ready.
+  open_path: Option<PathBuf>
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RootProvenance {
+  root_path: String,
+  /// `flag` (--root), `directory` (a directory argument), `inferred` (the nearest project
+  /// marker above a file argument) or `parent` (the file's directory, when no marker was found).
+  source: &'static str,
+  requested_path: String,
+  /// The project marker that placed an inferred root.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  marker: Option<&'static str>
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenPathEvent {
+  path: String,
+  is_directory: bool
+}
+
+/// Serializes read-modify-write updates of tasks.json between concurrent commands.
+#[derive(Default)]
+struct TaskStore {
+  write_lock: Mutex<()>
+}
+
+/// Serializes read-modify-write updates of workspace.json, so a revision check and the write it
+/// guards cannot interleave with another command's.
+#[derive(Default)]
+struct WorkspaceStore {
+  write_lock: Mutex<()>
+}
+
+/// Remembered pane sizes, loaded from terminal-layouts.json on first use. Changes are written
+/// back `TERMINAL_LAYOUT_FLUSH_DELAY` after the first unflushed one, and on shutdown.
+#[derive(Default)]
+struct TerminalLayoutStore {
+  layouts: Mutex<Option<HashMap<String, TerminalLayout>>>,
+  /// Set while changes are waiting for a flush.
+  pending: AtomicBool,
+  /// Held while writing, so an older snapshot never lands after a newer one.
+  write_lock: Mutex<()>
+}
+
+struct SettingsState {
+  settings: Mutex<AppSettings>
+}
+
+/// Where the opt-in automation socket is listening, if it was enabled for this launch.
+#[derive(Default)]
+struct ControlSocketState {
+  path: Option<PathBuf>
+}
+
+#[derive(Default)]
+struct ShutdownState {
+  closing: AtomicBool
+}
+
+#[derive(Default)]
+struct SafeModeState {
+  reason: Option<String>,
+  disabled_subsystems: Mutex<Vec<String>>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalCreateRequest {
+  cwd: Option<String>,
+  cols: Option<u16>,
+  rows: Option<u16>,
+  /// Size of the whole terminal area in pixels, for programs that draw images (sixel, kitty).
+  #[serde(alias = "pixelCols")]
+  pixel_width: Option<u16>,
+  #[serde(alias = "pixelRows")]
+  pixel_height: Option<u16>,
+  shell: Option<String>,
+  args: Option<Vec<String>>,
+  term_name: Option<String>,
+  restart_on_exit: Option<bool>,
+  max_restarts: Option<u32>,
+  restart_backoff_ms: Option<u64>,
+  keep_alive: Option<bool>,
+  /// Niceness for the shell, clamped to -20..19.
+  nice: Option<i32>,
+  ionice_class: Option<IoniceClass>
+}
+
+/// The scheduling priority asked for a session, re-applied when its process restarts.
+#[derive(Clone, Copy, Debug, Default)]
+struct TerminalPriority {
+  nice: Option<i32>,
+  ionice_class: Option<IoniceClass>
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum IoniceClass {
+  Realtime,
+  BestEffort,
+  Idle
+}
+
+#[derive(Clone, Copy, Debug)]
+enum PriorityTarget {
+  Process(u32),
+  Group(u32)
+}
+
+/// What was actually set. Lowering niceness or using the realtime I/O class needs privileges,
+/// so a request can be accepted yet not applied; the warnings say why.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalPriorityResult {
+  nice: Option<i32>,
+  nice_applied: bool,
+  ionice_class: Option<IoniceClass>,
+  ionice_applied: bool,
+  warnings: Vec<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalSetPriorityRequest {
+  session_id: String,
+  nice: Option<i32>,
+  ionice_class: Option<IoniceClass>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalCreateResponse {
+  session_id: String,
+  pid: u32,
+  term: String,
+  cols: u16,
+  rows: u16,
+  /// `request`, `workspace` (the last pane size in this workspace), `session` (copied by
+  /// `terminal_duplicate`) or `default`.
+  size_source: String,
+  /// Present when the request asked for a niceness or I/O class.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  priority: Option<TerminalPriorityResult>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalWriteRequest {
+  session_id: String,
+  data: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalWriteBytesRequest {
+  session_id: String,
+  data_base64: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TerminalBroadcastTargets {
+  Sessions(Vec<String>),
+  Keyword(String)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalBroadcastRequest {
+  session_ids: TerminalBroadcastTargets,
+  data: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalBroadcastResult {
+  ok: bool,
+  error: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalPasteRequest {
+  session_id: String,
+  data: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalSendControlRequest {
+  session_id: String,
+  name: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalStartLoggingRequest {
+  session_id: String,
+  path: String,
+  strip_ansi: Option<bool>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalStopLoggingRequest {
+  session_id: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalStatsRequest {
+  session_id: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalStatsListRequest {
+  session_id: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalVisibilityRequest {
+  session_id: String,
+  visible: bool
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalSearchBufferRequest {
+  session_id: String,
+  query: String,
+  regex: Option<bool>,
+  case_insensitive: Option<bool>,
+  max_results: Option<usize>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalSearchBufferResponse {
+  matches: Vec<TerminalBufferMatch>,
+  /// True when more matches exist than were returned.
+  truncated: bool
+}
+
+/// A hit in the ANSI-stripped scrollback. `line` counts from the session's first output line;
+/// `start` and `end` are character offsets within it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalBufferMatch {
+  line: u64,
+  start: usize,
+  end: usize,
+  context: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalInfoRequest {
+  session_id: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalInfo {
+  session_id: String,
+  pid: u32,
+  shell: String,
+  term: String,
+  visible: bool,
+  builtin: bool,
+  last_activity_ms: u64,
+  exited: bool,
+  exit_code: Option<i32>,
+  window_label: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalStatsResponse {
+  session_id: String,
+  bytes_written: u64,
+  bytes_read: u64,
+  output_events: u64,
+  last_activity_ms: u64,
+  uptime_secs: u64
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalResizeRequest {
+  session_id: String,
+  cols: u16,
+  rows: u16,
+  /// Omitted pixel sizes keep the session's current ones.
+  #[serde(alias = "pixelCols")]
+  pixel_width: Option<u16>,
+  #[serde(alias = "pixelRows")]
+  pixel_height: Option<u16>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalKillRequest {
+  session_id: String,
+  signal: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalDisposeRequest {
+  session_id: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalClearRequest {
+  session_id: String,
+  /// Also send the clear sequence through the output stream so every attached view resets.
+  #[serde(default)]
+  inject_clear: bool
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalDuplicateRequest {
+  session_id: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalAdoptRequest {
+  session_id: String
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TerminalLogFormat {
+  /// Escape sequences and carriage returns removed.
+  Text,
+  /// The output exactly as the PTY produced it.
+  Ansi
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalExportLogRequest {
+  session_id: String,
+  output_path: String,
+  format: TerminalLogFormat,
+  #[serde(default)]
+  overwrite: bool
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalExportLogResponse {
+  output_path: String,
+  size_bytes: u64,
+  /// Older output had already left the scrollback, so the export starts part way through.
+  truncated: bool
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalOutputEvent {
+  session_id: String,
+  data: String
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalExitEvent {
+  session_id: String,
+  exit_code: i32,
+  signal: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalRestartedEvent {
+  session_id: String,
+  attempt: u32,
+  pid: u32
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalResizeEvent {
+  session_id: String,
+  cols: u16,
+  rows: u16,
+  pixel_width: u16,
+  pixel_height: u16
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalLogErrorEvent {
+  session_id: String,
+  path: String,
+  message: String
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalCommandStartEvent {
+  session_id: String
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalCommandEndEvent {
+  session_id: String,
+  exit_code: Option<i32>
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalCwdChangedEvent {
+  session_id: String,
+  cwd: String
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalIdleEvent {
+  session_id: String,
+  idle_secs: u64,
+  kill_in_secs: u64
+}
+
+#[derive(Debug, Deserialize)]
+struct ShellIntegrationRequest {
+  shell: Option<String>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShellIntegrationResponse {
+  shell: String,
+  path: String,
+  source_line: String
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsListRequest {
+  path: String,
+  #[serde(default)]
+  sort_by: Option<FsSortKey>,
+  #[serde(default)]
+  sort_desc: Option<bool>,
+  /// Takes precedence over `sort_desc` when both are given.
+  #[serde(default)]
+  sort_order: Option<FsSortOrder>,
+  /// Defaults to true; when false directories are sorted in among the files.
+  #[serde(default)]
+  directories_first: Option<bool>,
+  #[serde(default)]
+  natural_sort: bool,
+  /// Only files whose name matches are listed. Directories are always kept so the tree can
+  /// still be browsed.
+  #[serde(default)]
+  filter_glob: Option<String>,
+  #[serde(default, alias = "showHidden")]
+  include_hidden: Option<bool>,
+  /// Entries whose name matches any of these are left out.
+  #[serde(default)]
+  exclude_globs: Vec<String>,
+  /// Flag entries matched by `.gitignore` files between the listed directory and the
+  /// repository or workspace root.
+  #[serde(default)]
+  respect_gitignore: bool,
+  /// With `respect_gitignore`, leave ignored entries out instead of flagging them.
+  #[serde(default)]
+  hide_ignored: bool,
+  /// Return at most this many entries as an `FsListPage` instead of the whole array.
+  #[serde(default)]
+  max_entries: Option<usize>,
+  /// Entries to skip in the sorted listing. Also switches the response to an `FsListPage`.
+  #[serde(default)]
+  offset: Option<usize>,
+  /// The `snapshotId` of an earlier page. The request fails with STALE once that listing is
+  /// gone, e.g. because the directory changed, instead of paging through a different one.
+  #[serde(default)]
+  snapshot_id: Option<String>
+}
+
+/// A plain array unless the request asked for a page, which keeps existing callers working.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum FsListResponse {
+  Entries(Vec<FilesystemEntry>),
+  Page(FsListPage)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsListPage {
+  entries: Vec<FilesystemEntry>,
+  offset: usize,
+  total_count: usize,
+  has_more: bool,
+  /// Pass back as `snapshotId` so later pages come from this same listing.
+  snapshot_id: String,
+  /// Some entries could not be read and are missing; `warnings` says which directory and why.
+  partial: bool,
+  warnings: Vec<String>
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FsSortKey {
+  #[default]
+  Name,
+  Size,
+  Mtime,
+  /// By extension, so files of the same kind sit together.
+  #[serde(alias = "extension")]
+  Type
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FsSortOrder {
+  Asc,
+  Desc
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FsSortOptions {
+  key: FsSortKey,
+  descending: bool,
+  directories_first: bool,
+  /// Compare runs of digits by value, so `file2` comes before `file10`.
+  natural: bool
+}
+
+impl Default for FsSortOptions {
+  fn default() -> Self {
+    Self {
+      key: FsSortKey::Name,
+      descending: false,
+      directories_first: true,
+      natural: false
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemEntry {
+  name: String,
+  path: String,
+  is_directory: bool,
+  /// Apparent size in bytes. `None` for directories, whose dirent size says nothing about
+  /// their contents.
+  size: Option<u64>,
+  size_on_disk: Option<u64>,
+  mtime_ms: u64,
+  /// Nesting level below the listed directory, starting at 1. Only set by recursive listings.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  depth: Option<u32>,
+  /// Matched by a `.gitignore`. Only set when a listing asks to respect them.
+  ignored: bool,
+  /// The path is a symlink. `is_directory`, sizes and mtime describe what it points to.
+  is_symlink: bool,
+  /// The link's target as written; `fs_stat` reports where it finally resolves instead.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  symlink_target: Option<String>,
+  /// A symlink whose target does not exist; it is listed as a file.
+  broken: bool,
+  /// Why the entry's metadata could not be read. Only the name, path and kind are then known.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
+}
+
+/// Filters and sort options mean the same as in `FsListRequest` and apply at every level.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsTreeRequest {
+  path: String,
+  /// Levels below `path` to load; 1 loads only its direct children.
+  #[serde(default)]
+  max_depth: Option<usize>,
+  #[serde(default)]
+  max_entries_per_directory: Option<usize>,
+  #[serde(default)]
+  sort_by: Option<FsSortKey>,
+  #[serde(default)]
+  sort_order: Option<FsSortOrder>,
+  #[serde(default)]
+  directories_first: Option<bool>,
+  #[serde(default)]
+  natural_sort: bool,
+  #[serde(default, alias = "showHidden")]
+  include_hidden: Option<bool>,
+  #[serde(default)]
+  exclude_globs: Vec<String>,
+  #[serde(default)]
+  respect_gitignore: bool,
+  #[serde(default)]
+  hide_ignored: bool
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsTreeNode {
+  #[serde(flatten)]
+  entry: FilesystemEntry,
+  /// The directory's children in `filesystem_list` order. `None` for files and for directories
+  /// that were not loaded.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  children: Option<Vec<FsTreeNode>>,
+  /// Only the first `maxEntriesPerDirectory` children are included.
+  children_truncated: bool,
+  /// A directory whose children still have to be listed: it is past the depth or entry limit,
+  /// would close a symlink cycle, or could not be read (`error` then says why).
+  not_loaded: bool,
+  /// Children that could not be read and are missing from `children`.
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  warnings: Vec<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsListRecursiveRequest {
+  path: String,
+  max_depth: usize,
+  #[serde(default)]
+  include_hidden: bool
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsListRecursiveStarted {
+  operation_id: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsListRecursiveFinishedEvent {
+  operation_id: String,
+  path: String,
+  entries: Vec<FilesystemEntry>,
+  /// The listing stopped at `FILESYSTEM_LIST_RECURSIVE_MAX_ENTRIES`.
+  truncated: bool,
+  /// Entries and subdirectories that could not be read and are missing from `entries`.
+  warnings: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsRenameRequest {
+  source_path: String,
+  destination_path: String,
+  #[serde(default)]
+  overwrite: bool
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsDeleteRequest {
+  path: String,
+  #[serde(default)]
+  recursive: bool,
+  #[serde(default)]
+  use_trash: bool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum FsDeleteAction {
+  Trashed,
+  Deleted
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsDeleteResult {
+  path: String,
+  action: FsDeleteAction,
+  /// Where the item now lives under the trash's `files` directory, for undo.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  trashed_path: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  trash_info_path: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct FsMkdirRequest {
+  path: String,
+  #[serde(default)]
+  recursive: bool
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsCreateFileRequest {
+  path: String,
+  #[serde(default)]
+  content: Option<String>,
+  #[serde(default)]
+  create_parents: bool,
+  /// Create with O_EXCL so a concurrent create of the same path fails instead of sharing it.
+  #[serde(default)]
+  exclusive: bool
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReadRequest {
+  path: String,
+  /// Caps the bytes returned for a binary file. Defaults to FILESYSTEM_READ_BINARY_MAX_BYTES.
+  #[serde(default)]
+  max_binary_bytes: Option<u64>
+}
+
+#[derive(Debug, Deserialize)]
+struct FsStatRequest {
+  path: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsStatResponse {
+  #[serde(flatten)]
+  entry: FilesystemEntry,
+  /// Inode change time on Unix, creation time on Windows.
+  ctime_ms: Option<u64>,
+  /// Unix permission bits, e.g. `0o644`. `None` on other platforms.
+  mode: Option<u32>,
+  writable: bool,
+  /// `None` for directories.
+  is_binary: Option<bool>,
+  /// Whether far less is allocated than the file's size suggests; `None` for directories.
+  is_sparse: Option<bool>
+}
+
+#[derive(Debug, Deserialize)]
+struct FilesystemResolveRequest {
+  path: String
+}
+
+#[derive(Debug, Serialize)]
+struct FilesystemResolveResponse {
+  resolved: String,
+  exists: bool
+}
+
+#[derive(Debug, Deserialize)]
+struct FilesystemIsTextRequest {
+  path: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemIsTextResponse {
+  is_text: bool,
+  encoding: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct FilesystemHashRequest {
+  path: String,
+  algorithm: HashAlgorithm
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum HashAlgorithm {
+  Sha256,
+  Md5
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemHashStarted {
+  operation_id: String
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesystemHashFinishedEvent {
+  operation_id: String,
+  path: String,
+  algorithm: HashAlgorithm,
+  /// Absent when the hash failed or was cancelled.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  hex_digest: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>
+}
+
+#[derive(Debug, Serialize)]
+struct FilesystemPathResponse {
+  path: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReadResponse {
+  path: String,
+  /// Text decoded to UTF-8, or base64 of the raw bytes when `is_binary` is set.
+  content: String,
+  /// The encoding the text was decoded from, as an encoding_rs name such as `windows-1252`.
+  /// `None` for binary files.
+  encoding: Option<String>,
+  is_binary: bool,
+  /// Set when a binary file was longer than the cap and `content` holds only its start.
+  truncated: bool,
+  /// Modification time after the read or write, to send back as `expected_mtime_ms` on save.
+  mtime_ms: u64,
+  /// Only set by `filesystem_write`: `false` when no temp file could be created next to the
+  /// target and it was overwritten in place.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  atomic: Option<bool>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReadRangeRequest {
+  path: String,
+  offset_bytes: u64,
+  max_bytes: u64
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReadRangeResponse {
+  path: String,
+  offset_bytes: u64,
+  /// Bytes consumed from the file. A UTF-8 character cut off at the end of the range is left
+  /// for the next page, so `offset_bytes + bytes_read` is where that page should start.
+  bytes_read: u64,
+  content: String,
+  total_size: u64,
+  eof: bool
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReadLinesRequest {
+  path: String,
+  /// Zero-based.
+  start_line: u64,
+  line_count: usize
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FsReadLinesResponse {
+  path: String,
+  start_line: u64,
+  /// Lines without their terminators, decoded as UTF-8 with invalid bytes replaced.
+  lines: Vec<String>,
+  /// Set when a line was longer than the `fileReadMaxBytes` setting and was cut there.
+  truncated: bool,
+  eof: bool
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FsWriteRequest {
+  path: String,
+  content: String,
+  /// When set, the write fails with CONFLICT if the file's mtime differs, e.g. because it was
+  /// changed by a `git pull` after the editor read it.
+  #[serde(default, alias = "expected_mtime_ms")]
+  expected_mtime_ms: Option<u64>,
+  /// Write even if `expected_mtime_ms` no longer matches.
+  #[serde(default)]
+  force: bool,
+  /// Encoding label to write the text in, e.g. the one `filesystem_read` reported. UTF-8 when
+  /// unset.
+  #[serde(default)]
+  encoding: Option<String>,
+  /// On CONFLICT, also send the file's current text, so a merge view needs no second read.
+  #[serde(default)]
+  include_current_content: bool
+}
+
+/// The `details` of a CONFLICT from `filesystem_write`. `current_mtime_ms` is unset when the
+/// file was deleted; `current_content` is only filled in when asked for and the file is text
+/// within the `fileReadMaxBytes` setting.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WriteConflict {
+  path: String,
+  expected_mtime_ms: u64,
+  current_mtime_ms: Option<u64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  current_content: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  current_encoding: Option<String>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskState {
+  tasks: Vec<serde_json::Value>,
+  #[serde(default)]
+  revision: u64,
+  #[serde(default)]
+  updated_at: String
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkspaceState {
+  root_path: Option<String>,
+  /// Most recent first, one entry per canonical path.
+  recent_paths: Vec<String>,
+  /// Recent paths that are never evicted and don't count toward `recentPathsMax`.
+  #[serde(default)]
+  pinned_paths: Vec<String>,
+  #[serde(default)]
+  revision: u64,
+  #[serde(default)]
+  updated_at: String,
+  /// Set by workspace_load while the root is still the one the launch arguments chose; never
+  /// persisted.
+  #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+  root_provenance: Option<RootProvenance>
+}
+
+/// What `workspace_save` accepts. Unlike `WorkspaceState`, `pinnedPaths` may be left out, in
+/// which case the stored pins are kept rather than cleared.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkspaceSaveState {
+  root_path: Option<String>,
+  recent_paths: Vec<String>,
+  #[serde(default)]
+  pinned_paths: Option<Vec<String>>,
+  #[serde(default)]
+  revision: u64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AppSettings {
+  #[serde(default = "default_first_run")]
+  first_run: bool,
+  #[serde(default = "default_recent_paths_max")]
+  recent_paths_max: usize,
+  #[serde(default)]
+  control_socket_enabled: bool,
+  /// Sessions without input or output for this long get a `terminal:idle` warning and are
+  /// killed after a further grace period. `None` disables the sweep.
+  #[serde(default)]
+  terminal_idle_timeout_minutes: Option<u64>,
+  #[serde(default = "default_terminal_idle_grace_minutes")]
+  terminal_idle_grace_minutes: u64,
+  /// State files whose compact JSON is larger than this are written compact; smaller ones stay
+  /// pretty-printed for hand editing.
+  #[serde(default = "default_json_pretty_max_bytes")]
+  json_pretty_max_bytes: u64,
+  /// `filesystem_read` refuses files larger than this with TOO_LARGE, and it also caps a single
+  /// `fs_read_range` or `fs_read_lines` page.
+  #[serde(default = "default_file_read_max_bytes")]
+  file_read_max_bytes: u64,
+  updated_at: String
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AppHealth {
+  safe_mode: bool,
+  safe_mode_reason: Option<String>,
+  disabled_subsystems: Vec<String>,
+  terminal_sessions: usize,
+  control_socket: Option<String>
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShutdownProgressEvent {
+  phase: String,
+  remaining: usize,
+  message: String
+}
+
+#[derive(Debug, Deserialize)]
+struct SafeModeEnableRequest {
+  subsystem: String
+}
+
+#[derive(Debug, Deserialize)]
+struct ShellsListRequest {
+  refresh: Option<bool>
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShellInfo {
+  path: String,
+  name: String,
+  is_default: bool,
+  default_args: Vec<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct SystemListHandlersRequest {
+  path: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SystemHandler {
+  desktop_id: String,
+  name: String,
+  icon: Option<String>,
+  is_default: bool
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SystemListHandlersResponse {
+  path: String,
+  mime_type: String,
+  handlers: Vec<SystemHandler>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SystemOpenWithRequest {
+  path: String,
+  desktop_id: String
+}
+
+#[derive(Debug, Deserialize)]
+struct TasksCreateRequest {
+  task: serde_json::Value
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TasksDeleteRequest {
+  task_id: String
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TasksArchiveRequest {
+  task_ids: Vec<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TasksUpdateOneRequest {
+  task_id: String,
+  patch: serde_json::Value
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TasksReorderRequest {
+  task_id: String,
+  new_index: usize
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceRecentRequest {
+  path: String
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspacePinRecentRequest {
+  path: String,
+  pinned: bool
+}
+
+/// How new terminals start in a given workspace when the create request leaves it open.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalProfile {
+  shell: Option<String>,
+  args: Option<Vec<String>>,
+  #[serde(default)]
+  env: HashMap<String, String>,
+  /// Typed into the terminal once the shell prints its first output.
+  startup_command: Option<String>
+}
+
+/// The last pane size seen in a workspace, used for terminals created without cols/rows.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct TerminalLayout {
+  cols: u16,
+  rows: u16
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalProfileGetRequest {
+  root_path: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TerminalProfileSetRequest {
+  root_path: Option<String>,
+  profile: Option<TerminalProfile>
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceSetRootRequest {
+  path: String
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceValidateRootRequest {
+  path: String
+}
+
+/// Whether a path can be opened as a workspace root, checked without changing the root.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkspaceValidation {
+  exists: bool,
+  is_directory: bool,
+  /// The directory's entries could be listed.
+  is_readable: bool,
+  /// A `.git` entry (directory or worktree file) sits directly in the directory.
+  is_git_repo: bool,
+  /// Canonical path when it exists, otherwise the resolved path as given.
+  absolute_path: String
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkspaceRootChangedEvent {
+  root_path: String
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceActivityRequest {
+  root: String,
+  days: Option<u32>
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ActivityBucket {
+  date: String,
+  directory: String,
+  count: u32
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkspaceActivity {
+  root: String,
+  days: u32,
+  sources: Vec<String>,
+  truncated: bool,
+  buckets: Vec<ActivityBucket>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProcessEntry {
+  pid: u32,
+  name: String,
+  state: String,
+  rss_kb: u64,
+  cpu_percent: f32,
+  user: String,
+  cmdline: Vec<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct ProcessKillRequest {
+  pid: u32,
+  signal: String
+}
+
+#[derive(Debug, Deserialize)]
+struct EnvironmentGetRequest {
+  key: String
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SystemInfo {
+  hostname: String,
+  os_name: String,
+  os_version: String,
+  kernel_version: String,
+  cpu_count: usize,
+  cpu_model: String,
+  total_memory_kb: u64,
+  available_memory_kb: u64,
+  disk_entries: Vec<DiskInfo>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DiskInfo {
+  mount_point: String,
+  total_bytes: u64,
+  free_bytes: u64,
+  filesystem_type: String
+}
+
+#[derive(Debug, Deserialize)]
+struct SampleWorkspaceRequest {
+  path: String
+}
+
+/// Renders a catalog message in English. Unknown keys render as the key itself so a missing
+/// entry is visible rather than silent.
+fn message(key: &str, params: &[(&str, String)]) -> String {
+  let template = MESSAGE_CATALOG
+    .iter()
+    .find(|(catalog_key, _)| *catalog_key == key)
+    .map_or(key, |(_, template)| template);
+  params.iter().fold(template.to_string(), |rendered, (name, value)| {
+    rendered.replace(&format!("{{{name}}}"), value)
+  })
+}
+
+/// A user-facing error. Commands return it serialized as their error string, so the frontend
+/// gets the code to branch on, the catalog key and parameters to translate from, and the English
+/// text, which is also what ends up in logs.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageError {
+  code: String,
+  message_key: String,
+  params: BTreeMap<String, String>,
+  message: String,
+  /// Typed data for errors the frontend recovers from, e.g. `WriteConflict` for CONFLICT.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  details: Option<serde_json::Value>
+}
+
+fn message_error(code: &str, key: &str, params: &[(&str, String)]) -> String {
+  message_error_with_details(code, key, params, None)
+}
+
+fn message_error_with_details(code: &str, key: &str, params: &[(&str, String)], details: Option<serde_json::Value>) -> String {
+  let error = MessageError {
+    code: code.to_string(),
+    message_key: key.to_string(),
+    params: params
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.clone()))
+      .collect(),
+    message: message(key, params),
+    details
+  };
+  serde_json::to_string(&error).unwrap_or_else(|_| format!("{code}: {}", error.message))
+}
+
+/// The code of a `message_error`, or the `CODE:` prefix of a plain error string.
+fn error_code(error: &str) -> Option<String> {
+  if let Ok(error) = serde_json::from_str::<MessageError>(error) {
+    return Some(error.code);
+  }
+  let (code, _) = error.split_once(':')?;
+  (!code.is_empty() && code.bytes().all(|byte| byte.is_ascii_uppercase() || byte == b'_')).then(|| code.to_string())
+}
+
+#[tauri::command]
+fn messages_catalog() -> HashMap<&'static str, &'static str> {
+  MESSAGE_CATALOG.iter().copied().collect()
+}
+
+fn lock_error(name: &str) -> String {
+  format!("failed to lock {name}")
+}
+
+/// Terminal state stays usable after a panic mid-update: every field is valid on its own, so a
+/// poisoned lock is recovered rather than failing every later command.
+fn lock_recover<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn io_error(message: &str, error: std::io::Error) -> String {
+  format!("{message}: {error}")
+}
+
+fn home_dir() -> Option<PathBuf> {
+  let variables: &[&str] = if cfg!(windows) {
+    &["USERPROFILE", "HOME"]
+  } else {
+    &["HOME"]
+  };
+
+  variables
+    .iter()
+    .filter_map(std::env::var_os)
+    .find(|value| !value.is_empty())
+    .map(PathBuf::from)
+}
+
+fn expand_tilde(input: &str) -> String {
+  let is_home_relative = input == "~" || input.starts_with("~/") || (cfg!(windows) && input.starts_with("~\\"));
+  if is_home_relative {
+    if let Some(home) = home_dir() {
+      return input.replacen('~', &home.to_string_lossy(), 1);
+    }
+  }
+  input.to_string()
+}
+
+fn default_shell() -> String {
+  if cfg!(windows) {
+    return std::env::var("COMSPEC").unwrap_or_else(|_| String::from("cmd.exe"));
+  }
+  std::env::var("SHELL")
+    .ok()
+    .filter(|shell| !shell.is_empty())
+    .or_else(passwd_shell)
+    .unwrap_or_else(|| String::from("/bin/bash"))
+}
+
+#[cfg(unix)]
+fn passwd_shell() -> Option<String> {
+  let user = nix::unistd::User::from_uid(nix::unistd::getuid()).ok()??;
+  Some(user.shell.to_string_lossy().into_owned()).filter(|shell| !shell.is_empty())
+}
+
+#[cfg(not(unix))]
+fn passwd_shell() -> Option<String> {
+  None
+}
+
+/// Login flags so the shell reads the user's profile, as a terminal emulator's shell would.
+fn default_shell_args(shell: &str) -> Vec<String> {
+  let name = Path::new(shell)
+    .file_stem()
+    .map(|name| name.to_string_lossy().to_ascii_lowercase())
+    .unwrap_or_default();
+  match name.as_str() {
+    "bash" => vec![String::from("--login")],
+    "zsh" | "fish" | "nu" => vec![String::from("-l")],
+    _ => Vec::new()
+  }
+}
+
+fn is_executable_file(path: &Path) -> bool {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+  }
+  #[cfg(not(unix))]
+  {
+    path.is_file()
+  }
+}
+
+fn shell_is_usable(shell: &str) -> bool {
+  let path = Path::new(shell);
+  if path.components().count() > 1 {
+    return is_executable_file(path);
+  }
+  std::env::var_os("PATH").is_some_and(|paths| {
+    std::env::split_paths(&paths).any(|directory| {
+      is_executable_file(&directory.join(shell))
+        || (cfg!(windows) && is_executable_file(&directory.join(format!("{shell}.exe"))))
+    })
+  })
+}
+
+/// Picks the shell a session runs. When the requested shell is missing, the default shell and
+/// then `/bin/sh` are tried before falling back to the builtin shell.
+fn resolve_launch_shell(shell: String, args: Vec<String>) -> Result<(String, Vec<String>, bool), String> {
+  if shell != BUILTIN_SHELL_NAME {
+    if shell_is_usable(&shell) {
+      return Ok((shell, args, false));
+    }
+    for fallback in [default_shell(), String::from("/bin/sh")] {
+      if fallback != shell && shell_is_usable(&fallback) {
+        let args = default_shell_args(&fallback);
+        return Ok((fallback, args, false));
+      }
+    }
+  }
+
+  let executable = std::env::current_exe().map_err(|error| io_error("failed to locate the builtin shell", error))?;
+  Ok((
+    executable.to_string_lossy().into_owned(),
+    vec![String::from(BUILTIN_SHELL_FLAG)],
+    true
+  ))
+}
+
+/// A minimal line-oriented shell, run inside the PTY as `openspace --builtin-shell`. The terminal
+/// driver handles echo and line editing; there are no pipes, redirection or globbing.
+fn run_builtin_shell() -> i32 {
+  ignore_builtin_shell_interrupts();
+  let stdin = std::io::stdin();
+  let mut stdout = std::io::stdout();
+  let _ = writeln!(
+    stdout,
+    "OpenSpace builtin shell (no pipes, redirection or globbing).\nBuiltins: cd, export NAME=VALUE, unset NAME, env, pwd, exit [CODE]."
+  );
+
+  let mut status = 0;
+  loop {
+    let cwd = std::env::current_dir()
+      .map(|path| path.display().to_string())
+      .unwrap_or_default();
+    let _ = write!(stdout, "{cwd} $ ");
+    let _ = stdout.flush();
+
+    let mut line = String::new();
+    match stdin.read_line(&mut line) {
+      Ok(0) => return status,
+      Ok(_) => {}
+      Err(_) => return 1
+    }
+
+    let arguments = split_exec_line(line.trim());
+    let Some((program, rest)) = arguments.split_first() else {
+      continue;
+    };
+    status = match program.as_str() {
+      "exit" => return rest.first().and_then(|code| code.parse().ok()).unwrap_or(status),
+      "cd" => {
+        let target = match rest.first() {
+          Some(target) => expand_tilde(target),
+          None => home_dir()
+            .map(|home| home.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("/"))
+        };
+        match std::env::set_current_dir(&target) {
+          Ok(()) => 0,
+          Err(error) => {
+            eprintln!("cd: {target}: {error}");
+            1
+          }
+        }
+      }
+      "pwd" => {
+        println!("{cwd}");
+        0
+      }
+      "env" => {
+        for (name, value) in std::env::vars_os() {
+          println!("{}={}", name.to_string_lossy(), value.to_string_lossy());
+        }
+        0
+      }
+      "unset" => {
+        for name in rest {
+          std::env::remove_var(name);
+        }
+        0
+      }
+      "export" => rest.iter().fold(0, |status, assignment| {
+        if set_builtin_shell_variable(assignment) {
+          status
+        } else {
+          eprintln!("export: expected NAME=VALUE, got {assignment}");
+          1
+        }
+      }),
+      _ if rest.is_empty() && set_builtin_shell_variable(program) => 0,
+      _ => match std::process::Command::new(program).args(rest).status() {
+        Ok(exit) => exit.code().unwrap_or(1),
+        Err(error) => {
+          eprintln!("{program}: {error}");
+          127
+        }
+      }
+    };
+  }
+}
+
+fn set_builtin_shell_variable(assignment: &str) -> bool {
+  match assignment.split_once('=') {
+    Some((name, value)) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+      std::env::set_var(name, value);
+      true
+    }
+    _ => false
+  }
+}
+
+/// Ctrl-C and Ctrl-\ go to the whole foreground process group, so the builtin shell has to
+/// survive them while a program it started is interrupted. A no-op handler (unlike SIG_IGN)
+/// is reset to the default disposition in the programs it runs.
+#[cfg(unix)]
+fn ignore_builtin_shell_interrupts() {
+  use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+  extern "C" fn ignore(_: nix::libc::c_int) {}
+
+  let action = SigAction::new(SigHandler::Handler(ignore), SaFlags::SA_RESTART, SigSet::empty());
+  for signal in [Signal::SIGINT, Signal::SIGQUIT] {
+    // SAFETY: the handler does nothing, so it is async-signal-safe.
+    let _ = unsafe { sigaction(signal, &action) };
+  }
+}
+
+#[cfg(not(unix))]
+fn ignore_builtin_shell_interrupts() {}
+
+fn scan_shells() -> Vec<ShellInfo> {
+  let default = default_shell();
+  let candidates: Vec<String> = match fs::read_to_string("/etc/shells") {
+    Ok(raw) if !cfg!(windows) => raw
+      .lines()
+      .map(str::trim)
+      .filter(|line| !line.is_empty() && !line.starts_with('#'))
+      .map(String::from)
+      .collect(),
+    _ => vec![default.clone()]
+  };
+
+  // /etc/shells often lists the same binary under /bin and /usr/bin.
+  let mut seen = Vec::new();
+  let mut shells: Vec<ShellInfo> = Vec::new();
+  for candidate in candidates {
+    let path = Path::new(&candidate);
+    if path.is_absolute() && !is_executable_file(path) {
+      continue;
+    }
+    let name = path
+      .file_name()
+      .map(|name| name.to_string_lossy().into_owned())
+      .unwrap_or_else(|| candidate.clone());
+    let key = (name.clone(), fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+    if seen.contains(&key) {
+      continue;
+    }
+    seen.push(key);
+
+    shells.push(ShellInfo {
+      default_args: default_shell_args(&candidate),
+      is_default: candidate == default,
+      path: candidate,
+      name
+    });
+  }
+
+  if !shells.iter().any(|shell| shell.is_default) {
+    if let Ok(default_target) = fs::canonicalize(&default) {
+      if let Some(shell) = shells
+        .iter_mut()
+        .find(|shell| fs::canonicalize(&shell.path).is_ok_and(|target| target == default_target))
+      {
+        shell.is_default = true;
+      }
+    }
+  }
+
+  shells
+}
+
+fn resolve_path(input: &str) -> Result<PathBuf, String> {
+  let expanded = expand_tilde(input);
+  let candidate = PathBuf::from(&expanded);
+  if candidate.is_absolute() {
+    return Ok(candidate);
+  }
+  std::env::current_dir()
+    .map(|cwd| cwd.join(candidate))
+    .map_err(|error| io_error("failed to resolve relative path", error))
+}
+
+/// Canonicalizes the deepest existing ancestor and re-appends the rest, so paths that are
+/// about to be created can still be compared against the workspace root.
+fn canonicalize_lenient(path: &Path) -> Result<PathBuf, String> {
+  let mut existing = path.to_path_buf();
+  let mut remainder = Vec::new();
+
+  loop {
+    match fs::canonicalize(&existing) {
+      Ok(canonical) => {
+        return Ok(remainder.iter().rev().fold(canonical, |joined, part| joined.join(part)));
+      }
+      Err(error) if error.kind() == ErrorKind::NotFound => {
+        let file_name = existing
+          .file_name()
+          .map(|name| name.to_os_string())
+          .ok_or_else(|| io_error(&format!("failed to resolve {}", path.display()), error))?;
+        remainder.push(file_name);
+        if !existing.pop() {
+          return Err(format!("failed to resolve {}", path.display()));
+        }
+      }
+      Err(error) => return Err(io_error(&format!("failed to resolve {}", path.display()), error))
+    }
+  }
+}
+
+fn confine_to_root(path: &Path, root: &Path) -> Result<(), String> {
+  let canonical_path = canonicalize_lenient(path)?;
+  let canonical_root = canonicalize_lenient(root)?;
+  if canonical_path.starts_with(&canonical_root) {
+    return Ok(());
+  }
+  Err(PATH_ESCAPES_WORKSPACE_ERROR.to_string())
+}
+
+/// Resolves a path from a filesystem command, refusing anything outside the workspace root
+/// when one is active.
+fn resolve_workspace_path(startup_context: &StartupContext, input: &str) -> Result<PathBuf, String> {
+  let path = resolve_path(input)?;
+  if let Some(root) = current_workspace_root(startup_context)? {
+    confine_to_root(&path, Path::new(&root))?;
+  }
+  Ok(path)
+}
+
+fn current_workspace_root(startup_context: &StartupContext) -> Result<Option<String>, String> {
+  Ok(startup_context
+    .root_path
+    .lock()
+    .map_err(|_| lock_error("workspace root"))?
+    .clone())
+}
+
+/// Makes `root` the active workspace root, records it in the recents and tells the frontend.
+fn set_workspace_root(
+  app: &AppHandle,
+  startup_context: &StartupContext,
+  settings_state: &SettingsState,
+  root: &str
+) -> Result<WorkspaceState, String> {
+  ensure_not_closing(app)?;
+  let store = app.state::<WorkspaceStore>();
+  let _guard = store.write_lock.lock().map_err(|_| lock_error("workspace store"))?;
+  let path = persistence_file_path(app, WORKSPACE_FILE_NAME)?;
+  let mut state = read_json_or_default(&path, default_workspace_state())?;
+  push_recent_path(&mut state, root, recent_paths_max(settings_state));
+  state.revision += 1;
+  state.updated_at = Utc::now().to_rfc3339();
+  write_json(&path, &state)?;
+
+  *startup_context
+    .root_path
+    .lock()
+    .map_err(|_| lock_error("workspace root"))? = Some(root.to_string());
+  let _ = emit_replayable(
+    app,
+    WORKSPACE_ROOT_CHANGED_EVENT,
+    WorkspaceRootChangedEvent {
+      root_path: root.to_string()
+    }
+  );
+  Ok(state)
+}
+
+/// Walks up from a file's directory to the nearest one holding a project marker, looking at no
+/// more than `WORKSPACE_ROOT_SEARCH_MAX_LEVELS` directories. Falls back to the file's directory.
+fn infer_workspace_root(file: &Path) -> Result<(PathBuf, Option<&'static str>), String> {
+  let parent = file
+    .parent()
+    .ok_or_else(|| format!("failed to determine workspace root for file path {}", file.display()))?;
+  let marked = parent
+    .ancestors()
+    .take(WORKSPACE_ROOT_SEARCH_MAX_LEVELS)
+    .find_map(|directory| {
+      WORKSPACE_ROOT_MARKERS
+        .iter()
+        .find(|marker| directory.join(marker).exists())
+        .map(|marker| (directory.to_path_buf(), Some(*marker)))
+    });
+  Ok(marked.unwrap_or_else(|| (parent.to_path_buf(), None)))
+}
+
+fn has_cli_flag(flag: &str) -> bool {
+  std::env::args().skip(1).any(|argument| argument == flag)
+}
+
+/// The startup root, how it was chosen and the file to open, from `[--root <path>] [path]`.
+/// `--root` wins over inferring the root from a file argument.
+fn resolve_startup_from_args() -> Result<Option<(RootProvenance, Option<PathBuf>)>, String> {
+  let mut root_flag = None;
+  let mut target = None;
+  let mut arguments = std::env::args().skip(1);
+  while let Some(argument) = arguments.next() {
+    if argument == ROOT_FLAG {
+      root_flag = arguments.next();
+    } else if let Some(value) = argument.strip_prefix(ROOT_FLAG).and_then(|rest| rest.strip_prefix('=')) {
+      root_flag = Some(value.to_string());
+    } else if !argument.starts_with("--") && target.is_none() && !argument.trim().is_empty() {
+      target = Some(argument);
+    }
+  }
+
+  let existing_path = |argument: &str| -> Result<PathBuf, String> {
+    let resolved = resolve_path(argument)?;
+    if !resolved.exists() {
+      return Err(format!("startup path does not exist: {}", resolved.display()));
+    }
+    fs::canonicalize(&resolved).map_err(|error| io_error("failed to canonicalize startup path", error))
+  };
+  let target_path = target.as_deref().map(existing_path).transpose()?;
+  let open_path = target_path.clone().filter(|path| path.is_file());
+
+  let (root, source, marker, requested) = match (root_flag, target_path) {
+    (Some(flag), _) => {
+      let root = existing_path(&flag)?;
+      if !root.is_dir() {
+        return Err(format!("{ROOT_FLAG} must name a directory: {}", root.display()));
+      }
+      (root, "flag", None, flag)
+    }
+    (None, Some(path)) if path.is_dir() => (path, "directory", None, target.unwrap_or_default()),
+    (None, Some(path)) if path.is_file() => {
+      let (root, marker) = infer_workspace_root(&path)?;
+      (root, if marker.is_some() { "inferred" } else { "parent" }, marker, target.unwrap_or_default())
+    }
+    (None, Some(path)) => {
+      return Err(format!("startup path must be a file or directory: {}", path.display()));
+    }
+    (None, None) => return Ok(None)
+  };
+
+  let provenance = RootProvenance {
+    root_path: root.to_string_lossy().into_owned(),
+    source,
+    requested_path: requested,
+    marker
+  };
+  Ok(Some((provenance, open_path)))
+}
+
+fn unix_time_ms() -> u64 {
+  u64::try_from(Utc::now().timestamp_millis()).unwrap_or_default()
+}
+
+/// Bytes actually allocated, which is less than the apparent size for sparse files.
+fn size_on_disk(metadata: &Metadata) -> u64 {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+  }
+  #[cfg(not(unix))]
+  {
+    metadata.len()
+  }
+}
+
+/// Less than half the apparent size is allocated. Small files are left out, since filesystems
+/// that pack them into metadata or compress them would otherwise all look sparse.
+fn looks_sparse(size: u64, size_on_disk: u64) -> bool {
+  size >= SPARSE_MIN_SIZE && size_on_disk < size / 2
+}
+
+fn changed_time_ms(metadata: &Metadata) -> Option<u64> {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::MetadataExt;
+    let millis = metadata.ctime().checked_mul(1000)? + metadata.ctime_nsec() / 1_000_000;
+    u64::try_from(millis).ok()
+  }
+  #[cfg(not(unix))]
+  {
+    metadata
+      .created()
+      .ok()
+      .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+      .and_then(|duration| u64::try_from(duration.as_millis()).ok())
+  }
+}
+
+/// Treats a file as binary when its first 8 KiB contain a NUL byte, as git and most editors do.
+fn looks_binary(path: &Path) -> std::io::Result<bool> {
+  let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+  fs::File::open(path)?
+    .take(BINARY_SNIFF_BYTES as u64)
+    .read_to_end(&mut head)?;
+  Ok(head.contains(&0))
+}
+
+fn modified_time_ms(metadata: &Metadata) -> u64 {
+  metadata
+    .modified()
+    .ok()
+    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+    .and_then(|duration| u64::try_from(duration.as_millis()).ok())
+    .unwrap_or_default()
+}
+
+fn default_task_state() -> TaskState {
+  TaskState {
+    tasks: Vec::new(),
+    revision: 0,
+    updated_at: Utc::now().to_rfc3339()
+  }
+}
+
+fn default_workspace_state() -> WorkspaceState {
+  WorkspaceState {
+    root_path: None,
+    recent_paths: Vec::new(),
+    pinned_paths: Vec::new(),
+    revision: 0,
+    updated_at: Utc::now().to_rfc3339(),
+    root_provenance: None
+  }
+}
+
+fn default_first_run() -> bool {
+  true
+}
+
+fn default_recent_paths_max() -> usize {
+  DEFAULT_RECENT_PATHS_MAX
+}
+
+fn default_terminal_idle_grace_minutes() -> u64 {
+  DEFAULT_TERMINAL_IDLE_GRACE_MINUTES
+}
+
+fn default_json_pretty_max_bytes() -> u64 {
+  DEFAULT_JSON_PRETTY_MAX_BYTES
+}
+
+fn default_file_read_max_bytes() -> u64 {
+  DEFAULT_FILE_READ_MAX_BYTES
+}
+
+fn default_app_settings() -> AppSettings {
+  AppSettings {
+    first_run: default_first_run(),
+    recent_paths_max: default_recent_paths_max(),
+    control_socket_enabled: false,
+    terminal_idle_timeout_minutes: None,
+    terminal_idle_grace_minutes: default_terminal_idle_grace_minutes(),
+    json_pretty_max_bytes: default_json_pretty_max_bytes(),
+    file_read_max_bytes: default_file_read_max_bytes(),
+    updated_at: Utc::now().to_rfc3339()
+  }
+}
+
+fn recent_paths_max(settings_state: &SettingsState) -> usize {
+  settings_state
+    .settings
+    .lock()
+    .map(|settings| settings.recent_paths_max)
+    .unwrap_or(DEFAULT_RECENT_PATHS_MAX)
+    .max(1)
+}
+
+fn json_pretty_max_bytes(settings_state: &SettingsState) -> u64 {
+  settings_state
+    .settings
+    .lock()
+    .map(|settings| settings.json_pretty_max_bytes)
+    .unwrap_or(DEFAULT_JSON_PRETTY_MAX_BYTES)
+}
+
+fn file_read_max_bytes(settings_state: &SettingsState) -> u64 {
+  settings_state
+    .settings
+    .lock()
+    .map(|settings| settings.file_read_max_bytes)
+    .unwrap_or(DEFAULT_FILE_READ_MAX_BYTES)
+    .max(1)
+}
+
+/// The form a recent path is stored and compared in: canonical when it still exists, so a
+/// trailing slash or a symlinked spelling doesn't make a second entry, and lexically tidied when
+/// it doesn't.
+fn canonical_recent_path(path: &str) -> String {
+  fs::canonicalize(path)
+    .unwrap_or_else(|_| Path::new(path).components().collect())
+    .to_string_lossy()
+    .into_owned()
+}
+
+/// The one place recents are maintained. Puts `latest` (if any) first, folds entries that name
+/// the same canonical path together, keeps pinned entries, and evicts the oldest unpinned ones
+/// beyond `max_paths`.
+fn update_recent_paths(state: &mut WorkspaceState, latest: Option<&str>, max_paths: usize) {
+  let mut pinned: Vec<String> = Vec::new();
+  for path in state.pinned_paths.iter().map(|path| canonical_recent_path(path)) {
+    if !pinned.contains(&path) {
+      pinned.push(path);
+    }
+  }
+
+  let mut recent: Vec<String> = Vec::new();
+  for path in latest.into_iter().chain(state.recent_paths.iter().map(String::as_str)) {
+    let path = canonical_recent_path(path);
+    if !recent.contains(&path) {
+      recent.push(path);
+    }
+  }
+  for path in &pinned {
+    if !recent.contains(path) {
+      recent.push(path.clone());
+    }
+  }
+
+  let mut unpinned = 0;
+  recent.retain(|path| {
+    if pinned.contains(path) {
+      return true;
+    }
+    unpinned += 1;
+    unpinned <= max_paths
+  });
+
+  state.recent_paths = recent;
+  state.pinned_paths = pinned;
+}
+
+/// Rejects recents from a frontend save that `update_recent_paths` would have to rewrite, so
+/// the two sides can't keep undoing each other.
+fn validate_recent_paths(state: &WorkspaceState, max_paths: usize) -> Result<(), String> {
+  let invalid = |key: &str, params: &[(&str, String)]| message_error("INVALID_RECENTS", key, params);
+  let recent: Vec<String> = state.recent_paths.iter().map(|path| canonical_recent_path(path)).collect();
+  for (index, path) in recent.iter().enumerate() {
+    if recent[..index].contains(path) {
+      return Err(invalid("workspace.recentDuplicate", &[("path", state.recent_paths[index].clone())]));
+    }
+  }
+
+  let pinned: Vec<String> = state.pinned_paths.iter().map(|path| canonical_recent_path(path)).collect();
+  if let Some(index) = pinned.iter().position(|path| !recent.contains(path)) {
+    return Err(invalid("workspace.pinnedNotRecent", &[("path", state.pinned_paths[index].clone())]));
+  }
+
+  let unpinned = recent.iter().filter(|path| !pinned.contains(path)).count();
+  if unpinned > max_paths {
+    return Err(invalid(
+      "workspace.recentsOverLimit",
+      &[("count", unpinned.to_string()), ("max", max_paths.to_string())]
+    ));
+  }
+  Ok(())
+}
+
+impl WorkspaceSaveState {
+  /// Validates the saved recents and builds the state to store. Pins left out of the save come
+  /// from `stored`; any the saved recents don't list are added back instead of being rejected.
+  fn into_state(self, stored: &WorkspaceState, max_paths: usize) -> Result<WorkspaceState, String> {
+    let mut state = WorkspaceState {
+      root_path: self.root_path,
+      recent_paths: self.recent_paths,
+      pinned_paths: Vec::new(),
+      revision: self.revision,
+      updated_at: String::new(),
+      root_provenance: None
+    };
+    match self.pinned_paths {
+      Some(pinned_paths) => {
+        state.pinned_paths = pinned_paths;
+        validate_recent_paths(&state, max_paths)?;
+      }
+      None => {
+        let recent: Vec<String> = state.recent_paths.iter().map(|path| canonical_recent_path(path)).collect();
+        state.pinned_paths = stored
+          .pinned_paths
+          .iter()
+          .filter(|path| recent.contains(&canonical_recent_path(path)))
+          .cloned()
+          .collect();
+        validate_recent_paths(&state, max_paths)?;
+        state.pinned_paths = stored.pinned_paths.clone();
+      }
+    }
+    update_recent_paths(&mut state, None, max_paths);
+    Ok(state)
+  }
+}
+
+fn push_recent_path(state: &mut WorkspaceState, root: &str, max_paths: usize) {
+  update_recent_paths(state, Some(root), max_paths);
+  state.root_path = Some(root.to_string());
+}
+
+fn subsystem_enabled(safe_mode: &SafeModeState, subsystem: &str) -> bool {
+  safe_mode
+    .disabled_subsystems
+    .lock()
+    .map(|disabled| !disabled.iter().any(|name| name == subsystem))
+    .unwrap_or(false)
+}
+
+fn ensure_subsystem_enabled(safe_mode: &SafeModeState, subsystem: &str) -> Result<(), String> {
+  if subsystem_enabled(safe_mode, subsystem) {
+    return Ok(());
+  }
+  Err(format!(
+    "{subsystem} is disabled in safe mode; enable it with safe_mode_enable to use it"
+  ))
+}
+
+fn app_health_snapshot(app: &AppHandle) -> Result<AppHealth, String> {
+  let state = app.state::<AppState>();
+  let safe_mode = app.state::<SafeModeState>();
+  let control_socket = app.state::<ControlSocketState>();
+  let disabled_subsystems = safe_mode
+    .disabled_subsystems
+    .lock()
+    .map_err(|_| lock_error("safe mode subsystems"))?
+    .clone();
+  let terminal_sessions = lock_recover(&state.sessions).len();
+
+  Ok(AppHealth {
+    safe_mode: safe_mode.reason.is_some(),
+    safe_mode_reason: safe_mode.reason.clone(),
+    disabled_subsystems,
+    terminal_sessions,
+    control_socket: control_socket
+      .path
+      .as_ref()
+      .map(|path| path.to_string_lossy().into_owned())
+  })
+}
+
+/// Decides whether this launch runs in safe mode and arms crash detection for the next one.
+/// A leftover startup marker means the previous launch never reached `app:ready`.
+fn begin_startup(app: &AppHandle) -> Result<SafeModeState, String> {
+  let startup_marker = persistence_file_path(app, STARTUP_MARKER_FILE_NAME)?;
+  let safe_mode_marker = persistence_file_path(app, SAFE_MODE_MARKER_FILE_NAME)?;
+
+  if startup_marker.exists() {
+    fs::write(&safe_mode_marker, Utc::now().to_rfc3339())
+      .map_err(|error| io_error("failed to write safe mode marker", error))?;
+  }
+
+  let reason = if has_cli_flag(SAFE_MODE_FLAG) {
+    Some(format!("requested with {SAFE_MODE_FLAG}"))
+  } else if safe_mode_marker.exists() {
+    Some(String::from("the previous startup did not complete"))
+  } else {
+    None
+  };
+
+  fs::write(&startup_marker, Utc::now().to_rfc3339())
+    .map_err(|error| io_error("failed to write startup marker", error))?;
+
+  let disabled_subsystems = if reason.is_some() {
+    SAFE_MODE_SUBSYSTEMS.iter().map(|name| name.to_string()).collect()
+  } else {
+    Vec::new()
+  };
+
+  Ok(SafeModeState {
+    reason,
+    disabled_subsystems: Mutex::new(disabled_subsystems)
+  })
+}
+
+fn finish_startup(app: &AppHandle) {
+  for marker in [STARTUP_MARKER_FILE_NAME, SAFE_MODE_MARKER_FILE_NAME] {
+    if let Ok(path) = persistence_file_path(app, marker) {
+      if let Err(error) = fs::remove_file(&path) {
+        if error.kind() != ErrorKind::NotFound {
+          eprintln!("failed to remove {}: {error}", path.display());
+        }
+      }
+    }
+  }
+
+  let _ = emit_replayable(app, APP_READY_EVENT, ());
+
+  if let Some(path) = app.state::<StartupContext>().open_path.as_ref() {
+    let _ = emit_replayable(
+      app,
+      APP_OPEN_PATH_EVENT,
+      OpenPathEvent {
+        path: path.to_string_lossy().into_owned(),
+        is_directory: false
+      }
+    );
+  }
+
+  let safe_mode = app.state::<SafeModeState>();
+  if safe_mode.reason.is_some() {
+    if let Ok(health) = app_health_snapshot(app) {
+      let _ = emit_replayable(app, APP_SAFE_MODE_EVENT, health);
+    }
+  }
+}
+
+/// Emits `event` and, if it has an entry in `EVENT_REPLAY_POLICIES`, keeps a copy for windows
+/// that register their listeners too late to see it.
+fn emit_replayable<S>(app: &AppHandle, event: &str, payload: S) -> tauri::Result<()>
+where
+  S: Serialize + Clone
+{
+  record_for_replay(app, None, event, &payload);
+  app.emit(event, payload)
+}
+
+/// Like `emit_replayable`, but for events that only the window `window_label` receives.
+fn emit_replayable_to<S>(app: &AppHandle, window_label: &str, event: &str, payload: S) -> tauri::Result<()>
+where
+  S: Serialize + Clone
+{
+  record_for_replay(app, Some(window_label.to_string()), event, &payload);
+  app.emit_to(window_label, event, payload)
+}
+
+/// Like `emit_replayable`, but for events that only the session's window receives.
+fn emit_replayable_to_session<S>(app: &AppHandle, session: &TerminalSession, event: &str, payload: S) -> tauri::Result<()>
+where
+  S: Serialize + Clone
+{
+  let window_label = lock_recover(&session.window_label).clone();
+  record_for_replay(app, Some(window_label), event, &payload);
+  emit_to_session_window(app, session, event, payload)
+}
+
+fn record_for_replay<S>(app: &AppHandle, target: Option<String>, event: &str, payload: &S)
+where
+  S: Serialize
+{
+  let Some((_, policy)) = EVENT_REPLAY_POLICIES.iter().find(|(name, _)| *name == event) else {
+    return;
+  };
+  if let Ok(value) = serde_json::to_value(payload) {
+    let state = app.state::<EventReplayState>();
+    let mut buffer = lock_recover(&state.buffer);
+    record_replayed_event(&mut buffer, target, event, value, *policy);
+  }
+}
+
+fn record_replayed_event(
+  buffer: &mut EventReplayBuffer,
+  target: Option<String>,
+  event: &str,
+  payload: serde_json::Value,
+  policy: EventReplayPolicy
+) {
+  let now_ms = unix_time_ms();
+  buffer
+    .events
+    .retain(|existing| replay_retention(&existing.event).is_some_and(|retention| is_within(now_ms, existing, retention)));
+  match policy {
+    EventReplayPolicy::Latest => {
+      let session_id = payload.get("sessionId").cloned();
+      buffer
+        .events
+        .retain(|existing| existing.event != event || existing.payload.get("sessionId") != session_id.as_ref());
+    }
+    EventReplayPolicy::Recent(window) => {
+      let cutoff_ms = now_ms.saturating_sub(window.as_millis() as u64);
+      buffer
+        .events
+        .retain(|existing| existing.event != event || existing.emitted_at_ms >= cutoff_ms);
+      let buffered = buffer.events.iter().filter(|existing| existing.event == event).count();
+      if buffered >= EVENT_REPLAY_MAX_PER_TYPE {
+        if let Some(oldest) = buffer.events.iter().position(|existing| existing.event == event) {
+          buffer.events.remove(oldest);
+        }
+      }
+    }
+  }
+
+  buffer.next_sequence += 1;
+  buffer.events.push_back(ReplayedEvent {
+    sequence: buffer.next_sequence,
+    event: event.to_string(),
+    payload,
+    emitted_at_ms: now_ms,
+    replayed: true,
+    target
+  });
+}
+
+/// How long events of type `event` stay replayable, or `None` for events that are never buffered.
+fn replay_retention(event: &str) -> Option<Duration> {
+  EVENT_REPLAY_POLICIES
+    .iter()
+    .find(|(name, _)| *name == event)
+    .map(|(_, policy)| match policy {
+      EventReplayPolicy::Latest => EVENT_REPLAY_LATEST_TTL,
+      EventReplayPolicy::Recent(window) => *window
+    })
+}
+
+fn is_within(now_ms: u64, event: &ReplayedEvent, retention: Duration) -> bool {
+  now_ms.saturating_sub(event.emitted_at_ms) <= retention.as_millis() as u64
+}
+
+/// Buffered events in emission order. Windows call this once their listeners are registered;
+/// an event emitted in between can arrive both live and here, so handlers should tolerate a
+/// repeat.
+#[tauri::command]
+fn events_replay(
+  window: WebviewWindow,
+  replay: State<'_, EventReplayState>,
+  request: EventsReplayRequest
+) -> Vec<ReplayedEvent> {
+  replayable_events(&lock_recover(&replay.buffer), request.since.unwrap_or(0), window.label(), unix_time_ms())
+}
+
+/// The events after sequence `since` that `window_label` should see and that have not expired.
+fn replayable_events(buffer: &EventReplayBuffer, since: u64, window_label: &str, now_ms: u64) -> Vec<ReplayedEvent> {
+  buffer
+    .events
+    .iter()
+    .filter(|event| event.sequence > since)
+    .filter(|event| event.target.as_deref().map_or(true, |target| target == window_label))
+    .filter(|event| replay_retention(&event.event).is_some_and(|retention| is_within(now_ms, event, retention)))
+    .cloned()
+    .collect()
+}
+
+/// Opt-in automation socket speaking newline-delimited JSON. Off unless `controlSocketEnabled`
+/// is set in the app settings.
+#[cfg(unix)]
+mod control_socket {
+  use super::*;
+  use std::io::{BufRead, BufReader};
+  use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+  use std::os::unix::net::{UnixListener, UnixStream};
+  use tauri::{EventId, Listener};
+
+  const CONTROL_SOCKET_DIR_NAME: &str = "openspace";
+  const CONTROL_SOCKET_FILE_NAME: &str = "control.sock";
+  const CONTROL_SOCKET_EVENTS: &[&str] = &[TERMINAL_EXIT_EVENT, WORKSPACE_ROOT_CHANGED_EVENT];
+
+  #[derive(Debug, Deserialize)]
+  struct ControlRequest {
+    #[serde(default)]
+    id: serde_json::Value,
+    command: String,
+    #[serde(default)]
+    args: serde_json::Value
+  }
+
+  #[derive(Debug, Serialize)]
+  struct ControlResponse {
+    id: serde_json::Value,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>
+  }
+
+  #[derive(Debug, Serialize)]
+  struct ControlEvent<'a> {
+    event: &'a str,
+    payload: serde_json::Value
+  }
+
+  #[derive(Debug, Deserialize)]
+  struct ControlOpenArgs {
+    path: String
+  }
+
+  #[derive(Debug, Deserialize)]
+  struct ControlSubscribeArgs {
+    events: Vec<String>
+  }
+
+  pub(crate) fn start(app: &AppHandle) -> Result<PathBuf, String> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+      .filter(|value| !value.is_empty())
+      .map(PathBuf::from)
+      .ok_or_else(|| String::from("XDG_RUNTIME_DIR is not set; not starting the control socket"))?;
+    let socket_dir = runtime_dir.join(CONTROL_SOCKET_DIR_NAME);
+    fs::DirBuilder::new()
+      .recursive(true)
+      .mode(0o700)
+      .create(&socket_dir)
+      .map_err(|error| io_error("failed to create control socket directory", error))?;
+    secure_socket_dir(&socket_dir)?;
+
+    let socket_path = socket_dir.join(CONTROL_SOCKET_FILE_NAME);
+    if let Err(error) = fs::remove_file(&socket_path) {
+      if error.kind() != ErrorKind::NotFound {
+        return Err(io_error("failed to remove stale control socket", error));
+      }
+    }
+
+    // The socket is created with the process umask, which is not changed here since it would
+    // affect files other threads create meanwhile. Until it is narrowed below, the 0700
+    // directory checked above is what keeps other users from reaching it.
+    let listener = UnixListener::bind(&socket_path)
+      .map_err(|error| io_error(&format!("failed to bind {}", socket_path.display()), error))?;
+    fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))
+      .map_err(|error| io_error("failed to restrict control socket permissions", error))?;
+
+    let app = app.clone();
+    std::thread::spawn(move || {
+      for stream in listener.incoming() {
+        match stream {
+          Ok(stream) => {
+            let app = app.clone();
+            std::thread::spawn(move || serve_control_connection(app, stream));
+          }
+          Err(error) => eprintln!("control socket accept failed: {error}")
+        }
+      }
+    });
+
+    Ok(socket_path)
+  }
+
+  /// `DirBuilder::mode` only applies to a directory it creates, so an existing one is checked:
+  /// it must be a real directory owned by us, and is narrowed to 0700 if it is looser.
+  pub(crate) fn secure_socket_dir(socket_dir: &Path) -> Result<(), String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(socket_dir)
+      .map_err(|error| io_error("failed to inspect control socket directory", error))?;
+    if !metadata.file_type().is_dir() {
+      return Err(format!("{} is not a directory; not starting the control socket", socket_dir.display()));
+    }
+    if metadata.uid() != nix::unistd::getuid().as_raw() {
+      return Err(format!(
+        "{} is owned by another user; not starting the control socket",
+        socket_dir.display()
+      ));
+    }
+    if metadata.mode() & 0o777 != 0o700 {
+      fs::set_permissions(socket_dir, fs::Permissions::from_mode(0o700))
+        .map_err(|error| io_error("failed to restrict control socket directory permissions", error))?;
+    }
+    Ok(())
+  }
+
+  /// Serves one newline-delimited JSON client until it disconnects.
+  fn serve_control_connection(app: AppHandle, stream: UnixStream) {
+    let writer = match stream.try_clone() {
+      Ok(writer) => Arc::new(Mutex::new(writer)),
+      Err(error) => {
+        eprintln!("failed to clone control socket stream: {error}");
+        return;
+      }
+    };
+    let mut listeners = Vec::new();
+
+    for line in BufReader::new(stream).lines() {
+      let Ok(line) = line else {
+        break;
+      };
+      if line.trim().is_empty() {
+        continue;
+      }
+
+      let response = match serde_json::from_str::<ControlRequest>(&line) {
+        Ok(request) => {
+          let id = request.id.clone();
+          match handle_control_request(&app, request, &writer, &mut listeners) {
+            Ok(result) => ControlResponse {
+              id,
+              ok: true,
+              result: Some(result),
+              error: None
+            },
+            Err(error) => ControlResponse {
+              id,
+              ok: false,
+              result: None,
+              error: Some(error)
+            }
+          }
+        }
+        Err(error) => ControlResponse {
+          id: serde_json::Value::Null,
+          ok: false,
+          result: None,
+          error: Some(format!("invalid request: {error}"))
+        }
+      };
+
+      if write_control_line(&writer, &response).is_err() {
+        break;
+      }
+    }
+
+    for listener in listeners {
+      app.unlisten(listener);
+    }
+  }
+
+  fn write_control_line<T>(writer: &Mutex<UnixStream>, value: &T) -> Result<(), String>
+  where
+    T: Serialize
+  {
+    let mut line = serde_json::to_vec(value).map_err(|error| error.to_string())?;
+    line.push(b'\n');
+    let mut writer = writer.lock().map_err(|_| lock_error("control socket"))?;
+    writer
+      .write_all(&line)
+      .map_err(|error| io_error("failed to write to control socket", error))
+  }
+
+  /// Only commands that can neither read file contents nor write to arbitrary paths are reachable
+  /// from the control socket.
+  fn handle_control_request(
+    app: &AppHandle,
+    request: ControlRequest,
+    writer: &Arc<Mutex<UnixStream>>,
+    listeners: &mut Vec<EventId>
+  ) -> Result<serde_json::Value, String> {
+    match request.command.as_str() {
+      "app_health" => serde_json::to_value(app_health_snapshot(app)?).map_err(|error| error.to_string()),
+      "terminal_list" => {
+        let state = app.state::<AppState>();
+        let sessions = lock_recover(&state.sessions);
+        let terminals: Vec<TerminalInfo> = sessions
+          .iter()
+          .map(|(session_id, session)| terminal_info_snapshot(session_id, session))
+          .collect();
+        serde_json::to_value(terminals).map_err(|error| error.to_string())
+      }
+      "open" => {
+        let args: ControlOpenArgs = serde_json::from_value(request.args)
+          .map_err(|error| format!("invalid arguments for open: {error}"))?;
+        let path = resolve_workspace_path(&app.state::<StartupContext>(), &args.path)?;
+        let metadata = fs::metadata(&path)
+          .map_err(|error| io_error(&format!("failed to stat {}", path.display()), error))?;
+        let payload = OpenPathEvent {
+          path: path.to_string_lossy().into_owned(),
+          is_directory: metadata.is_dir()
+        };
+        emit_replayable(app, APP_OPEN_PATH_EVENT, payload.clone()).map_err(|error| error.to_string())?;
+        serde_json::to_value(payload).map_err(|error| error.to_string())
+      }
+      "subscribe" => {
+        let args: ControlSubscribeArgs = serde_json::from_value(request.args)
+          .map_err(|error| format!("invalid arguments for subscribe: {error}"))?;
+        if let Some(unknown) = args
+          .events
+          .iter()
+          .find(|event| !CONTROL_SOCKET_EVENTS.contains(&event.as_str()))
+        {
+          return Err(format!(
+            "event \"{unknown}\" cannot be subscribed to; expected one of: {}",
+            CONTROL_SOCKET_EVENTS.join(", ")
+          ));
+        }
+
+        for event in &args.events {
+          let writer = writer.clone();
+          let event_name = event.clone();
+          let listener = app.listen_any(event.clone(), move |event| {
+            let payload = serde_json::from_str(event.payload()).unwrap_or(serde_json::Value::Null);
+            let _ = write_control_line(
+              &writer,
+              &ControlEvent {
+                event: &event_name,
+                payload
+              }
+            );
+          });
+          listeners.push(listener);
+        }
+
+        serde_json::to_value(args.events).map_err(|error| error.to_string())
+      }
+      other => Err(format!("command \"{other}\" is not available over the control socket"))
+    }
+  }
+}
+
+#[cfg(unix)]
+fn start_control_socket(app: &AppHandle) -> Result<PathBuf, String> {
+  control_socket::start(app)
+}
+
+#[cfg(not(unix))]
+fn start_control_socket(_app: &AppHandle) -> Result<PathBuf, String> {
+  Err(String::from("the control socket is only available on Unix platforms"))
+}
+
+fn ensure_not_closing(app: &AppHandle) -> Result<(), String> {
+  if app.state::<ShutdownState>().closing.load(Ordering::SeqCst) {
+    return Err(message_error("APP_CLOSING", "app.closing", &[]));
+  }
+  Ok(())
+}
+
+fn emit_shutdown_progress(app: &AppHandle, phase: &str, remaining: usize, message: String) {
+  let _ = app.emit(
+    SHUTDOWN_PROGRESS_EVENT,
+    ShutdownProgressEvent {
+      phase: phase.to_string(),
+      remaining,
+      message
+    }
+  );
+}
+
+/// Closing the last window starts an orderly shutdown instead of tearing everything down at
+/// once. A second close request while shutting down quits immediately.
+fn handle_close_request(window: &tauri::Window, api: &tauri::CloseRequestApi) {
+  let app = window.app_handle().clone();
+  if app.webview_windows().len() > 1 {
+    return;
+  }
+
+  if app.state::<ShutdownState>().closing.swap(true, Ordering::SeqCst) {
+    emit_shutdown_progress(&app, "forced", 0, String::from("Quitting now"));
+    app.exit(0);
+    return;
+  }
+
+  api.prevent_close();
+  std::thread::spawn(move || run_shutdown(&app));
+}
+
+/// Rejects new mutating commands (via the closing flag), cancels background operations and
+/// waits for them, flushes terminal logs, terminates every terminal with its process groups,
+/// then exits. Each wait is bounded by the grace period, and a watchdog forces the exit if any
+/// step hangs.
+fn run_shutdown(app: &AppHandle) {
+  let watchdog_app = app.clone();
+  std::thread::spawn(move || {
+    std::thread::sleep(SHUTDOWN_FORCE_QUIT_TIMEOUT);
+    emit_shutdown_progress(&watchdog_app, "forced", 0, String::from("Shutdown timed out; quitting"));
+    watchdog_app.exit(0);
+  });
+
+  // Background operations go first: an export or copy still running would otherwise be cut
+  // off mid-file when the process exits. Each one leaves the registry when its thread is done.
+  let registry = app.state::<OperationRegistry>();
+  for handle in lock_recover(&registry.operations).values() {
+    handle.cancelled.store(true, Ordering::SeqCst);
+  }
+  let deadline = Instant::now() + SHUTDOWN_GRACE_PERIOD;
+  wait_for_shutdown_step(
+    deadline,
+    || lock_recover(&registry.operations).len(),
+    |remaining| emit_shutdown_progress(app, "operations", remaining, format!("Finishing {remaining} operation(s)…"))
+  );
+
+  let state = app.state::<AppState>();
+  let session_ids: Vec<String> = lock_recover(&state.sessions).keys().cloned().collect();
+
+  let mut pending: Vec<std::thread::JoinHandle<()>> = session_ids
+    .into_iter()
+    .map(|session_id| {
+      let sessions = state.sessions.clone();
+      std::thread::spawn(move || {
+        let session = lock_recover(&sessions).get(&session_id).cloned();
+        if let Some(session) = session {
+          if let Err(message) = close_terminal_log(&session) {
+            eprintln!("{message}");
+          }
+        }
+        if let Err(message) = kill_terminal_session(&sessions, &session_id) {
+          eprintln!("{message}");
+        }
+      })
+    })
+    .collect();
+
+  let deadline = Instant::now() + SHUTDOWN_GRACE_PERIOD;
+  let remaining = wait_for_shutdown_step(
+    deadline,
+    || {
+      pending.retain(|handle| !handle.is_finished());
+      pending.len()
+    },
+    |remaining| emit_shutdown_progress(app, "terminals", remaining, format!("Closing {remaining} terminal(s)…"))
+  );
+
+  reap_terminal_children(&state);
+  if let Ok(path) = persistence_file_path(app, TERMINAL_LAYOUTS_FILE_NAME) {
+    if let Err(message) = flush_terminal_layouts(&app.state::<TerminalLayoutStore>(), &path) {
+      eprintln!("{message}");
+    }
+  }
+  emit_shutdown_progress(app, "done", remaining, String::from("Goodbye"));
+  app.exit(0);
+}
+
+/// Polls `remaining` until it reaches zero or `deadline` passes, calling `report` whenever the
+/// count changes. Returns what was still outstanding.
+fn wait_for_shutdown_step(
+  deadline: Instant,
+  mut remaining: impl FnMut() -> usize,
+  mut report: impl FnMut(usize)
+) -> usize {
+  let mut reported = usize::MAX;
+  loop {
+    let count = remaining();
+    if count != reported {
+      reported = count;
+      report(count);
+    }
+    if count == 0 || Instant::now() >= deadline {
+      return count;
+    }
+    std::thread::sleep(Duration::from_millis(50));
+  }
+}
+
+/// Collects any terminal process that exited without being waited on, e.g. one whose session
+/// thread was still busy when the grace period ran out. Only the sessions' own children are
+/// waited on, so a child some other part of the app is waiting for keeps its exit status.
+fn reap_terminal_children(state: &AppState) {
+  for session in lock_recover(&state.sessions).values() {
+    if let Ok(mut child) = session.handles.child.try_lock() {
+      let _ = child.try_wait();
+    }
+  }
+}
+
+fn persistence_file_path(app: &AppHandle, file_name: &str) -> Result<PathBuf, String> {
+  let mut path = app.path().app_data_dir().map_err(|error| error.to_string())?;
+  path.push("state");
+  fs::create_dir_all(&path).map_err(|error| io_error("failed to create app state directory", error))?;
+  path.push(file_name);
+  Ok(path)
+}
+
+fn read_json_or_default<T>(file_path: &Path, fallback: T) -> Result<T, String>
+where
+  T: for<'de> Deserialize<'de>
+{
+  match fs::read_to_string(file_path) {
+    Ok(raw) => serde_json::from_str(&raw)
+      .map_err(|error| format!("failed to parse JSON at {}: {error}", file_path.display())),
+    Err(error) if error.kind() == ErrorKind::NotFound => Ok(fallback),
+    Err(error) => Err(io_error(
+      &format!("failed to read {}", file_path.display()),
+      error
+    ))
+  }
+}
+
+fn stored_revision(file_path: &Path) -> Result<u64, String> {
+  let stored: serde_json::Value = read_json_or_default(file_path, serde_json::Value::Null)?;
+  Ok(stored
+    .get("revision")
+    .and_then(serde_json::Value::as_u64)
+    .unwrap_or_default())
+}
+
+/// Conflicts are detected on the revision counter rather than `updated_at`, which follows the
+/// wall clock and can go backwards. A client that sends revision 0 predates revisions and
+/// overwrites unconditionally, as before.
+fn next_revision(file_path: &Path, expected_revision: u64) -> Result<u64, String> {
+  let current_revision = stored_revision(file_path)?;
+  if expected_revision != 0 && expected_revision != current_revision {
+    return Err(message_error(
+      "CONFLICT",
+      "state.revisionConflict",
+      &[
+        ("path", file_path.display().to_string()),
+        ("current", current_revision.to_string()),
+        ("expected", expected_revision.to_string())
+      ]
+    ));
+  }
+  Ok(current_revision + 1)
+}
+
+fn task_not_found(task_id: &str) -> String {
+  message_error("NOT_FOUND", "task.notFound", &[("taskId", task_id.to_string())])
+}
+
+fn task_id(task: &serde_json::Value) -> Option<&str> {
+  task.get("id").and_then(serde_json::Value::as_str)
+}
+
+fn update_tasks<F>(app: &AppHandle, store: &TaskStore, update: F) -> Result<TaskState, String>
+where
+  F: FnOnce(&mut Vec<serde_json::Value>) -> Result<(), String>
+{
+  ensure_not_closing(app)?;
+  let _guard = store.write_lock.lock().map_err(|_| lock_error("task store"))?;
+  let path = persistence_file_path(app, TASKS_FILE_NAME)?;
+  let mut state = read_json_or_default(&path, default_task_state())?;
+  update(&mut state.tasks)?;
+  state.revision += 1;
+  state.updated_at = Utc::now().to_rfc3339();
+  write_json_with_threshold(&path, &state, json_pretty_max_bytes(&app.state::<SettingsState>()))?;
+  Ok(state)
+}
+
+/// Reads the task archive: newline-delimited JSON, or a JSON array as older versions wrote it.
+/// A last line without its newline is a torn append and is dropped if it does not parse.
+fn read_task_archive(path: &Path) -> Result<Vec<serde_json::Value>, String> {
+  let raw = match fs::read_to_string(path) {
+    Ok(raw) => raw,
+    Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(error) => return Err(io_error(&format!("failed to read {}", path.display()), error))
+  };
+  if raw.trim_start().starts_with('[') {
+    return serde_json::from_str(&raw).map_err(|error| format!("failed to parse JSON at {}: {error}", path.display()));
+  }
+
+  let complete = raw.ends_with('\n');
+  let lines: Vec<&str> = raw.lines().collect();
+  let mut tasks = Vec::with_capacity(lines.len());
+  for (index, line) in lines.iter().enumerate() {
+    if line.trim().is_empty() {
+      continue;
+    }
+    match serde_json::from_str(line) {
+      Ok(task) => tasks.push(task),
+      Err(_) if !complete && index + 1 == lines.len() => {}
+      Err(error) => return Err(format!("failed to parse {} line {}: {error}", path.display(), index + 1))
+    }
+  }
+  Ok(tasks)
+}
+
+/// Appends `tasks` to the archive, one line each. An archive still in the old array format is
+/// converted first; a torn last line is cut off so the new lines start cleanly.
+fn append_task_archive(path: &Path, tasks: &[serde_json::Value]) -> Result<(), String> {
+  let write_error = |error| io_error(&format!("failed to write {}", path.display()), error);
+  let existing = match fs::read(path) {
+    Ok(existing) => existing,
+    Err(error) if error.kind() == ErrorKind::NotFound => Vec::new(),
+    Err(error) => return Err(io_error(&format!("failed to read {}", path.display()), error))
+  };
+  let legacy = existing.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'[');
+  if legacy || existing.last().is_some_and(|byte| *byte != b'\n') {
+    let mut archived = read_task_archive(path)?;
+    archived.extend_from_slice(tasks);
+    return write_file_atomically(
+      path,
+      |file| {
+        let mut writer = BufWriter::new(file);
+        for task in &archived {
+          serde_json::to_writer(&mut writer, task)?;
+          writer.write_all(b"\n")?;
+        }
+        writer.flush()
+      },
+      || Ok(())
+    )
+    .map(|_| ())
+    .map_err(write_error);
+  }
+
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
+  }
+  let file = OpenOptions::new().create(true).append(true).open(path).map_err(write_error)?;
+  let mut writer = BufWriter::new(file);
+  let mut lines = Vec::new();
+  for task in tasks {
+    serde_json::to_writer(&mut lines, task).map_err(|error| format!("failed to serialize task: {error}"))?;
+    lines.push(b'\n');
+  }
+  writer.write_all(&lines).map_err(write_error)?;
+  writer
+    .into_inner()
+    .map_err(|error| write_error(error.into_error()))?
+    .sync_all()
+    .map_err(write_error)
+}
+
+/// Applies an RFC 7396 JSON merge patch: objects merge recursively and `null` removes a key.
+fn merge_json_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+  let serde_json::Value::Object(patch_fields) = patch else {
+    *target = patch.clone();
+    return;
+  };
+  if !target.is_object() {
+    *target = serde_json::Value::Object(serde_json::Map::new());
+  }
+  if let serde_json::Value::Object(target_fields) = target {
+    for (key, value) in patch_fields {
+      if value.is_null() {
+        target_fields.remove(key);
+      } else {
+        merge_json_patch(
+          target_fields.entry(key.clone()).or_insert(serde_json::Value::Null),
+          value
+        );
+      }
+    }
+  }
+}
+
+/// Passes writes through to `inner`, counting the bytes.
+struct CountingWriter<W> {
+  inner: W,
+  count: u64
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    let written = self.inner.write(buf)?;
+    self.count += written as u64;
+    Ok(written)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+fn write_json<T>(file_path: &Path, value: &T) -> Result<(), String>
+where
+  T: Serialize
+{
+  write_json_with_threshold(file_path, value, DEFAULT_JSON_PRETTY_MAX_BYTES)
+}
+
+/// Streams `value` to disk, pretty-printed unless its compact form exceeds `pretty_max_bytes`;
+/// indentation makes up most of a large task list. The compact form is written first and only
+/// rewritten pretty when it turned out small, so large files are serialized once.
+fn write_json_with_threshold<T>(file_path: &Path, value: &T, pretty_max_bytes: u64) -> Result<(), String>
+where
+  T: Serialize
+{
+  if let Some(parent) = file_path.parent() {
+    fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
+  }
+
+  write_file_atomically(
+    file_path,
+    |file| {
+      let mut writer = BufWriter::new(CountingWriter { inner: file, count: 0 });
+      serde_json::to_writer(&mut writer, value)?;
+      let file = writer.into_inner().map_err(|error| error.into_error())?;
+      if file.count > pretty_max_bytes {
+        return Ok(());
+      }
+      let file = file.inner;
+      file.set_len(0)?;
+      file.seek(SeekFrom::Start(0))?;
+      let mut writer = BufWriter::new(file);
+      serde_json::to_writer_pretty(&mut writer, value)?;
+      writer.flush()
+    },
+    || Ok(())
+  )
+  .map_err(|error| io_error(&format!("failed to write {}", file_path.display()), error))?;
+  Ok(())
+}
+
+/// A unique name next to `path`, for writing a replacement that is renamed into place.
+fn temp_sibling_path(path: &Path) -> PathBuf {
+  let file_name = path
+    .file_name()
+    .map(|name| name.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  path.with_file_name(format!("{file_name}.{}.tmp", Uuid::new_v4()))
+}
+
+/// Writes a sibling temp file, syncs it and renames it over `path`, so a crash mid-write never
+/// leaves a truncated file behind. The original's permissions, and on Unix its owner where
+/// allowed, carry over, and a symlink is written through rather than replaced. When no temp
+/// file can be created next to the target, it is written in place instead. `before_replace`
+/// runs right before the target is touched, the last point at which the write can still be
+/// abandoned. Returns whether the write was atomic.
+fn write_file_atomically(
+  path: &Path,
+  write_contents: impl FnOnce(&mut fs::File) -> std::io::Result<()>,
+  before_replace: impl FnOnce() -> std::io::Result<()>
+) -> std::io::Result<bool> {
+  let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+  let existing = fs::metadata(&path).ok();
+  let temp_path = temp_sibling_path(&path);
+
+  let mut temp_file = match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+    Ok(file) => file,
+    Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+      before_replace()?;
+      let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(&path)?;
+      write_contents(&mut file)?;
+      file.sync_all()?;
+      return Ok(false);
+    }
+    Err(error) => return Err(error)
+  };
+
+  let result = (|| {
+    if let Some(metadata) = &existing {
+      temp_file.set_permissions(metadata.permissions())?;
+      #[cfg(unix)]
+      {
+        use std::os::unix::fs::MetadataExt;
+        let _ = nix::unistd::fchown(
+          std::os::unix::io::AsRawFd::as_raw_fd(&temp_file),
+          Some(nix::unistd::Uid::from_raw(metadata.uid())),
+          Some(nix::unistd::Gid::from_raw(metadata.gid()))
+        );
+      }
+    }
+    write_contents(&mut temp_file)?;
+    temp_file.sync_all()?;
+    before_replace()?;
+    fs::rename(&temp_path, &path)
+  })();
+  if let Err(error) = result {
+    let _ = fs::remove_file(&temp_path);
+    return Err(error);
+  }
+
+  // Persist the rename itself.
+  #[cfg(unix)]
+  if let Some(parent) = path.parent() {
+    let _ = fs::File::open(parent).and_then(|directory| directory.sync_all());
+  }
+  Ok(true)
+}
+
+impl ScrollbackBuffer {
+  fn new(capacity: usize) -> Self {
+    Self {
+      data: VecDeque::with_capacity(capacity.min(64 * 1024)),
+      capacity,
+      total_bytes: 0,
+      dropped_lines: 0,
+      cleared_at: 0,
+      hidden_at: None
+    }
+  }
+
+  fn start_offset(&self) -> u64 {
+    self.total_bytes - self.data.len() as u64
+  }
+
+  fn append(&mut self, chunk: &[u8]) {
+    self.total_bytes += chunk.len() as u64;
+    let chunk = &chunk[chunk.len().saturating_sub(self.capacity)..];
+    let overflow = (self.data.len() + chunk.len()).saturating_sub(self.capacity);
+    self.dropped_lines += self.data.range(..overflow).filter(|&&byte| byte == b'\n').count() as u64;
+    self.data.drain(..overflow);
+    self.data.extend(chunk);
+  }
+
+  /// Returns output appended since `offset`, capped to the last `max_bytes`, along with how
+  /// many bytes in that range could not be returned.
+  fn since(&self, offset: u64, max_bytes: usize) -> (Vec<u8>, u64) {
+    let wanted = self.total_bytes.saturating_sub(offset);
+    let available = (self.total_bytes - self.start_offset().max(offset)).min(max_bytes as u64);
+    let skipped = wanted - available;
+    let start = self.data.len() - available as usize;
+    (self.data.range(start..).copied().collect(), skipped)
+  }
+
+  /// Like `since`, but when output was skipped the result starts at a line start instead of part
+  /// way through a character or escape sequence; the bytes passed over count as skipped.
+  fn catch_up_since(&self, offset: u64, max_bytes: usize) -> (Vec<u8>, u64) {
+    let (mut data, mut skipped) = self.since(offset, max_bytes);
+    if skipped > 0 {
+      let start = catch_up_boundary(&data);
+      data.drain(..start);
+      skipped += start as u64;
+    }
+    (data, skipped)
+  }
+
+  /// Drops everything retained while keeping offsets and line numbers counting from where they were.
+  fn clear(&mut self) {
+    self.dropped_lines += self.data.iter().filter(|&&byte| byte == b'\n').count() as u64;
+    self.data.clear();
+    self.cleared_at = self.total_bytes;
+  }
+
+  /// Whether output appended since the last clear has been evicted for space.
+  fn evicted(&self) -> bool {
+    self.start_offset() > self.cleared_at
+  }
+}
+
+/// Where output cut at an arbitrary byte can safely resume: after its first newline, since
+/// escape sequences and characters do not span lines in practice, or, with no newline to go
+/// by, past any UTF-8 continuation bytes at the start.
+fn catch_up_boundary(data: &[u8]) -> usize {
+  match data.iter().position(|&byte| byte == b'\n') {
+    Some(index) => index + 1,
+    None => data.iter().take_while(|&&byte| byte & 0xc0 == 0x80).count()
+  }
+}
+
+impl AnsiStripper {
+  fn strip(&mut self, input: &[u8], output: &mut Vec<u8>) {
+    for &byte in input {
+      self.state = match (self.state, byte) {
+        (AnsiState::Text, 0x1b) => AnsiState::Escape,
+        (AnsiState::Text, b'\r') => AnsiState::Text,
+        (AnsiState::Text, _) => {
+          output.push(byte);
+          AnsiState::Text
+        }
+        (AnsiState::Escape, b'[') => AnsiState::Csi,
+        (AnsiState::Escape, b']') => AnsiState::Osc,
+        (AnsiState::Escape, 0x20..=0x2f) => AnsiState::Escape,
+        (AnsiState::Escape, _) => AnsiState::Text,
+        (AnsiState::Csi, 0x40..=0x7e) => AnsiState::Text,
+        (AnsiState::Csi, _) => AnsiState::Csi,
+        (AnsiState::Osc, 0x07) => AnsiState::Text,
+        (AnsiState::Osc, 0x1b) => AnsiState::OscEscape,
+        (AnsiState::Osc, _) => AnsiState::Osc,
+        (AnsiState::OscEscape, b'\\') => AnsiState::Text,
+        (AnsiState::OscEscape, _) => AnsiState::Osc
+      };
+    }
+  }
+}
+
+impl TerminalLog {
+  fn open(path: PathBuf, strip_ansi: bool) -> Result<Self, String> {
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
+    }
+
+    let file = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&path)
+      .map_err(|error| io_error(&format!("failed to open {}", path.display()), error))?;
+
+    Ok(Self {
+      path,
+      writer: BufWriter::new(file),
+      stripper: strip_ansi.then(AnsiStripper::default),
+      last_flush: Instant::now()
+    })
+  }
+
+  fn append(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+    match self.stripper.as_mut() {
+      Some(stripper) => {
+        let mut stripped = Vec::with_capacity(chunk.len());
+        stripper.strip(chunk, &mut stripped);
+        self.writer.write_all(&stripped)?;
+      }
+      None => self.writer.write_all(chunk)?
+    }
+    self.flush_if_due()
+  }
+
+  /// Flushes buffered output once `TERMINAL_LOG_FLUSH_INTERVAL` has passed since the last flush.
+  fn flush_if_due(&mut self) -> std::io::Result<()> {
+    if self.last_flush.elapsed() >= TERMINAL_LOG_FLUSH_INTERVAL {
+      self.writer.flush()?;
+      self.last_flush = Instant::now();
+    }
+    Ok(())
+  }
+
+  /// Writes a line of our own into the transcript. Any escape sequence the stripper was in the
+  /// middle of is abandoned so the marker is never swallowed.
+  fn write_marker(&mut self, marker: &str) -> std::io::Result<()> {
+    if let Some(stripper) = self.stripper.as_mut() {
+      *stripper = AnsiStripper::default();
+    }
+    writeln!(self.writer, "\n{marker}")?;
+    self.writer.flush()?;
+    self.last_flush = Instant::now();
+    Ok(())
+  }
+}
+
+/// Tees a chunk of output into the session log, if any.
+fn append_terminal_log(app: &AppHandle, session_id: &str, session: &TerminalSession, chunk: &[u8]) {
+  update_terminal_log(app, session_id, session, |log| log.append(chunk));
+}
+
+/// Flushes logs whose sessions have gone quiet, so a transcript does not sit in the buffer until
+/// the next output arrives.
+fn flush_terminal_logs(app: &AppHandle) {
+  let state = app.state::<AppState>();
+  let sessions: Vec<(String, TerminalSession)> = lock_recover(&state.sessions)
+    .iter()
+    .map(|(session_id, session)| (session_id.clone(), session.clone()))
+    .collect();
+  for (session_id, session) in sessions {
+    update_terminal_log(app, &session_id, &session, TerminalLog::flush_if_due);
+  }
+}
+
+/// Runs `action` on the session log, if any. A failing log is closed and reported so the
+/// terminal itself keeps running.
+fn update_terminal_log(
+  app: &AppHandle,
+  session_id: &str,
+  session: &TerminalSession,
+  action: impl FnOnce(&mut TerminalLog) -> std::io::Result<()>
+) {
+  let mut log = lock_recover(&session.log);
+  let Some(active_log) = log.as_mut() else {
+    return;
+  };
+
+  if let Err(error) = action(active_log) {
+    let path = active_log.path.to_string_lossy().into_owned();
+    *log = None;
+    let _ = emit_to_session_window(
+      app,
+      session,
+      TERMINAL_LOG_ERROR_EVENT,
+      TerminalLogErrorEvent {
+        session_id: session_id.to_string(),
+        path,
+        message: error.to_string()
+      }
+    );
+  }
+}
+
+fn close_terminal_log(session: &TerminalSession) -> Result<(), String> {
+  let mut log = lock_recover(&session.log);
+
+  match log.take() {
+    Some(mut active_log) => active_log
+      .writer
+      .flush()
+      .map_err(|error| io_error(&format!("failed to flush {}", active_log.path.display()), error)),
+    None => Ok(())
+  }
+}
+
+impl OscParser {
+  fn feed(&mut self, input: &[u8], sequences: &mut Vec<String>) {
+    for &byte in input {
+      self.state = match (self.state, byte) {
+        (OscState::Text, 0x1b) => OscState::Escape,
+        (OscState::Text, _) => OscState::Text,
+        (OscState::Escape, b']') => {
+          self.payload.clear();
+          self.overflowed = false;
+          OscState::Payload
+        }
+        (OscState::Escape, 0x1b) => OscState::Escape,
+        (OscState::Escape, _) => OscState::Text,
+        (OscState::Payload, 0x07) => {
+          self.finish(sequences);
+          OscState::Text
+        }
+        (OscState::Payload, 0x1b) => OscState::PayloadEscape,
+        (OscState::Payload, _) => {
+          self.push(byte);
+          OscState::Payload
+        }
+        (OscState::PayloadEscape, b'\\') => {
+          self.finish(sequences);
+          OscState::Text
+        }
+        // Any other escape aborts the OSC and may itself start a new one.
+        (OscState::PayloadEscape, b']') => {
+          self.payload.clear();
+          self.overflowed = false;
+          OscState::Payload
+        }
+        (OscState::PayloadEscape, _) => OscState::Text
+      };
+    }
+  }
+
+  fn push(&mut self, byte: u8) {
+    if self.payload.len() < OSC_PAYLOAD_MAX_BYTES {
+      self.payload.push(byte);
+    } else {
+      self.overflowed = true;
+    }
+  }
+
+  fn finish(&mut self, sequences: &mut Vec<String>) {
+    if !self.overflowed {
+      sequences.push(String::from_utf8_lossy(&self.payload).into_owned());
+    }
+    self.payload.clear();
+    self.overflowed = false;
+  }
+}
+
+fn percent_decode(input: &str) -> String {
+  let bytes = input.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+
+  while index < bytes.len() {
+    if bytes[index] == b'%' && index + 2 < bytes.len() {
+      let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).unwrap_or_default();
+      if let Ok(value) = u8::from_str_radix(hex, 16) {
+        decoded.push(value);
+        index += 3;
+        continue;
+      }
+    }
+    decoded.push(bytes[index]);
+    index += 1;
+  }
+
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn parse_shell_integration_sequence(payload: &str) -> Option<ShellIntegrationEvent> {
+  let (code, rest) = payload.split_once(';').unwrap_or((payload, ""));
+  match code {
+    "133" => {
+      let mut parts = rest.split(';');
+      match parts.next() {
+        Some("C") => Some(ShellIntegrationEvent::CommandStart),
+        Some("D") => Some(ShellIntegrationEvent::CommandEnd(
+          parts.next().and_then(|value| value.trim().parse().ok())
+        )),
+        _ => None
+      }
+    }
+    "1337" => rest
+      .strip_prefix("CurrentDir=")
+      .map(|cwd| ShellIntegrationEvent::CwdChanged(cwd.to_string())),
+    "7" => {
+      let location = rest.strip_prefix("file://")?;
+      let path_start = location.find('/')?;
+      Some(ShellIntegrationEvent::CwdChanged(percent_decode(&location[path_start..])))
+    }
+    _ => None
+  }
+}
+
+fn emit_shell_integration_events(
+  app: &AppHandle,
+  session_id: &str,
+  session: &TerminalSession,
+  parser: &mut OscParser,
+  chunk: &[u8]
+) {
+  let mut sequences = Vec::new();
+  parser.feed(chunk, &mut sequences);
+
+  for event in sequences
+    .iter()
+    .filter_map(|payload| parse_shell_integration_sequence(payload))
+  {
+    let session_id = session_id.to_string();
+    let _ = match event {
+      ShellIntegrationEvent::CommandStart => emit_to_session_window(
+        app,
+        session,
+        TERMINAL_COMMAND_START_EVENT,
+        TerminalCommandStartEvent { session_id }
+      ),
+      ShellIntegrationEvent::CommandEnd(exit_code) => emit_to_session_window(
+        app,
+        session,
+        TERMINAL_COMMAND_END_EVENT,
+        TerminalCommandEndEvent { session_id, exit_code }
+      ),
+      ShellIntegrationEvent::CwdChanged(cwd) => {
+        *lock_recover(&session.cwd) = Some(cwd.clone());
+        emit_replayable_to_session(
+          app,
+          session,
+          TERMINAL_CWD_CHANGED_EVENT,
+          TerminalCwdChangedEvent { session_id, cwd }
+        )
+      }
+    };
+  }
+}
+
+fn shell_quote(value: &str) -> String {
+  format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn find_last(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+  haystack.windows(needle.len()).rposition(|window| window == needle)
+}
+
+/// Follows DECSET/DECRST 2004 in PTY output. `pending` carries the tail of the previous
+/// chunk so a mode switch split across two reads is still seen.
+fn track_bracketed_paste(pending: &mut Vec<u8>, chunk: &[u8], bracketed_paste: &AtomicBool) {
+  pending.extend_from_slice(chunk);
+
+  let enabled_at = find_last(pending, BRACKETED_PASTE_ENABLE);
+  let disabled_at = find_last(pending, BRACKETED_PASTE_DISABLE);
+  match (enabled_at, disabled_at) {
+    (Some(enabled), Some(disabled)) => bracketed_paste.store(enabled > disabled, Ordering::Relaxed),
+    (Some(_), None) => bracketed_paste.store(true, Ordering::Relaxed),
+    (None, Some(_)) => bracketed_paste.store(false, Ordering::Relaxed),
+    (None, None) => {}
+  }
+
+  let keep = BRACKETED_PASTE_ENABLE.len() - 1;
+  if pending.len() > keep {
+    pending.drain(..pending.len() - keep);
+  }
+}
+
+/// Reads a control character from the PTY's termios so remapped `stty` settings are honored.
+/// `Ok(None)` means the PTY has no termios to consult.
+#[cfg(unix)]
+fn configured_control_character(
+  master: &(dyn portable_pty::MasterPty + Send),
+  name: &str
+) -> Result<Option<u8>, String> {
+  use nix::sys::termios::SpecialCharacterIndices;
+
+  let index = match name {
+    "eof" => SpecialCharacterIndices::VEOF,
+    "interrupt" => SpecialCharacterIndices::VINTR,
+    "suspend" => SpecialCharacterIndices::VSUSP,
+    "quit" => SpecialCharacterIndices::VQUIT,
+    "erase" => SpecialCharacterIndices::VERASE,
+    _ => return Ok(None)
+  };
+  let Some(termios) = master.get_termios() else {
+    return Ok(None);
+  };
+
+  let value = termios.control_chars[index as usize];
+  if value == nix::libc::_POSIX_VDISABLE {
+    return Err(format!("control character \"{name}\" is disabled in the terminal settings"));
+  }
+  Ok(Some(value))
+}
+
+#[cfg(not(unix))]
+fn configured_control_character(
+  _master: &(dyn portable_pty::MasterPty + Send),
+  _name: &str
+) -> Result<Option<u8>, String> {
+  Ok(None)
+}
+
+fn terminal_info_snapshot(session_id: &str, session: &TerminalSession) -> TerminalInfo {
+  let exit_code = *lock_recover(&session.exit_code);
+  TerminalInfo {
+    session_id: session_id.to_string(),
+    pid: session.pid.load(Ordering::Relaxed),
+    shell: session.launch.shell.clone(),
+    term: session.launch.term.clone(),
+    visible: session.visible.load(Ordering::Relaxed),
+    builtin: session.launch.builtin,
+    last_activity_ms: session.last_activity_ms.load(Ordering::Relaxed),
+    exited: exit_code.is_some(),
+    exit_code,
+    window_label: lock_recover(&session.window_label).clone()
+  }
+}
+
+fn terminal_stats_snapshot(session_id: &str, session: &TerminalSession) -> TerminalStatsResponse {
+  TerminalStatsResponse {
+    session_id: session_id.to_string(),
+    bytes_written: session.bytes_written.load(Ordering::Relaxed),
+    bytes_read: session.bytes_read.load(Ordering::Relaxed),
+    output_events: session.output_events.load(Ordering::Relaxed),
+    last_activity_ms: session.last_activity_ms.load(Ordering::Relaxed),
+    uptime_secs: session.created_at.elapsed().as_secs()
+  }
+}
+
+/// Sessions owned by another window are reported as missing, so windows never act on each
+/// other's terminals.
+fn get_terminal_session(state: &State<'_, AppState>, window_label: &str, session_id: &str) -> Result<TerminalSession, String> {
+  let sessions = lock_recover(&state.sessions);
+
+  sessions
+    .get(session_id)
+    .filter(|session| *lock_recover(&session.window_label) == window_label)
+    .cloned()
+    .ok_or_else(|| terminal_not_found(session_id))
+}
+
+fn terminal_not_found(session_id: &str) -> String {
+  message_error("NOT_FOUND", "terminal.notFound", &[("sessionId", session_id.to_string())])
+}
+
+fn session_owned_by(session: &TerminalSession, window_label: &str) -> bool {
+  *lock_recover(&session.window_label) == window_label
+}
+
+/// Session events go only to the window that owns the session.
+fn emit_to_session_window<S>(app: &AppHandle, session: &TerminalSession, event: &str, payload: S) -> tauri::Result<()>
+where
+  S: Serialize + Clone
+{
+  let window_label = lock_recover(&session.window_label).clone();
+  app.emit_to(window_label.as_str(), event, payload)
+}
+
+fn debug_log(message: &str) {
+  if cfg!(debug_assertions) {
+    eprintln!("[openspace] {message}");
+  }
+}
+
+fn xdg_dirs(home_variable: &str, home_fallback: &str, dirs_variable: &str, dirs_fallback: &str) -> Vec<PathBuf> {
+  let mut dirs = Vec::new();
+
+  match std::env::var_os(home_variable).filter(|value| !value.is_empty()) {
+    Some(value) => dirs.push(PathBuf::from(value)),
+    None => {
+      if let Some(home) = home_dir() {
+        dirs.push(home.join(home_fallback));
+      }
+    }
+  }
+
+  let system_dirs = std::env::var(dirs_variable)
+    .ok()
+    .filter(|value| !value.is_empty())
+    .unwrap_or_else(|| dirs_fallback.to_string());
+  dirs.extend(
+    system_dirs
+      .split(':')
+      .filter(|value| !value.is_empty())
+      .map(PathBuf::from)
+  );
+  dirs
+}
+
+fn xdg_data_dirs() -> Vec<PathBuf> {
+  xdg_dirs("XDG_DATA_HOME", ".local/share", "XDG_DATA_DIRS", "/usr/local/share:/usr/share")
+}
+
+fn xdg_config_dirs() -> Vec<PathBuf> {
+  xdg_dirs("XDG_CONFIG_HOME", ".config", "XDG_CONFIG_DIRS", "/etc/xdg")
+}
+
+/// Guesses a MIME type from the shared-mime-info glob database, which is what file managers
+/// consult before falling back to content sniffing.
+fn detect_mime_type(path: &Path) -> String {
+  if path.is_dir() {
+    return String::from("inode/directory");
+  }
+
+  let file_name = match path.file_name() {
+    Some(name) => name.to_string_lossy().to_lowercase(),
+    None => return String::from("application/octet-stream")
+  };
+
+  let mut best_match: Option<(u32, usize, String)> = None;
+  for data_dir in xdg_data_dirs() {
+    let Ok(raw) = fs::read_to_string(data_dir.join("mime/globs2")) else {
+      continue;
+    };
+
+    for line in raw.lines().filter(|line| !line.starts_with('#')) {
+      let mut fields = line.splitn(4, ':');
+      let (Some(weight), Some(mime_type), Some(glob)) = (fields.next(), fields.next(), fields.next()) else {
+        continue;
+      };
+      let weight = weight.parse::<u32>().unwrap_or(50);
+      let glob = glob.to_lowercase();
+
+      let matched = match glob.strip_prefix('*') {
+        Some(suffix) if !suffix.contains(['*', '?', '[']) => file_name.ends_with(suffix),
+        Some(_) => false,
+        None => file_name == glob
+      };
+      if !matched {
+        continue;
+      }
+
+      let candidate = (weight, glob.len(), mime_type.to_string());
+      let is_better = best_match
+        .as_ref()
+        .map_or(true, |(best_weight, best_length, _)| (weight, glob.len()) > (*best_weight, *best_length));
+      if is_better {
+        best_match = Some(candidate);
+      }
+    }
+  }
+
+  best_match
+    .map(|(_, _, mime_type)| mime_type)
+    .unwrap_or_else(|| String::from("application/octet-stream"))
+}
+
+fn parse_desktop_entry(file_path: &Path, id: String) -> Option<DesktopEntry> {
+  let raw = match fs::read_to_string(file_path) {
+    Ok(raw) => raw,
+    Err(error) => {
+      debug_log(&format!("skipping desktop entry {}: {error}", file_path.display()));
+      return None;
+    }
+  };
+
+  let mut in_main_group = false;
+  let mut fields: HashMap<&str, &str> = HashMap::new();
+  for line in raw.lines().map(str::trim) {
+    if line.starts_with('[') {
+      in_main_group = line == "[Desktop Entry]";
+      continue;
+    }
+    if !in_main_group || line.starts_with('#') {
+      continue;
+    }
+    if let Some((key, value)) = line.split_once('=') {
+      fields.entry(key.trim()).or_insert(value.trim());
+    }
+  }
+
+  if fields.get("Type").copied() != Some("Application")
+    || fields.get("Hidden").copied() == Some("true")
+    || fields.get("NoDisplay").copied() == Some("true")
+  {
+    return None;
+  }
+
+  let (Some(name), Some(exec)) = (fields.get("Name"), fields.get("Exec")) else {
+    debug_log(&format!(
+      "skipping malformed desktop entry {}: missing Name or Exec",
+      file_path.display()
+    ));
+    return None;
+  };
+
+  Some(DesktopEntry {
+    id,
+    file_path: file_path.to_path_buf(),
+    name: name.to_string(),
+    icon: fields.get("Icon").map(|icon| icon.to_string()),
+    exec: exec.to_string(),
+    mime_types: fields
+      .get("MimeType")
+      .map(|value| {
+        value
+          .split(';')
+          .filter(|mime_type| !mime_type.is_empty())
+          .map(str::to_string)
+          .collect()
+      })
+      .unwrap_or_default()
+  })
+}
+
+fn scan_desktop_entries() -> Vec<DesktopEntry> {
+  let mut entries: Vec<DesktopEntry> = Vec::new();
+
+  for data_dir in xdg_data_dirs() {
+    let applications_dir = data_dir.join("applications");
+    let mut pending = vec![applications_dir.clone()];
+
+    while let Some(directory) = pending.pop() {
+      let Ok(read_dir) = fs::read_dir(&directory) else {
+        continue;
+      };
+
+      for entry in read_dir.filter_map(Result::ok) {
+        let entry_path = entry.path();
+        if entry_path.is_dir() {
+          pending.push(entry_path);
+          continue;
+        }
+        if entry_path.extension().and_then(|extension| extension.to_str()) != Some("desktop") {
+          continue;
+        }
+
+        // Desktop file IDs use '-' in place of the path separator below applications/.
+        let Ok(relative_path) = entry_path.strip_prefix(&applications_dir) else {
+          continue;
+        };
+        let id = relative_path.to_string_lossy().replace('/', "-");
+        if entries.iter().any(|existing| existing.id == id) {
+          continue;
+        }
+
+        if let Some(desktop_entry) = parse_desktop_entry(&entry_path, id) {
+          entries.push(desktop_entry);
+        }
+      }
+    }
+  }
+
+  entries
+}
+
+fn cached_desktop_entries(cache: &DesktopEntryCache) -> Result<Arc<Vec<DesktopEntry>>, String> {
+  let mut scanned = cache
+    .scanned
+    .lock()
+    .map_err(|_| lock_error("desktop entry cache"))?;
+
+  if let Some((scanned_at, entries)) = scanned.as_ref() {
+    if scanned_at.elapsed() < DESKTOP_ENTRY_CACHE_TTL {
+      return Ok(entries.clone());
+    }
+  }
+
+  let entries = Arc::new(scan_desktop_entries());
+  *scanned = Some((Instant::now(), entries.clone()));
+  Ok(entries)
+}
+
+fn read_mime_associations() -> MimeAssociations {
+  let mut associations = MimeAssociations::default();
+  let mut list_files: Vec<PathBuf> = xdg_config_dirs()
+    .into_iter()
+    .map(|dir| dir.join("mimeapps.list"))
+    .collect();
+  list_files.extend(
+    xdg_data_dirs()
+      .into_iter()
+      .map(|dir| dir.join("applications/mimeapps.list"))
+  );
+
+  // Files are listed from highest to lowest precedence, so earlier values are kept first.
+  for list_file in list_files {
+    let Ok(raw) = fs::read_to_string(&list_file) else {
+      continue;
+    };
+
+    let mut section = "";
+    for line in raw.lines().map(str::trim) {
+      if line.starts_with('[') {
+        section = line;
+        continue;
+      }
+      let Some((mime_type, desktop_ids)) = line.split_once('=') else {
+        continue;
+      };
+
+      let target = match section {
+        "[Default Applications]" => &mut associations.defaults,
+        "[Added Associations]" => &mut associations.added,
+        "[Removed Associations]" => &mut associations.removed,
+        _ => continue
+      };
+      let ids = target.entry(mime_type.trim().to_string()).or_default();
+      for desktop_id in desktop_ids.split(';').map(str::trim).filter(|id| !id.is_empty()) {
+        if !ids.iter().any(|existing| existing == desktop_id) {
+          ids.push(desktop_id.to_string());
+        }
+      }
+    }
+  }
+
+  associations
+}
+
+fn split_exec_line(exec: &str) -> Vec<String> {
+  let mut arguments = Vec::new();
+  let mut current = String::new();
+  let mut in_quotes = false;
+  let mut has_argument = false;
+  let mut chars = exec.chars();
+
+  while let Some(character) = chars.next() {
+    match character {
+      '"' => {
+        in_quotes = !in_quotes;
+        has_argument = true;
+      }
+      '\\' if in_quotes => {
+        if let Some(escaped) = chars.next() {
+          current.push(escaped);
+        }
+      }
+      ' ' | '\t' if !in_quotes => {
+        if has_argument {
+          arguments.push(std::mem::take(&mut current));
+          has_argument = false;
+        }
+      }
+      _ => {
+        current.push(character);
+        has_argument = true;
+      }
+    }
+  }
+
+  if has_argument {
+    arguments.push(current);
+  }
+  arguments
+}
+
+/// Expands the Exec field codes from the desktop entry spec for a single file.
+fn desktop_exec_arguments(entry: &DesktopEntry, target: &Path) -> Vec<String> {
+  let target = target.to_string_lossy().into_owned();
+  let mut arguments = Vec::new();
+  let mut used_target = false;
+
+  for argument in split_exec_line(&entry.exec) {
+    match argument.as_str() {
+      "%f" | "%F" | "%u" | "%U" => {
+        arguments.push(target.clone());
+        used_target = true;
+      }
+      "%i" => {
+        if let Some(icon) = entry.icon.as_ref() {
+          arguments.push(String::from("--icon"));
+          arguments.push(icon.clone());
+        }
+      }
+      "%c" => arguments.push(entry.name.clone()),
+      "%k" => arguments.push(entry.file_path.to_string_lossy().into_owned()),
+      "%d" | "%D" | "%n" | "%N" | "%v" | "%m" => {}
+      _ => arguments.push(argument.replace("%%", "%"))
+    }
+  }
+
+  if !used_target {
+    arguments.push(target);
+  }
+  arguments
+}
+
+/// CPU ticks (utime + stime) per process at one moment, kept between `process_list` calls.
+struct ProcessSample {
+  taken_at: Instant,
+  ticks: HashMap<u32, u64>
+}
+
+struct ProcessStat {
+  name: String,
+  state: String,
+  process_group: u32,
+  session: u32,
+  ticks: u64,
+  /// When the process started, in clock ticks after boot.
+  start_ticks: u64
+}
+
+/// Parses /proc/<pid>/stat. The command name is parenthesized and may itself contain spaces
+/// or parentheses, so the fields after it are found from the last `)`.
+fn parse_process_stat(raw: &str) -> Option<ProcessStat> {
+  let name_start = raw.find('(')?;
+  let name_end = raw.rfind(')')?;
+  let name = raw.get(name_start + 1..name_end)?.to_string();
+  let fields: Vec<&str> = raw.get(name_end + 1..)?.split_whitespace().collect();
+  // fields[0] is the state, followed by ppid, pgrp and session; utime and stime are stat
+  // fields 14 and 15, starttime is field 22.
+  let utime: u64 = fields.get(11)?.parse().ok()?;
+  let stime: u64 = fields.get(12)?.parse().ok()?;
+
+  Some(ProcessStat {
+    name,
+    state: fields.first()?.to_string(),
+    process_group: fields.get(2)?.parse().ok()?,
+    session: fields.get(3)?.parse().ok()?,
+    ticks: utime + stime,
+    start_ticks: fields.get(19).and_then(|value| value.parse().ok()).unwrap_or_default()
+  })
+}
+
+/// Seconds since boot, from /proc/uptime.
+fn system_uptime_secs() -> Option<f64> {
+  fs::read_to_string("/proc/uptime").ok()?.split_whitespace().next()?.parse().ok()
+}
+
+/// CPU use as a percentage of one core, from the ticks a process used over `window`: the ticks
+/// it had at the start of the window and the window's length in seconds.
+fn cpu_percent(ticks: u64, window: Option<(u64, f64)>, ticks_per_second: f64) -> f32 {
+  window
+    .filter(|(_, seconds)| *seconds > 0.0)
+    .map(|(before, seconds)| ticks.saturating_sub(before) as f64 / ticks_per_second / seconds * 100.0)
+    .unwrap_or_default() as f32
+}
+
+fn clock_ticks_per_second() -> f64 {
+  #[cfg(unix)]
+  {
+    if let Ok(Some(ticks)) = nix::unistd::sysconf(nix::unistd::SysconfVar::CLK_TCK) {
+      if ticks > 0 {
+        return ticks as f64;
+      }
+    }
+  }
+  100.0
+}
+
+fn user_name_for_uid(uid: u32, names: &mut HashMap<u32, String>) -> String {
+  names
+    .entry(uid)
+    .or_insert_with(|| {
+      #[cfg(unix)]
+      {
+        if let Ok(Some(user)) = nix::unistd::User::from_uid(nix::unistd::Uid::from_raw(uid)) {
+          return user.name;
+        }
+      }
+      uid.to_string()
+    })
+    .clone()
+}
+
+/// One process's entry along with its CPU ticks so far, for the next call's sample. CPU use is
+/// measured since `previous`, or over the process's lifetime when it was not in that sample.
+fn read_process_entry(
+  pid: u32,
+  previous: Option<&ProcessSample>,
+  uptime_secs: Option<f64>,
+  ticks_per_second: f64,
+  user_names: &mut HashMap<u32, String>
+) -> Option<(ProcessEntry, u64)> {
+  let process_dir = PathBuf::from("/proc").join(pid.to_string());
+  let stat = parse_process_stat(&fs::read_to_string(process_dir.join("stat")).ok()?)?;
+  let status = fs::read_to_string(process_dir.join("status")).unwrap_or_default();
+
+  let status_value = |key: &str| {
+    status
+      .lines()
+      .find_map(|line| line.strip_prefix(key))
+      .and_then(|value| value.split_whitespace().next())
+      .and_then(|value| value.parse::<u64>().ok())
+  };
+  // Kernel threads have no VmRSS line.
+  let rss_kb = status_value("VmRSS:").unwrap_or_default();
+  let user = status_value("Uid:")
+    .and_then(|uid| u32::try_from(uid).ok())
+    .map(|uid| user_name_for_uid(uid, user_names))
+    .unwrap_or_default();
+
+  let cmdline = fs::read(process_dir.join("cmdline"))
+    .map(|raw| {
+      raw
+        .split(|byte| *byte == 0)
+        .filter(|argument| !argument.is_empty())
+        .map(|argument| String::from_utf8_lossy(argument).into_owned())
+        .collect()
+    })
+    .unwrap_or_default();
+
+  let window = previous
+    .and_then(|sample| Some((*sample.ticks.get(&pid)?, sample.taken_at.elapsed().as_secs_f64())))
+    .or_else(|| Some((0, uptime_secs? - stat.start_ticks as f64 / ticks_per_second)));
+  let cpu_percent = cpu_percent(stat.ticks, window, ticks_per_second);
+
+  let entry = ProcessEntry {
+    pid,
+    name: stat.name,
+    state: stat.state,
+    rss_kb,
+    cpu_percent,
+    user,
+    cmdline
+  };
+  Some((entry, stat.ticks))
+}
+
+/// Stores an activity result, dropping entries computed on an earlier day and then the oldest
+/// ones so at most `ACTIVITY_CACHE_MAX_ENTRIES` remain.
+fn cache_activity(entries: &mut ActivityCacheEntries, key: (String, u32, String), activity: WorkspaceActivity) {
+  entries.retain(|(_, _, date), _| date == &key.2);
+  let sequence = entries.values().map(|(sequence, _)| sequence + 1).max().unwrap_or(0);
+  while entries.len() >= ACTIVITY_CACHE_MAX_ENTRIES {
+    let Some(oldest) = entries
+      .iter()
+      .min_by_key(|(_, (sequence, _))| *sequence)
+      .map(|(key, _)| key.clone())
+    else {
+      break;
+    };
+    entries.remove(&oldest);
+  }
+  entries.insert(key, (sequence, activity));
+}
+
+fn activity_directory(relative_path: &Path) -> String {
+  relative_path
+    .parent()
+    .map(|parent| parent.to_string_lossy().replace('\\', "/"))
+    .filter(|parent| !parent.is_empty())
+    .unwrap_or_else(|| String::from("."))
+}
+
+/// Runs git in `root` with a fixed locale, so its messages can be matched, and with the
+/// repository's fsmonitor and hooks turned off, since a cloned repository's config could name
+/// any program for them.
+fn git_output(root: &Path, args: &[&str]) -> Result<std::process::Output, String> {
+  std::process::Command::new("git")
+    .env("LC_ALL", "C")
+    .args(["-c", "core.fsmonitor=false", "-c", "core.hooksPath=/dev/null", "-C"])
+    .arg(root)
+    .args(args)
+    .output()
+    .map_err(|error| io_error("failed to run git", error))
+}
+
+fn run_git(root: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+  let output = git_output(root, args)?;
+  if !output.status.success() {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("not a git repository") {
+      return Err(message_error("NOT_A_REPOSITORY", "git.notRepository", &[("path", root.display().to_string())]));
+    }
+    return Err(format!("git {} failed: {}", args.first().unwrap_or(&""), stderr.trim()));
+  }
+  Ok(output.stdout)
+}
+
+fn resolve_git_root(startup_context: &StartupContext, root: &str) -> Result<PathBuf, String> {
+  fs::canonicalize(resolve_workspace_path(startup_context, root)?)
+    .map_err(|error| io_error("failed to resolve git root", error))
+}
+
+/// Parses `git log -z` output in the `GIT_LOG_FORMAT` layout.
+fn parse_git_log(raw: &[u8]) -> Vec<GitCommit> {
+  raw
+    .split(|&byte| byte == 0)
+    .filter_map(|record| {
+      let record = String::from_utf8_lossy(record);
+      let mut fields = record.trim_start_matches('\n').split('\x1f');
+      Some(GitCommit {
+        hash: fields.next().filter(|hash| !hash.is_empty())?.to_string(),
+        author_name: fields.next()?.to_string(),
+        author_email: fields.next()?.to_string(),
+        timestamp: fields.next()?.to_string(),
+        subject: fields.next()?.to_string()
+      })
+    })
+    .collect()
+}
+
+fn parse_git_status(raw: &[u8]) -> Vec<GitStatusEntry> {
+  let mut entries = Vec::new();
+  let mut fields = raw.split(|&byte| byte == 0).filter(|field| !field.is_empty());
+  while let Some(field) = fields.next() {
+    let field = String::from_utf8_lossy(field);
+    let mut status = field.chars();
+    let (Some(index_status), Some(worktree_status)) = (status.next(), status.next()) else {
+      continue;
+    };
+    let Some(path) = field.get(3..) else {
+      continue;
+    };
+    // With -z, a rename or copy is followed by its source path as a separate field.
+    let original_path = if matches!(index_status, 'R' | 'C') || matches!(worktree_status, 'R' | 'C') {
+      fields.next().map(|original| String::from_utf8_lossy(original).into_owned())
+    } else {
+      None
+    };
+    entries.push(GitStatusEntry {
+      path: path.to_string(),
+      index_status,
+      worktree_status,
+      original_path
+    });
+  }
+  entries
+}
+
+#[tauri::command]
+fn git_status(
+  state: State<'_, AppState>,
+  safe_mode: State<'_, SafeModeState>,
+  startup_context: State<'_, StartupContext>,
+  request: GitStatusRequest
+) -> Result<Vec<GitStatusEntry>, String> {
+  ensure_subsystem_enabled(&safe_mode, "git")?;
+  let root = resolve_git_root(&startup_context, &request.root)?;
+  let root_key = root.to_string_lossy().into_owned();
+
+  if let Some(snapshot) = lock_recover(&state.git_status_cache).get(&root_key) {
+    if snapshot.fetched_at.elapsed() < GIT_STATUS_CACHE_TTL {
+      return Ok(snapshot.entries.clone());
+    }
+  }
+
+  let raw = run_git(&root, &["status", "--porcelain=v1", "-z"])?;
+  let entries = parse_git_status(&raw);
+  let mut cache = lock_recover(&state.git_status_cache);
+  cache.retain(|_, snapshot| snapshot.fetched_at.elapsed() < GIT_STATUS_CACHE_TTL);
+  cache.insert(
+    root_key,
+    GitStatusSnapshot {
+      fetched_at: Instant::now(),
+      entries: entries.clone()
+    }
+  );
+  Ok(entries)
+}
+
+/// Counts added and removed lines inside hunks, so file headers and removed lines that happen
+/// to start with `--` are not mistaken for one another.
+fn count_diff_lines(diff: &str) -> (u32, u32) {
+  let mut in_hunk = false;
+  let (mut additions, mut deletions) = (0, 0);
+  for line in diff.lines() {
+    if line.starts_with("diff ") {
+      in_hunk = false;
+    } else if line.starts_with("@@") {
+      in_hunk = true;
+    } else if in_hunk {
+      match line.as_bytes().first() {
+        Some(b'+') => additions += 1,
+        Some(b'-') => deletions += 1,
+        _ => {}
+      }
+    }
+  }
+  (additions, deletions)
+}
+
+/// The working-tree diff of one file against the index, or of the index against HEAD when
+/// `staged` is set.
+#[tauri::command]
+fn git_diff(
+  safe_mode: State<'_, SafeModeState>,
+  startup_context: State<'_, StartupContext>,
+  request: GitDiffRequest
+) -> Result<GitDiffResponse, String> {
+  ensure_subsystem_enabled(&safe_mode, "git")?;
+  let root = resolve_git_root(&startup_context, &request.root)?;
+  let tracked = run_git(&root, &["ls-files", "-z", "--", &request.path])?;
+  if tracked.is_empty() {
+    return Ok(GitDiffResponse {
+      path: request.path,
+      diff: String::new(),
+      additions: 0,
+      deletions: 0,
+      untracked: true
+    });
+  }
+
+  let mut args = vec!["diff", "--no-color", "--no-ext-diff", "--no-textconv"];
+  if request.staged {
+    args.push("--cached");
+  }
+  args.extend(["--", &request.path]);
+  let diff = String::from_utf8_lossy(&run_git(&root, &args)?).into_owned();
+  let (additions, deletions) = count_diff_lines(&diff);
+  Ok(GitDiffResponse {
+    path: request.path,
+    diff,
+    additions,
+    deletions,
+    untracked: false
+  })
+}
+
+/// The files git lists, tab-indented, under "would be overwritten by checkout".
+fn parse_checkout_conflicts(stderr: &str) -> Vec<String> {
+  let mut conflicts = Vec::new();
+  let mut in_list = false;
+  for line in stderr.lines() {
+    if line.contains("would be overwritten by checkout") {
+      in_list = true;
+    } else if let Some(path) = line.strip_prefix('\t').filter(|_| in_list) {
+      conflicts.push(path.to_string());
+    } else {
+      in_list = false;
+    }
+  }
+  conflicts
+}
+
+/// The checked-out branch, or the abbreviated commit when HEAD is detached. symbolic-ref also
+/// names a branch with no commits yet.
+fn git_head_name(root: &Path) -> Result<String, String> {
+  let name = match run_git(root, &["symbolic-ref", "--short", "-q", "HEAD"]) {
+    Ok(name) => name,
+    Err(_) => run_git(root, &["rev-parse", "--short", "HEAD"])?
+  };
+  Ok(String::from_utf8_lossy(&name).trim().to_string())
+}
+
+/// Switches to `ref`, or restores `paths` from it. Local changes are never overwritten unless
+/// `force` is set for a path restore; they come back as conflicts instead.
+#[tauri::command]
+fn git_checkout(
+  app: AppHandle,
+  state: State<'_, AppState>,
+  safe_mode: State<'_, SafeModeState>,
+  startup_context: State<'_, StartupContext>,
+  request: GitCheckoutRequest
+) -> Result<GitCheckoutResult, String> {
+  ensure_not_closing(&app)?;
+  ensure_subsystem_enabled(&safe_mode, "git")?;
+  // git would read a ref such as `--force` as an option.
+  if request.ref_.starts_with('-') {
+    return Err(message_error("INVALID_REF", "git.invalidRef", &[("ref", request.ref_.clone())]));
+  }
+  let root = resolve_git_root(&startup_context, &request.root)?;
+
+  let conflicts = match request.paths.as_deref() {
+    Some(paths) => {
+      let mut status_args = vec!["status", "--porcelain=v1", "-z", "--"];
+      status_args.extend(paths.iter().map(String::as_str));
+      // Untracked files count too: checking out a path that exists in `ref` overwrites them.
+      let changed: Vec<String> = parse_git_status(&run_git(&root, &status_args)?)
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect();
+      if changed.is_empty() || request.force {
+        let mut checkout_args = vec!["checkout", request.ref_.as_str(), "--"];
+        checkout_args.extend(paths.iter().map(String::as_str));
+        run_git(&root, &checkout_args)?;
+        Vec::new()
+      } else {
+        changed
+      }
+    }
+    None => {
+      let output = git_output(&root, &["checkout", request.ref_.as_str()])?;
+      let stderr = String::from_utf8_lossy(&output.stderr);
+      let conflicts = parse_checkout_conflicts(&stderr);
+      if !output.status.success() && conflicts.is_empty() {
+        return Err(format!("git checkout failed: {}", stderr.trim()));
+      }
+      conflicts
+    }
+  };
+
+  lock_recover(&state.git_status_cache).remove(root.to_string_lossy().as_ref());
+  Ok(GitCheckoutResult {
+    switched_to: git_head_name(&root)?,
+    conflicts
+  })
+}
+
+#[tauri::command]
+fn git_branches(
+  safe_mode: State<'_, SafeModeState>,
+  startup_context: State<'_, StartupContext>,
+  request: GitBranchesRequest
+) -> Result<GitBranchList, String> {
+  ensure_subsystem_enabled(&safe_mode, "git")?;
+  let root = resolve_git_root(&startup_context, &request.root)?;
+  let current = git_head_name(&root)?;
+
+  let refs = run_git(
+    &root,
+    &["for-each-ref", "--format=%(refname)%00%(symref)", "refs/heads", "refs/remotes"]
+  )?;
+  let mut local = Vec::new();
+  let mut remote = Vec::new();
+  for line in String::from_utf8_lossy(&refs).lines() {
+    let (name, symref) = line.split_once('\0').unwrap_or((line, ""));
+    if let Some(branch) = name.strip_prefix("refs/heads/") {
+      local.push(branch.to_string());
+    } else if let Some(branch) = name.strip_prefix("refs/remotes/") {
+      if symref.is_empty() {
+        remote.push(branch.to_string());
+      }
+    }
+  }
+
+  Ok(GitBranchList {
+    current,
+    local,
+    remote
+  })
+}
+
+/// The newest `maxCount` commits reachable from HEAD, at most `GIT_LOG_MAX_COUNT`, optionally
+/// limited to one path. A repository without commits yet has an empty history rather than an
+/// error.
+#[tauri::command]
+fn git_log(
+  safe_mode: State<'_, SafeModeState>,
+  startup_context: State<'_, StartupContext>,
+  request: GitLogRequest
+) -> Result<Vec<GitCommit>, String> {
+  ensure_subsystem_enabled(&safe_mode, "git")?;
+  let root = resolve_git_root(&startup_context, &request.root)?;
+  let max_count = format!("--max-count={}", request.max_count.clamp(1, GIT_LOG_MAX_COUNT));
+  let mut args = vec!["log", "-z", GIT_LOG_FORMAT, &max_count];
+  if let Some(path) = request.path.as_deref() {
+    args.extend(["--", path]);
+  }
+  match run_git(&root, &args) {
+    Ok(raw) => Ok(parse_git_log(&raw)),
+    Err(error) if error.contains("does not have any commits yet") => Ok(Vec::new()),
+    Err(error) => Err(error)
+  }
+}
+
+/// Counts files touched per day and directory under `root` from `git log`, returning whether the
+/// commit cap cut the walk short. Paths are relative to `root`, and commits touching nothing
+/// under it are skipped, when `root` is below the top of the repository. `None` means the root
+/// is not a usable git work tree.
+fn collect_git_activity(
+  root: &Path,
+  days: u32,
+  counts: &mut HashMap<(String, String), u32>
+) -> Option<bool> {
+  let output = git_output(
+    root,
+    &[
+      "log",
+      &format!("--since={days} days ago"),
+      &format!("--max-count={}", ACTIVITY_MAX_COMMITS + 1),
+      "--date=short",
+      "--format=%x1e%ad",
+      "--name-only",
+      "--no-renames",
+      "--relative",
+      "--",
+      "."
+    ]
+  )
+  .ok()?;
+  if !output.status.success() {
+    return None;
+  }
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let mut commits = 0;
+  for record in stdout.split('\x1e').filter(|record| !record.trim().is_empty()) {
+    commits += 1;
+    if commits > ACTIVITY_MAX_COMMITS {
+      return Some(true);
+    }
+
+    let mut lines = record.lines();
+    let Some(date) = lines.next().map(str::trim) else {
+      continue;
+    };
+    for file in lines.map(str::trim).filter(|line| !line.is_empty()) {
+      let key = (date.to_string(), activity_directory(Path::new(file)));
+      *counts.entry(key).or_default() += 1;
+    }
+  }
+
+  Some(false)
+}
+
+/// Falls back to file modification times when there is no git history, returning whether the
+/// walk hit its entry cap.
+fn collect_mtime_activity(root: &Path, days: u32, counts: &mut HashMap<(String, String), u32>) -> bool {
+  let cutoff = Utc::now() - chrono::Duration::days(i64::from(days));
+  let mut pending = vec![root.to_path_buf()];
+  let mut walked = 0;
+
+  while let Some(directory) = pending.pop() {
+    let Ok(entries) = fs::read_dir(&directory) else {
+      continue;
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+      walked += 1;
+      if walked > ACTIVITY_MAX_WALKED_ENTRIES {
+        return true;
+      }
+
+      let Ok(file_type) = entry.file_type() else {
+        continue;
+      };
+      let entry_path = entry.path();
+      if file_type.is_dir() {
+        let name = entry.file_name();
+        if !ACTIVITY_SKIPPED_DIRECTORIES.contains(&name.to_string_lossy().as_ref()) {
+          pending.push(entry_path);
+        }
+        continue;
+      }
+
+      let Some(modified) = entry.metadata().ok().and_then(|metadata| metadata.modified().ok()) else {
+        continue;
+      };
+      let modified = chrono::DateTime::<Utc>::from(modified);
+      if modified < cutoff {
+        continue;
+      }
+
+      let Ok(relative_path) = entry_path.strip_prefix(root) else {
+        continue;
+      };
+      let key = (
+        modified.format("%Y-%m-%d").to_string(),
+        activity_directory(relative_path)
+      );
+      *counts.entry(key).or_default() += 1;
+    }
+  }
+
+  false
+}
+
+/// Clamps a requested niceness into the range the kernel accepts, noting any adjustment.
+fn requested_priority(
+  nice: Option<i32>,
+  ionice_class: Option<IoniceClass>,
+  warnings: &mut Vec<String>
+) -> TerminalPriority {
+  let nice = nice.map(|requested| {
+    let clamped = requested.clamp(-20, 19);
+    if clamped != requested {
+      warnings.push(format!("nice {requested} is outside -20..19; using {clamped}"));
+    }
+    clamped
+  });
+  TerminalPriority { nice, ionice_class }
+}
+
+fn apply_terminal_priority(
+  targets: &[PriorityTarget],
+  priority: TerminalPriority,
+  mut warnings: Vec<String>
+) -> TerminalPriorityResult {
+  let nice_applied = priority.nice.is_some_and(|nice| {
+    apply_to_targets(targets, &format!("nice {nice}"), &mut warnings, |target| set_niceness(target, nice))
+  });
+  let ionice_applied = priority.ionice_class.is_some_and(|class| {
+    apply_to_targets(targets, &format!("I/O class {class:?}"), &mut warnings, |target| {
+      set_io_priority(target, class)
+    })
+  });
+
+  TerminalPriorityResult {
+    nice: priority.nice,
+    nice_applied,
+    ionice_class: priority.ionice_class,
+    ionice_applied,
+    warnings
+  }
+}
+
+fn apply_to_targets(
+  targets: &[PriorityTarget],
+  setting: &str,
+  warnings: &mut Vec<String>,
+  set: impl Fn(PriorityTarget) -> Result<(), String>
+) -> bool {
+  let failures: Vec<String> = targets.iter().filter_map(|&target| set(target).err()).collect();
+  let applied = failures.is_empty();
+  warnings.extend(failures.into_iter().map(|error| format!("could not set {setting}: {error}")));
+  applied
+}
+
+/// Every process group in the terminal's session. An interactive shell puts each job in its
+/// own group, so the shell's group alone would miss a running build.
+fn terminal_process_groups(pid: u32) -> Vec<PriorityTarget> {
+  let mut groups: Vec<u32> = fs::read_dir("/proc")
+    .map(|entries| {
+      entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| parse_process_stat(&fs::read_to_string(entry.path().join("stat")).ok()?))
+        .filter(|stat| stat.session == pid)
+        .map(|stat| stat.process_group)
+        .collect()
+    })
+    .unwrap_or_default();
+  if groups.is_empty() {
+    groups.push(pid);
+  }
+  groups.sort_unstable();
+  groups.dedup();
+  groups.into_iter().map(PriorityTarget::Group).collect()
+}
+
+#[cfg(unix)]
+fn set_niceness(target: PriorityTarget, nice: i32) -> Result<(), String> {
+  use nix::libc;
+
+  let (which, id) = match target {
+    PriorityTarget::Process(pid) => (libc::PRIO_PROCESS, pid),
+    PriorityTarget::Group(group) => (libc::PRIO_PGRP, group)
+  };
+  // SAFETY: setpriority only reads its integer arguments.
+  if unsafe { libc::setpriority(which, id as libc::id_t, nice) } == 0 {
+    Ok(())
+  } else {
+    Err(nix::errno::Errno::last().desc().to_string())
+  }
+}
+
+#[cfg(not(unix))]
+fn set_niceness(_target: PriorityTarget, _nice: i32) -> Result<(), String> {
+  Err(String::from("process niceness is not supported on this platform"))
+}
+
+#[cfg(target_os = "linux")]
+fn set_io_priority(target: PriorityTarget, class: IoniceClass) -> Result<(), String> {
+  use nix::libc;
+
+  const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+  const IOPRIO_WHO_PGRP: libc::c_int = 2;
+  const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+  let (who, id) = match target {
+    PriorityTarget::Process(pid) => (IOPRIO_WHO_PROCESS, pid),
+    PriorityTarget::Group(group) => (IOPRIO_WHO_PGRP, group)
+  };
+  // Realtime and best-effort take a level from 0 (highest) to 7; 4 is the kernel default.
+  let (class, level) = match class {
+    IoniceClass::Realtime => (1, 4),
+    IoniceClass::BestEffort => (2, 4),
+    IoniceClass::Idle => (3, 0)
+  };
+  // SAFETY: ioprio_set takes three integers and touches no memory of ours.
+  let result = unsafe { libc::syscall(libc::SYS_ioprio_set, who, id as libc::c_int, (class << IOPRIO_CLASS_SHIFT) | level) };
+  if result == 0 {
+    Ok(())
+  } else {
+    Err(nix::errno::Errno::last().desc().to_string())
+  }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_io_priority(_target: PriorityTarget, _class: IoniceClass) -> Result<(), String> {
+  Err(String::from("I/O priority classes are only supported on Linux"))
+}
+
+fn spawn_terminal_process(launch: &TerminalLaunchConfig, size: PtySize) -> Result<SpawnedTerminal, String> {
+  let pty_system = native_pty_system();
+  let pty_pair = pty_system
+    .openpty(size)
+    .map_err(|error| format!("failed to open PTY: {error}"))?;
+
+  let mut command = CommandBuilder::new(launch.shell.clone());
+  command.args(&launch.args);
+  command.cwd(&launch.cwd);
+
+  for (key, value) in std::env::vars() {
+    command.env(key, value);
+  }
+
+  // Apps launched from a desktop entry often inherit TERM=dumb and no locale, which breaks
+  // ncurses programs and line-drawing characters.
+  command.env("TERM", &launch.term);
+  command.env("COLORTERM", "truecolor");
+
+  let has_locale = ["LC_ALL", "LANG"]
+    .iter()
+    .any(|key| std::env::var(key).is_ok_and(|value| !value.is_empty()));
+  if !has_locale {
+    command.env("LANG", FALLBACK_UTF8_LOCALE);
+  }
+
+  for (key, value) in &launch.env {
+    command.env(key, value);
+  }
+
+  let master = pty_pair.master;
+  let reader = master
+    .try_clone_reader()
+    .map_err(|error| format!("failed to clone PTY reader: {error}"))?;
+  let writer = master
+    .take_writer()
+    .map_err(|error| format!("failed to take PTY writer: {error}"))?;
+  set_pty_nonblocking(master.as_ref())?;
+  let child = pty_pair
+    .slave
+    .spawn_command(command)
+    .map_err(|error| message_error("SPAWN_FAILED", "terminal.spawnFailed", &[("error", error.to_string())]))?;
+  let pid = child.process_id().unwrap_or_default();
+
+  Ok(SpawnedTerminal {
+    master,
+    reader,
+    writer,
+    child: ChildReaper(child),
+    pid
+  })
+}
+
+/// Warns about sessions idle past the configured timeout and kills them once the grace period
+/// has also run out. Sessions created with `keepAlive` are never touched.
+fn sweep_idle_terminals(app: &AppHandle) {
+  let settings_state = app.state::<SettingsState>();
+  let Some((timeout_minutes, grace_minutes)) = settings_state
+    .settings
+    .lock()
+    .ok()
+    .and_then(|settings| {
+      settings
+        .terminal_idle_timeout_minutes
+        .map(|timeout| (timeout, settings.terminal_idle_grace_minutes))
+    })
+  else {
+    return;
+  };
+  let timeout_ms = timeout_minutes.max(1).saturating_mul(60_000);
+  let grace_ms = grace_minutes.saturating_mul(60_000);
+
+  let state = app.state::<AppState>();
+  let sessions: Vec<(String, TerminalSession)> = lock_recover(&state.sessions)
+    .iter()
+    .filter(|(_, session)| !session.keep_alive && lock_recover(&session.exit_code).is_none())
+    .map(|(session_id, session)| (session_id.clone(), session.clone()))
+    .collect();
+
+  let now_ms = unix_time_ms();
+  for (session_id, session) in sessions {
+    let idle_ms = now_ms.saturating_sub(session.last_activity_ms.load(Ordering::Relaxed));
+    if idle_ms < timeout_ms {
+      session.idle_warned.store(false, Ordering::Relaxed);
+    } else if idle_ms >= timeout_ms.saturating_add(grace_ms) {
+      if let Err(message) = kill_terminal_session(&state.sessions, &session_id) {
+        eprintln!("{message}");
+      }
+    } else if !session.idle_warned.swap(true, Ordering::Relaxed) {
+      let _ = emit_to_session_window(
+        app,
+        &session,
+        TERMINAL_IDLE_EVENT,
+        TerminalIdleEvent {
+          session_id,
+          idle_secs: idle_ms / 1000,
+          kill_in_secs: (timeout_ms.saturating_add(grace_ms) - idle_ms) / 1000
+        }
+      );
+    }
+  }
+}
+
+fn publish_terminal_output(app: &AppHandle, session_id: &str, session: &TerminalSession, chunk: &[u8]) {
+  append_terminal_log(app, session_id, session, chunk);
+  // Hidden sessions keep filling the scrollback; terminal_set_visibility replays it.
+  let is_visible = {
+    let mut scrollback = lock_recover(&session.scrollback);
+    scrollback.append(chunk);
+    session.visible.load(Ordering::Relaxed)
+  };
+  if is_visible {
+    let payload = TerminalOutputEvent {
+      session_id: session_id.to_string(),
+      data: String::from_utf8_lossy(chunk).into_owned()
+    };
+    let _ = emit_to_session_window(app, session, TERMINAL_OUTPUT_EVENT, payload);
+    session.output_events.fetch_add(1, Ordering::Relaxed);
+  }
+}
+
+/// Forwards one process's output until its PTY closes and returns the exit code to report.
+fn pump_terminal_output(app: &AppHandle, session_id: &str, session: &TerminalSession, reader: &mut dyn Read) {
+  let mut buffer = [0_u8; 8192];
+  let mut pending_mode_bytes = Vec::new();
+  let mut osc_parser = OscParser::default();
+  let mut startup_command = session.launch.startup_command.clone();
+  let pty_fd = pty_raw_fd(session);
+
+  loop {
+    match reader.read(&mut buffer) {
+      Ok(0) => return,
+      Ok(bytes_read) => {
+        session.bytes_read.fetch_add(bytes_read as u64, Ordering::Relaxed);
+        session.last_activity_ms.store(unix_time_ms(), Ordering::Relaxed);
+        track_bracketed_paste(&mut pending_mode_bytes, &buffer[..bytes_read], &session.bracketed_paste);
+        emit_shell_integration_events(app, session_id, session, &mut osc_parser, &buffer[..bytes_read]);
+        publish_terminal_output(app, session_id, session, &buffer[..bytes_read]);
+
+        // The first output means the shell is up, so the profile's startup command won't be
+        // swallowed by its initialization.
+        if let Some(command) = startup_command.take() {
+          if let Err(message) = write_to_session(session, format!("{command}\r").as_bytes()) {
+            eprintln!("{message}");
+          }
+        }
+      }
+      Err(error) => {
+        if error.kind() == ErrorKind::Interrupted {
+          continue;
+        }
+        // The PTY is non-blocking for the sake of writers; wait until there is output.
+        if error.kind() == ErrorKind::WouldBlock {
+          wait_for_pty_output(pty_fd);
+          continue;
+        }
+        return;
+      }
+    }
+  }
+}
+
+#[cfg(unix)]
+fn set_pty_nonblocking(master: &(dyn portable_pty::MasterPty + Send)) -> Result<(), String> {
+  use nix::fcntl::{fcntl, FcntlArg, OFlag};
+
+  let Some(fd) = master.as_raw_fd() else {
+    return Ok(());
+  };
+  let flags = fcntl(fd, FcntlArg::F_GETFL).map_err(|error| format!("failed to read PTY flags: {error}"))?;
+  fcntl(fd, FcntlArg::F_SETFL(OFlag::from_bits_truncate(flags) | OFlag::O_NONBLOCK))
+    .map_err(|error| format!("failed to make the PTY non-blocking: {error}"))?;
+  Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_pty_nonblocking(_master: &(dyn portable_pty::MasterPty + Send)) -> Result<(), String> {
+  Ok(())
+}
+
+#[cfg(unix)]
+fn pty_raw_fd(session: &TerminalSession) -> Option<i32> {
+  session.handles.resize.raw_fd()
+}
+
+#[cfg(not(unix))]
+fn pty_raw_fd(_session: &TerminalSession) -> Option<i32> {
+  None
+}
+
+/// Blocks until the PTY has output or has closed; the read that follows reports which.
+#[cfg(unix)]
+fn wait_for_pty_output(fd: Option<i32>) {
+  use nix::poll::{poll, PollFd, PollFlags};
+
+  match fd {
+    Some(fd) => {
+      let _ = poll(&mut [PollFd::new(fd, PollFlags::POLLIN)], -1);
+    }
+    None => std::thread::sleep(TERMINAL_WRITE_RETRY_INTERVAL)
+  }
+}
+
+#[cfg(not(unix))]
+fn wait_for_pty_output(_fd: Option<i32>) {
+  std::thread::sleep(TERMINAL_WRITE_RETRY_INTERVAL);
+}
+
+/// Waits for a session's process once its PTY has closed so it doesn't linger as a zombie.
+/// The child lock is only held per poll, so terminal_kill can still get in. Returns the exit
+/// code and, when a signal ended the process, its number; the code is then `128 + signal` as
+/// a shell would report it.
+fn reap_terminal_child(session: &TerminalSession) -> (i32, Option<u32>) {
+  loop {
+    match lock_recover(&session.handles.child).try_wait() {
+      Ok(Some(status)) => {
+        return match exit_signal(&status) {
+          Some(signal) => (128 + signal as i32, Some(signal)),
+          None => (status.exit_code() as i32, None)
+        };
+      }
+      Ok(None) => std::thread::sleep(TERMINAL_REAP_POLL_INTERVAL),
+      Err(_) => return (-1, None)
+    }
+  }
+}
+
+/// portable-pty keeps only the `strsignal` description of a terminating signal, so it is
+/// matched back to the signal's number.
+#[cfg(unix)]
+fn exit_signal(status: &portable_pty::ExitStatus) -> Option<u32> {
+  let status = status.to_string();
+  let description = status.strip_prefix("Terminated by ")?;
+  nix::sys::signal::Signal::iterator()
+    .find(|&signal| {
+      let name = unsafe { nix::libc::strsignal(signal as nix::libc::c_int) };
+      !name.is_null() && unsafe { std::ffi::CStr::from_ptr(name) }.to_string_lossy() == description
+    })
+    .map(|signal| signal as u32)
+    .or_else(|| description.strip_prefix("Signal ")?.parse().ok())
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: &portable_pty::ExitStatus) -> Option<u32> {
+  None
+}
+
+/// Respawns the process of a session whose shell exited, if its restart policy allows another
+/// attempt. The new PTY replaces the old one in place, so the session id stays valid.
+fn restart_terminal_process(
+  app: &AppHandle,
+  sessions: &Mutex<HashMap<String, TerminalSession>>,
+  session_id: &str,
+  session: &TerminalSession,
+  attempt: u32
+) -> Option<Box<dyn Read + Send>> {
+  if !session.restart_on_exit.load(Ordering::Relaxed) {
+    return None;
+  }
+  if session.restart_policy.max_restarts.is_some_and(|max| attempt > max) {
+    return None;
+  }
+
+  let delay = session
+    .restart_policy
+    .backoff
+    .saturating_mul(1 << (attempt - 1).min(16))
+    .min(TERMINAL_RESTART_BACKOFF_MAX);
+  std::thread::sleep(delay);
+
+  // terminal_kill may have run while we were backing off.
+  let still_open = lock_recover(sessions).contains_key(session_id);
+  if !still_open || !session.restart_on_exit.load(Ordering::Relaxed) {
+    return None;
+  }
+
+  let spawned = match spawn_terminal_process(&session.launch, current_pty_size(session)) {
+    Ok(spawned) => spawned,
+    Err(message) => {
+      eprintln!("{message}");
+      return None;
+    }
+  };
+
+  session.handles.resize.replace(spawned.master);
+  let mut writer = lock_recover(&session.handles.writer);
+  let mut child = lock_recover(&session.handles.child);
+  *writer = spawned.writer;
+  // Dropping the old reaper kills the previous process if needed and reaps it.
+  *child = spawned.child;
+  drop((writer, child));
+
+  session.pid.store(spawned.pid, Ordering::Relaxed);
+  session.bracketed_paste.store(false, Ordering::Relaxed);
+  session.pty_closed.store(false, Ordering::SeqCst);
+  let priority = *lock_recover(&session.priority);
+  if priority.nice.is_some() || priority.ionice_class.is_some() {
+    let result = apply_terminal_priority(&[PriorityTarget::Process(spawned.pid)], priority, Vec::new());
+    for warning in result.warnings {
+      eprintln!("{warning}");
+    }
+  }
+  let marker = format!("\r\n[openspace: process restarted (attempt {attempt})]\r\n");
+  publish_terminal_output(app, session_id, session, marker.as_bytes());
+  let _ = emit_replayable_to_session(
+    app,
+    session,
+    TERMINAL_RESTARTED_EVENT,
+    TerminalRestartedEvent {
+      session_id: session_id.to_string(),
+      attempt,
+      pid: spawned.pid
+    }
+  );
+
+  Some(spawned.reader)
+}
+
+/// The attempt number for the restart after a process that ran for `uptime`.
+fn next_restart_attempt(previous: u32, uptime: Duration) -> u32 {
+  if uptime >= TERMINAL_RESTART_STABLE_UPTIME {
+    1
+  } else {
+    previous.saturating_add(1)
+  }
+}
+
+fn run_terminal_session(
+  app: AppHandle,
+  sessions: Arc<Mutex<HashMap<String, TerminalSession>>>,
+  session_id: String,
+  session: TerminalSession,
+  mut reader: Box<dyn Read + Send>
+) {
+  let outcome = std::panic::catch_unwind(AssertUnwindSafe(|| {
+    let mut attempt = 0_u32;
+    loop {
+      let started = Instant::now();
+      pump_terminal_output(&app, &session_id, &session, reader.as_mut());
+      session.pty_closed.store(true, Ordering::SeqCst);
+      let exit_status = reap_terminal_child(&session);
+      attempt = next_restart_attempt(attempt, started.elapsed());
+      match restart_terminal_process(&app, &sessions, &session_id, &session, attempt) {
+        Some(next_reader) => reader = next_reader,
+        None => break exit_status
+      }
+    }
+  }));
+  let ((exit_code, signal), error) = match outcome {
+    Ok(exit_status) => (exit_status, None),
+    Err(panic) => {
+      // Nothing reads the PTY anymore, so the process would block once its output fills up.
+      session.restart_on_exit.store(false, Ordering::Relaxed);
+      let mut child = lock_recover(&session.handles.child);
+      let _ = child.kill();
+      let _ = child.wait();
+      (
+        (-1, None),
+        Some(format!("terminal reader stopped unexpectedly: {}", panic_message(panic.as_ref())))
+      )
+    }
+  };
+
+  // Keep the exited session around so exit handlers can still inspect it.
+  *lock_recover(&session.exit_code) = Some(exit_code);
+
+  if let Err(message) = close_terminal_log(&session) {
+    eprintln!("{message}");
+  }
+
+  let _ = emit_replayable_to_session(
+    &app,
+    &session,
+    TERMINAL_EXIT_EVENT,
+    TerminalExitEvent {
+      session_id: session_id.clone(),
+      exit_code,
+      signal,
+      error
+    }
+  );
+
+  std::thread::sleep(TERMINAL_EXITED_RETENTION);
+  lock_recover(&sessions).remove(&session_id);
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+  panic
+    .downcast_ref::<&str>()
+    .copied()
+    .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+    .unwrap_or("unknown panic")
+}
+
+/// The session's PTY size, with the pixel size it was last given.
+fn current_pty_size(session: &TerminalSession) -> PtySize {
+  session.handles.resize.size()
+}
+
+/// Where a duplicate of the session should start: the directory shell integration last
+/// reported, else the process's current directory where the OS exposes it, else where it started.
+fn current_terminal_cwd(session: &TerminalSession) -> PathBuf {
+  if let Some(cwd) = lock_recover(&session.cwd).as_deref().map(PathBuf::from) {
+    if cwd.is_dir() {
+      return cwd;
+    }
+  }
+  #[cfg(target_os = "linux")]
+  {
+    let pid = session.pid.load(Ordering::Relaxed);
+    if let Ok(cwd) = fs::read_link(format!("/proc/{pid}/cwd")) {
+      if cwd.is_dir() {
+        return cwd;
+      }
+    }
+  }
+  session.launch.cwd.clone()
+}
+
+/// Spawns the process, registers the session for `window_label` and starts its reader thread.
+fn start_terminal_session(
+  app: &AppHandle,
+  state: &AppState,
+  window_label: &str,
+  launch: TerminalLaunchConfig,
+  size: PtySize,
+  options: TerminalSessionOptions
+) -> Result<(String, u32), String> {
+  let session_id = Uuid::new_v4().to_string();
+  let spawned = spawn_terminal_process(&launch, size)?;
+  let pid = spawned.pid;
+
+  let session = TerminalSession {
+    handles: TerminalSessionHandles {
+      resize: Arc::new(PtyResizeHandle::new(spawned.master, size)),
+      writer: Arc::new(Mutex::new(spawned.writer)),
+      child: Arc::new(Mutex::new(spawned.child))
+    },
+    bracketed_paste: Arc::new(AtomicBool::new(false)),
+    log: Arc::new(Mutex::new(None)),
+    bytes_written: Arc::new(AtomicU64::new(0)),
+    bytes_read: Arc::new(AtomicU64::new(0)),
+    output_events: Arc::new(AtomicU64::new(0)),
+    last_activity_ms: Arc::new(AtomicU64::new(unix_time_ms())),
+    created_at: Instant::now(),
+    pid: Arc::new(AtomicU32::new(pid)),
+    launch: Arc::new(launch),
+    restart_on_exit: Arc::new(AtomicBool::new(options.restart_on_exit)),
+    restart_policy: options.restart_policy,
+    keep_alive: options.keep_alive,
+    idle_warned: Arc::new(AtomicBool::new(false)),
+    scrollback: Arc::new(Mutex::new(ScrollbackBuffer::new(TERMINAL_SCROLLBACK_MAX_BYTES))),
+    visible: Arc::new(AtomicBool::new(true)),
+    exit_code: Arc::new(Mutex::new(None)),
+    pty_closed: Arc::new(AtomicBool::new(false)),
+    window_label: Arc::new(Mutex::new(window_label.to_string())),
+    priority: Arc::new(Mutex::new(options.priority)),
+    cwd: Arc::new(Mutex::new(None))
+  };
+
+  {
+    let mut sessions = lock_recover(&state.sessions);
+    sessions.insert(session_id.clone(), session.clone());
+  }
+
+  let sessions_for_thread = state.sessions.clone();
+  let app_for_thread = app.clone();
+  let session_id_for_thread = session_id.clone();
+  let reader = spawned.reader;
+
+  std::thread::spawn(move || {
+    run_terminal_session(app_for_thread, sessions_for_thread, session_id_for_thread, session, reader)
+  });
+
+  Ok((session_id, pid))
+}
+
+#[tauri::command]
+fn terminal_create(
+  window: WebviewWindow,
+  app: AppHandle,
+  state: State<'_, AppState>,
+  startup_context: State<'_, StartupContext>,
+  request: TerminalCreateRequest
+) -> Result<TerminalCreateResponse, String> {
+  ensure_not_closing(&app)?;
+  let workspace_root = current_workspace_root(&startup_context)?;
+  let profile = match &workspace_root {
+    Some(root) => load_terminal_profiles(&app)?.remove(root).unwrap_or_default(),
+    None => TerminalProfile::default()
+  };
+
+  // Profile args are meant for the profile's shell, not one the request picked explicitly.
+  let profile_args = if request.shell.is_some() { None } else { profile.args };
+  let shell = request.shell.or(profile.shell).unwrap_or_else(default_shell);
+  let args = request
+    .args
+    .or(profile_args)
+    .unwrap_or_else(|| default_shell_args(&shell));
+  let (shell, args, builtin) = resolve_launch_shell(shell, args)?;
+  let cwd = request
+    .cwd
+    .as_deref()
+    .map(resolve_path)
+    .transpose()?
+    .unwrap_or(std::env::current_dir().map_err(|error| io_error("failed to resolve cwd", error))?);
+  let term = request
+    .term_name
+    .filter(|name| !name.trim().is_empty())
+    .unwrap_or_else(|| String::from(DEFAULT_TERM_NAME));
+
+  let remembered_layout = match (&workspace_root, request.cols.zip(request.rows)) {
+    (Some(root), None) => remembered_terminal_layout(&app, root)?,
+    _ => None
+  };
+  let (cols, rows, size_source) = match remembered_layout {
+    Some(layout) => (
+      request.cols.unwrap_or(layout.cols),
+      request.rows.unwrap_or(layout.rows),
+      "workspace"
+    ),
+    None if request.cols.is_some() || request.rows.is_some() => (
+      request.cols.unwrap_or(TERMINAL_DEFAULT_COLS),
+      request.rows.unwrap_or(TERMINAL_DEFAULT_ROWS),
+      "request"
+    ),
+    None => (TERMINAL_DEFAULT_COLS, TERMINAL_DEFAULT_ROWS, "default")
+  };
+  let (cols, rows) = clamp_terminal_size(cols, rows);
+  let pixel_width = request.pixel_width.unwrap_or(0);
+  let pixel_height = request.pixel_height.unwrap_or(0);
+
+  let launch = TerminalLaunchConfig {
+    shell,
+    args,
+    cwd,
+    term: term.clone(),
+    env: profile.env,
+    startup_command: profile.startup_command.filter(|command| !command.trim().is_empty()),
+    builtin
+  };
+  let mut priority_warnings = Vec::new();
+  let priority = requested_priority(request.nice, request.ionice_class, &mut priority_warnings);
+  let (session_id, pid) = start_terminal_session(
+    &app,
+    &state,
+    window.label(),
+    launch,
+    PtySize {
+      rows,
+      cols,
+      pixel_width,
+      pixel_height
+    },
+    TerminalSessionOptions {
+      restart_on_exit: request.restart_on_exit.unwrap_or(false),
+      restart_policy: TerminalRestartPolicy {
+        max_restarts: request.max_restarts,
+        backoff: request
+          .restart_backoff_ms
+          .map(Duration::from_millis)
+          .unwrap_or(TERMINAL_RESTART_BACKOFF_DEFAULT)
+      },
+      keep_alive: request.keep_alive.unwrap_or(false),
+      priority
+    }
+  )?;
+  let priority_result = (priority.nice.is_some() || priority.ionice_class.is_some())
+    .then(|| apply_terminal_priority(&[PriorityTarget::Process(pid)], priority, priority_warnings));
+
+  Ok(TerminalCreateResponse {
+    session_id,
+    pid,
+    term,
+    cols,
+    rows,
+    size_source: String::from(size_source),
+    priority: priority_result
+  })
+}
+
+/// Writes to the session's PTY, giving up with `WRITE_TIMEOUT` after `TERMINAL_WRITE_TIMEOUT`
+/// rather than blocking on a PTY nobody drains. Bytes the PTY did accept before a failure still
+/// count towards `bytes_written`, and the error reports how many there were.
+fn write_to_session(session: &TerminalSession, data: &[u8]) -> Result<(), String> {
+  if let Some(exit_code) = *lock_recover(&session.exit_code) {
+    return Err(message_error("EXITED", "terminal.exited", &[("exitCode", exit_code.to_string())]));
+  }
+  if session.pty_closed.load(Ordering::SeqCst) {
+    return Err(message_error("EXITED", "terminal.closed", &[]));
+  }
+
+  let deadline = Instant::now() + TERMINAL_WRITE_TIMEOUT;
+  let mut writer = loop {
+    match session.handles.writer.try_lock() {
+      Ok(writer) => break writer,
+      Err(std::sync::TryLockError::Poisoned(poisoned)) => break poisoned.into_inner(),
+      Err(std::sync::TryLockError::WouldBlock) if Instant::now() < deadline => {
+        std::thread::sleep(TERMINAL_WRITE_RETRY_INTERVAL);
+      }
+      Err(std::sync::TryLockError::WouldBlock) => return Err(write_timeout_error(0, data.len()))
+    }
+  };
+
+  let mut written = 0;
+  let result = write_pty_data(&mut **writer, data, deadline, &session.pty_closed, &mut written);
+  session.bytes_written.fetch_add(written as u64, Ordering::Relaxed);
+  if written > 0 {
+    session.last_activity_ms.store(unix_time_ms(), Ordering::Relaxed);
+  }
+  result
+}
+
+fn write_timeout_error(written: usize, total: usize) -> String {
+  message_error(
+    "WRITE_TIMEOUT",
+    "terminal.writeTimeout",
+    &[
+      ("timeoutMs", TERMINAL_WRITE_TIMEOUT.as_millis().to_string()),
+      ("bytesWritten", written.to_string()),
+      ("totalBytes", total.to_string())
+    ]
+  )
+}
+
+/// The write loop behind `write_to_session`, tracking progress in `written`. The PTY is
+/// non-blocking, so a full buffer shows up as `WouldBlock` and is retried until `deadline`;
+/// a PTY that closes meanwhile ends the write with `EXITED`.
+fn write_pty_data<W: Write + ?Sized>(
+  writer: &mut W,
+  data: &[u8],
+  deadline: Instant,
+  pty_closed: &AtomicBool,
+  written: &mut usize
+) -> Result<(), String> {
+  while *written < data.len() {
+    match writer.write(&data[*written..]) {
+      Ok(0) => return Err(String::from("failed to write to PTY: it accepted no data")),
+      Ok(count) => *written += count,
+      Err(error) if error.kind() == ErrorKind::Interrupted => {}
+      Err(error) if error.kind() == ErrorKind::WouldBlock => {
+        if pty_closed.load(Ordering::SeqCst) {
+          return Err(message_error(
+            "EXITED",
+            "terminal.closedDuringWrite",
+            &[("bytesWritten", written.to_string()), ("totalBytes", data.len().to_string())]
+          ));
+        }
+        if Instant::now() >= deadline {
+          return Err(write_timeout_error(*written, data.len()));
+        }
+        std::thread::sleep(TERMINAL_WRITE_RETRY_INTERVAL);
+      }
+      Err(error) => return Err(io_error("failed to write to PTY", error))
+    }
+  }
+  writer
+    .flush()
+    .map_err(|error| io_error("failed to flush PTY writer", error))
+}
+
+#[tauri::command]
+fn terminal_write(window: WebviewWindow, state: State<'_, AppState>, request: TerminalWriteRequest) -> Result<(), String> {
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  write_to_session(&session, request.data.as_bytes())
+}
+
+#[tauri::command]
+fn terminal_write_bytes(window: WebviewWindow, state: State<'_, AppState>, request: TerminalWriteBytesRequest) -> Result<(), String> {
+  let data = BASE64
+    .decode(request.data_base64.as_bytes())
+    .map_err(|error| format!("failed to decode base64 terminal input: {error}"))?;
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  write_to_session(&session, &data)
+}
+
+#[tauri::command]
+fn terminal_broadcast(
+  window: WebviewWindow,
+  state: State<'_, AppState>,
+  request: TerminalBroadcastRequest
+) -> Result<HashMap<String, TerminalBroadcastResult>, String> {
+  let targets: Vec<(String, Option<TerminalSession>)> = {
+    let sessions = lock_recover(&state.sessions);
+
+    match request.session_ids {
+      TerminalBroadcastTargets::Keyword(keyword) if keyword == "all" => sessions
+        .iter()
+        .filter(|(_, session)| session_owned_by(session, window.label()))
+        .map(|(session_id, session)| (session_id.clone(), Some(session.clone())))
+        .collect(),
+      TerminalBroadcastTargets::Keyword(keyword) => {
+        return Err(format!(
+          "unknown broadcast target \"{keyword}\"; pass \"all\" or a list of session ids"
+        ))
+      }
+      TerminalBroadcastTargets::Sessions(session_ids) => session_ids
+        .into_iter()
+        .map(|session_id| {
+          let session = sessions
+            .get(&session_id)
+            .filter(|session| session_owned_by(session, window.label()))
+            .cloned();
+          (session_id, session)
+        })
+        .collect()
+    }
+  };
+
+  // Each write runs on its own thread so a session whose writer is wedged on a full PTY
+  // only costs the deadline instead of blocking every other session.
+  let data = Arc::new(request.data.into_bytes());
+  let (sender, receiver) = std::sync::mpsc::channel();
+  let mut results = HashMap::new();
+  let mut pending_ids = Vec::new();
+
+  for (session_id, session) in targets {
+    let Some(session) = session else {
+      results.insert(
+        session_id.clone(),
+        TerminalBroadcastResult {
+          ok: false,
+          error: Some(terminal_not_found(&session_id))
+        }
+      );
+      continue;
+    };
+
+    pending_ids.push(session_id.clone());
+    let sender = sender.clone();
+    let data = data.clone();
+    std::thread::spawn(move || {
+      let result = write_to_session(&session, &data);
+      let _ = sender.send((session_id, result));
+    });
+  }
+  drop(sender);
+
+  let deadline = Instant::now() + TERMINAL_BROADCAST_TIMEOUT;
+  let mut outstanding = pending_ids.len();
+  while outstanding > 0 {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    let Ok((session_id, result)) = receiver.recv_timeout(remaining) else {
+      break;
+    };
+    outstanding -= 1;
+    results.insert(
+      session_id,
+      TerminalBroadcastResult {
+        ok: result.is_ok(),
+        error: result.err()
+      }
+    );
+  }
+
+  for session_id in pending_ids {
+    results.entry(session_id).or_insert_with(|| TerminalBroadcastResult {
+      ok: false,
+      error: Some(String::from("timed out waiting for the terminal writer"))
+    });
+  }
+
+  Ok(results)
+}
+
+/// Removes every end-of-paste marker from `data`. An embedded marker would let pasted text
+/// escape the paste and run as typed input, and removing one can join the text around it into
+/// another, so this repeats until none is left.
+fn strip_bracketed_paste_end(data: &str) -> String {
+  let mut data = data.to_string();
+  while data.contains(BRACKETED_PASTE_END) {
+    data = data.replace(BRACKETED_PASTE_END, "");
+  }
+  data
+}
+
+#[tauri::command]
+fn terminal_paste(window: WebviewWindow, state: State<'_, AppState>, request: TerminalPasteRequest) -> Result<(), String> {
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  if !session.bracketed_paste.load(Ordering::Relaxed) {
+    return write_to_session(&session, request.data.as_bytes());
+  }
+
+  let data = strip_bracketed_paste_end(&request.data);
+  let payload = format!("{BRACKETED_PASTE_START}{data}{BRACKETED_PASTE_END}");
+  write_to_session(&session, payload.as_bytes())
+}
+
+fn load_terminal_profiles(app: &AppHandle) -> Result<HashMap<String, TerminalProfile>, String> {
+  let path = persistence_file_path(app, TERMINAL_PROFILES_FILE_NAME)?;
+  read_json_or_default(&path, HashMap::new())
+}
+
+fn remembered_terminal_layout(app: &AppHandle, root: &str) -> Result<Option<TerminalLayout>, String> {
+  let path = persistence_file_path(app, TERMINAL_LAYOUTS_FILE_NAME)?;
+  let store = app.state::<TerminalLayoutStore>();
+  let mut layouts = loaded_terminal_layouts(&store, &path)?;
+  Ok(layouts.as_mut().and_then(|layouts| layouts.get(root).copied()))
+}
+
+/// The store's layouts, read from `path` if this is the first use.
+fn loaded_terminal_layouts<'a>(
+  store: &'a TerminalLayoutStore,
+  path: &Path
+) -> Result<MutexGuard<'a, Option<HashMap<String, TerminalLayout>>>, String> {
+  let mut layouts = lock_recover(&store.layouts);
+  if layouts.is_none() {
+    *layouts = Some(read_json_or_default(path, HashMap::new())?);
+  }
+  Ok(layouts)
+}
+
+/// Records `layout` for `root` in memory. Returns true when it changed something and no flush
+/// was pending yet, i.e. when the caller should schedule one.
+fn store_terminal_layout(store: &TerminalLayoutStore, path: &Path, root: String, layout: TerminalLayout) -> Result<bool, String> {
+  if layout.cols < TERMINAL_REMEMBERED_MIN_COLS || layout.rows < TERMINAL_REMEMBERED_MIN_ROWS {
+    return Ok(false);
+  }
+  let mut layouts = loaded_terminal_layouts(store, path)?;
+  let layouts = layouts.get_or_insert_with(HashMap::new);
+  if layouts.get(&root) == Some(&layout) {
+    return Ok(false);
+  }
+  layouts.insert(root, layout);
+  Ok(!store.pending.swap(true, Ordering::SeqCst))
+}
+
+/// Writes the layouts out if any change is still pending.
+fn flush_terminal_layouts(store: &TerminalLayoutStore, path: &Path) -> Result<(), String> {
+  let _guard = lock_recover(&store.write_lock);
+  if !store.pending.swap(false, Ordering::SeqCst) {
+    return Ok(());
+  }
+  let layouts = lock_recover(&store.layouts).clone().unwrap_or_default();
+  write_json(path, &layouts)
+}
+
+fn remember_terminal_layout(app: &AppHandle, root: String, layout: TerminalLayout) -> Result<(), String> {
+  let path = persistence_file_path(app, TERMINAL_LAYOUTS_FILE_NAME)?;
+  if store_terminal_layout(&app.state::<TerminalLayoutStore>(), &path, root, layout)? {
+    let app = app.clone();
+    std::thread::spawn(move || {
+      std::thread::sleep(TERMINAL_LAYOUT_FLUSH_DELAY);
+      if let Err(message) = flush_terminal_layouts(&app.state::<TerminalLayoutStore>(), &path) {
+        eprintln!("{message}");
+      }
+    });
+  }
+  Ok(())
+}
+
+fn clamp_terminal_size(cols: u16, rows: u16) -> (u16, u16) {
+  (cols.clamp(1, TERMINAL_MAX_COLS), rows.clamp(1, TERMINAL_MAX_ROWS))
+}
+
+/// Profiles are keyed by canonical workspace root; `None` means the active root.
+fn terminal_profile_key(startup_context: &StartupContext, root_path: Option<&str>) -> Result<String, String> {
+  match root_path {
+    Some(root_path) => Ok(canonicalize_lenient(&resolve_path(root_path)?)?
+      .to_string_lossy()
+      .into_owned()),
+    None => current_workspace_root(startup_context)?
+      .ok_or_else(|| String::from("no workspace root is active"))
+  }
+}
+
+#[tauri::command]
+fn terminal_profile_get(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  request: TerminalProfileGetRequest
+) -> Result<Option<TerminalProfile>, String> {
+  let key = terminal_profile_key(&startup_context, request.root_path.as_deref())?;
+  Ok(load_terminal_profiles(&app)?.remove(&key))
+}
+
+#[tauri::command]
+fn terminal_profile_set(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  request: TerminalProfileSetRequest
+) -> Result<Option<TerminalProfile>, String> {
+  ensure_not_closing(&app)?;
+  let key = terminal_profile_key(&startup_context, request.root_path.as_deref())?;
+  let mut profiles = load_terminal_profiles(&app)?;
+  match request.profile.clone() {
+    Some(profile) => {
+      profiles.insert(key, profile);
+    }
+    None => {
+      profiles.remove(&key);
+    }
+  }
+
+  let path = persistence_file_path(&app, TERMINAL_PROFILES_FILE_NAME)?;
+  write_json(&path, &profiles)?;
+  Ok(request.profile)
+}
+
+#[tauri::command]
+fn terminal_send_control(window: WebviewWindow, state: State<'_, AppState>, request: TerminalSendControlRequest) -> Result<(), String> {
+  let default_value = TERMINAL_CONTROL_CHARACTERS
+    .iter()
+    .find(|(name, _)| *name == request.name)
+    .map(|(_, value)| *value)
+    .ok_or_else(|| {
+      let names: Vec<&str> = TERMINAL_CONTROL_CHARACTERS.iter().map(|(name, _)| *name).collect();
+      format!(
+        "unknown control character \"{}\"; expected one of: {}",
+        request.name,
+        names.join(", ")
+      )
+    })?;
+
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  let value = session
+    .handles
+    .resize
+    .control_character(&request.name)?
+    .unwrap_or(default_value);
+
+  write_to_session(&session, &[value])
+}
+
+#[tauri::command]
+fn terminal_start_logging(
+  window: WebviewWindow,
+  state: State<'_, AppState>,
+  startup_context: State<'_, StartupContext>,
+  request: TerminalStartLoggingRequest
+) -> Result<(), String> {
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  let path = resolve_workspace_path(&startup_context, &request.path)?;
+  let next_log = TerminalLog::open(path, request.strip_ansi.unwrap_or(false))?;
+
+  close_terminal_log(&session)?;
+  *lock_recover(&session.log) = Some(next_log);
+  Ok(())
+}
+
+#[tauri::command]
+fn terminal_stop_logging(window: WebviewWindow, state: State<'_, AppState>, request: TerminalStopLoggingRequest) -> Result<(), String> {
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  close_terminal_log(&session)
+}
+
+#[tauri::command]
+fn terminal_get_stats(window: WebviewWindow, state: State<'_, AppState>, request: TerminalStatsRequest) -> Result<TerminalStatsResponse, String> {
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  Ok(terminal_stats_snapshot(&request.session_id, &session))
+}
+
+#[tauri::command]
+fn terminal_stats(
+  window: WebviewWindow,
+  state: State<'_, AppState>,
+  request: TerminalStatsListRequest
+) -> Result<Vec<TerminalStatsResponse>, String> {
+  if let Some(session_id) = request.session_id {
+    let session = get_terminal_session(&state, window.label(), &session_id)?;
+    return Ok(vec![terminal_stats_snapshot(&session_id, &session)]);
+  }
+
+  let sessions = lock_recover(&state.sessions);
+  Ok(sessions
+    .iter()
+    .filter(|(_, session)| session_owned_by(session, window.label()))
+    .map(|(session_id, session)| terminal_stats_snapshot(session_id, session))
+    .collect())
+}
+
+#[tauri::command]
+fn terminal_search_buffer(
+  window: WebviewWindow,
+  state: State<'_, AppState>,
+  request: TerminalSearchBufferRequest
+) -> Result<TerminalSearchBufferResponse, String> {
+  if request.query.is_empty() {
+    return Err(String::from("search query must not be empty"));
+  }
+  let pattern = if request.regex.unwrap_or(false) {
+    request.query.clone()
+  } else {
+    regex::escape(&request.query)
+  };
+  let matcher = regex::RegexBuilder::new(&pattern)
+    .case_insensitive(request.case_insensitive.unwrap_or(false))
+    .build()
+    .map_err(|error| format!("invalid search pattern: {error}"))?;
+  let max_results = request
+    .max_results
+    .unwrap_or(TERMINAL_SEARCH_DEFAULT_RESULTS)
+    .clamp(1, TERMINAL_SEARCH_MAX_RESULTS);
+
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  let (text, first_line) = {
+    let scrollback = lock_recover(&session.scrollback);
+    let (front, back) = scrollback.data.as_slices();
+    let mut stripped = Vec::with_capacity(scrollback.data.len());
+    let mut stripper = AnsiStripper::default();
+    stripper.strip(front, &mut stripped);
+    stripper.strip(back, &mut stripped);
+    (String::from_utf8_lossy(&stripped).into_owned(), scrollback.dropped_lines)
+  };
+
+  let mut matches = Vec::new();
+  let mut truncated = false;
+  'lines: for (index, line) in text.split('\n').enumerate() {
+    for found in matcher.find_iter(line) {
+      if found.as_str().is_empty() {
+        continue;
+      }
+      if matches.len() == max_results {
+        truncated = true;
+        break 'lines;
+      }
+      matches.push(TerminalBufferMatch {
+        line: first_line + index as u64,
+        start: line[..found.start()].chars().count(),
+        end: line[..found.end()].chars().count(),
+        context: line.to_string()
+      });
+    }
+  }
+
+  Ok(TerminalSearchBufferResponse { matches, truncated })
+}
+
+#[tauri::command]
+fn terminal_info(window: WebviewWindow, state: State<'_, AppState>, request: TerminalInfoRequest) -> Result<TerminalInfo, String> {
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  Ok(terminal_info_snapshot(&request.session_id, &session))
+}
+
+/// Changes the niceness and I/O class of everything running in the terminal. Omitted fields keep
+/// their current setting.
+#[tauri::command]
+fn terminal_set_priority(
+  window: WebviewWindow,
+  state: State<'_, AppState>,
+  request: TerminalSetPriorityRequest
+) -> Result<TerminalPriorityResult, String> {
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  let mut warnings = Vec::new();
+  let requested = requested_priority(request.nice, request.ionice_class, &mut warnings);
+  let targets = terminal_process_groups(session.pid.load(Ordering::Relaxed));
+  let result = apply_terminal_priority(&targets, requested, warnings);
+
+  let mut priority = lock_recover(&session.priority);
+  if result.nice_applied {
+    priority.nice = result.nice;
+  }
+  if result.ionice_applied {
+    priority.ionice_class = result.ionice_class;
+  }
+  Ok(result)
+}
+
+#[tauri::command]
+fn terminal_set_visibility(
+  window: WebviewWindow,
+  app: AppHandle,
+  state: State<'_, AppState>,
+  request: TerminalVisibilityRequest
+) -> Result<(), String> {
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  let mut scrollback = lock_recover(&session.scrollback);
+
+  let was_visible = session.visible.swap(request.visible, Ordering::Relaxed);
+  if !request.visible {
+    if was_visible {
+      scrollback.hidden_at = Some(scrollback.total_bytes);
+    }
+    return Ok(());
+  }
+
+  let Some(hidden_at) = scrollback.hidden_at.take() else {
+    return Ok(());
+  };
+  let (catch_up, skipped) = scrollback.catch_up_since(hidden_at, TERMINAL_CATCH_UP_MAX_BYTES);
+  if catch_up.is_empty() && skipped == 0 {
+    return Ok(());
+  }
+
+  let mut data = String::new();
+  if skipped > 0 {
+    data.push_str(&format!(
+      "\r\n[openspace: {skipped} bytes of output skipped while this terminal was hidden]\r\n"
+    ));
+  }
+  data.push_str(&String::from_utf8_lossy(&catch_up));
+
+  // Emitting while the scrollback lock is held keeps live output from overtaking the catch-up.
+  let _ = emit_to_session_window(
+    &app,
+    &session,
+    TERMINAL_OUTPUT_EVENT,
+    TerminalOutputEvent {
+      session_id: request.session_id,
+      data
+    }
+  );
+  session.output_events.fetch_add(1, Ordering::Relaxed);
+  Ok(())
+}
+
+#[tauri::command]
+fn terminal_install_shell_integration(
+  app: AppHandle,
+  safe_mode: State<'_, SafeModeState>,
+  request: ShellIntegrationRequest
+) -> Result<ShellIntegrationResponse, String> {
+  ensure_subsystem_enabled(&safe_mode, "shell-integration")?;
+
+  let shell = request.shell.unwrap_or_else(default_shell);
+  let shell_name = Path::new(&shell)
+    .file_name()
+    .map(|name| name.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  let (file_name, snippet) = match shell_name.as_str() {
+    "bash" => ("openspace.bash", BASH_SHELL_INTEGRATION),
+    "zsh" => ("openspace.zsh", ZSH_SHELL_INTEGRATION),
+    _ => {
+      return Err(format!(
+        "shell integration is only available for bash and zsh, not \"{shell_name}\""
+      ))
+    }
+  };
+
+  let mut path = app.path().app_data_dir().map_err(|error| error.to_string())?;
+  path.push(SHELL_INTEGRATION_DIR_NAME);
+  fs::create_dir_all(&path).map_err(|error| io_error("failed to create shell integration directory", error))?;
+  path.push(file_name);
+  fs::write(&path, snippet)
+    .map_err(|error| io_error(&format!("failed to write {}", path.display()), error))?;
+
+  let path = path.to_string_lossy().into_owned();
+  Ok(ShellIntegrationResponse {
+    shell: shell_name,
+    source_line: format!("source {}", shell_quote(&path)),
+    path
+  })
+}
+
+#[tauri::command]
+fn terminal_resize(
+  window: WebviewWindow,
+  app: AppHandle,
+  state: State<'_, AppState>,
+  startup_context: State<'_, StartupContext>,
+  request: TerminalResizeRequest
+) -> Result<(), String> {
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  let (cols, rows) = clamp_terminal_size(request.cols, request.rows);
+
+  // A cols/rows-only resize keeps the last pixel size instead of resetting it to zero.
+  let (pixel_width, pixel_height) =
+    session.handles.resize.resize(cols, rows, request.pixel_width, request.pixel_height)?;
+
+  if let Some(root) = current_workspace_root(&startup_context)? {
+    if let Err(message) = remember_terminal_layout(&app, root, TerminalLayout { cols, rows }) {
+      eprintln!("{message}");
+    }
+  }
+
+  let _ = emit_to_session_window(
+    &app,
+    &session,
+    TERMINAL_RESIZE_EVENT,
+    TerminalResizeEvent {
+      session_id: request.session_id,
+      cols,
+      rows,
+      pixel_width,
+      pixel_height
+    }
+  );
+  Ok(())
+}
+
+#[tauri::command]
+fn terminal_kill(window: WebviewWindow, state: State<'_, AppState>, request: TerminalKillRequest) -> Result<(), String> {
+  let _signal = request.signal;
+  get_terminal_session(&state, window.label(), &request.session_id)?;
+  kill_terminal_session(&state.sessions, &request.session_id)
+}
+
+fn kill_terminal_session(sessions: &Mutex<HashMap<String, TerminalSession>>, session_id: &str) -> Result<(), String> {
+  let session = {
+    let mut sessions = lock_recover(sessions);
+    sessions.remove(session_id)
+  }
+  .ok_or_else(|| terminal_not_found(session_id))?;
+  session.restart_on_exit.store(false, Ordering::Relaxed);
+  // The exited process is already reaped; signalling its pid could hit an unrelated process.
+  if lock_recover(&session.exit_code).is_some() {
+    return Ok(());
+  }
+
+  let mut child = lock_recover(&session.handles.child);
+
+  // Jobs the shell started sit in process groups of their own, which killing the shell alone
+  // would leave running.
+  #[cfg(unix)]
+  for target in terminal_process_groups(session.pid.load(Ordering::Relaxed)) {
+    if let PriorityTarget::Group(group) = target {
+      let _ = nix::sys::signal::killpg(
+        nix::unistd::Pid::from_raw(group as i32),
+        nix::sys::signal::Signal::SIGKILL
+      );
+    }
+  }
+  child
+    .kill()
+    .map_err(|error| format!("failed to kill terminal process: {error}"))?;
+  let _ = child.wait();
+  Ok(())
+}
+
+/// Releases a session: kills it if it is still running, or drops an exited one before its
+/// retention window ends.
+#[tauri::command]
+fn terminal_dispose(window: WebviewWindow, state: State<'_, AppState>, request: TerminalDisposeRequest) -> Result<(), String> {
+  get_terminal_session(&state, window.label(), &request.session_id)?;
+  kill_terminal_session(&state.sessions, &request.session_id)
+}
+
+/// Empties a session's retained scrollback and marks the clear in its transcript log. Nothing is
+/// written to the process, so a running program is not disturbed.
+#[tauri::command]
+fn terminal_clear(
+  app: AppHandle,
+  window: WebviewWindow,
+  state: State<'_, AppState>,
+  request: TerminalClearRequest
+) -> Result<(), String> {
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  lock_recover(&session.scrollback).clear();
+
+  {
+    let mut log = lock_recover(&session.log);
+    if let Some(active_log) = log.as_mut() {
+      let marker = format!("--- terminal cleared at {} ---", Utc::now().to_rfc3339());
+      if let Err(error) = active_log.write_marker(&marker) {
+        let path = active_log.path.display().to_string();
+        *log = None;
+        return Err(io_error(&format!("failed to write {path}"), error));
+      }
+    }
+  }
+
+  if request.inject_clear {
+    publish_terminal_output(&app, &request.session_id, &session, TERMINAL_CLEAR_SEQUENCE);
+  }
+  Ok(())
+}
+
+/// Saves a session's retained scrollback to a workspace file, raw or with escape sequences
+/// stripped. An existing file, or a symlink, at the path is only replaced when `overwrite` is
+/// set, and then the path itself is replaced rather than written through.
+#[tauri::command]
+fn terminal_export_log(
+  app: AppHandle,
+  window: WebviewWindow,
+  state: State<'_, AppState>,
+  startup_context: State<'_, StartupContext>,
+  request: TerminalExportLogRequest
+) -> Result<TerminalExportLogResponse, String> {
+  ensure_not_closing(&app)?;
+  let session = get_terminal_session(&state, window.label(), &request.session_id)?;
+  let path = resolve_workspace_path(&startup_context, &request.output_path)?;
+  if !request.overwrite && fs::symlink_metadata(&path).is_ok() {
+    return Err(exists_error(&path));
+  }
+
+  let (raw, truncated) = {
+    let scrollback = lock_recover(&session.scrollback);
+    let truncated = scrollback.evicted();
+    // Eviction can cut a line, or an escape sequence, in half; start at the next whole line.
+    let skip = if truncated {
+      scrollback.data.iter().position(|&byte| byte == b'\n').map_or(0, |index| index + 1)
+    } else {
+      0
+    };
+    (scrollback.data.range(skip..).copied().collect::<Vec<u8>>(), truncated)
+  };
+  let contents = match request.format {
+    TerminalLogFormat::Ansi => raw,
+    TerminalLogFormat::Text => {
+      let mut stripped = Vec::with_capacity(raw.len());
+      AnsiStripper::default().strip(&raw, &mut stripped);
+      stripped
+    }
+  };
+
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
+  }
+  let temp_path = temp_sibling_path(&path);
+  let written = OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .open(&temp_path)
+    .and_then(|mut file| {
+      file.write_all(&contents)?;
+      file.sync_all()
+    })
+    .map_err(|error| io_error(&format!("failed to write {}", path.display()), error))
+    .and_then(|()| {
+      // Checked again in case something appeared at the path while the log was written.
+      if !request.overwrite && fs::symlink_metadata(&path).is_ok() {
+        return Err(exists_error(&path));
+      }
+      fs::rename(&temp_path, &path).map_err(|error| io_error(&format!("failed to write {}", path.display()), error))
+    });
+  if let Err(error) = written {
+    let _ = fs::remove_file(&temp_path);
+    return Err(error);
+  }
+
+  Ok(TerminalExportLogResponse {
+    output_path: path.display().to_string(),
+    size_bytes: contents.len() as u64,
+    truncated
+  })
+}
+
+/// Starts a new session with the same shell, arguments, environment, size and restart
+/// behaviour as an existing one, in the directory that session is currently in.
+#[tauri::command]
+fn terminal_duplicate(
+  window: WebviewWindow,
+  app: AppHandle,
+  state: State<'_, AppState>,
+  request: TerminalDuplicateRequest
+) -> Result<TerminalCreateResponse, String> {
+  ensure_not_closing(&app)?;
+  let source = get_terminal_session(&state, window.label(), &request.session_id)?;
+  let launch = TerminalLaunchConfig {
+    cwd: current_terminal_cwd(&source),
+    ..(*source.launch).clone()
+  };
+  let term = launch.term.clone();
+  let size = current_pty_size(&source);
+  let priority = *lock_recover(&source.priority);
+
+  let (session_id, pid) = start_terminal_session(
+    &app,
+    &state,
+    window.label(),
+    launch,
+    size,
+    TerminalSessionOptions {
+      restart_on_exit: source.restart_on_exit.load(Ordering::Relaxed),
+      restart_policy: source.restart_policy,
+      keep_alive: source.keep_alive,
+      priority
+    }
+  )?;
+  let priority_result = (priority.nice.is_some() || priority.ionice_class.is_some())
+    .then(|| apply_terminal_priority(&[PriorityTarget::Process(pid)], priority, Vec::new()));
+
+  Ok(TerminalCreateResponse {
+    session_id,
+    pid,
+    term,
+    cols: size.cols,
+    rows: size.rows,
+    size_source: String::from("session"),
+    priority: priority_result
+  })
+}
+
+/// Moves a session whose window has closed over to the calling window.
+#[tauri::command]
+fn terminal_adopt(
+  app: AppHandle,
+  window: WebviewWindow,
+  state: State<'_, AppState>,
+  request: TerminalAdoptRequest
+) -> Result<TerminalInfo, String> {
+  let session = lock_recover(&state.sessions)
+    .get(&request.session_id)
+    .cloned()
+    .ok_or_else(|| terminal_not_found(&request.session_id))?;
+
+  {
+    let mut window_label = lock_recover(&session.window_label);
+    if *window_label != window.label() && app.get_webview_window(&window_label).is_some() {
+      return Err(message_error(
+        "CONFLICT",
+        "terminal.openInOtherWindow",
+        &[("sessionId", request.session_id.clone()), ("windowLabel", window_label.clone())]
+      ));
+    }
+    *window_label = window.label().to_string();
+  }
+  Ok(terminal_info_snapshot(&request.session_id, &session))
+}
+
+#[tauri::command]
+fn terminal_list(window: WebviewWindow, state: State<'_, AppState>) -> Result<Vec<TerminalInfo>, String> {
+  let sessions = lock_recover(&state.sessions);
+  Ok(sessions
+    .iter()
+    .filter(|(_, session)| session_owned_by(session, window.label()))
+    .map(|(session_id, session)| terminal_info_snapshot(session_id, session))
+    .collect())
+}
+
+/// Builds an entry from `symlink_metadata`. Symlinks are followed for the kind, size and mtime
+/// so a linked directory still expands, but stay marked so callers can avoid walking into loops.
+fn filesystem_entry(path: &Path, link_metadata: &Metadata) -> FilesystemEntry {
+  let is_symlink = link_metadata.file_type().is_symlink();
+  let target_metadata = is_symlink.then(|| fs::metadata(path).ok()).flatten();
+  let broken = is_symlink && target_metadata.is_none();
+  let metadata = target_metadata.as_ref().unwrap_or(link_metadata);
+  let is_directory = metadata.is_dir();
+  FilesystemEntry {
+    name: path
+      .file_name()
+      .map(|name| name.to_string_lossy().into_owned())
+      .unwrap_or_default(),
+    path: path.to_string_lossy().into_owned(),
+    is_directory,
+    size: (!is_directory).then_some(metadata.len()),
+    size_on_disk: (!is_directory).then(|| size_on_disk(metadata)),
+    mtime_ms: modified_time_ms(metadata),
+    depth: None,
+    ignored: false,
+    is_symlink,
+    symlink_target: is_symlink
+      .then(|| fs::read_link(path).ok())
+      .flatten()
+      .map(|target| target.to_string_lossy().into_owned()),
+    broken,
+    error: None
+  }
+}
+
+/// Entries whose metadata cannot be read are still listed, marked with the error. Ones that
+/// vanished since `read_dir` are dropped.
+fn filesystem_dir_entry(entry: &fs::DirEntry) -> Option<FilesystemEntry> {
+  let path = entry.path();
+  let error = match entry.metadata() {
+    Ok(metadata) => return Some(filesystem_entry(&path, &metadata)),
+    Err(error) if error.kind() == ErrorKind::NotFound => return None,
+    Err(error) => error
+  };
+  let file_type = entry.file_type().ok();
+  Some(FilesystemEntry {
+    name: entry.file_name().to_string_lossy().into_owned(),
+    path: path.to_string_lossy().into_owned(),
+    is_directory: file_type.is_some_and(|file_type| file_type.is_dir()),
+    size: None,
+    size_on_disk: None,
+    mtime_ms: 0,
+    depth: None,
+    ignored: false,
+    is_symlink: file_type.is_some_and(|file_type| file_type.is_symlink()),
+    symlink_target: None,
+    broken: false,
+    error: Some(error.to_string())
+  })
+}
+
+/// A directory's readable entries, plus a warning for each one that could not be read, so the
+/// listing can be flagged as partial rather than silently coming up short.
+fn read_dir_entries(directory: &Path) -> std::io::Result<(Vec<fs::DirEntry>, Vec<String>)> {
+  let mut entries = Vec::new();
+  let mut warnings = Vec::new();
+  for entry in fs::read_dir(directory)? {
+    match entry {
+      Ok(entry) => entries.push(entry),
+      Err(error) => warnings.push(io_error(&format!("failed to read an entry of {}", directory.display()), error))
+    }
+  }
+  Ok((entries, warnings))
+}
+
+/// Sorts by `key`, falling back to the case-insensitive name, with directories first unless
+/// turned off. `descending` reverses the key order but not the directories-first grouping.
+fn sort_filesystem_entries(list: &mut [FilesystemEntry], options: FsSortOptions) {
+  let by_name = |left: &FilesystemEntry, right: &FilesystemEntry| {
+    if options.natural {
+      natural_cmp(&left.name, &right.name)
+    } else {
+      left
+        .name
+        .to_ascii_lowercase()
+        .cmp(&right.name.to_ascii_lowercase())
+    }
+  };
+  let by_key: fn(&FilesystemEntry, &FilesystemEntry) -> std::cmp::Ordering = match options.key {
+    FsSortKey::Name => |_, _| std::cmp::Ordering::Equal,
+    FsSortKey::Size => |left, right| left.size.cmp(&right.size),
+    FsSortKey::Mtime => |left, right| left.mtime_ms.cmp(&right.mtime_ms),
+    FsSortKey::Type => |left, right| file_extension(&left.name).cmp(&file_extension(&right.name))
+  };
+
+  list.sort_by(|left, right| {
+    if options.directories_first && left.is_directory != right.is_directory {
+      return right.is_directory.cmp(&left.is_directory);
+    }
+
+    let ordering = by_key(left, right).then_with(|| by_name(left, right));
+    if options.descending {
+      ordering.reverse()
+    } else {
+      ordering
+    }
+  });
+}
+
+/// Case-insensitive comparison that orders runs of digits by their numeric value.
+fn natural_cmp(left: &str, right: &str) -> std::cmp::Ordering {
+  let mut left = left.chars().peekable();
+  let mut right = right.chars().peekable();
+  loop {
+    let (left_char, right_char) = match (left.peek().copied(), right.peek().copied()) {
+      (None, None) => return std::cmp::Ordering::Equal,
+      (None, Some(_)) => return std::cmp::Ordering::Less,
+      (Some(_), None) => return std::cmp::Ordering::Greater,
+      (Some(left_char), Some(right_char)) => (left_char, right_char)
+    };
+    if left_char.is_ascii_digit() && right_char.is_ascii_digit() {
+      let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        let mut digits = String::new();
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+          digits.push(digit);
+        }
+        digits
+      };
+      let (left_digits, right_digits) = (take_number(&mut left), take_number(&mut right));
+      let (left_value, right_value) = (left_digits.trim_start_matches('0'), right_digits.trim_start_matches('0'));
+      let ordering = left_value
+        .len()
+        .cmp(&right_value.len())
+        .then_with(|| left_value.cmp(right_value))
+        // Equal values: fewer leading zeros first, so the order is still total.
+        .then_with(|| left_digits.len().cmp(&right_digits.len()));
+      if ordering != std::cmp::Ordering::Equal {
+        return ordering;
+      }
+      continue;
+    }
+    let ordering = left_char.to_ascii_lowercase().cmp(&right_char.to_ascii_lowercase());
+    if ordering != std::cmp::Ordering::Equal {
+      return ordering;
+    }
+    left.next();
+    right.next();
+  }
+}
+
+fn file_extension(name: &str) -> String {
+  Path::new(name)
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+    .unwrap_or_default()
+}
+
+#[tauri::command]
+fn filesystem_get_home_dir() -> Result<FilesystemPathResponse, String> {
+  let home = home_dir().ok_or_else(|| message_error("NOT_FOUND", "file.homeUnknown", &[]))?;
+  Ok(FilesystemPathResponse {
+    path: home.to_string_lossy().into_owned()
+  })
+}
+
+#[tauri::command]
+fn filesystem_get_temp_dir() -> FilesystemPathResponse {
+  FilesystemPathResponse {
+    path: std::env::temp_dir().to_string_lossy().into_owned()
+  }
+}
+
+/// Expands `~` and canonicalizes a path typed by the user. A path that does not exist is only
+/// made absolute and has `.` and `..` removed lexically.
+#[tauri::command]
+fn filesystem_resolve(request: FilesystemResolveRequest) -> Result<FilesystemResolveResponse, String> {
+  let path = resolve_path(&request.path)?;
+  let (resolved, exists) = match fs::canonicalize(&path) {
+    Ok(canonical) => (canonical, true),
+    Err(_) => (normalize_lexically(&path), false)
+  };
+  Ok(FilesystemResolveResponse {
+    resolved: resolved.to_string_lossy().into_owned(),
+    exists
+  })
+}
+
+fn normalize_lexically(path: &Path) -> PathBuf {
+  let mut normalized = PathBuf::new();
+  for component in path.components() {
+    match component {
+      std::path::Component::CurDir => {}
+      std::path::Component::ParentDir => {
+        normalized.pop();
+      }
+      component => normalized.push(component)
+    }
+  }
+  normalized
+}
+
+/// Lists a directory. With `maxEntries` or `offset` one page of the sorted listing is returned;
+/// the listing is cached briefly so later pages are cheap and consistent with the first.
+#[tauri::command]
+fn filesystem_list(
+  state: State<'_, AppState>,
+  startup_context: State<'_, StartupContext>,
+  request: FsListRequest
+) -> Result<FsListResponse, String> {
+  let target_path = resolve_workspace_path(&startup_context, &request.path)?;
+  if request.max_entries.is_none() && request.offset.is_none() {
+    // The plain array has nowhere to put warnings; callers that care ask for a page.
+    let (entries, warnings) = list_directory(&startup_context, &target_path, &request)?;
+    for warning in warnings {
+      eprintln!("{warning}");
+    }
+    return Ok(FsListResponse::Entries(entries));
+  }
+
+  let directory_mtime_ms = fs::metadata(&target_path)
+    .map(|metadata| modified_time_ms(&metadata))
+    .map_err(|error| io_error(&format!("failed to list {}", target_path.display()), error))?;
+  let cache_key = format!(
+    "{}\n{:?}",
+    target_path.display(),
+    (
+      request.sort_by,
+      request.sort_desc,
+      request.sort_order,
+      request.directories_first,
+      request.natural_sort,
+      &request.filter_glob,
+      request.include_hidden,
+      &request.exclude_globs,
+      request.respect_gitignore,
+      request.hide_ignored
+    )
+  );
+  let cached = cached_listing(
+    &mut lock_recover(&state.listing_cache),
+    &cache_key,
+    directory_mtime_ms,
+    request.snapshot_id.as_deref(),
+    &target_path
+  )?;
+  let (snapshot_id, entries, warnings) = match cached {
+    Some(cached) => cached,
+    None => {
+      let (entries, warnings) = list_directory(&startup_context, &target_path, &request)?;
+      let (entries, warnings) = (Arc::new(entries), Arc::new(warnings));
+      let snapshot_id = Uuid::new_v4().to_string();
+      let mut cache = lock_recover(&state.listing_cache);
+      cache.retain(|_, snapshot| snapshot.last_used.elapsed() < FILESYSTEM_LIST_SNAPSHOT_TTL);
+      cache.insert(
+        cache_key,
+        FsListingSnapshot {
+          id: snapshot_id.clone(),
+          fetched_at: Instant::now(),
+          last_used: Instant::now(),
+          directory_mtime_ms,
+          entries: entries.clone(),
+          warnings: warnings.clone()
+        }
+      );
+      (snapshot_id, entries, warnings)
+    }
+  };
+
+  let offset = request.offset.unwrap_or(0).min(entries.len());
+  let end = request
+    .max_entries
+    .map_or(entries.len(), |max_entries| offset.saturating_add(max_entries).min(entries.len()));
+  Ok(FsListResponse::Page(FsListPage {
+    entries: entries[offset..end].to_vec(),
+    offset,
+    total_count: entries.len(),
+    has_more: end < entries.len(),
+    snapshot_id,
+    partial: !warnings.is_empty(),
+    warnings: warnings.to_vec()
+  }))
+}
+
+/// Snapshot id, entries and warnings of a cached listing.
+type CachedListing = (String, Arc<Vec<FilesystemEntry>>, Arc<Vec<String>>);
+
+/// The cached listing under `key`, if it can be reused.
+/// Without `snapshot_id` a listing younger than `FILESYSTEM_LIST_CACHE_TTL` is shared; with
+/// one, that exact listing is required and fails with STALE once the directory changed or it
+/// went unused for `FILESYSTEM_LIST_SNAPSHOT_TTL`.
+fn cached_listing(
+  cache: &mut HashMap<String, FsListingSnapshot>,
+  key: &str,
+  directory_mtime_ms: u64,
+  snapshot_id: Option<&str>,
+  path: &Path
+) -> Result<Option<CachedListing>, String> {
+  let snapshot = cache
+    .get_mut(key)
+    .filter(|snapshot| snapshot.directory_mtime_ms == directory_mtime_ms);
+  let snapshot = match snapshot_id {
+    Some(snapshot_id) => Some(
+      snapshot
+        .filter(|snapshot| snapshot.id == snapshot_id && snapshot.last_used.elapsed() < FILESYSTEM_LIST_SNAPSHOT_TTL)
+        .ok_or_else(|| message_error("STALE", "listing.stale", &[("path", path.display().to_string())]))?
+    ),
+    None => snapshot.filter(|snapshot| snapshot.fetched_at.elapsed() < FILESYSTEM_LIST_CACHE_TTL)
+  };
+  Ok(snapshot.map(|snapshot| {
+    snapshot.last_used = Instant::now();
+    (snapshot.id.clone(), snapshot.entries.clone(), snapshot.warnings.clone())
+  }))
+}
+
+/// The sorted, filtered entries of `target_path`, and warnings for entries that could not be
+/// read.
+fn list_directory(
+  startup_context: &StartupContext,
+  target_path: &Path,
+  request: &FsListRequest
+) -> Result<(Vec<FilesystemEntry>, Vec<String>), String> {
+  let filter = request
+    .filter_glob
+    .as_deref()
+    .map(|pattern| glob::Pattern::new(pattern).map_err(|error| format!("invalid filter pattern \"{pattern}\": {error}")))
+    .transpose()?;
+  let exclude = request
+    .exclude_globs
+    .iter()
+    .map(|pattern| glob::Pattern::new(pattern).map_err(|error| format!("invalid exclude pattern \"{pattern}\": {error}")))
+    .collect::<Result<Vec<_>, _>>()?;
+  let include_hidden = request.include_hidden.unwrap_or(false);
+  let (entries, warnings) = read_dir_entries(target_path)
+    .map_err(|error| io_error(&format!("failed to list {}", target_path.display()), error))?;
+  let gitignore = if request.respect_gitignore {
+    let canonical_path = fs::canonicalize(target_path)
+      .map_err(|error| io_error(&format!("failed to resolve {}", target_path.display()), error))?;
+    let workspace_root = current_workspace_root(startup_context)?.and_then(|root| fs::canonicalize(root).ok());
+    let matchers = gitignore_matchers(&canonical_path, workspace_root.as_deref());
+    Some((canonical_path, matchers))
+  } else {
+    None
+  };
+
+  let mut list: Vec<FilesystemEntry> = entries
+    .into_iter()
+    .filter(|entry| include_hidden || !entry.file_name().to_string_lossy().starts_with('.'))
+    .filter_map(|entry| filesystem_dir_entry(&entry))
+    .filter(|entry| entry.is_directory || filter.as_ref().map_or(true, |pattern| pattern.matches(&entry.name)))
+    .filter(|entry| !exclude.iter().any(|pattern| pattern.matches(&entry.name)))
+    .map(|mut entry| {
+      if let Some((canonical_path, matchers)) = &gitignore {
+        // Git treats a symlink as a file even when it points at a directory.
+        let is_directory = entry.is_directory && !entry.is_symlink;
+        entry.ignored = is_gitignored(matchers, &canonical_path.join(&entry.name), is_directory);
+      }
+      entry
+    })
+    .filter(|entry| !(request.hide_ignored && entry.ignored))
+    .collect();
+
+  sort_filesystem_entries(
+    &mut list,
+    FsSortOptions {
+      key: request.sort_by.unwrap_or_default(),
+      descending: request
+        .sort_order
+        .map(|order| order == FsSortOrder::Desc)
+        .or(request.sort_desc)
+        .unwrap_or(false),
+      directories_first: request.directories_first.unwrap_or(true),
+      natural: request.natural_sort
+    }
+  );
+  Ok((list, warnings))
+}
+
+/// The `.gitignore` files that apply inside `directory`, deepest first, found by walking up to
+/// the enclosing repository (whose `.git/info/exclude` is included) or `stop_at`.
+fn gitignore_matchers(directory: &Path, stop_at: Option<&Path>) -> Vec<ignore::gitignore::Gitignore> {
+  let mut matchers = Vec::new();
+  for ancestor in directory.ancestors() {
+    let mut builder = ignore::gitignore::GitignoreBuilder::new(ancestor);
+    let mut found = false;
+    for file in [ancestor.join(".gitignore"), ancestor.join(".git/info/exclude")] {
+      if file.is_file() {
+        found |= builder.add(file).is_none();
+      }
+    }
+    if found {
+      if let Ok(matcher) = builder.build() {
+        matchers.push(matcher);
+      }
+    }
+    if ancestor.join(".git").exists() || stop_at.is_some_and(|stop_at| ancestor == stop_at) {
+      break;
+    }
+  }
+  matchers
+}
+
+/// The deepest `.gitignore` with an opinion on `path` or one of its parents decides, so a
+/// nested `!pattern` can re-include what an outer file ignores.
+fn is_gitignored(matchers: &[ignore::gitignore::Gitignore], path: &Path, is_directory: bool) -> bool {
+  for matcher in matchers {
+    match matcher.matched_path_or_any_parents(path, is_directory) {
+      ignore::Match::Ignore(_) => return true,
+      ignore::Match::Whitelist(_) => return false,
+      ignore::Match::None => {}
+    }
+  }
+  false
+}
+
+/// Quick-open search: walks the tree under `root` in parallel on a background thread and ranks
+/// file paths by how well the query fuzzily matches them. The ranking so far is streamed through
+/// `fs:search-results`, the final one through `fs:search-finished`. Cancel with
+/// `operation_cancel` and the search id.
+#[tauri::command]
+fn fs_search_filenames(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  registry: State<'_, OperationRegistry>,
+  request: FsSearchFilenamesRequest
+) -> Result<FsSearchStarted, String> {
+  let root = resolve_workspace_path(&startup_context, &request.root)?;
+  if !root.is_dir() {
+    return Err(format!("{} is not a directory", root.display()));
+  }
+  let query: Vec<char> = request
+    .query
+    .chars()
+    .filter(|character| !character.is_whitespace())
+    .flat_map(char::to_lowercase)
+    .collect();
+  let max_results = request
+    .max_results
+    .unwrap_or(FS_SEARCH_DEFAULT_RESULTS)
+    .clamp(1, FS_SEARCH_MAX_RESULTS);
+  let respect_gitignore = request.respect_gitignore.unwrap_or(true);
+  let skip_dirs = request
+    .skip_dirs
+    .unwrap_or_else(|| FS_SEARCH_SKIPPED_DIRS.iter().map(|name| name.to_string()).collect());
+
+  let search_id = Uuid::new_v4().to_string();
+  let cancelled = Arc::new(AtomicBool::new(false));
+  lock_recover(&registry.operations).insert(
+    search_id.clone(),
+    OperationHandle {
+      kind: "search",
+      cancelled: cancelled.clone()
+    }
+  );
+
+  let started = FsSearchStarted {
+    search_id: search_id.clone()
+  };
+  std::thread::spawn(move || {
+    let search = FilenameSearch {
+      root,
+      query,
+      max_results,
+      respect_gitignore,
+      skip_dirs
+    };
+    let outcome = search.run(&cancelled, &|matches, scanned_files| {
+      let _ = app.emit(
+        FS_SEARCH_RESULTS_EVENT,
+        FsSearchResultsEvent {
+          search_id: search_id.clone(),
+          matches,
+          scanned_files
+        }
+      );
+    });
+    let finished = FsSearchFinishedEvent {
+      search_id: search_id.clone(),
+      matches: outcome.matches,
+      scanned_files: outcome.scanned_files,
+      total_matches: outcome.total_matches,
+      error: cancelled
+        .load(Ordering::SeqCst)
+        .then(|| String::from("CANCELLED: the search was cancelled"))
+    };
+    finish_operation(&app, &search_id, FS_SEARCH_FINISHED_EVENT, finished);
+  });
+  Ok(started)
+}
+
+struct FilenameSearch {
+  root: PathBuf,
+  /// Lowercased, without whitespace.
+  query: Vec<char>,
+  max_results: usize,
+  respect_gitignore: bool,
+  skip_dirs: Vec<String>
+}
+
+struct FilenameSearchOutcome {
+  matches: Vec<FsSearchMatch>,
+  scanned_files: u64,
+  total_matches: u64
+}
+
+impl FilenameSearch {
+  /// Walks the tree on the `ignore` crate's worker threads. `report` gets the ranking so far at
+  /// most every `FS_SEARCH_RESULTS_INTERVAL`, from whichever worker found the latest match.
+  fn run(
+    &self,
+    cancelled: &AtomicBool,
+    report: &(dyn Fn(Vec<FsSearchMatch>, u64) + Sync)
+  ) -> FilenameSearchOutcome {
+    let matches = Mutex::new(Vec::new());
+    let total_matches = AtomicU64::new(0);
+    let scanned_files = AtomicU64::new(0);
+    let last_report = Mutex::new(Instant::now());
+    let walker = search_walk_builder(&self.root, self.respect_gitignore, self.skip_dirs.clone()).build_parallel();
+
+    walker.run(|| {
+      Box::new(|entry| {
+        if cancelled.load(Ordering::SeqCst) {
+          return ignore::WalkState::Quit;
+        }
+        let Ok(entry) = entry else {
+          return ignore::WalkState::Continue;
+        };
+        if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+          return ignore::WalkState::Continue;
+        }
+        scanned_files.fetch_add(1, Ordering::Relaxed);
+        let relative_path = entry
+          .path()
+          .strip_prefix(&self.root)
+          .unwrap_or(entry.path())
+          .to_string_lossy()
+          .replace('\\', "/");
+        let Some(score) = fuzzy_path_score(&self.query, &relative_path) else {
+          return ignore::WalkState::Continue;
+        };
+        total_matches.fetch_add(1, Ordering::Relaxed);
+        {
+          let mut matches = lock_recover(&matches);
+          matches.push(FsSearchMatch {
+            path: entry.path().to_string_lossy().into_owned(),
+            relative_path,
+            score
+          });
+          if matches.len() >= self.max_results * 2 {
+            rank_search_matches(&mut matches, self.max_results);
+          }
+        }
+
+        // Workers that find the report already being sent just carry on walking.
+        if let Ok(mut last_report) = last_report.try_lock() {
+          if last_report.elapsed() >= FS_SEARCH_RESULTS_INTERVAL {
+            let mut ranked = lock_recover(&matches).clone();
+            rank_search_matches(&mut ranked, self.max_results);
+            report(ranked, scanned_files.load(Ordering::Relaxed));
+            *last_report = Instant::now();
+          }
+        }
+        ignore::WalkState::Continue
+      })
+    });
+
+    let mut matches = matches.into_inner().unwrap_or_else(PoisonError::into_inner);
+    rank_search_matches(&mut matches, self.max_results);
+    FilenameSearchOutcome {
+      matches,
+      scanned_files: scanned_files.into_inner(),
+      total_matches: total_matches.into_inner()
+    }
+  }
+}
+
+/// A walk over everything under `root` including dotfiles, honouring ignore files when asked and
+/// never entering directories named in `skip_dirs`.
+fn search_walk_builder(root: &Path, respect_gitignore: bool, skip_dirs: Vec<String>) -> ignore::WalkBuilder {
+  let mut builder = ignore::WalkBuilder::new(root);
+  builder
+    .hidden(false)
+    .git_ignore(respect_gitignore)
+    .git_exclude(respect_gitignore)
+    .git_global(respect_gitignore)
+    .ignore(respect_gitignore)
+    .parents(respect_gitignore)
+    .require_git(false)
+    .filter_entry(move |entry| {
+      !(entry.file_type().is_some_and(|file_type| file_type.is_dir())
+        && skip_dirs.iter().any(|name| entry.file_name() == name.as_str()))
+    });
+  builder
+}
+
+/// Resolves `scope_paths` against `root`. Each must exist and lie under `root`; the result is
+/// expressed under `root` as given, so it prefixes the walker's paths, with scopes inside
+/// another scope dropped so no file is searched twice.
+fn resolve_search_scopes(root: &Path, scope_paths: &[String]) -> Result<Vec<PathBuf>, String> {
+  let canonical_root =
+    fs::canonicalize(root).map_err(|error| io_error(&format!("failed to resolve {}", root.display()), error))?;
+  let mut scopes = Vec::with_capacity(scope_paths.len());
+  for scope_path in scope_paths {
+    let expanded = PathBuf::from(expand_tilde(scope_path));
+    let candidate = if expanded.is_absolute() { expanded } else { root.join(expanded) };
+    let canonical = match fs::canonicalize(&candidate) {
+      Ok(canonical) => canonical,
+      Err(error) if error.kind() == ErrorKind::NotFound => {
+        return Err(message_error("NOT_FOUND", "file.notFound", &[("path", candidate.display().to_string())]));
+      }
+      Err(error) => return Err(io_error(&format!("failed to resolve {}", candidate.display()), error))
+    };
+    let relative = canonical
+      .strip_prefix(&canonical_root)
+      .map_err(|_| PATH_ESCAPES_WORKSPACE_ERROR.to_string())?;
+    scopes.push(root.join(relative));
+  }
+
+  scopes.sort();
+  let mut kept: Vec<PathBuf> = Vec::with_capacity(scopes.len());
+  for scope in scopes {
+    if !kept.iter().any(|outer| scope.starts_with(outer)) {
+      kept.push(scope);
+    }
+  }
+  Ok(kept)
+}
+
+impl GrepMatchBatch {
+  fn new() -> Self {
+    Self {
+      pending: Vec::new(),
+      last_sent: Instant::now()
+    }
+  }
+
+  /// Adds a match and hands back the batch to send if one is due.
+  fn push(&mut self, found: FsGrepMatch) -> Option<Vec<FsGrepMatch>> {
+    self.pending.push(found);
+    let due = self.pending.len() >= FS_GREP_MATCHES_PER_EVENT || self.last_sent.elapsed() >= FS_GREP_MATCHES_INTERVAL;
+    due.then(|| self.take())
+  }
+
+  fn take(&mut self) -> Vec<FsGrepMatch> {
+    self.last_sent = Instant::now();
+    std::mem::take(&mut self.pending)
+  }
+}
+
+/// Project-wide text search. Matching lines are sent in `fs:grep-matches` batches as they are
+/// found, at most every `FS_GREP_MATCHES_INTERVAL`, then `fs:grep-finished` carries the totals
+/// and the binary files that were skipped. Cancel with `operation_cancel` and the search id.
+#[tauri::command]
+fn fs_search_content(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  registry: State<'_, OperationRegistry>,
+  request: FsSearchContentRequest
+) -> Result<FsSearchStarted, String> {
+  let max_matches = request
+    .max_matches
+    .unwrap_or(FS_GREP_DEFAULT_MAX_MATCHES)
+    .clamp(1, FS_GREP_MAX_MATCHES);
+  let search = ContentSearch::new(&startup_context, request.query, max_matches)?;
+
+  let search_id = Uuid::new_v4().to_string();
+  let cancelled = Arc::new(AtomicBool::new(false));
+  lock_recover(&registry.operations).insert(
+    search_id.clone(),
+    OperationHandle {
+      kind: "grep",
+      cancelled: cancelled.clone()
+    }
+  );
+
+  let started = FsSearchStarted {
+    search_id: search_id.clone()
+  };
+  std::thread::spawn(move || {
+    let send = |matches: Vec<FsGrepMatch>| {
+      if matches.is_empty() {
+        return;
+      }
+      let _ = app.emit(
+        FS_GREP_MATCHES_EVENT,
+        FsGrepMatchesEvent {
+          search_id: search_id.clone(),
+          matches
+        }
+      );
+    };
+    let batch = Mutex::new(GrepMatchBatch::new());
+    let outcome = search.run(&cancelled, &|found| {
+      let due = lock_recover(&batch).push(found);
+      if let Some(matches) = due {
+        send(matches);
+      }
+    });
+    send(lock_recover(&batch).take());
+    let finished = FsGrepFinishedEvent {
+      search_id: search_id.clone(),
+      searched_files: outcome.searched_files,
+      matched_files: outcome.matched_files,
+      total_matches: outcome.total_matches,
+      limit_reached: outcome.limit_reached,
+      binary_files_skipped: outcome.binary_files_skipped,
+      binary_files: outcome.binary_files,
+      error: cancelled
+        .load(Ordering::SeqCst)
+        .then(|| String::from("CANCELLED: the search was cancelled"))
+    };
+    finish_operation(&app, &search_id, FS_GREP_FINISHED_EVENT, finished);
+  });
+  Ok(started)
+}
+
+/// Rewrites every match of a content search on a background thread and reports the outcome
+/// through `fs:replace-finished`. A dry run lists the files that would change with per-file
+/// counts and previews. Execution rewrites each file atomically, but only files named in
+/// `expectedMtimes` whose mtime still matches. Files over the `fileReadMaxBytes` setting are
+/// skipped. Cancel with `operation_cancel` and the operation id.
+#[tauri::command]
+fn fs_replace_in_files(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  settings_state: State<'_, SettingsState>,
+  registry: State<'_, OperationRegistry>,
+  request: FsReplaceInFilesRequest
+) -> Result<FsReplaceStarted, String> {
+  if !request.dry_run {
+    ensure_not_closing(&app)?;
+  }
+  let literal = !request.query.is_regex;
+  let search = ContentSearch::new(&startup_context, request.query, usize::MAX)?;
+  let max_file_bytes = file_read_max_bytes(&settings_state);
+
+  let operation_id = Uuid::new_v4().to_string();
+  let cancelled = Arc::new(AtomicBool::new(false));
+  lock_recover(&registry.operations).insert(
+    operation_id.clone(),
+    OperationHandle {
+      kind: "replace",
+      cancelled: cancelled.clone()
+    }
+  );
+
+  let started = FsReplaceStarted {
+    operation_id: operation_id.clone()
+  };
+  std::thread::spawn(move || {
+    let mut finished = FsReplaceFinishedEvent {
+      operation_id: operation_id.clone(),
+      dry_run: request.dry_run,
+      files: Vec::new(),
+      files_changed: 0,
+      matches_replaced: 0,
+      skipped: Vec::new(),
+      error: None
+    };
+    let walker = search
+      .walk_builder()
+      .sort_by_file_name(|left, right| left.cmp(right))
+      .build();
+    for entry in walker.filter_map(Result::ok) {
+      if cancelled.load(Ordering::SeqCst) {
+        finished.error = Some(String::from("CANCELLED: the replace was cancelled"));
+        break;
+      }
+      if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+        continue;
+      }
+      let path = entry.path();
+      let path_string = path.to_string_lossy().into_owned();
+      let Ok(metadata) = fs::metadata(path) else {
+        continue;
+      };
+      let skip = |error: String| FsReplaceSkippedFile {
+        path: path_string.clone(),
+        error
+      };
+      if metadata.len() > max_file_bytes {
+        finished.skipped.push(skip(message_error(
+          "TOO_LARGE",
+          "file.tooLarge",
+          &[("path", path_string.clone()), ("size", metadata.len().to_string()), ("max", max_file_bytes.to_string())]
+        )));
+        continue;
+      }
+      let Ok(content) = fs::read(path) else {
+        continue;
+      };
+      if content[..content.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+        continue;
+      }
+      let replaced = replace_matches(&search.regex, &content, request.replacement.as_bytes(), literal);
+      if replaced.matches == 0 {
+        continue;
+      }
+      let relative_path = path
+        .strip_prefix(&search.root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/");
+      let mtime_ms = modified_time_ms(&metadata);
+
+      if request.dry_run {
+        finished.matches_replaced += replaced.matches;
+        finished.files.push(FsReplaceFileResult {
+          path: path_string,
+          relative_path,
+          matches: replaced.matches,
+          mtime_ms,
+          previews: replaced.previews
+        });
+        continue;
+      }
+
+      let Some(&expected_mtime_ms) = request.expected_mtimes.get(&path_string) else {
+        finished.skipped.push(skip(message_error("CONFLICT", "file.notPreviewed", &[("path", path_string.clone())])));
+        continue;
+      };
+      if let Err(conflict) = check_write_conflict(path, expected_mtime_ms, None) {
+        finished.skipped.push(skip(conflict));
+        continue;
+      }
+      let mut conflict = None;
+      let written = write_file_atomically(
+        path,
+        |file| file.write_all(&replaced.content),
+        || {
+          check_write_conflict(path, expected_mtime_ms, None).map_err(|error| {
+            conflict = Some(error);
+            std::io::Error::other("the file changed while it was being written")
+          })
+        }
+      );
+      match written {
+        Ok(_) => {
+          finished.files_changed += 1;
+          finished.matches_replaced += replaced.matches;
+          finished.files.push(FsReplaceFileResult {
+            path: path_string.clone(),
+            relative_path,
+            matches: replaced.matches,
+            mtime_ms: fs::metadata(path).map_or(mtime_ms, |metadata| modified_time_ms(&metadata)),
+            previews: Vec::new()
+          });
+        }
+        Err(error) => finished.skipped.push(skip(
+          conflict
+            .take()
+            .unwrap_or_else(|| io_error(&format!("failed to write {path_string}"), error))
+        ))
+      }
+    }
+    finish_operation(&app, &operation_id, FS_REPLACE_FINISHED_EVENT, finished);
+  });
+  Ok(started)
+}
+
+/// A file's content with every match replaced, how many there were, and previews of the first
+/// `FS_REPLACE_PREVIEWS_PER_FILE` changed lines taken from that same replacement.
+struct ReplacedContent {
+  content: Vec<u8>,
+  matches: u64,
+  previews: Vec<FsReplacePreview>
+}
+
+/// Replaces every non-empty match, as content search only reports non-empty ones. In `literal`
+/// mode the replacement is inserted as is, otherwise `$1` and `${name}` are expanded.
+fn replace_matches(regex: &regex::bytes::Regex, content: &[u8], replacement: &[u8], literal: bool) -> ReplacedContent {
+  /// The original lines `start..end` and where their replacement starts in the output.
+  struct Region {
+    line_number: u64,
+    start: usize,
+    end: usize,
+    output_start: usize,
+    /// Output length and input offset after the region's last match, to place its end.
+    output_mark: usize,
+    input_mark: usize
+  }
+
+  let mut output = Vec::with_capacity(content.len());
+  let mut matches = 0;
+  let mut regions: Vec<Region> = Vec::new();
+  let mut last = 0;
+  let mut line_number = 1;
+  let mut counted_to = 0;
+  for captures in regex.captures_iter(content) {
+    let Some(found) = captures.get(0).filter(|found| found.end() > found.start()) else {
+      continue;
+    };
+    let line_start = content[..found.start()]
+      .iter()
+      .rposition(|&byte| byte == b'\n')
+      .map_or(0, |index| index + 1);
+    let line_end = content[found.end()..]
+      .iter()
+      .position(|&byte| byte == b'\n')
+      .map_or(content.len(), |index| found.end() + index);
+    let previous = regions.last_mut().filter(|region| line_start <= region.end);
+    if let Some(region) = previous {
+      // Lines already covered by the previous region, e.g. a second match on the same line.
+      region.end = region.end.max(line_end);
+    } else if regions.len() < FS_REPLACE_PREVIEWS_PER_FILE {
+      line_number += content[counted_to..line_start].iter().filter(|&&byte| byte == b'\n').count() as u64;
+      counted_to = line_start;
+      regions.push(Region {
+        line_number,
+        start: line_start,
+        end: line_end,
+        output_start: output.len() + (line_start - last),
+        output_mark: 0,
+        input_mark: 0
+      });
+    }
+
+    output.extend_from_slice(&content[last..found.start()]);
+    if literal {
+      output.extend_from_slice(replacement);
+    } else {
+      captures.expand(replacement, &mut output);
+    }
+    last = found.end();
+    matches += 1;
+    if let Some(region) = regions.last_mut().filter(|region| region.end >= last) {
+      region.output_mark = output.len();
+      region.input_mark = last;
+    }
+  }
+  output.extend_from_slice(&content[last..]);
+
+  let shown = |text: &[u8]| {
+    let text = text.strip_suffix(b"\r").unwrap_or(text);
+    String::from_utf8_lossy(&text[..text.len().min(FS_GREP_LINE_MAX_BYTES)]).into_owned()
+  };
+  let previews = regions
+    .iter()
+    .map(|region| {
+      let output_end = region.output_mark + (region.end - region.input_mark);
+      FsReplacePreview {
+        line_number: region.line_number,
+        before: shown(&content[region.start..region.end]),
+        after: shown(&output[region.output_start..output_end])
+      }
+    })
+    .collect();
+  ReplacedContent {
+    content: output,
+    matches,
+    previews
+  }
+}
+
+struct ContentSearch {
+  root: PathBuf,
+  /// Paths under `root` to walk instead of all of it; empty for the whole root.
+  scopes: Vec<PathBuf>,
+  regex: regex::bytes::Regex,
+  overrides: ignore::overrides::Override,
+  max_matches: usize,
+  respect_gitignore: bool,
+  skip_dirs: Vec<String>
+}
+
+struct ContentSearchOutcome {
+  searched_files: u64,
+  matched_files: u64,
+  total_matches: u64,
+  limit_reached: bool,
+  binary_files_skipped: u64,
+  binary_files: Vec<String>
+}
+
+impl ContentSearch {
+  /// Resolves the root and compiles the query and globs, so a bad pattern fails the command
+  /// rather than the background search.
+  fn new(startup_context: &StartupContext, query: FsContentQuery, max_matches: usize) -> Result<Self, String> {
+    let root = resolve_workspace_path(startup_context, &query.root)?;
+    if !root.is_dir() {
+      return Err(format!("{} is not a directory", root.display()));
+    }
+    if query.query.is_empty() {
+      return Err(String::from("the search query is empty"));
+    }
+    let pattern = if query.is_regex {
+      query.query.clone()
+    } else {
+      regex::escape(&query.query)
+    };
+    let pattern = if query.whole_word {
+      format!(r"\b(?:{pattern})\b")
+    } else {
+      pattern
+    };
+    // Multi-line so `^` and `$` mean the same to a whole-file replace as to the line-by-line
+    // search, with `$` before a CRLF as well as an LF.
+    let regex = regex::bytes::RegexBuilder::new(&pattern)
+      .case_insensitive(!query.case_sensitive)
+      .multi_line(true)
+      .crlf(true)
+      .build()
+      .map_err(|error| format!("invalid search pattern: {error}"))?;
+
+    let mut overrides = ignore::overrides::OverrideBuilder::new(&root);
+    for glob in &query.include_globs {
+      overrides
+        .add(glob)
+        .map_err(|error| format!("invalid include pattern \"{glob}\": {error}"))?;
+    }
+    for glob in &query.exclude_globs {
+      overrides
+        .add(&format!("!{glob}"))
+        .map_err(|error| format!("invalid exclude pattern \"{glob}\": {error}"))?;
+    }
+    let overrides = overrides
+      .build()
+      .map_err(|error| format!("invalid search patterns: {error}"))?;
+    let scopes = resolve_search_scopes(&root, &query.scope_paths)?;
+
+    Ok(Self {
+      root,
+      scopes,
+      regex,
+      overrides,
+      max_matches,
+      respect_gitignore: query.respect_gitignore.unwrap_or(true),
+      skip_dirs: query
+        .skip_dirs
+        .unwrap_or_else(|| FS_SEARCH_SKIPPED_DIRS.iter().map(|name| name.to_string()).collect())
+    })
+  }
+
+  /// A walker over the scopes, or the whole root when there are none.
+  fn walk_builder(&self) -> ignore::WalkBuilder {
+    let mut starts = self.scopes.iter();
+    let mut builder = search_walk_builder(
+      starts.next().unwrap_or(&self.root),
+      self.respect_gitignore,
+      self.skip_dirs.clone()
+    );
+    for start in starts {
+      builder.add(start);
+    }
+    builder.overrides(self.overrides.clone());
+    builder
+  }
+
+  fn scope_of(&self, path: &Path) -> Option<String> {
+    self
+      .scopes
+      .iter()
+      .find(|scope| path.starts_with(scope))
+      .map(|scope| scope.to_string_lossy().into_owned())
+  }
+
+  /// Searches files on the `ignore` crate's worker threads, handing each matching line to
+  /// `on_match` as it is found. Stops once `max_matches` lines have matched.
+  fn run(&self, cancelled: &AtomicBool, on_match: &(dyn Fn(FsGrepMatch) + Sync)) -> ContentSearchOutcome {
+    let searched_files = AtomicU64::new(0);
+    let matched_files = AtomicU64::new(0);
+    let total_matches = AtomicU64::new(0);
+    let limit_reached = AtomicBool::new(false);
+    let binary_files = Mutex::new((0_u64, Vec::new()));
+    let walker = self.walk_builder().build_parallel();
+
+    walker.run(|| {
+      Box::new(|entry| {
+        if cancelled.load(Ordering::SeqCst) || limit_reached.load(Ordering::SeqCst) {
+          return ignore::WalkState::Quit;
+        }
+        let Ok(entry) = entry else {
+          return ignore::WalkState::Continue;
+        };
+        if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+          return ignore::WalkState::Continue;
+        }
+        let Ok(file) = fs::File::open(entry.path()) else {
+          return ignore::WalkState::Continue;
+        };
+        searched_files.fetch_add(1, Ordering::Relaxed);
+        let mut reader = std::io::BufReader::with_capacity(64 * 1024, file);
+        let is_binary = std::io::BufRead::fill_buf(&mut reader)
+          .map(|head| head[..head.len().min(BINARY_SNIFF_BYTES)].contains(&0))
+          .unwrap_or(true);
+        if is_binary {
+          let mut binary_files = lock_recover(&binary_files);
+          binary_files.0 += 1;
+          if binary_files.1.len() < FS_GREP_BINARY_FILES_LISTED {
+            binary_files.1.push(entry.path().to_string_lossy().into_owned());
+          }
+          return ignore::WalkState::Continue;
+        }
+
+        let path = entry.path().to_string_lossy().into_owned();
+        let relative_path = entry
+          .path()
+          .strip_prefix(&self.root)
+          .unwrap_or(entry.path())
+          .to_string_lossy()
+          .replace('\\', "/");
+        let scope = self.scope_of(entry.path());
+        let mut file_matched = false;
+        let mut line = Vec::new();
+        let mut line_number = 0_u64;
+        loop {
+          line.clear();
+          match read_line_capped(&mut reader, &mut line, FS_GREP_SCAN_LINE_MAX_BYTES) {
+            Ok((0, _)) | Err(_) => break,
+            Ok(_) => {}
+          }
+          line_number += 1;
+          let content = line.strip_suffix(b"\n").unwrap_or(&line);
+          let content = content.strip_suffix(b"\r").unwrap_or(content);
+          let mut submatches = self
+            .regex
+            .find_iter(content)
+            .filter(|found| found.end() > found.start())
+            .map(|found| FsGrepSubmatch {
+              start: found.start(),
+              end: found.end()
+            })
+            .peekable();
+          if submatches.peek().is_none() {
+            continue;
+          }
+          if total_matches.fetch_add(1, Ordering::SeqCst) >= self.max_matches as u64 {
+            total_matches.fetch_sub(1, Ordering::SeqCst);
+            limit_reached.store(true, Ordering::SeqCst);
+            break;
+          }
+
+          let truncated = content.len() > FS_GREP_LINE_MAX_BYTES;
+          let shown = &content[..content.len().min(FS_GREP_LINE_MAX_BYTES)];
+          let line_text = String::from_utf8_lossy(shown);
+          // Replacement characters are wider than the bytes they stand for, so offsets into a
+          // lossily decoded line are moved to match.
+          let aligned = line_text.len() == shown.len();
+          let offset = |index: usize| {
+            if aligned {
+              index
+            } else {
+              String::from_utf8_lossy(&shown[..index]).len()
+            }
+          };
+          let submatches: Vec<FsGrepSubmatch> = submatches
+            .filter(|submatch| submatch.end <= shown.len())
+            .map(|submatch| FsGrepSubmatch {
+              start: offset(submatch.start),
+              end: offset(submatch.end)
+            })
+            .collect();
+          file_matched = true;
+          on_match(FsGrepMatch {
+            path: path.clone(),
+            relative_path: relative_path.clone(),
+            line_number,
+            line: line_text.into_owned(),
+            submatches,
+            truncated,
+            scope: scope.clone()
+          });
+          if cancelled.load(Ordering::SeqCst) {
+            break;
+          }
+        }
+        if file_matched {
+          matched_files.fetch_add(1, Ordering::Relaxed);
+        }
+        ignore::WalkState::Continue
+      })
+    });
+
+    let (binary_files_skipped, binary_files) = binary_files.into_inner().unwrap_or_else(PoisonError::into_inner);
+    ContentSearchOutcome {
+      searched_files: searched_files.into_inner(),
+      matched_files: matched_files.into_inner(),
+      total_matches: total_matches.into_inner(),
+      limit_reached: limit_reached.into_inner(),
+      binary_files_skipped,
+      binary_files
+    }
+  }
+}
+
+/// Best first: highest score, then the shorter path, then alphabetical. Keeps at most `limit`.
+fn rank_search_matches(matches: &mut Vec<FsSearchMatch>, limit: usize) {
+  matches.sort_by(|left, right| {
+    right
+      .score
+      .cmp(&left.score)
+      .then_with(|| left.relative_path.len().cmp(&right.relative_path.len()))
+      .then_with(|| left.relative_path.cmp(&right.relative_path))
+  });
+  matches.truncate(limit);
+}
+
+/// Scores `path` against a lowercased query matched as a subsequence, or `None` when it does not
+/// match. Characters are taken greedily; runs of consecutive characters, matches at the start of
+/// a path segment or word, and matches within the file name score higher, and long paths lower.
+fn fuzzy_path_score(query: &[char], path: &str) -> Option<i64> {
+  let characters: Vec<char> = path.chars().collect();
+  let name_start = characters
+    .iter()
+    .rposition(|&character| character == '/')
+    .map_or(0, |index| index + 1);
+  let mut score = 0_i64;
+  let mut position = 0;
+  let mut previous: Option<usize> = None;
+  for &wanted in query {
+    let index = (position..characters.len()).find(|&index| characters[index].to_lowercase().eq(std::iter::once(wanted)))?;
+    score += 1;
+    if previous.is_some_and(|previous| previous + 1 == index) {
+      score += 5;
+    }
+    let at_boundary = index == 0
+      || matches!(characters[index - 1], '/' | '_' | '-' | '.' | ' ')
+      || (characters[index - 1].is_lowercase() && characters[index].is_uppercase());
+    if at_boundary {
+      score += 8;
+    }
+    if index >= name_start {
+      score += 3;
+    }
+    previous = Some(index);
+    position = index + 1;
+  }
+  Some(score * 16 - characters.len() as i64)
+}
+
+/// Lists a directory tree depth-first on a worker thread and reports it through
+/// `filesystem:list-recursive-finished`. Each directory's children come in `filesystem_list`
+/// order; symlinked directories are reported but not followed.
+#[tauri::command]
+fn filesystem_list_recursive(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  registry: State<'_, OperationRegistry>,
+  request: FsListRecursiveRequest
+) -> Result<FsListRecursiveStarted, String> {
+  let target_path = resolve_workspace_path(&startup_context, &request.path)?;
+  let max_depth = request.max_depth.min(FILESYSTEM_LIST_MAX_DEPTH) as u32;
+
+  let operation_id = Uuid::new_v4().to_string();
+  let cancelled = Arc::new(AtomicBool::new(false));
+  lock_recover(&registry.operations).insert(
+    operation_id.clone(),
+    OperationHandle {
+      kind: "list",
+      cancelled: cancelled.clone()
+    }
+  );
+
+  let started = FsListRecursiveStarted {
+    operation_id: operation_id.clone()
+  };
+  std::thread::spawn(move || {
+    let mut warnings = Vec::new();
+    let result = list_recursive(
+      &target_path,
+      max_depth,
+      request.include_hidden,
+      FILESYSTEM_LIST_RECURSIVE_MAX_ENTRIES,
+      &cancelled,
+      &mut warnings
+    );
+    let (entries, truncated, error) = match result {
+      Ok((entries, truncated)) => (entries, truncated, None),
+      Err(error) => (Vec::new(), false, Some(error))
+    };
+    finish_operation(
+      &app,
+      &operation_id,
+      FILESYSTEM_LIST_RECURSIVE_FINISHED_EVENT,
+      FsListRecursiveFinishedEvent {
+        operation_id: operation_id.clone(),
+        path: target_path.to_string_lossy().into_owned(),
+        entries,
+        truncated,
+        warnings,
+        error
+      }
+    );
+  });
+  Ok(started)
+}
+
+/// The walk behind `filesystem_list_recursive`. Returns the entries and whether `max_entries`
+/// cut it short; what could not be read below the top directory goes into `warnings`.
+fn list_recursive(
+  target_path: &Path,
+  max_depth: u32,
+  include_hidden: bool,
+  max_entries: usize,
+  cancelled: &AtomicBool,
+  warnings: &mut Vec<String>
+) -> Result<(Vec<FilesystemEntry>, bool), String> {
+  let read_children = |directory: &Path, depth: u32, warnings: &mut Vec<String>| {
+    let (entries, unreadable) = read_dir_entries(directory)?;
+    warnings.extend(unreadable);
+    let mut children: Vec<FilesystemEntry> = entries
+      .into_iter()
+      .filter(|entry| include_hidden || !entry.file_name().to_string_lossy().starts_with('.'))
+      .filter_map(|entry| filesystem_dir_entry(&entry))
+      .map(|entry| FilesystemEntry { depth: Some(depth), ..entry })
+      .collect();
+    sort_filesystem_entries(&mut children, FsSortOptions::default());
+    // Reversed so popping the stack visits children in order.
+    children.reverse();
+    Ok::<_, std::io::Error>(children)
+  };
+
+  let mut list = Vec::new();
+  if max_depth == 0 {
+    return Ok((list, false));
+  }
+  let mut stack = read_children(target_path, 1, warnings)
+    .map_err(|error| io_error(&format!("failed to list {}", target_path.display()), error))?;
+  while let Some(entry) = stack.pop() {
+    if cancelled.load(Ordering::SeqCst) {
+      return Err(String::from("CANCELLED: the listing was cancelled"));
+    }
+    if list.len() >= max_entries {
+      return Ok((list, true));
+    }
+    let depth = entry.depth.unwrap_or_default();
+    if entry.is_directory && !entry.is_symlink && depth < max_depth {
+      match read_children(Path::new(&entry.path), depth + 1, warnings) {
+        Ok(children) => stack.extend(children),
+        Err(error) => warnings.push(format!("failed to list {}: {error}", entry.path))
+      }
+    }
+    list.push(entry);
+  }
+
+  Ok((list, false))
+}
+
+/// Loads the tree under a directory in one round trip, breadth-first so that the entry limit
+/// cuts off the deepest levels rather than whole sibling subtrees. Symlinked directories are
+/// followed unless they lead back to one of their own ancestors.
+#[tauri::command]
+fn fs_tree(startup_context: State<'_, StartupContext>, request: FsTreeRequest) -> Result<FsTreeNode, String> {
+  let target_path = resolve_workspace_path(&startup_context, &request.path)?;
+  let link_metadata = fs::symlink_metadata(&target_path)
+    .map_err(|error| io_error(&format!("failed to list {}", target_path.display()), error))?;
+  let root = filesystem_entry(&target_path, &link_metadata);
+  if !root.is_directory {
+    return Err(format!("{} is not a directory", target_path.display()));
+  }
+
+  let max_depth = request
+    .max_depth
+    .unwrap_or(FS_TREE_DEFAULT_DEPTH)
+    .min(FILESYSTEM_LIST_MAX_DEPTH) as u32;
+  let max_entries_per_directory = request
+    .max_entries_per_directory
+    .unwrap_or(FS_TREE_DEFAULT_ENTRIES_PER_DIRECTORY)
+    .max(1);
+  let listing = FsListRequest {
+    sort_by: request.sort_by,
+    sort_order: request.sort_order,
+    directories_first: request.directories_first,
+    natural_sort: request.natural_sort,
+    include_hidden: request.include_hidden,
+    exclude_globs: request.exclude_globs,
+    respect_gitignore: request.respect_gitignore,
+    hide_ignored: request.hide_ignored,
+    ..FsListRequest::default()
+  };
+
+  // Nodes are collected flat with their parent's index, then assembled into the tree.
+  let mut nodes = vec![FsTreeNode {
+    entry: root,
+    children: None,
+    children_truncated: false,
+    not_loaded: false,
+    warnings: Vec::new()
+  }];
+  let mut parents = vec![0];
+  let mut queue = VecDeque::from([(0, Vec::new())]);
+  let mut remaining = FS_TREE_MAX_ENTRIES;
+  while let Some((index, mut ancestors)) = queue.pop_front() {
+    let node = &mut nodes[index];
+    let depth = node.entry.depth.unwrap_or(0);
+    if depth >= max_depth || remaining == 0 {
+      node.not_loaded = true;
+      continue;
+    }
+    let directory = PathBuf::from(&node.entry.path);
+    let listed = fs::canonicalize(&directory)
+      .map_err(|error| io_error(&format!("failed to resolve {}", directory.display()), error))
+      .and_then(|canonical| {
+        if ancestors.contains(&canonical) {
+          return Ok(None);
+        }
+        let listed = list_directory(&startup_context, &directory, &listing)?;
+        Ok(Some((canonical, listed)))
+      });
+    let (canonical, (mut entries, warnings)) = match listed {
+      Ok(Some(listed)) => listed,
+      // A symlink leading back to one of its own ancestors.
+      Ok(None) => {
+        node.not_loaded = true;
+        continue;
+      }
+      Err(message) if index == 0 => return Err(message),
+      Err(message) => {
+        node.not_loaded = true;
+        node.entry.error.get_or_insert(message);
+        continue;
+      }
+    };
+
+    let limit = max_entries_per_directory.min(remaining);
+    node.children_truncated = entries.len() > limit;
+    entries.truncate(limit);
+    remaining -= entries.len();
+    node.children = Some(Vec::with_capacity(entries.len()));
+    node.warnings = warnings;
+    ancestors.push(canonical);
+    for mut entry in entries {
+      entry.depth = Some(depth + 1);
+      if entry.is_directory {
+        queue.push_back((nodes.len(), ancestors.clone()));
+      }
+      nodes.push(FsTreeNode {
+        entry,
+        children: None,
+        children_truncated: false,
+        not_loaded: false,
+        warnings: Vec::new()
+      });
+      parents.push(index);
+    }
+  }
+
+  // Children always come after their parent, so walking backwards finishes each node before
+  // it is moved into its parent. Siblings arrive in reverse and are flipped once complete.
+  while nodes.len() > 1 {
+    let mut node = nodes.pop().expect("more than one node");
+    if let Some(children) = node.children.as_mut() {
+      children.reverse();
+    }
+    let parent = parents[nodes.len()];
+    if let Some(children) = nodes[parent].children.as_mut() {
+      children.push(node);
+    }
+  }
+  let mut root = nodes.pop().expect("the root node");
+  if let Some(children) = root.children.as_mut() {
+    children.reverse();
+  }
+  Ok(root)
+}
+
+fn exists_error(path: &Path) -> String {
+  message_error("EXISTS", "file.exists", &[("path", path.display().to_string())])
+}
+
+/// Creates a directory, failing with EXISTS rather than succeeding when something is already
+/// at the path.
+#[tauri::command]
+fn fs_mkdir(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  request: FsMkdirRequest
+) -> Result<FilesystemEntry, String> {
+  ensure_not_closing(&app)?;
+  let path = resolve_workspace_path(&startup_context, &request.path)?;
+  if fs::symlink_metadata(&path).is_ok() {
+    return Err(exists_error(&path));
+  }
+  let created = if request.recursive {
+    fs::create_dir_all(&path)
+  } else {
+    fs::create_dir(&path)
+  };
+  match created {
+    Ok(()) => {}
+    Err(error) if error.kind() == ErrorKind::AlreadyExists => return Err(exists_error(&path)),
+    Err(error) => return Err(io_error(&format!("failed to create {}", path.display()), error))
+  }
+  let metadata = fs::metadata(&path).map_err(|error| io_error(&format!("failed to stat {}", path.display()), error))?;
+  Ok(filesystem_entry(&path, &metadata))
+}
+
+/// Creates a new file, optionally with content. An existing file is never truncated.
+#[tauri::command]
+fn fs_create_file(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  request: FsCreateFileRequest
+) -> Result<FilesystemEntry, String> {
+  ensure_not_closing(&app)?;
+  let path = resolve_workspace_path(&startup_context, &request.path)?;
+  if fs::symlink_metadata(&path).is_ok() {
+    return Err(exists_error(&path));
+  }
+  if request.create_parents {
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
+    }
+  }
+
+  let mut options = OpenOptions::new();
+  options.write(true);
+  if request.exclusive {
+    options.create_new(true);
+  } else {
+    options.create(true).truncate(false);
+  }
+  let mut file = match options.open(&path) {
+    Ok(file) => file,
+    Err(error) if error.kind() == ErrorKind::AlreadyExists => return Err(exists_error(&path)),
+    Err(error) => return Err(io_error(&format!("failed to create {}", path.display()), error))
+  };
+  if let Some(content) = request.content.as_deref().filter(|content| !content.is_empty()) {
+    // Without O_EXCL another writer may have won the race; never write over its data.
+    if file.metadata().is_ok_and(|metadata| metadata.len() > 0) {
+      return Err(exists_error(&path));
+    }
+    file
+      .write_all(content.as_bytes())
+      .map_err(|error| io_error(&format!("failed to write {}", path.display()), error))?;
+  }
+  let metadata = file
+    .metadata()
+    .map_err(|error| io_error(&format!("failed to stat {}", path.display()), error))?;
+  Ok(filesystem_entry(&path, &metadata))
+}
+
+/// Metadata for a single path. A missing path fails with NOT_FOUND so callers can treat it as
+/// deleted.
+#[tauri::command]
+fn fs_stat(startup_context: State<'_, StartupContext>, request: FsStatRequest) -> Result<FsStatResponse, String> {
+  stat_path(&resolve_workspace_path(&startup_context, &request.path)?)
+}
+
+fn stat_path(path: &Path) -> Result<FsStatResponse, String> {
+  let link_metadata = match fs::symlink_metadata(path) {
+    Ok(metadata) => metadata,
+    Err(error) if error.kind() == ErrorKind::NotFound => {
+      return Err(message_error("NOT_FOUND", "file.notFound", &[("path", path.display().to_string())]));
+    }
+    Err(error) => return Err(io_error(&format!("failed to stat {}", path.display()), error))
+  };
+  let mut entry = filesystem_entry(path, &link_metadata);
+  if entry.is_symlink && !entry.broken {
+    entry.symlink_target = fs::canonicalize(path)
+      .ok()
+      .map(|target| target.to_string_lossy().into_owned())
+      .or(entry.symlink_target);
+  }
+  let metadata = if entry.is_symlink {
+    fs::metadata(path).unwrap_or(link_metadata)
+  } else {
+    link_metadata
+  };
+
+  #[cfg(unix)]
+  let (mode, writable) = {
+    use std::os::unix::fs::PermissionsExt;
+    let writable = nix::unistd::access(path, nix::unistd::AccessFlags::W_OK).is_ok();
+    (Some(metadata.permissions().mode() & 0o7777), writable)
+  };
+  #[cfg(not(unix))]
+  let (mode, writable) = (None, !metadata.permissions().readonly());
+
+  let is_binary = metadata.is_file().then(|| looks_binary(path).unwrap_or(false));
+  let is_sparse = metadata
+    .is_file()
+    .then(|| looks_sparse(metadata.len(), size_on_disk(&metadata)));
+  Ok(FsStatResponse {
+    entry,
+    ctime_ms: changed_time_ms(&metadata),
+    mode,
+    writable,
+    is_binary,
+    is_sparse
+  })
+}
+
+/// Renames or moves a file or directory within the workspace. Moves across filesystems fall
+/// back to copy and delete.
+#[tauri::command]
+fn fs_rename(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  request: FsRenameRequest
+) -> Result<FilesystemEntry, String> {
+  ensure_not_closing(&app)?;
+  let source_path = resolve_workspace_path(&startup_context, &request.source_path)?;
+  let destination_path = resolve_workspace_path(&startup_context, &request.destination_path)?;
+  let source_metadata = fs::symlink_metadata(&source_path)
+    .map_err(|error| io_error(&format!("failed to stat {}", source_path.display()), error))?;
+
+  // A case-only rename on a case-insensitive filesystem finds the source at the destination.
+  let same_file = same_directory_entry(&source_path, &destination_path);
+  if let (Ok(destination_metadata), false) = (fs::symlink_metadata(&destination_path), same_file) {
+    let destination_is_full_directory = destination_metadata.is_dir()
+      && fs::read_dir(&destination_path).map_or(true, |mut entries| entries.next().is_some());
+    if source_metadata.is_dir() && destination_is_full_directory {
+      return Err(message_error(
+        "NOT_EMPTY",
+        "file.directoryNotEmpty",
+        &[("path", destination_path.display().to_string())]
+      ));
+    }
+    if !request.overwrite {
+      return Err(exists_error(&destination_path));
+    }
+  }
+
+  if let Some(parent) = destination_path.parent() {
+    fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
+  }
+  let rename_error = |error| {
+    io_error(
+      &format!("failed to move {} to {}", source_path.display(), destination_path.display()),
+      error
+    )
+  };
+  match fs::rename(&source_path, &destination_path) {
+    Ok(()) => {}
+    Err(error) if is_cross_device_error(&error) => {
+      // Copy next to the destination and rename it into place, so a failed copy leaves
+      // whatever was already there untouched.
+      let temp_path = temp_sibling_path(&destination_path);
+      let moved = copy_recursively(&source_path, &temp_path).and_then(|()| fs::rename(&temp_path, &destination_path));
+      if let Err(error) = moved {
+        let _ = remove_path(&temp_path);
+        return Err(rename_error(error));
+      }
+      remove_path(&source_path).map_err(|error| io_error(&format!("failed to remove {}", source_path.display()), error))?;
+    }
+    Err(error) => return Err(rename_error(error))
+  }
+
+  let metadata = fs::symlink_metadata(&destination_path)
+    .map_err(|error| io_error(&format!("failed to stat {}", destination_path.display()), error))?;
+  Ok(filesystem_entry(&destination_path, &metadata))
+}
+
+/// Whether both paths name the same directory entry. A final symlink is not followed, so a link
+/// and the file it points to are different entries.
+fn same_directory_entry(left: &Path, right: &Path) -> bool {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::symlink_metadata(left), fs::symlink_metadata(right)) {
+      (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+      _ => false
+    }
+  }
+  #[cfg(not(unix))]
+  {
+    // Same canonical parent and the same name up to case, as NTFS compares names.
+    let entry = |path: &Path| {
+      let parent = fs::canonicalize(path.parent()?).ok()?;
+      Some((parent, path.file_name()?.to_string_lossy().to_lowercase()))
+    };
+    entry(left).is_some() && entry(left) == entry(right)
+  }
+}
+
+fn is_cross_device_error(error: &std::io::Error) -> bool {
+  #[cfg(unix)]
+  {
+    error.raw_os_error() == Some(nix::libc::EXDEV)
+  }
+  #[cfg(windows)]
+  {
+    // ERROR_NOT_SAME_DEVICE
+    error.raw_os_error() == Some(17)
+  }
+  #[cfg(not(any(unix, windows)))]
+  {
+    let _ = error;
+    false
+  }
+}
+
+/// Copies a file, symlink or directory tree. Symlinks are copied as links, not followed.
+fn copy_recursively(source: &Path, destination: &Path) -> std::io::Result<()> {
+  let metadata = fs::symlink_metadata(source)?;
+  if metadata.file_type().is_symlink() {
+    let target = fs::read_link(source)?;
+    #[cfg(unix)]
+    {
+      std::os::unix::fs::symlink(target, destination)
+    }
+    #[cfg(windows)]
+    {
+      if fs::metadata(source).is_ok_and(|metadata| metadata.is_dir()) {
+        std::os::windows::fs::symlink_dir(target, destination)
+      } else {
+        std::os::windows::fs::symlink_file(target, destination)
+      }
+    }
+  } else if metadata.is_dir() {
+    fs::create_dir_all(destination)?;
+    fs::set_permissions(destination, metadata.permissions())?;
+    for entry in fs::read_dir(source)? {
+      let entry = entry?;
+      copy_recursively(&entry.path(), &destination.join(entry.file_name()))?;
+    }
+    Ok(())
+  } else {
+    fs::copy(source, destination).map(|_| ())
+  }
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+  if fs::symlink_metadata(path)?.is_dir() {
+    fs::remove_dir_all(path)
+  } else {
+    fs::remove_file(path)
+  }
+}
+
+/// Deletes a file or directory, either permanently or by moving it to the freedesktop trash.
+#[tauri::command]
+fn fs_delete(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  request: FsDeleteRequest
+) -> Result<FsDeleteResult, String> {
+  ensure_not_closing(&app)?;
+  let path = resolve_workspace_path(&startup_context, &request.path)?;
+  let metadata = fs::symlink_metadata(&path)
+    .map_err(|error| io_error(&format!("failed to stat {}", path.display()), error))?;
+  if metadata.is_dir() && !request.recursive {
+    let is_empty = fs::read_dir(&path)
+      .map_err(|error| io_error(&format!("failed to list {}", path.display()), error))?
+      .next()
+      .is_none();
+    if !is_empty {
+      return Err(message_error("NOT_EMPTY", "file.directoryNotEmpty", &[("path", path.display().to_string())]));
+    }
+  }
+
+  let display_path = path.to_string_lossy().into_owned();
+  if request.use_trash {
+    #[cfg(unix)]
+    {
+      let trashed = trash::move_to_trash(&path)?;
+      return Ok(FsDeleteResult {
+        path: display_path,
+        action: FsDeleteAction::Trashed,
+        trashed_path: Some(trashed.file_path.to_string_lossy().into_owned()),
+        trash_info_path: Some(trashed.info_path.to_string_lossy().into_owned())
+      });
+    }
+    #[cfg(not(unix))]
+    return Err(message_error("UNSUPPORTED", "file.trashUnsupported", &[]));
+  }
+
+  let removed = if !metadata.is_dir() {
+    fs::remove_file(&path)
+  } else if request.recursive {
+    fs::remove_dir_all(&path)
+  } else {
+    fs::remove_dir(&path)
+  };
+  removed.map_err(|error| io_error(&format!("failed to delete {}", path.display()), error))?;
+  Ok(FsDeleteResult {
+    path: display_path,
+    action: FsDeleteAction::Deleted,
+    trashed_path: None,
+    trash_info_path: None
+  })
+}
+
+/// Freedesktop trash support. Items on the home partition go to `$XDG_DATA_HOME/Trash`; items
+/// elsewhere go to `$topdir/.Trash/$uid` when the administrator created a sticky `.Trash`, or
+/// else to `$topdir/.Trash-$uid`.
+#[cfg(unix)]
+mod trash {
+  use super::*;
+  use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+  pub(super) struct TrashedItem {
+    pub(super) file_path: PathBuf,
+    pub(super) info_path: PathBuf
+  }
+
+  struct TrashDir {
+    root: PathBuf,
+    /// The top directory relative paths in `.trashinfo` are resolved against. `None` for the
+    /// home trash, which records absolute paths.
+    topdir: Option<PathBuf>
+  }
+
+  pub(super) fn move_to_trash(path: &Path) -> Result<TrashedItem, String> {
+    let parent = path
+      .parent()
+      .and_then(|parent| fs::canonicalize(parent).ok())
+      .ok_or_else(|| format!("failed to resolve parent of {}", path.display()))?;
+    let file_name = path
+      .file_name()
+      .ok_or_else(|| format!("cannot trash {}", path.display()))?;
+    let original_path = parent.join(file_name);
+    let device = fs::symlink_metadata(&original_path)
+      .map_err(|error| io_error(&format!("failed to stat {}", original_path.display()), error))?
+      .dev();
+
+    let trash = trash_dir_for(&original_path, device)?;
+    let files_dir = trash.root.join("files");
+    let info_dir = trash.root.join("info");
+    for directory in [&files_dir, &info_dir] {
+      fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(directory)
+        .map_err(|error| io_error(&format!("failed to create {}", directory.display()), error))?;
+    }
+
+    let recorded_path = match &trash.topdir {
+      Some(topdir) => original_path.strip_prefix(topdir).unwrap_or(&original_path),
+      None => original_path.as_path()
+    };
+    let info = format!(
+      "[Trash Info]\nPath={}\nDeletionDate={}\n",
+      encode_trash_path(recorded_path),
+      chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+
+    // The info file is created exclusively first; that reserves the name for this item.
+    let base_name = file_name.to_string_lossy().into_owned();
+    for attempt in 1u32.. {
+      let name = if attempt == 1 {
+        base_name.clone()
+      } else {
+        format!("{base_name}.{attempt}")
+      };
+      let file_path = files_dir.join(&name);
+      let info_path = info_dir.join(format!("{name}.trashinfo"));
+      if fs::symlink_metadata(&file_path).is_ok() {
+        continue;
+      }
+      let mut info_file = match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => continue,
+        Err(error) => return Err(io_error(&format!("failed to create {}", info_path.display()), error))
+      };
+      let moved = info_file
+        .write_all(info.as_bytes())
+        .and_then(|()| info_file.sync_all())
+        .and_then(|()| fs::rename(&original_path, &file_path));
+      if let Err(error) = moved {
+        let _ = fs::remove_file(&info_path);
+        return Err(io_error(&format!("failed to move {} to the trash", original_path.display()), error));
+      }
+      return Ok(TrashedItem { file_path, info_path });
+    }
+    unreachable!("trash name candidates are unbounded")
+  }
+
+  fn trash_dir_for(path: &Path, device: u64) -> Result<TrashDir, String> {
+    if let Some(data_home) = xdg_data_dirs().into_iter().next() {
+      if existing_ancestor_device(&data_home) == Some(device) {
+        return Ok(TrashDir { root: data_home.join("Trash"), topdir: None });
+      }
+    }
+
+    let topdir = mount_point(path, device);
+    let uid = nix::unistd::Uid::current().as_raw();
+    let shared = topdir.join(".Trash");
+    let shared_is_valid = fs::symlink_metadata(&shared).is_ok_and(|metadata| {
+      metadata.is_dir() && !metadata.file_type().is_symlink() && metadata.permissions().mode() & 0o1000 != 0
+    });
+    if shared_is_valid {
+      let root = shared.join(uid.to_string());
+      let usable = fs::DirBuilder::new().mode(0o700).create(&root).is_ok()
+        || fs::symlink_metadata(&root).is_ok_and(|metadata| metadata.is_dir() && !metadata.file_type().is_symlink());
+      if usable {
+        return Ok(TrashDir { root, topdir: Some(topdir) });
+      }
+    }
+
+    let root = topdir.join(format!(".Trash-{uid}"));
+    match fs::DirBuilder::new().mode(0o700).create(&root) {
+      Ok(()) => {}
+      Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+        let metadata = fs::symlink_metadata(&root)
+          .map_err(|error| io_error(&format!("failed to stat {}", root.display()), error))?;
+        if !metadata.is_dir() || metadata.file_type().is_symlink() || metadata.uid() != uid {
+          return Err(format!("refusing to use {} as a trash directory", root.display()));
+        }
+      }
+      Err(error) => return Err(io_error(&format!("failed to create {}", root.display()), error))
+    }
+    Ok(TrashDir { root, topdir: Some(topdir) })
+  }
+
+  /// Device of `path`, or of its nearest existing ancestor when it does not exist yet.
+  fn existing_ancestor_device(path: &Path) -> Option<u64> {
+    path.ancestors().find_map(|ancestor| fs::metadata(ancestor).ok()).map(|metadata| metadata.dev())
+  }
+
+  /// Walks up from `path` to the highest ancestor still on `device`.
+  fn mount_point(path: &Path, device: u64) -> PathBuf {
+    let mut topdir = path.parent().unwrap_or(path).to_path_buf();
+    while let Some(parent) = topdir.parent() {
+      if fs::metadata(parent).map_or(true, |metadata| metadata.dev() != device) {
+        break;
+      }
+      topdir = parent.to_path_buf();
+    }
+    topdir
+  }
+
+  /// Percent-encodes a path for the `Path=` key, leaving `/` and RFC 2396 unreserved
+  /// characters as they are.
+  fn encode_trash_path(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+      if byte.is_ascii_alphanumeric() || b"/-_.!~*'()".contains(&byte) {
+        encoded.push(byte as char);
+      } else {
+        encoded.push_str(&format!("%{byte:02X}"));
+      }
+    }
+    encoded
+  }
+}
+
+#[tauri::command]
+fn filesystem_read(
+  startup_context: State<'_, StartupContext>,
+  settings_state: State<'_, SettingsState>,
+  request: FsReadRequest
+) -> Result<FsReadResponse, String> {
+  let target_path = resolve_workspace_path(&startup_context, &request.path)?;
+  let read_error = |error| io_error(&format!("failed to read {}", target_path.display()), error);
+  let mut file = fs::File::open(&target_path).map_err(read_error)?;
+  let metadata = file.metadata().map_err(read_error)?;
+  let size = metadata.len();
+  let mtime_ms = modified_time_ms(&metadata);
+  let max = file_read_max_bytes(&settings_state);
+  if size > max {
+    return Err(message_error(
+      "TOO_LARGE",
+      "file.tooLarge",
+      &[("path", target_path.display().to_string()), ("size", size.to_string()), ("max", max.to_string())]
+    ));
+  }
+  let mut bytes = Vec::new();
+  file.read_to_end(&mut bytes).map_err(read_error)?;
+  let path = target_path.to_string_lossy().into_owned();
+
+  if let Some((content, encoding)) = decode_text(&bytes) {
+    return Ok(FsReadResponse {
+      path,
+      content,
+      encoding: Some(encoding.name().to_string()),
+      is_binary: false,
+      truncated: false,
+      mtime_ms,
+      atomic: None
+    });
+  }
+
+  let cap = request.max_binary_bytes.unwrap_or(FILESYSTEM_READ_BINARY_MAX_BYTES);
+  let kept = usize::try_from(cap).unwrap_or(usize::MAX).min(bytes.len());
+  Ok(FsReadResponse {
+    path,
+    content: BASE64.encode(&bytes[..kept]),
+    encoding: None,
+    is_binary: true,
+    truncated: kept < bytes.len(),
+    mtime_ms,
+    atomic: None
+  })
+}
+
+/// Reads up to `max_bytes` (capped by the `fileReadMaxBytes` setting) from `offset_bytes`, for
+/// paging through files too large for `filesystem_read`. A range past the end is empty with
+/// `eof` set.
+#[tauri::command]
+fn fs_read_range(
+  startup_context: State<'_, StartupContext>,
+  settings_state: State<'_, SettingsState>,
+  request: FsReadRangeRequest
+) -> Result<FsReadRangeResponse, String> {
+  let path = resolve_workspace_path(&startup_context, &request.path)?;
+  let read_error = |error| io_error(&format!("failed to read {}", path.display()), error);
+  let mut file = fs::File::open(&path).map_err(read_error)?;
+  let total_size = file.metadata().map_err(read_error)?.len();
+  let max_bytes = request.max_bytes.min(file_read_max_bytes(&settings_state));
+
+  let mut bytes = Vec::new();
+  if request.offset_bytes < total_size {
+    file.seek(SeekFrom::Start(request.offset_bytes)).map_err(read_error)?;
+    file.take(max_bytes).read_to_end(&mut bytes).map_err(read_error)?;
+  }
+  let eof = request.offset_bytes + bytes.len() as u64 >= total_size;
+  if !eof {
+    if let Err(error) = std::str::from_utf8(&bytes) {
+      if error.error_len().is_none() && error.valid_up_to() > 0 {
+        bytes.truncate(error.valid_up_to());
+      }
+    }
+  }
+
+  Ok(FsReadRangeResponse {
+    path: path.to_string_lossy().into_owned(),
+    offset_bytes: request.offset_bytes,
+    bytes_read: bytes.len() as u64,
+    content: String::from_utf8_lossy(&bytes).into_owned(),
+    total_size,
+    eof
+  })
+}
+
+/// Reads `line_count` lines starting at `start_line`. The page stops early once it holds more
+/// than the `fileReadMaxBytes` setting. Lines past the end come back empty with `eof` set.
+#[tauri::command]
+fn fs_read_lines(
+  startup_context: State<'_, StartupContext>,
+  settings_state: State<'_, SettingsState>,
+  request: FsReadLinesRequest
+) -> Result<FsReadLinesResponse, String> {
+  use std::io::BufRead;
+
+  let path = resolve_workspace_path(&startup_context, &request.path)?;
+  let read_error = |error| io_error(&format!("failed to read {}", path.display()), error);
+  let mut reader = std::io::BufReader::new(fs::File::open(&path).map_err(read_error)?);
+  let max_bytes = file_read_max_bytes(&settings_state);
+
+  let mut line = Vec::new();
+  let mut skipped = 0;
+  let mut eof = false;
+  while skipped < request.start_line {
+    line.clear();
+    if read_line_capped(&mut reader, &mut line, max_bytes).map_err(read_error)?.0 == 0 {
+      eof = true;
+      break;
+    }
+    skipped += 1;
+  }
+
+  let mut lines = Vec::new();
+  let mut page_bytes = 0u64;
+  let mut truncated = false;
+  while !eof && lines.len() < request.line_count && page_bytes <= max_bytes {
+    line.clear();
+    let (read, cut) = read_line_capped(&mut reader, &mut line, max_bytes).map_err(read_error)?;
+    if read == 0 {
+      eof = true;
+      break;
+    }
+    page_bytes += line.len() as u64;
+    truncated |= cut;
+    let text = line.strip_suffix(b"\n").unwrap_or(&line);
+    let text = text.strip_suffix(b"\r").unwrap_or(text);
+    lines.push(String::from_utf8_lossy(text).into_owned());
+  }
+  if !eof {
+    eof = reader.fill_buf().map_err(read_error)?.is_empty();
+  }
+
+  Ok(FsReadLinesResponse {
+    path: path.to_string_lossy().into_owned(),
+    start_line: request.start_line,
+    lines,
+    truncated,
+    eof
+  })
+}
+
+/// Reads one line into `line` like `read_until`, but keeps at most `max_bytes` of it and skips
+/// the rest unread. Returns the bytes consumed from `reader` (0 at end of file) and whether the
+/// line was cut.
+fn read_line_capped<R: std::io::BufRead>(reader: &mut R, line: &mut Vec<u8>, max_bytes: u64) -> std::io::Result<(usize, bool)> {
+  use std::io::BufRead;
+
+  let mut consumed = (&mut *reader).take(max_bytes).read_until(b'\n', line)?;
+  if (consumed as u64) < max_bytes || line.last() == Some(&b'\n') {
+    return Ok((consumed, false));
+  }
+  let mut cut = false;
+  loop {
+    let buffer = reader.fill_buf()?;
+    if buffer.is_empty() {
+      return Ok((consumed, cut));
+    }
+    let (length, done) = match buffer.iter().position(|byte| *byte == b'\n') {
+      Some(index) => (index + 1, true),
+      None => (buffer.len(), false)
+    };
+    cut |= !done || length > 1;
+    reader.consume(length);
+    consumed += length;
+    if done {
+      return Ok((consumed, cut));
+    }
+  }
+}
+
+/// Streams a file through the requested digest on a worker thread, for integrity checks and for
+/// spotting changes where mtimes are unreliable. The digest arrives through
+/// `filesystem:hash-finished`.
+#[tauri::command]
+fn filesystem_hash(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  registry: State<'_, OperationRegistry>,
+  request: FilesystemHashRequest
+) -> Result<FilesystemHashStarted, String> {
+  let path = resolve_workspace_path(&startup_context, &request.path)?;
+
+  let operation_id = Uuid::new_v4().to_string();
+  let cancelled = Arc::new(AtomicBool::new(false));
+  lock_recover(&registry.operations).insert(
+    operation_id.clone(),
+    OperationHandle {
+      kind: "hash",
+      cancelled: cancelled.clone()
+    }
+  );
+
+  let started = FilesystemHashStarted {
+    operation_id: operation_id.clone()
+  };
+  std::thread::spawn(move || {
+    let (hex_digest, error) = match hash_file(&path, request.algorithm, &cancelled) {
+      Ok(hex_digest) => (Some(hex_digest), None),
+      Err(error) => (None, Some(error))
+    };
+    finish_operation(
+      &app,
+      &operation_id,
+      FILESYSTEM_HASH_FINISHED_EVENT,
+      FilesystemHashFinishedEvent {
+        operation_id: operation_id.clone(),
+        path: path.to_string_lossy().into_owned(),
+        algorithm: request.algorithm,
+        hex_digest,
+        error
+      }
+    );
+  });
+  Ok(started)
+}
+
+fn hash_file(path: &Path, algorithm: HashAlgorithm, cancelled: &AtomicBool) -> Result<String, String> {
+  let mut file = fs::File::open(path).map_err(|error| io_error(&format!("failed to read {}", path.display()), error))?;
+  match algorithm {
+    HashAlgorithm::Sha256 => hash_reader(Sha256::new(), &mut file, cancelled),
+    HashAlgorithm::Md5 => hash_reader(md5::Md5::new(), &mut file, cancelled)
+  }
+  .map_err(|error| match error {
+    Some(error) => io_error(&format!("failed to read {}", path.display()), error),
+    None => String::from("CANCELLED: the hash was cancelled")
+  })
+}
+
+/// Feeds `reader` through `hasher` a chunk at a time. Fails with `None` once `cancelled` is set.
+fn hash_reader<D: Digest>(
+  mut hasher: D,
+  reader: &mut impl Read,
+  cancelled: &AtomicBool
+) -> Result<String, Option<std::io::Error>> {
+  let mut buffer = vec![0u8; FILESYSTEM_HASH_CHUNK_BYTES];
+  loop {
+    if cancelled.load(Ordering::SeqCst) {
+      return Err(None);
+    }
+    match reader.read(&mut buffer) {
+      Ok(0) => break,
+      Ok(read) => hasher.update(&buffer[..read]),
+      Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+      Err(error) => return Err(Some(error))
+    }
+  }
+  Ok(hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Guesses from the first 8 KiB whether a file is text, using the same rules as
+/// `filesystem_read`.
+#[tauri::command]
+fn filesystem_is_text(
+  startup_context: State<'_, StartupContext>,
+  request: FilesystemIsTextRequest
+) -> Result<FilesystemIsTextResponse, String> {
+  let path = resolve_workspace_path(&startup_context, &request.path)?;
+  let read_error = |error| io_error(&format!("failed to read {}", path.display()), error);
+  let mut file = fs::File::open(&path).map_err(read_error)?;
+  let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+  (&mut file)
+    .take(BINARY_SNIFF_BYTES as u64)
+    .read_to_end(&mut head)
+    .map_err(read_error)?;
+  let complete = file.read(&mut [0u8; 1]).map_err(read_error)? == 0;
+
+  let encoding = sniff_text_encoding(&head, complete);
+  Ok(FilesystemIsTextResponse {
+    is_text: encoding.is_some(),
+    encoding: encoding.map(|encoding| encoding.name().to_string())
+  })
+}
+
+/// The encoding `decode_text` would pick for a file starting with `head`. When the file is
+/// longer than `head`, a character cut off at the end is not held against it.
+fn sniff_text_encoding(head: &[u8], complete: bool) -> Option<&'static encoding_rs::Encoding> {
+  if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(head) {
+    return Some(encoding);
+  }
+  if head.contains(&0) {
+    return None;
+  }
+  match std::str::from_utf8(head) {
+    Ok(_) => return Some(encoding_rs::UTF_8),
+    Err(error) if !complete && error.error_len().is_none() => return Some(encoding_rs::UTF_8),
+    Err(_) => {}
+  }
+
+  let mut detector = chardetng::EncodingDetector::new();
+  detector.feed(head, complete);
+  let encoding = detector.guess(None, true);
+  let mut decoder = encoding.new_decoder_without_bom_handling();
+  let mut output = String::with_capacity(head.len() * 3);
+  let (result, _) = decoder.decode_to_string_without_replacement(head, &mut output, complete);
+  matches!(
+    result,
+    encoding_rs::DecoderResult::InputEmpty | encoding_rs::DecoderResult::OutputFull
+  )
+  .then_some(encoding)
+}
+
+/// Decodes file contents as text, returning `None` for binary data. BOM-marked text and UTF-8
+/// are taken as they are; a NUL byte in the first 8 KiB means binary; anything else is guessed
+/// with chardetng and must decode without errors.
+fn decode_text(bytes: &[u8]) -> Option<(String, &'static encoding_rs::Encoding)> {
+  if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(bytes) {
+    let (text, _, had_errors) = encoding.decode(bytes);
+    return (!had_errors).then(|| (text.into_owned(), encoding));
+  }
+  if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+    return None;
+  }
+  if let Ok(text) = std::str::from_utf8(bytes) {
+    return Some((text.to_string(), encoding_rs::UTF_8));
+  }
+
+  let mut detector = chardetng::EncodingDetector::new();
+  detector.feed(bytes, true);
+  let encoding = detector.guess(None, true);
+  let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+  (!had_errors).then(|| (text.into_owned(), encoding))
+}
+
+/// Encodes text for writing. UTF-16 gets a byte order mark, since encoding_rs only encodes
+/// into ASCII-compatible encodings; characters an encoding cannot represent are an error
+/// rather than being replaced.
+fn encode_text(content: &str, label: &str) -> Result<Vec<u8>, String> {
+  let encoding = encoding_rs::Encoding::for_label(label.trim().as_bytes())
+    .ok_or_else(|| message_error("ENCODING", "file.encodingUnknown", &[("encoding", label.to_string())]))?;
+  if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+    let little_endian = encoding == encoding_rs::UTF_16LE;
+    let mut bytes = Vec::with_capacity(2 + content.len() * 2);
+    for unit in std::iter::once(0xfeff).chain(content.encode_utf16()) {
+      bytes.extend_from_slice(&if little_endian {
+        unit.to_le_bytes()
+      } else {
+        unit.to_be_bytes()
+      });
+    }
+    return Ok(bytes);
+  }
+
+  let (bytes, _, had_unmappable) = encoding.encode(content);
+  if had_unmappable {
+    return Err(message_error("ENCODING", "file.encodingUnmappable", &[("encoding", encoding.name().to_string())]));
+  }
+  Ok(bytes.into_owned())
+}
+
+#[tauri::command]
+fn filesystem_write(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  settings_state: State<'_, SettingsState>,
+  request: FsWriteRequest
+) -> Result<FsReadResponse, String> {
+  ensure_not_closing(&app)?;
+  let target_path = resolve_workspace_path(&startup_context, &request.path)?;
+  let encoded = request
+    .encoding
+    .as_deref()
+    .map(|label| encode_text(&request.content, label))
+    .transpose()?;
+
+  // Checked up front so a stale editor fails fast, and again right before the temp file
+  // replaces the target, so a change landing while the data is written is not overwritten.
+  let expected_mtime_ms = request.expected_mtime_ms.filter(|_| !request.force);
+  let content_max_bytes = request
+    .include_current_content
+    .then(|| file_read_max_bytes(&settings_state));
+  if let Some(expected_mtime_ms) = expected_mtime_ms {
+    check_write_conflict(&target_path, expected_mtime_ms, content_max_bytes)?;
+  }
+
+  if let Some(parent) = target_path.parent() {
+    fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
+  }
+
+  let bytes = encoded.as_deref().unwrap_or(request.content.as_bytes());
+  let mut conflict = None;
+  let written = write_file_atomically(
+    &target_path,
+    |file| file.write_all(bytes),
+    || match expected_mtime_ms {
+      Some(expected_mtime_ms) => {
+        check_write_conflict(&target_path, expected_mtime_ms, content_max_bytes).map_err(|error| {
+          conflict = Some(error);
+          std::io::Error::other("the file changed while it was being written")
+        })
+      }
+      None => Ok(())
+    }
+  );
+  let atomic = written.map_err(|error| {
+    conflict
+      .take()
+      .unwrap_or_else(|| io_error(&format!("failed to write {}", target_path.display()), error))
+  })?;
+  let mtime_ms = fs::metadata(&target_path)
+    .map(|metadata| modified_time_ms(&metadata))
+    .map_err(|error| io_error(&format!("failed to stat {}", target_path.display()), error))?;
+
+  let encoding = request
+    .encoding
+    .as_deref()
+    .and_then(|label| encoding_rs::Encoding::for_label(label.trim().as_bytes()))
+    .unwrap_or(encoding_rs::UTF_8);
+  Ok(FsReadResponse {
+    path: target_path.to_string_lossy().into_owned(),
+    content: request.content,
+    encoding: Some(encoding.name().to_string()),
+    is_binary: false,
+    truncated: false,
+    mtime_ms,
+    atomic: Some(atomic)
+  })
+}
+
+/// Fails with a CONFLICT carrying `WriteConflict` details when `path` was deleted or its mtime
+/// is no longer `expected_mtime_ms`. With `content_max_bytes` set, the details include the
+/// current text if it fits.
+fn check_write_conflict(path: &Path, expected_mtime_ms: u64, content_max_bytes: Option<u64>) -> Result<(), String> {
+  let current_mtime_ms = match fs::metadata(path) {
+    Ok(metadata) if modified_time_ms(&metadata) == expected_mtime_ms => return Ok(()),
+    Ok(metadata) => Some(modified_time_ms(&metadata)),
+    Err(error) if error.kind() == ErrorKind::NotFound => None,
+    Err(error) => return Err(io_error(&format!("failed to stat {}", path.display()), error))
+  };
+
+  let current = content_max_bytes
+    .filter(|_| current_mtime_ms.is_some())
+    .and_then(|max_bytes| {
+      let file = fs::File::open(path).ok()?;
+      let mut bytes = Vec::new();
+      file.take(max_bytes + 1).read_to_end(&mut bytes).ok()?;
+      (bytes.len() as u64 <= max_bytes).then_some(bytes)
+    })
+    .and_then(|bytes| decode_text(&bytes));
+  let (current_content, current_encoding) = match current {
+    Some((text, encoding)) => (Some(text), Some(encoding.name().to_string())),
+    None => (None, None)
+  };
+  let details = serde_json::to_value(WriteConflict {
+    path: path.to_string_lossy().into_owned(),
+    expected_mtime_ms,
+    current_mtime_ms,
+    current_content,
+    current_encoding
+  })
+  .ok();
+
+  let path = path.display().to_string();
+  Err(match current_mtime_ms {
+    None => message_error_with_details("CONFLICT", "file.deletedOnDisk", &[("path", path)], details),
+    Some(current_mtime_ms) => message_error_with_details(
+      "CONFLICT",
+      "file.modifiedOnDisk",
+      &[
+        ("path", path),
+        ("currentMtimeMs", current_mtime_ms.to_string()),
+        ("expectedMtimeMs", expected_mtime_ms.to_string())
+      ],
+      details
+    )
+  })
+}
+
+impl FsWatchFilter {
+  fn is_ignored(&self, relative: &Path) -> bool {
+    self.ignore.iter().any(|pattern| {
+      pattern.matches_path(relative)
+        || relative
+          .components()
+          .any(|component| pattern.matches(&component.as_os_str().to_string_lossy()))
+    })
+  }
+
+  /// Whether a change at `path` is reported: inside the root, not ignored, and in a directory
+  /// no deeper than `max_depth`.
+  fn allows(&self, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(&self.root) else {
+      return false;
+    };
+    let within_depth = self
+      .max_depth
+      .map_or(true, |max_depth| relative.components().count() <= max_depth + 1);
+    within_depth && !self.is_ignored(relative)
+  }
+
+  /// `directory` and the directories below it that should get their own watch.
+  fn directories_to_watch(&self, directory: &Path) -> Vec<PathBuf> {
+    let mut directories = Vec::new();
+    let mut pending = vec![directory.to_path_buf()];
+    while let Some(directory) = pending.pop() {
+      let Ok(relative) = directory.strip_prefix(&self.root) else {
+        continue;
+      };
+      let depth = relative.components().count();
+      if self.max_depth.is_some_and(|max_depth| depth > max_depth) || self.is_ignored(relative) {
+        continue;
+      }
+      if let Ok(entries) = fs::read_dir(&directory) {
+        pending.extend(
+          entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+            .map(|entry| entry.path())
+        );
+      }
+      directories.push(directory);
+    }
+    directories
+  }
+}
+
+fn fs_change_kind(kind: &notify::EventKind) -> Option<&'static str> {
+  use notify::event::ModifyKind;
+  use notify::EventKind;
+
+  match kind {
+    EventKind::Create(_) => Some("created"),
+    EventKind::Modify(ModifyKind::Name(_)) => Some("renamed"),
+    EventKind::Modify(_) | EventKind::Any | EventKind::Other => Some("modified"),
+    EventKind::Remove(_) => Some("removed"),
+    EventKind::Access(_) => None
+  }
+}
+
+/// Watches `path` and emits batched `fs:changed` events to the calling window until
+/// `fs_watch_stop` or the window closes.
+#[tauri::command]
+fn fs_watch_start(
+  app: AppHandle,
+  window: WebviewWindow,
+  state: State<'_, AppState>,
+  startup_context: State<'_, StartupContext>,
+  safe_mode: State<'_, SafeModeState>,
+  request: FsWatchStartRequest
+) -> Result<FsWatchStartResponse, String> {
+  ensure_subsystem_enabled(&safe_mode, "watchers")?;
+  let root = fs::canonicalize(resolve_workspace_path(&startup_context, &request.path)?)
+    .map_err(|error| io_error(&format!("failed to resolve {}", request.path), error))?;
+  let ignore = request
+    .ignore
+    .iter()
+    .map(|pattern| glob::Pattern::new(pattern).map_err(|error| format!("invalid ignore pattern \"{pattern}\": {error}")))
+    .collect::<Result<Vec<_>, _>>()?;
+  let filter = FsWatchFilter {
+    root,
+    max_depth: request.max_depth,
+    ignore
+  };
+  let per_directory = request.recursive && (filter.max_depth.is_some() || !filter.ignore.is_empty());
+
+  let (sender, receiver) = std::sync::mpsc::channel();
+  let mut watcher = notify::recommended_watcher(move |event| {
+    let _ = sender.send(event);
+  })
+  .map_err(|error| format!("failed to start watcher: {error}"))?;
+  let watch_error = |error: notify::Error| format!("failed to watch {}: {error}", filter.root.display());
+  if per_directory {
+    for directory in filter.directories_to_watch(&filter.root) {
+      watcher
+        .watch(&directory, notify::RecursiveMode::NonRecursive)
+        .map_err(watch_error)?;
+    }
+  } else {
+    let mode = if request.recursive {
+      notify::RecursiveMode::Recursive
+    } else {
+      notify::RecursiveMode::NonRecursive
+    };
+    watcher.watch(&filter.root, mode).map_err(watch_error)?;
+  }
+
+  let watch_id = Uuid::new_v4().to_string();
+  let watcher = Arc::new(Mutex::new(watcher));
+  let added_watcher = per_directory.then(|| Arc::downgrade(&watcher));
+  lock_recover(&state.watches).insert(
+    watch_id.clone(),
+    FsWatch {
+      window_label: window.label().to_string(),
+      _watcher: watcher
+    }
+  );
+
+  let window_label = window.label().to_string();
+  let thread_watch_id = watch_id.clone();
+  std::thread::spawn(move || {
+    run_fs_watch(&app, &thread_watch_id, &window_label, &receiver, &filter, added_watcher)
+  });
+  Ok(FsWatchStartResponse { watch_id })
+}
+
+#[tauri::command]
+fn fs_watch_stop(window: WebviewWindow, state: State<'_, AppState>, request: FsWatchStopRequest) -> Result<(), String> {
+  let mut watches = lock_recover(&state.watches);
+  match watches.get(&request.watch_id) {
+    Some(watch) if watch.window_label == window.label() => {
+      watches.remove(&request.watch_id);
+      Ok(())
+    }
+    _ => Err(message_error("NOT_FOUND", "watch.notFound", &[("watchId", request.watch_id)]))
+  }
+}
+
+/// Folds a change to `path` into the batch so each path is reported once. A path created in
+/// this batch stays `created` whatever else happens to it, unless it is removed again, in which
+/// case nobody needs to hear about it; removed-then-created is reported as `modified`.
+fn record_fs_change(changes: &mut BTreeMap<PathBuf, &'static str>, path: PathBuf, kind: &'static str) {
+  match (changes.get(&path).copied(), kind) {
+    (Some("created"), "removed") => {
+      changes.remove(&path);
+    }
+    (Some("created"), _) => {}
+    (Some("removed"), "created") => {
+      changes.insert(path, "modified");
+    }
+    _ => {
+      changes.insert(path, kind);
+    }
+  }
+}
+
+/// Debounces raw watcher events into one `fs:changed` per kind, each path reported once. When
+/// the backend drops events (queue overflow, watcher errors) or a batch grows past
+/// `FS_WATCH_MAX_CHANGES_PER_BATCH`, a single `rescan` event for the root is sent instead, since
+/// the individual paths can no longer be trusted. Returns once the watcher is dropped. With
+/// per-directory watching, new directories get a watch of their own through `watcher`.
+fn run_fs_watch(
+  app: &AppHandle,
+  watch_id: &str,
+  window_label: &str,
+  receiver: &std::sync::mpsc::Receiver<notify::Result<notify::Event>>,
+  filter: &FsWatchFilter,
+  watcher: Option<std::sync::Weak<Mutex<notify::RecommendedWatcher>>>
+) {
+  use std::sync::mpsc::RecvTimeoutError;
+
+  while let Ok(first) = receiver.recv() {
+    let mut batch = vec![first];
+    let deadline = Instant::now() + FS_WATCH_DEBOUNCE;
+    loop {
+      match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(event) => batch.push(event),
+        Err(RecvTimeoutError::Timeout) => break,
+        Err(RecvTimeoutError::Disconnected) => return
+      }
+    }
+
+    let mut changes: BTreeMap<PathBuf, &'static str> = BTreeMap::new();
+    let mut rescan = false;
+    for event in batch {
+      let event = match event {
+        Ok(event) if !event.need_rescan() => event,
+        _ => {
+          rescan = true;
+          continue;
+        }
+      };
+      let Some(kind) = fs_change_kind(&event.kind) else {
+        continue;
+      };
+      for path in event.paths.into_iter().filter(|path| filter.allows(path)) {
+        record_fs_change(&mut changes, path, kind);
+      }
+    }
+    rescan |= changes.len() > FS_WATCH_MAX_CHANGES_PER_BATCH;
+
+    if let Some(watcher) = watcher.as_ref().and_then(std::sync::Weak::upgrade) {
+      let mut watcher = lock_recover(&watcher);
+      for (path, _) in changes.iter().filter(|(path, kind)| **kind == "created" && path.is_dir()) {
+        for directory in filter.directories_to_watch(path) {
+          let _ = watcher.watch(&directory, notify::RecursiveMode::NonRecursive);
+        }
+      }
+    }
+
+    if rescan {
+      let _ = emit_replayable_to(
+        app,
+        window_label,
+        FS_CHANGED_EVENT,
+        FsChangedEvent {
+          watch_id: watch_id.to_string(),
+          kind: "rescan",
+          paths: vec![filter.root.to_string_lossy().into_owned()]
+        }
+      );
+      continue;
+    }
+
+    let mut by_kind: BTreeMap<&'static str, Vec<String>> = BTreeMap::new();
+    for (path, kind) in changes {
+      by_kind.entry(kind).or_default().push(path.to_string_lossy().into_owned());
+    }
+    for (kind, paths) in by_kind {
+      for chunk in paths.chunks(FS_WATCH_MAX_PATHS_PER_EVENT) {
+        let _ = emit_replayable_to(
+          app,
+          window_label,
+          FS_CHANGED_EVENT,
+          FsChangedEvent {
+            watch_id: watch_id.to_string(),
+            kind,
+            paths: chunk.to_vec()
+          }
+        );
+      }
+    }
+  }
+}
+
+#[tauri::command]
+fn operations_list(registry: State<'_, OperationRegistry>) -> Vec<OperationInfo> {
+  lock_recover(&registry.operations)
+    .iter()
+    .map(|(operation_id, handle)| OperationInfo {
+      operation_id: operation_id.clone(),
+      kind: handle.kind.to_string()
+    })
+    .collect()
+}
+
+/// Takes a finished operation out of the registry, keeps its payload for `operation_result` and
+/// emits it as `event`.
+fn finish_operation<S>(app: &AppHandle, operation_id: &str, event: &'static str, payload: S)
+where
+  S: Serialize + Clone
+{
+  let registry = app.state::<OperationRegistry>();
+  if let Ok(value) = serde_json::to_value(&payload) {
+    let mut results = lock_recover(&registry.results);
+    results.retain(|finished| finished.finished_at.elapsed() < OPERATION_RESULT_RETENTION);
+    if results.len() >= OPERATION_RESULTS_MAX {
+      results.pop_front();
+    }
+    results.push_back(FinishedOperation {
+      operation_id: operation_id.to_string(),
+      event,
+      finished_at: Instant::now(),
+      payload: value
+    });
+  }
+  lock_recover(&registry.operations).remove(operation_id);
+  let _ = emit_replayable(app, event, payload);
+}
+
+/// The outcome of an operation, for callers that missed its finished event. Results are kept
+/// for `OPERATION_RESULT_RETENTION`.
+#[tauri::command]
+fn operation_result(registry: State<'_, OperationRegistry>, request: OperationResultRequest) -> Result<OperationResult, String> {
+  if let Some(finished) = lock_recover(&registry.results)
+    .iter()
+    .rev()
+    .find(|finished| finished.operation_id == request.operation_id)
+  {
+    return Ok(OperationResult {
+      operation_id: request.operation_id,
+      running: false,
+      event: Some(finished.event),
+      payload: Some(finished.payload.clone())
+    });
+  }
+  if lock_recover(&registry.operations).contains_key(&request.operation_id) {
+    return Ok(OperationResult {
+      operation_id: request.operation_id,
+      running: true,
+      event: None,
+      payload: None
+    });
+  }
+  Err(message_error(
+    "NOT_FOUND",
+    "operation.notFound",
+    &[("operationId", request.operation_id)]
+  ))
+}
+
+/// Asks an operation to stop. It finishes on its own thread, reporting the cancellation in its
+/// finished event.
+#[tauri::command]
+fn operation_cancel(registry: State<'_, OperationRegistry>, request: OperationCancelRequest) -> Result<(), String> {
+  let operations = lock_recover(&registry.operations);
+  let handle = operations.get(&request.operation_id).ok_or_else(|| {
+    message_error("NOT_FOUND", "operation.notFound", &[("operationId", request.operation_id.clone())])
+  })?;
+  handle.cancelled.store(true, Ordering::SeqCst);
+  Ok(())
+}
+
+/// Bundles the app's state files into one JSON file on a background thread, reporting
+/// `state:op-progress` as it goes and `state:op-finished` at the end. Task and workspace writes
+/// wait until the files have been read, so the bundle is consistent. An existing output is only
+/// replaced with `overwrite`; a cancelled or failed export leaves no partial file behind.
+#[tauri::command]
+fn workspace_export(
+  app: AppHandle,
+  registry: State<'_, OperationRegistry>,
+  request: WorkspaceExportRequest
+) -> Result<WorkspaceExportStarted, String> {
+  let output_path = resolve_path(&request.output_path)?;
+  if !request.overwrite && fs::symlink_metadata(&output_path).is_ok() {
+    return Err(exists_error(&output_path));
+  }
+  // Pane sizes are kept in memory for a moment before they are written; export what is current.
+  flush_terminal_layouts(
+    &app.state::<TerminalLayoutStore>(),
+    &persistence_file_path(&app, TERMINAL_LAYOUTS_FILE_NAME)?
+  )?;
+  let files = STATE_EXPORT_FILE_NAMES
+    .iter()
+    .map(|name| Ok((*name, persistence_file_path(&app, name)?)))
+    .collect::<Result<Vec<_>, String>>()?;
+
+  let operation_id = Uuid::new_v4().to_string();
+  let cancelled = Arc::new(AtomicBool::new(false));
+  lock_recover(&registry.operations).insert(
+    operation_id.clone(),
+    OperationHandle {
+      kind: "workspace-export",
+      cancelled: cancelled.clone()
+    }
+  );
+
+  let started = WorkspaceExportStarted {
+    operation_id: operation_id.clone()
+  };
+  std::thread::spawn(move || {
+    let result = {
+      let task_store = app.state::<TaskStore>();
+      let workspace_store = app.state::<WorkspaceStore>();
+      let _task_guard = lock_recover(&task_store.write_lock);
+      let _workspace_guard = lock_recover(&workspace_store.write_lock);
+      write_state_bundle(
+        &files,
+        &output_path,
+        request.overwrite,
+        &cancelled,
+        &mut |phase, items_processed, items_total, bytes| {
+          let _ = app.emit(
+            STATE_OP_PROGRESS_EVENT,
+            StateOpProgressEvent {
+              operation_id: operation_id.clone(),
+              phase,
+              items_processed,
+              items_total,
+              bytes
+            }
+          );
+        }
+      )
+    };
+    let (bytes_written, files, error) = match result {
+      Ok((bytes_written, files)) => (bytes_written, files, None),
+      Err(error) => (0, Vec::new(), Some(error))
+    };
+    finish_operation(
+      &app,
+      &operation_id,
+      STATE_OP_FINISHED_EVENT,
+      WorkspaceExportFinishedEvent {
+        operation_id: operation_id.clone(),
+        output_path: output_path.to_string_lossy().into_owned(),
+        bytes_written,
+        files,
+        error
+      }
+    );
+  });
+  Ok(started)
+}
+
+/// Writes `{"version": 1, "exportedAt": …, "files": {name: contents}}` for the state files that
+/// exist, through a temporary sibling of `output` that is only renamed into place once complete.
+/// Returns the bytes written and the names included. `on_progress` gets the phase, files done,
+/// file count and bytes so far.
+fn write_state_bundle(
+  files: &[(&str, PathBuf)],
+  output: &Path,
+  overwrite: bool,
+  cancelled: &AtomicBool,
+  on_progress: &mut dyn FnMut(&'static str, u64, u64, u64)
+) -> Result<(u64, Vec<String>), String> {
+  let cancelled_error = || String::from("CANCELLED: the export was cancelled");
+  let items_total = files.len() as u64;
+  let mut bundled = serde_json::Map::new();
+  let mut bytes = 0;
+  for (index, (name, path)) in files.iter().enumerate() {
+    if cancelled.load(Ordering::SeqCst) {
+      return Err(cancelled_error());
+    }
+    match fs::read(path) {
+      Ok(raw) => {
+        let value: serde_json::Value = serde_json::from_slice(&raw)
+          .map_err(|error| format!("failed to parse JSON at {}: {error}", path.display()))?;
+        bytes += raw.len() as u64;
+        bundled.insert(name.to_string(), value);
+      }
+      Err(error) if error.kind() == ErrorKind::NotFound => {}
+      Err(error) => return Err(io_error(&format!("failed to read {}", path.display()), error))
+    }
+    on_progress("reading", index as u64 + 1, items_total, bytes);
+  }
+
+  let names: Vec<String> = bundled.keys().cloned().collect();
+  let bundle = serde_json::json!({
+    "version": 1,
+    "exportedAt": Utc::now().to_rfc3339(),
+    "files": bundled
+  });
+  let contents = serde_json::to_vec_pretty(&bundle).map_err(|error| error.to_string())?;
+  if cancelled.load(Ordering::SeqCst) {
+    return Err(cancelled_error());
+  }
+  if let Some(parent) = output.parent() {
+    fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
+  }
+  let temp_path = temp_sibling_path(output);
+  let written = fs::write(&temp_path, &contents)
+    .map_err(|error| io_error(&format!("failed to write {}", output.display()), error))
+    .and_then(|()| {
+      if cancelled.load(Ordering::SeqCst) {
+        return Err(cancelled_error());
+      }
+      if !overwrite && fs::symlink_metadata(output).is_ok() {
+        return Err(exists_error(output));
+      }
+      fs::rename(&temp_path, output).map_err(|error| io_error(&format!("failed to write {}", output.display()), error))
+    });
+  if let Err(error) = written {
+    let _ = fs::remove_file(&temp_path);
+    return Err(error);
+  }
+  on_progress("writing", items_total, items_total, contents.len() as u64);
+  Ok((contents.len() as u64, names))
+}
+
+/// Copies a workspace file to any destination on a background thread. An interrupted export of
+/// the same source to the same destination resumes from the sidecar's verified offset.
+#[tauri::command]
+fn filesystem_export(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  registry: State<'_, OperationRegistry>,
+  request: FilesystemExportRequest
+) -> Result<FilesystemExportStarted, String> {
+  ensure_not_closing(&app)?;
+  let source_path = resolve_workspace_path(&startup_context, &request.source_path)?;
+  let destination_path = resolve_path(&request.destination_path)?;
+  let source_metadata = fs::metadata(&source_path)
+    .map_err(|error| io_error(&format!("failed to stat {}", source_path.display()), error))?;
+  if !source_metadata.is_file() {
+    return Err(format!("{} is not a file", source_path.display()));
+  }
+  if fs::canonicalize(&destination_path).ok() == fs::canonicalize(&source_path).ok() {
+    return Err(String::from("the export destination is the source file itself"));
+  }
+
+  let destination_dir = destination_path
+    .parent()
+    .ok_or_else(|| format!("{} has no parent directory", destination_path.display()))?;
+  fs::create_dir_all(destination_dir).map_err(|error| io_error("failed to create parent directory", error))?;
+
+  let mut checkpoint = ExportCheckpoint {
+    source_path: source_path.to_string_lossy().into_owned(),
+    source_size: source_metadata.len(),
+    source_mtime_ms: modified_time_ms(&source_metadata),
+    verified_offset: 0
+  };
+  let sidecar_path = export_sidecar_path(&destination_path);
+  checkpoint.verified_offset = export_resume_offset(&sidecar_path, &destination_path, &checkpoint);
+
+  let required = checkpoint.source_size - checkpoint.verified_offset;
+  if let Some((_, available)) = disk_space(destination_dir) {
+    if available < required {
+      return Err(message_error(
+        "NO_SPACE",
+        "file.insufficientSpace",
+        &[
+          ("path", destination_path.display().to_string()),
+          ("required", required.to_string()),
+          ("available", available.to_string())
+        ]
+      ));
+    }
+  }
+
+  let operation_id = Uuid::new_v4().to_string();
+  let cancelled = Arc::new(AtomicBool::new(false));
+  lock_recover(&registry.operations).insert(
+    operation_id.clone(),
+    OperationHandle {
+      kind: "export",
+      cancelled: cancelled.clone()
+    }
+  );
+
+  let started = FilesystemExportStarted {
+    operation_id: operation_id.clone(),
+    total_bytes: checkpoint.source_size,
+    resumed_from: checkpoint.verified_offset
+  };
+  std::thread::spawn(move || {
+    let result = run_export(
+      &app,
+      &operation_id,
+      &destination_path,
+      &sidecar_path,
+      checkpoint,
+      request.checksum,
+      &cancelled
+    );
+    let (bytes_written, sha256, error) = match result {
+      Ok((bytes_written, sha256)) => (bytes_written, sha256, None),
+      Err(error) => (0, None, Some(error))
+    };
+    finish_operation(
+      &app,
+      &operation_id,
+      FILESYSTEM_EXPORT_FINISHED_EVENT,
+      FilesystemExportFinishedEvent {
+        operation_id: operation_id.clone(),
+        destination_path: destination_path.to_string_lossy().into_owned(),
+        bytes_written,
+        sha256,
+        error
+      }
+    );
+  });
+  Ok(started)
+}
+
+fn export_sidecar_path(destination_path: &Path) -> PathBuf {
+  let mut sidecar = destination_path.as_os_str().to_owned();
+  sidecar.push(FILESYSTEM_EXPORT_SIDECAR_SUFFIX);
+  PathBuf::from(sidecar)
+}
+
+/// Where a retried export can pick up: the sidecar's verified offset when it describes the same,
+/// unchanged source and the partial file still holds that many bytes; otherwise the start.
+fn export_resume_offset(sidecar_path: &Path, destination_path: &Path, source: &ExportCheckpoint) -> u64 {
+  let Some(previous) = fs::read(sidecar_path)
+    .ok()
+    .and_then(|raw| serde_json::from_slice::<ExportCheckpoint>(&raw).ok())
+  else {
+    return 0;
+  };
+  let partial_len = fs::metadata(destination_path).map_or(0, |metadata| metadata.len());
+  let same_source = previous.source_path == source.source_path
+    && previous.source_size == source.source_size
+    && previous.source_mtime_ms == source.source_mtime_ms;
+  if same_source && previous.verified_offset <= source.source_size && previous.verified_offset <= partial_len {
+    previous.verified_offset
+  } else {
+    0
+  }
+}
+
+fn ensure_export_source_unchanged(checkpoint: &ExportCheckpoint) -> Result<(), String> {
+  let source_path = Path::new(&checkpoint.source_path);
+  let unchanged = fs::metadata(source_path).is_ok_and(|metadata| {
+    metadata.len() == checkpoint.source_size && modified_time_ms(&metadata) == checkpoint.source_mtime_ms
+  });
+  if unchanged {
+    Ok(())
+  } else {
+    Err(message_error("SOURCE_CHANGED", "file.sourceChanged", &[("path", checkpoint.source_path.clone())]))
+  }
+}
+
+/// Streams the source into the destination from `checkpoint.verified_offset`, returning the bytes
+/// in the finished file and, when asked, its SHA-256. A source that changes mid-copy discards the
+/// partial output so it cannot be mistaken for a good export.
+fn run_export(
+  app: &AppHandle,
+  operation_id: &str,
+  destination_path: &Path,
+  sidecar_path: &Path,
+  mut checkpoint: ExportCheckpoint,
+  checksum: bool,
+  cancelled: &AtomicBool
+) -> Result<(u64, Option<String>), String> {
+  let result = copy_export(app, operation_id, destination_path, sidecar_path, &mut checkpoint, checksum, cancelled);
+  match &result {
+    Ok(_) => {
+      let _ = fs::remove_file(sidecar_path);
+    }
+    Err(error) if error_code(error).as_deref() == Some("SOURCE_CHANGED") => {
+      let _ = fs::remove_file(sidecar_path);
+      let _ = fs::remove_file(destination_path);
+    }
+    Err(_) => {}
+  }
+  result
+}
+
+fn copy_export(
+  app: &AppHandle,
+  operation_id: &str,
+  destination_path: &Path,
+  sidecar_path: &Path,
+  checkpoint: &mut ExportCheckpoint,
+  checksum: bool,
+  cancelled: &AtomicBool
+) -> Result<(u64, Option<String>), String> {
+  let destination_error = |error| io_error(&format!("failed to write {}", destination_path.display()), error);
+  let source_error = |error| io_error(&format!("failed to read {}", checkpoint.source_path), error);
+
+  let mut source = fs::File::open(&checkpoint.source_path).map_err(source_error)?;
+  let mut destination = OpenOptions::new()
+    .read(true)
+    .write(true)
+    .create(true)
+    .truncate(false)
+    .open(destination_path)
+    .map_err(destination_error)?;
+  let offset = checkpoint.verified_offset;
+  destination.set_len(offset).map_err(destination_error)?;
+
+  // Hashing the resumed prefix also leaves both files positioned at `offset`.
+  let mut hasher = checksum.then(Sha256::new);
+  match hasher.as_mut() {
+    Some(hasher) => {
+      std::io::copy(&mut (&mut destination).take(offset), hasher).map_err(destination_error)?;
+    }
+    None => {
+      destination.seek(SeekFrom::Start(offset)).map_err(destination_error)?;
+    }
+  }
+  source.seek(SeekFrom::Start(offset)).map_err(source_error)?;
+  write_json(sidecar_path, checkpoint)?;
+
+  let total_bytes = checkpoint.source_size;
+  let mut source = source.take(total_bytes - offset);
+  let mut buffer = vec![0u8; FILESYSTEM_EXPORT_CHUNK_BYTES];
+  let mut copied = offset;
+  let mut last_progress = Instant::now();
+  loop {
+    if cancelled.load(Ordering::SeqCst) {
+      return Err(String::from("CANCELLED: the export was cancelled"));
+    }
+    let read = match source.read(&mut buffer) {
+      Ok(0) => break,
+      Ok(read) => read,
+      Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+      Err(error) => return Err(source_error(error))
+    };
+    destination.write_all(&buffer[..read]).map_err(destination_error)?;
+    if let Some(hasher) = hasher.as_mut() {
+      hasher.update(&buffer[..read]);
+    }
+    copied += read as u64;
+
+    if copied - checkpoint.verified_offset >= FILESYSTEM_EXPORT_CHECKPOINT_BYTES {
+      ensure_export_source_unchanged(checkpoint)?;
+      destination.sync_data().map_err(destination_error)?;
+      checkpoint.verified_offset = copied;
+      write_json(sidecar_path, checkpoint)?;
+    }
+    if last_progress.elapsed() >= FILESYSTEM_EXPORT_PROGRESS_INTERVAL {
+      last_progress = Instant::now();
+      let _ = app.emit(
+        FILESYSTEM_EXPORT_PROGRESS_EVENT,
+        FilesystemExportProgressEvent {
+          operation_id: operation_id.to_string(),
+          copied_bytes: copied,
+          total_bytes
+        }
+      );
+    }
+  }
+
+  // A source that shrank stops short; one that grew or was rewritten in place fails the stat.
+  if copied != total_bytes {
+    return Err(message_error("SOURCE_CHANGED", "file.sourceChanged", &[("path", checkpoint.source_path.clone())]));
+  }
+  ensure_export_source_unchanged(checkpoint)?;
+  destination.sync_all().map_err(destination_error)?;
+  Ok((copied, hasher.map(|hasher| format!("{:x}", hasher.finalize()))))
+}
+
+/// Copies a file or directory tree within the workspace on a background thread, reporting
+/// progress and the entries that failed through `fs:copy-*` events.
+#[tauri::command]
+fn fs_copy(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  registry: State<'_, OperationRegistry>,
+  request: FsCopyRequest
+) -> Result<FsCopyStarted, String> {
+  ensure_not_closing(&app)?;
+  let source_path = resolve_workspace_path(&startup_context, &request.source_path)?;
+  let destination_path = resolve_workspace_path(&startup_context, &request.destination_path)?;
+  let source_metadata = fs::metadata(&source_path)
+    .map_err(|error| io_error(&format!("failed to stat {}", source_path.display()), error))?;
+  if source_metadata.is_dir() && !request.recursive {
+    return Err(format!("{} is a directory; set recursive to copy it", source_path.display()));
+  }
+  if let Ok(destination_metadata) = fs::metadata(&destination_path) {
+    if !request.overwrite {
+      return Err(exists_error(&destination_path));
+    }
+    if destination_metadata.is_dir() != source_metadata.is_dir() {
+      return Err(format!(
+        "cannot replace {} with {}",
+        destination_path.display(),
+        source_path.display()
+      ));
+    }
+  }
+  let canonical_source = fs::canonicalize(&source_path)
+    .map_err(|error| io_error(&format!("failed to resolve {}", source_path.display()), error))?;
+  let canonical_destination = destination_path
+    .parent()
+    .and_then(|parent| fs::canonicalize(parent).ok())
+    .zip(destination_path.file_name())
+    .map(|(parent, name)| parent.join(name));
+  if canonical_destination.is_some_and(|destination| destination.starts_with(&canonical_source)) {
+    return Err(format!("cannot copy {} into itself", source_path.display()));
+  }
+  if let Some(parent) = destination_path.parent() {
+    fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
+  }
+
+  let operation_id = Uuid::new_v4().to_string();
+  let cancelled = Arc::new(AtomicBool::new(false));
+  lock_recover(&registry.operations).insert(
+    operation_id.clone(),
+    OperationHandle {
+      kind: "copy",
+      cancelled: cancelled.clone()
+    }
+  );
+
+  let started = FsCopyStarted {
+    operation_id: operation_id.clone()
+  };
+  std::thread::spawn(move || {
+    let mut copy = TreeCopy {
+      app: &app,
+      cancelled: &cancelled,
+      follow_symlinks: request.follow_symlinks,
+      progress: FsCopyProgressEvent {
+        operation_id: operation_id.clone(),
+        copied_bytes: 0,
+        copied_files: 0,
+        total_bytes: 0,
+        total_files: 0
+      },
+      skipped_symlinks: 0,
+      failures: Vec::new(),
+      ancestors: Vec::new(),
+      last_progress: Instant::now()
+    };
+    copy.measure(&source_path);
+    let result = copy.copy_entry(&source_path, &destination_path, true);
+    let finished = FsCopyFinishedEvent {
+      operation_id: operation_id.clone(),
+      destination_path: destination_path.to_string_lossy().into_owned(),
+      copied_bytes: copy.progress.copied_bytes,
+      copied_files: copy.progress.copied_files,
+      skipped_symlinks: copy.skipped_symlinks,
+      failures: copy.failures,
+      error: result.err()
+    };
+    finish_operation(&app, &operation_id, FS_COPY_FINISHED_EVENT, finished);
+  });
+  Ok(started)
+}
+
+/// State for one `fs_copy` run. Per-entry errors land in `failures`; only cancellation stops
+/// the walk.
+struct TreeCopy<'a> {
+  app: &'a AppHandle,
+  cancelled: &'a AtomicBool,
+  follow_symlinks: bool,
+  progress: FsCopyProgressEvent,
+  skipped_symlinks: u64,
+  failures: Vec<FsCopyFailure>,
+  last_progress: Instant,
+  /// Directories being walked, so a followed symlink back to one of them is not entered again.
+  ancestors: Vec<DirectoryKey>
+}
+
+/// Identifies a directory regardless of the path it was reached through.
+#[cfg(unix)]
+type DirectoryKey = (u64, u64);
+#[cfg(not(unix))]
+type DirectoryKey = PathBuf;
+
+#[cfg(unix)]
+fn directory_key(_path: &Path, metadata: &Metadata) -> Option<DirectoryKey> {
+  use std::os::unix::fs::MetadataExt;
+  Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn directory_key(path: &Path, _metadata: &Metadata) -> Option<DirectoryKey> {
+  fs::canonicalize(path).ok()
+}
+
+impl TreeCopy<'_> {
+  /// Stats that the copy will follow, or `None` for a skipped symlink. The top-level source is
+  /// always followed.
+  fn entry_metadata(&self, path: &Path, top_level: bool) -> std::io::Result<Option<Metadata>> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.file_type().is_symlink() {
+      return Ok(Some(metadata));
+    }
+    if top_level || self.follow_symlinks {
+      fs::metadata(path).map(Some)
+    } else {
+      Ok(None)
+    }
+  }
+
+  /// Adds up the files and bytes under `source` so progress events can carry totals.
+  fn measure(&mut self, source: &Path) {
+    self.measure_entry(source, true);
+  }
+
+  fn measure_entry(&mut self, path: &Path, top_level: bool) {
+    let Ok(Some(metadata)) = self.entry_metadata(path, top_level) else {
+      return;
+    };
+    if !metadata.is_dir() {
+      self.progress.total_files += 1;
+      self.progress.total_bytes += metadata.len();
+      return;
+    }
+    let Some(entered) = self.enter_directory(path, &metadata) else {
+      return;
+    };
+    if let Ok(entries) = fs::read_dir(path) {
+      for entry in entries.filter_map(Result::ok) {
+        self.measure_entry(&entry.path(), false);
+      }
+    }
+    self.leave_directory(entered);
+  }
+
+  /// Marks a directory as being walked. Returns `None` when a followed symlink has led back into
+  /// one of its own ancestors, `Some(true)` when the directory was recorded and
+  /// `leave_directory` must undo it, and `Some(false)` when it has no identity to record, so it
+  /// is walked without loop protection.
+  fn enter_directory(&mut self, path: &Path, metadata: &Metadata) -> Option<bool> {
+    let Some(key) = directory_key(path, metadata) else {
+      return Some(false);
+    };
+    if self.ancestors.contains(&key) {
+      return None;
+    }
+    self.ancestors.push(key);
+    Some(true)
+  }
+
+  fn leave_directory(&mut self, entered: bool) {
+    if entered {
+      self.ancestors.pop();
+    }
+  }
+
+  fn copy_entry(&mut self, source: &Path, destination: &Path, top_level: bool) -> Result<(), String> {
+    let metadata = match self.entry_metadata(source, top_level) {
+      Ok(Some(metadata)) => metadata,
+      Ok(None) => {
+        self.skipped_symlinks += 1;
+        return Ok(());
+      }
+      Err(error) => {
+        self.fail(source, io_error("failed to stat", error));
+        return Ok(());
+      }
+    };
+
+    if !metadata.is_dir() {
+      return self.copy_file(source, destination, &metadata);
+    }
+    let Some(entered) = self.enter_directory(source, &metadata) else {
+      self.fail(
+        source,
+        String::from("not copied: the symlink leads back into a directory that contains it")
+      );
+      return Ok(());
+    };
+    if let Err(error) = fs::create_dir_all(destination) {
+      self.leave_directory(entered);
+      self.fail(source, io_error(&format!("failed to create {}", destination.display()), error));
+      return Ok(());
+    }
+    let copied = self.copy_children(source, destination);
+    self.leave_directory(entered);
+    copied?;
+    // After the children, since creating them bumps the directory's mtime.
+    if let Err(error) = fs::set_permissions(destination, metadata.permissions()) {
+      self.fail(source, io_error("failed to copy permissions", error));
+    }
+    if let (Ok(modified), Ok(directory)) = (metadata.modified(), fs::File::open(destination)) {
+      let _ = directory.set_modified(modified);
+    }
+    Ok(())
+  }
+
+  fn copy_children(&mut self, source: &Path, destination: &Path) -> Result<(), String> {
+    match fs::read_dir(source) {
+      Ok(entries) => {
+        for entry in entries {
+          match entry {
+            Ok(entry) => self.copy_entry(&entry.path(), &destination.join(entry.file_name()), false)?,
+            Err(error) => self.fail(source, io_error("failed to list", error))
+          }
+        }
+      }
+      Err(error) => self.fail(source, io_error("failed to list", error))
+    }
+    Ok(())
+  }
+
+  /// Copies into a temporary sibling and renames it over `destination`, so a copy that fails
+  /// part way leaves an existing destination file as it was.
+  fn copy_file(&mut self, source: &Path, destination: &Path, metadata: &Metadata) -> Result<(), String> {
+    let temp_path = temp_sibling_path(destination);
+    let copied = self.copy_file_contents(source, &temp_path, metadata).and_then(|()| {
+      fs::rename(&temp_path, destination)
+        .map_err(|error| io_error(&format!("failed to write {}", destination.display()), error))
+    });
+    match copied {
+      Ok(()) => {
+        self.progress.copied_files += 1;
+        Ok(())
+      }
+      Err(error) => {
+        let _ = fs::remove_file(&temp_path);
+        if error.starts_with("CANCELLED:") {
+          return Err(error);
+        }
+        self.fail(source, error);
+        Ok(())
+      }
+    }
+  }
+
+  fn copy_file_contents(&mut self, source: &Path, destination: &Path, metadata: &Metadata) -> Result<(), String> {
+    let source_error = |error| io_error("failed to read", error);
+    let destination_error = |error| io_error(&format!("failed to write {}", destination.display()), error);
+    let mut reader = fs::File::open(source).map_err(source_error)?;
+    let mut writer = fs::File::create(destination).map_err(destination_error)?;
+    let mut buffer = vec![0u8; FS_COPY_CHUNK_BYTES];
+    loop {
+      if self.cancelled.load(Ordering::SeqCst) {
+        return Err(String::from("CANCELLED: the copy was cancelled"));
+      }
+      let read = match reader.read(&mut buffer) {
+        Ok(0) => break,
+        Ok(read) => read,
+        Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+        Err(error) => return Err(source_error(error))
+      };
+      writer.write_all(&buffer[..read]).map_err(destination_error)?;
+      self.progress.copied_bytes += read as u64;
+      if self.last_progress.elapsed() >= FS_COPY_PROGRESS_INTERVAL {
+        self.last_progress = Instant::now();
+        let _ = self.app.emit(FS_COPY_PROGRESS_EVENT, self.progress.clone());
+      }
+    }
+    writer.set_permissions(metadata.permissions()).map_err(destination_error)?;
+    if let Ok(modified) = metadata.modified() {
+      writer.set_modified(modified).map_err(destination_error)?;
+    }
+    Ok(())
+  }
+
+  fn fail(&mut self, path: &Path, error: String) {
+    self.failures.push(FsCopyFailure {
+      path: path.to_string_lossy().into_owned(),
+      error
+    });
+  }
+}
+
+/// Packs workspace files and directories into a zip or gzipped tar archive on a background
+/// thread. Directories are included recursively and symlinks are stored as links. The output
+/// must be in the workspace and is only replaced when `overwrite` is set; the archive is written
+/// to a temporary sibling and renamed into place, so a failed or cancelled run leaves nothing
+/// behind. Cancel with `operation_cancel` and the operation id.
+#[tauri::command]
+fn filesystem_archive(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  registry: State<'_, OperationRegistry>,
+  request: FilesystemArchiveRequest
+) -> Result<FilesystemArchiveStarted, String> {
+  ensure_not_closing(&app)?;
+  if request.paths.is_empty() {
+    return Err(String::from("no paths to archive"));
+  }
+  let inputs = request
+    .paths
+    .iter()
+    .map(|path| resolve_workspace_path(&startup_context, path))
+    .collect::<Result<Vec<_>, _>>()?;
+  let output = resolve_workspace_path(&startup_context, &request.output)?;
+  if !request.overwrite && fs::symlink_metadata(&output).is_ok() {
+    return Err(exists_error(&output));
+  }
+  if let Some(parent) = output.parent() {
+    fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
+  }
+
+  let operation_id = Uuid::new_v4().to_string();
+  let cancelled = Arc::new(AtomicBool::new(false));
+  lock_recover(&registry.operations).insert(
+    operation_id.clone(),
+    OperationHandle {
+      kind: "archive",
+      cancelled: cancelled.clone()
+    }
+  );
+
+  let started = FilesystemArchiveStarted {
+    operation_id: operation_id.clone(),
+    output: output.to_string_lossy().into_owned()
+  };
+  std::thread::spawn(move || {
+    let result = run_archive(
+      &app,
+      &operation_id,
+      &inputs,
+      &output,
+      request.format,
+      request.overwrite,
+      &cancelled
+    );
+    let (size_bytes, file_count, error) = match result {
+      Ok((size_bytes, file_count)) => (size_bytes, file_count, None),
+      Err(error) => (0, 0, Some(error))
+    };
+    finish_operation(
+      &app,
+      &operation_id,
+      FILESYSTEM_ARCHIVE_FINISHED_EVENT,
+      FilesystemArchiveFinishedEvent {
+        operation_id: operation_id.clone(),
+        output: output.to_string_lossy().into_owned(),
+        size_bytes,
+        file_count,
+        error
+      }
+    );
+  });
+  Ok(started)
+}
+
+/// Writes the archive and returns its size and file count.
+fn run_archive(
+  app: &AppHandle,
+  operation_id: &str,
+  inputs: &[PathBuf],
+  output: &Path,
+  format: ArchiveFormat,
+  overwrite: bool,
+  cancelled: &AtomicBool
+) -> Result<(u64, u64), String> {
+  let entries = collect_archive_entries(inputs, output)?;
+  let files_total = entries.iter().filter(|entry| !entry.metadata.is_dir()).count() as u64;
+  let mut files_done = 0;
+  let mut last_progress = Instant::now();
+  let mut on_entry = |entry: &ArchiveEntry| {
+    if cancelled.load(Ordering::SeqCst) {
+      return Err(String::from("CANCELLED: the archive was cancelled"));
+    }
+    if entry.metadata.is_dir() {
+      return Ok(());
+    }
+    files_done += 1;
+    if files_done == files_total || last_progress.elapsed() >= FILESYSTEM_ARCHIVE_PROGRESS_INTERVAL {
+      last_progress = Instant::now();
+      let _ = app.emit(
+        FILESYSTEM_ARCHIVE_PROGRESS_EVENT,
+        FilesystemArchiveProgressEvent {
+          operation_id: operation_id.to_string(),
+          output: output.to_string_lossy().into_owned(),
+          files_done,
+          files_total
+        }
+      );
+    }
+    Ok(())
+  };
+
+  let temp_path = temp_sibling_path(output);
+  let file = fs::File::create(&temp_path)
+    .map_err(|error| io_error(&format!("failed to create {}", output.display()), error))?;
+  let written = match format {
+    ArchiveFormat::Zip => write_zip_archive(file, &entries, &mut on_entry),
+    ArchiveFormat::TarGz => write_tar_gz_archive(file, &entries, &mut on_entry)
+  }
+  .map_err(|error| {
+    if cancelled.load(Ordering::SeqCst) {
+      String::from("CANCELLED: the archive was cancelled")
+    } else {
+      format!("failed to write {}: {error}", output.display())
+    }
+  })
+  .and_then(|()| {
+    // Checked again in case something appeared at the output while the archive was written.
+    if !overwrite && fs::symlink_metadata(output).is_ok() {
+      return Err(exists_error(output));
+    }
+    fs::rename(&temp_path, output).map_err(|error| io_error(&format!("failed to write {}", output.display()), error))
+  });
+  if let Err(error) = written {
+    let _ = fs::remove_file(&temp_path);
+    return Err(error);
+  }
+
+  let size_bytes = fs::metadata(output)
+    .map_err(|error| io_error(&format!("failed to stat {}", output.display()), error))?
+    .len();
+  Ok((size_bytes, files_total))
+}
+
+/// Unpacks a .zip, .tar.gz or .tar.xz archive into a workspace directory on a worker thread,
+/// reporting through `filesystem:extract-finished`. Entries that would land outside the
+/// destination are refused. Without `overwrite`, nothing is written if any entry would replace
+/// an existing file, and the error lists them.
+#[tauri::command]
+fn filesystem_extract(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  registry: State<'_, OperationRegistry>,
+  request: FilesystemExtractRequest
+) -> Result<FilesystemExtractStarted, String> {
+  ensure_not_closing(&app)?;
+  let archive = resolve_path(&request.archive)?;
+  let destination = resolve_workspace_path(&startup_context, &request.destination)?;
+  let format = extract_format(&archive)?;
+
+  let operation_id = Uuid::new_v4().to_string();
+  let cancelled = Arc::new(AtomicBool::new(false));
+  lock_recover(&registry.operations).insert(
+    operation_id.clone(),
+    OperationHandle {
+      kind: "extract",
+      cancelled: cancelled.clone()
+    }
+  );
+
+  let started = FilesystemExtractStarted {
+    operation_id: operation_id.clone(),
+    destination: destination.to_string_lossy().into_owned()
+  };
+  std::thread::spawn(move || {
+    let result = run_extract(
+      &app,
+      &operation_id,
+      &archive,
+      &destination,
+      format,
+      request.overwrite,
+      &cancelled
+    );
+    let (destination, file_count, error) = match result {
+      Ok((destination, file_count)) => (destination, file_count, None),
+      Err(error) => (destination, 0, Some(error))
+    };
+    finish_operation(
+      &app,
+      &operation_id,
+      FILESYSTEM_EXTRACT_FINISHED_EVENT,
+      FilesystemExtractFinishedEvent {
+        operation_id: operation_id.clone(),
+        archive: archive.to_string_lossy().into_owned(),
+        destination: destination.to_string_lossy().into_owned(),
+        file_count,
+        error
+      }
+    );
+  });
+  Ok(started)
+}
+
+/// Checks for conflicts, then unpacks. Returns the canonical destination and the file count.
+fn run_extract(
+  app: &AppHandle,
+  operation_id: &str,
+  archive: &Path,
+  destination: &Path,
+  format: ExtractFormat,
+  overwrite: bool,
+  cancelled: &AtomicBool
+) -> Result<(PathBuf, u64), String> {
+  let archive_error = |error: &dyn std::fmt::Display| format!("failed to read {}: {error}", archive.display());
+  let cancelled_error = || String::from("CANCELLED: the extract was cancelled");
+
+  let entries = list_archive_entries(archive, format).map_err(|error| archive_error(&error))?;
+  let files_total = entries.iter().filter(|(_, is_dir)| !is_dir).count() as u64;
+  if !overwrite {
+    let conflicts: Vec<String> = entries
+      .iter()
+      .filter(|(name, is_dir)| {
+        fs::symlink_metadata(destination.join(name)).is_ok_and(|metadata| !(*is_dir && metadata.is_dir()))
+      })
+      .map(|(name, _)| name.display().to_string())
+      .collect();
+    if !conflicts.is_empty() {
+      let mut listed = conflicts
+        .iter()
+        .take(FILESYSTEM_EXTRACT_CONFLICTS_LISTED)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+      if conflicts.len() > FILESYSTEM_EXTRACT_CONFLICTS_LISTED {
+        listed.push_str(&format!(" and {} more", conflicts.len() - FILESYSTEM_EXTRACT_CONFLICTS_LISTED));
+      }
+      return Err(message_error(
+        "EXISTS",
+        "file.extractConflicts",
+        &[("count", conflicts.len().to_string()), ("paths", listed)]
+      ));
+    }
+  }
+  if cancelled.load(Ordering::SeqCst) {
+    return Err(cancelled_error());
+  }
+
+  fs::create_dir_all(destination)
+    .map_err(|error| io_error(&format!("failed to create {}", destination.display()), error))?;
+  let destination = fs::canonicalize(destination)
+    .map_err(|error| io_error(&format!("failed to resolve {}", destination.display()), error))?;
+  let mut files_done = 0;
+  let mut last_progress = Instant::now();
+  let mut on_file = || {
+    if cancelled.load(Ordering::SeqCst) {
+      return Err(cancelled_error());
+    }
+    files_done += 1;
+    if files_done == files_total || last_progress.elapsed() >= FILESYSTEM_ARCHIVE_PROGRESS_INTERVAL {
+      last_progress = Instant::now();
+      let _ = app.emit(
+        FILESYSTEM_EXTRACT_PROGRESS_EVENT,
+        FilesystemExtractProgressEvent {
+          operation_id: operation_id.to_string(),
+          archive: archive.to_string_lossy().into_owned(),
+          files_done,
+          files_total
+        }
+      );
+    }
+    Ok(())
+  };
+  match format {
+    ExtractFormat::Zip => extract_zip(archive, &destination, &mut on_file),
+    ExtractFormat::TarGz | ExtractFormat::TarXz => extract_tar(archive, format, &destination, &mut on_file)
+  }
+  .map_err(|error| {
+    if cancelled.load(Ordering::SeqCst) {
+      cancelled_error()
+    } else {
+      archive_error(&error)
+    }
+  })?;
+
+  Ok((destination, files_total))
+}
+
+fn extract_format(archive: &Path) -> Result<ExtractFormat, String> {
+  let name = archive
+    .file_name()
+    .map(|name| name.to_string_lossy().to_ascii_lowercase())
+    .unwrap_or_default();
+  if name.ends_with(".zip") {
+    Ok(ExtractFormat::Zip)
+  } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+    Ok(ExtractFormat::TarGz)
+  } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+    Ok(ExtractFormat::TarXz)
+  } else {
+    Err(format!("{} is not a .zip, .tar.gz or .tar.xz archive", archive.display()))
+  }
+}
+
+fn open_tar(archive: &Path, format: ExtractFormat) -> std::io::Result<tar::Archive<Box<dyn Read>>> {
+  let file = std::io::BufReader::new(fs::File::open(archive)?);
+  let reader: Box<dyn Read> = match format {
+    ExtractFormat::TarXz => Box::new(xz2::read::XzDecoder::new(file)),
+    _ => Box::new(flate2::read::GzDecoder::new(file))
+  };
+  Ok(tar::Archive::new(reader))
+}
+
+/// A relative archive path with no root, prefix or `..`, so it cannot escape the destination.
+fn safe_archive_path(name: &Path) -> Option<PathBuf> {
+  let mut safe = PathBuf::new();
+  for component in name.components() {
+    match component {
+      std::path::Component::Normal(part) => safe.push(part),
+      std::path::Component::CurDir => {}
+      _ => return None
+    }
+  }
+  (!safe.as_os_str().is_empty()).then_some(safe)
+}
+
+/// Names and whether each is a directory, for the overwrite check and the progress total.
+fn list_archive_entries(
+  archive: &Path,
+  format: ExtractFormat
+) -> Result<Vec<(PathBuf, bool)>, Box<dyn std::error::Error>> {
+  let mut entries = Vec::new();
+  if format == ExtractFormat::Zip {
+    let mut zip = zip::ZipArchive::new(fs::File::open(archive)?)?;
+    for index in 0..zip.len() {
+      let entry = zip.by_index_raw(index)?;
+      let name = entry
+        .enclosed_name()
+        .ok_or_else(|| format!("unsafe entry path {}", entry.name()))?;
+      entries.push((name, entry.is_dir()));
+    }
+  } else {
+    for entry in open_tar(archive, format)?.entries()? {
+      let entry = entry?;
+      let raw = entry.path()?.into_owned();
+      let name = safe_archive_path(&raw).ok_or_else(|| format!("unsafe entry path {}", raw.display()))?;
+      entries.push((name, entry.header().entry_type().is_dir()));
+    }
+  }
+  Ok(entries)
+}
+
+/// Refuses to write through a symlink an earlier entry planted to point outside `destination`.
+fn ensure_inside_destination(path: &Path, destination: &Path) -> Result<(), String> {
+  let parent = path.parent().unwrap_or(destination);
+  if canonicalize_lenient(parent)?.starts_with(destination) {
+    Ok(())
+  } else {
+    Err(format!("{} would be written outside the destination", path.display()))
+  }
+}
+
+/// `on_file` runs after each file is written; an error from it stops the extract.
+fn extract_zip(
+  archive: &Path,
+  destination: &Path,
+  on_file: &mut dyn FnMut() -> Result<(), String>
+) -> Result<(), Box<dyn std::error::Error>> {
+  let mut zip = zip::ZipArchive::new(fs::File::open(archive)?)?;
+  for index in 0..zip.len() {
+    let mut entry = zip.by_index(index)?;
+    let name = entry
+      .enclosed_name()
+      .ok_or_else(|| format!("unsafe entry path {}", entry.name()))?;
+    let target = destination.join(name);
+    ensure_inside_destination(&target, destination)?;
+    if entry.is_dir() {
+      fs::create_dir_all(&target)?;
+      continue;
+    }
+    if let Some(parent) = target.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    if fs::symlink_metadata(&target).is_ok_and(|metadata| !metadata.is_dir()) {
+      fs::remove_file(&target)?;
+    }
+
+    let mode = entry.unix_mode();
+    #[cfg(unix)]
+    if mode.is_some_and(|mode| mode & 0o170000 == 0o120000) {
+      let mut link_target = String::new();
+      entry.read_to_string(&mut link_target)?;
+      std::os::unix::fs::symlink(link_target, &target)?;
+      on_file()?;
+      continue;
+    }
+    let mut file = fs::File::create(&target)?;
+    std::io::copy(&mut entry, &mut file)?;
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+      use std::os::unix::fs::PermissionsExt;
+      file.set_permissions(fs::Permissions::from_mode(mode & 0o7777))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    on_file()?;
+  }
+  Ok(())
+}
+
+fn extract_tar(
+  archive: &Path,
+  format: ExtractFormat,
+  destination: &Path,
+  on_file: &mut dyn FnMut() -> Result<(), String>
+) -> Result<(), Box<dyn std::error::Error>> {
+  let mut tar = open_tar(archive, format)?;
+  tar.set_preserve_permissions(true);
+  for entry in tar.entries()? {
+    let mut entry = entry?;
+    let is_dir = entry.header().entry_type().is_dir();
+    // unpack_in rejects `..` and absolute names and will not write through symlinks that
+    // lead outside the destination.
+    let raw = entry.path()?.into_owned();
+    if !entry.unpack_in(destination)? {
+      return Err(format!("unsafe entry path {}", raw.display()).into());
+    }
+    if !is_dir {
+      on_file()?;
+    }
+  }
+  Ok(())
+}
+
+/// Lists every path under `inputs` in the order it goes into the archive, parents first. The
+/// output file is left out in case it sits inside one of the inputs.
+fn collect_archive_entries(inputs: &[PathBuf], output: &Path) -> Result<Vec<ArchiveEntry>, String> {
+  let output = canonicalize_lenient(output)?;
+  let mut entries = Vec::new();
+  for input in inputs {
+    let base = input.parent().unwrap_or(input);
+    let mut stack = vec![input.clone()];
+    while let Some(path) = stack.pop() {
+      let metadata = fs::symlink_metadata(&path)
+        .map_err(|error| io_error(&format!("failed to stat {}", path.display()), error))?;
+      if canonicalize_lenient(&path).is_ok_and(|canonical| canonical == output) {
+        continue;
+      }
+      if metadata.is_dir() {
+        let mut children = fs::read_dir(&path)
+          .map_err(|error| io_error(&format!("failed to list {}", path.display()), error))?
+          .map(|entry| entry.map(|entry| entry.path()))
+          .collect::<Result<Vec<_>, _>>()
+          .map_err(|error| io_error(&format!("failed to list {}", path.display()), error))?;
+        children.sort_by(|left, right| right.cmp(left));
+        stack.extend(children);
+      }
+      let name = path
+        .strip_prefix(base)
+        .unwrap_or(&path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/");
+      entries.push(ArchiveEntry {
+        source: path,
+        name,
+        metadata
+      });
+    }
+  }
+  Ok(entries)
+}
+
+fn write_zip_archive(
+  file: fs::File,
+  entries: &[ArchiveEntry],
+  on_entry: &mut dyn FnMut(&ArchiveEntry) -> Result<(), String>
+) -> Result<(), Box<dyn std::error::Error>> {
+  use zip::write::SimpleFileOptions;
+
+  let mut writer = zip::ZipWriter::new(BufWriter::new(file));
+  for entry in entries {
+    let mut options = SimpleFileOptions::default()
+      .compression_method(zip::CompressionMethod::Deflated)
+      .large_file(entry.metadata.len() >= u64::from(u32::MAX));
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      options = options.unix_permissions(entry.metadata.permissions().mode() & 0o7777);
+    }
+    if entry.metadata.file_type().is_symlink() {
+      let target = fs::read_link(&entry.source)?;
+      writer.add_symlink(entry.name.as_str(), target.to_string_lossy(), options)?;
+    } else if entry.metadata.is_dir() {
+      writer.add_directory(entry.name.as_str(), options)?;
+    } else {
+      writer.start_file(entry.name.as_str(), options)?;
+      std::io::copy(&mut fs::File::open(&entry.source)?, &mut writer)?;
+    }
+    on_entry(entry)?;
+  }
+  writer.finish()?.into_inner().map_err(|error| error.into_error())?.sync_all()?;
+  Ok(())
+}
+
+fn write_tar_gz_archive(
+  file: fs::File,
+  entries: &[ArchiveEntry],
+  on_entry: &mut dyn FnMut(&ArchiveEntry) -> Result<(), String>
+) -> Result<(), Box<dyn std::error::Error>> {
+  let encoder = flate2::write::GzEncoder::new(BufWriter::new(file), flate2::Compression::default());
+  let mut builder = tar::Builder::new(encoder);
+  builder.follow_symlinks(false);
+  for entry in entries {
+    // Appends only the entry itself; directory contents come as their own entries.
+    builder.append_path_with_name(&entry.source, &entry.name)?;
+    on_entry(entry)?;
+  }
+  builder
+    .into_inner()?
+    .finish()?
+    .into_inner()
+    .map_err(|error| error.into_error())?
+    .sync_all()?;
+  Ok(())
+}
+
+#[tauri::command]
+fn tasks_load(app: AppHandle) -> Result<TaskState, String> {
+  let path = persistence_file_path(&app, TASKS_FILE_NAME)?;
+  read_json_or_default(&path, default_task_state())
+}
+
+#[tauri::command]
+fn tasks_save(
+  app: AppHandle,
+  store: State<'_, TaskStore>,
+  settings_state: State<'_, SettingsState>,
+  mut state: TaskState
+) -> Result<TaskState, String> {
+  ensure_not_closing(&app)?;
+  let _guard = store.write_lock.lock().map_err(|_| lock_error("task store"))?;
+  let path = persistence_file_path(&app, TASKS_FILE_NAME)?;
+  state.revision = next_revision(&path, state.revision)?;
+  state.updated_at = Utc::now().to_rfc3339();
+  write_json_with_threshold(&path, &state, json_pretty_max_bytes(&settings_state))?;
+  Ok(state)
+}
+
+#[tauri::command]
+fn tasks_create(
+  app: AppHandle,
+  store: State<'_, TaskStore>,
+  request: TasksCreateRequest
+) -> Result<serde_json::Value, String> {
+  let mut task = request.task;
+  let serde_json::Value::Object(fields) = &mut task else {
+    return Err(String::from("task must be a JSON object"));
+  };
+  fields.insert(
+    String::from("id"),
+    serde_json::Value::String(Uuid::new_v4().to_string())
+  );
+
+  update_tasks(&app, &store, |tasks| {
+    tasks.push(task.clone());
+    Ok(())
+  })?;
+  Ok(task)
+}
+
+#[tauri::command]
+fn tasks_delete(app: AppHandle, store: State<'_, TaskStore>, request: TasksDeleteRequest) -> Result<TaskState, String> {
+  update_tasks(&app, &store, |tasks| {
+    let index = tasks
+      .iter()
+      .position(|task| task_id(task) == Some(request.task_id.as_str()))
+      .ok_or_else(|| task_not_found(&request.task_id))?;
+    tasks.remove(index);
+    Ok(())
+  })
+}
+
+/// Moves tasks out of tasks.json into the archive. They are appended to the archive before
+/// tasks.json is rewritten, so a crash in between leaves a task in both rather than in neither.
+#[tauri::command]
+fn tasks_archive(app: AppHandle, store: State<'_, TaskStore>, request: TasksArchiveRequest) -> Result<TaskState, String> {
+  let archive_path = persistence_file_path(&app, TASKS_ARCHIVE_FILE_NAME)?;
+  update_tasks(&app, &store, |tasks| {
+    if let Some(missing) = request
+      .task_ids
+      .iter()
+      .find(|wanted| !tasks.iter().any(|task| task_id(task) == Some(wanted.as_str())))
+    {
+      return Err(task_not_found(missing));
+    }
+    let (archived, kept) = std::mem::take(tasks)
+      .into_iter()
+      .partition(|task| task_id(task).is_some_and(|id| request.task_ids.iter().any(|wanted| wanted == id)));
+    *tasks = kept;
+    append_task_archive(&archive_path, &archived)
+  })
+}
+
+#[tauri::command]
+fn tasks_archive_load(app: AppHandle) -> Result<Vec<serde_json::Value>, String> {
+  read_task_archive(&persistence_file_path(&app, TASKS_ARCHIVE_FILE_NAME)?)
+}
+
+#[tauri::command]
+fn tasks_update_one(
+  app: AppHandle,
+  store: State<'_, TaskStore>,
+  request: TasksUpdateOneRequest
+) -> Result<TaskState, String> {
+  if !request.patch.is_object() {
+    return Err(String::from("task patch must be a JSON object"));
+  }
+  if request.patch.get("id").is_some() {
+    return Err(String::from("task patch must not change the task id"));
+  }
+
+  update_tasks(&app, &store, |tasks| {
+    let task = tasks
+      .iter_mut()
+      .find(|task| task_id(task) == Some(request.task_id.as_str()))
+      .ok_or_else(|| task_not_found(&request.task_id))?;
+    merge_json_patch(task, &request.patch);
+    Ok(())
+  })
+}
+
+#[tauri::command]
+fn tasks_reorder(
+  app: AppHandle,
+  store: State<'_, TaskStore>,
+  request: TasksReorderRequest
+) -> Result<TaskState, String> {
+  update_tasks(&app, &store, |tasks| {
+    let index = tasks
+      .iter()
+      .position(|task| task_id(task) == Some(request.task_id.as_str()))
+      .ok_or_else(|| task_not_found(&request.task_id))?;
+    let task = tasks.remove(index);
+    tasks.insert(request.new_index.min(tasks.len()), task);
+    Ok(())
+  })
+}
+
+#[tauri::command]
+fn workspace_load(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  settings_state: State<'_, SettingsState>,
+  store: State<'_, WorkspaceStore>
+) -> Result<WorkspaceState, String> {
+  let _guard = store.write_lock.lock().map_err(|_| lock_error("workspace store"))?;
+  let path = persistence_file_path(&app, WORKSPACE_FILE_NAME)?;
+  let mut state = read_json_or_default(&path, default_workspace_state())?;
+  let previous_root = state.root_path.clone();
+  let previous_recent_paths = state.recent_paths.clone();
+  let previous_pinned_paths = state.pinned_paths.clone();
+
+  let startup_root = current_workspace_root(&startup_context)?;
+  match startup_root.as_deref() {
+    Some(root) => push_recent_path(&mut state, root, recent_paths_max(&settings_state)),
+    // Still applied so a lowered limit or an older file's duplicates are cleaned up.
+    None => update_recent_paths(&mut state, None, recent_paths_max(&settings_state))
+  }
+
+  let should_update = previous_root != state.root_path
+    || previous_recent_paths != state.recent_paths
+    || previous_pinned_paths != state.pinned_paths;
+  if should_update {
+    state.revision += 1;
+    state.updated_at = Utc::now().to_rfc3339();
+    write_json(&path, &state)?;
+  }
+
+  if let Some(startup_root) = startup_root {
+    state.root_provenance = startup_context
+      .root_provenance
+      .clone()
+      .filter(|provenance| provenance.root_path == startup_root);
+  }
+
+  Ok(state)
+}
+
+#[tauri::command]
+fn workspace_save(
+  app: AppHandle,
+  settings_state: State<'_, SettingsState>,
+  store: State<'_, WorkspaceStore>,
+  state: WorkspaceSaveState
+) -> Result<WorkspaceState, String> {
+  ensure_not_closing(&app)?;
+  let _guard = store.write_lock.lock().map_err(|_| lock_error("workspace store"))?;
+  let path = persistence_file_path(&app, WORKSPACE_FILE_NAME)?;
+  let stored = read_json_or_default(&path, default_workspace_state())?;
+  let mut state = state.into_state(&stored, recent_paths_max(&settings_state))?;
+  state.revision = next_revision(&path, state.revision)?;
+  state.updated_at = Utc::now().to_rfc3339();
+  write_json(&path, &state)?;
+  Ok(state)
+}
+
+#[tauri::command]
+fn workspace_set_root(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  settings_state: State<'_, SettingsState>,
+  request: WorkspaceSetRootRequest
+) -> Result<WorkspaceState, String> {
+  let resolved = resolve_path(&request.path)?;
+  if !resolved.is_dir() {
+    return Err(format!(
+      "workspace root must be an existing directory: {}",
+      resolved.display()
+    ));
+  }
+  let root = fs::canonicalize(&resolved)
+    .map_err(|error| io_error("failed to canonicalize workspace root", error))?
+    .to_string_lossy()
+    .into_owned();
+
+  set_workspace_root(&app, &startup_context, &settings_state, &root)
+}
+
+#[tauri::command]
+fn workspace_validate_root(request: WorkspaceValidateRootRequest) -> Result<WorkspaceValidation, String> {
+  let resolved = resolve_path(&request.path)?;
+  let metadata = fs::metadata(&resolved).ok();
+  let is_directory = metadata.as_ref().is_some_and(|metadata| metadata.is_dir());
+  let absolute_path = fs::canonicalize(&resolved).unwrap_or(resolved);
+
+  Ok(WorkspaceValidation {
+    exists: metadata.is_some(),
+    is_directory,
+    is_readable: is_directory && fs::read_dir(&absolute_path).is_ok(),
+    is_git_repo: is_directory && fs::symlink_metadata(absolute_path.join(".git")).is_ok(),
+    absolute_path: absolute_path.to_string_lossy().into_owned()
+  })
+}
+
+#[tauri::command]
+fn workspace_add_recent(
+  app: AppHandle,
+  settings_state: State<'_, SettingsState>,
+  store: State<'_, WorkspaceStore>,
+  request: WorkspaceRecentRequest
+) -> Result<WorkspaceState, String> {
+  ensure_not_closing(&app)?;
+  let recent_path = resolve_path(&request.path)?.to_string_lossy().into_owned();
+  let _guard = store.write_lock.lock().map_err(|_| lock_error("workspace store"))?;
+  let path = persistence_file_path(&app, WORKSPACE_FILE_NAME)?;
+  let mut state = read_json_or_default(&path, default_workspace_state())?;
+  update_recent_paths(&mut state, Some(&recent_path), recent_paths_max(&settings_state));
+  state.revision += 1;
+  state.updated_at = Utc::now().to_rfc3339();
+  write_json(&path, &state)?;
+  Ok(state)
+}
+
+#[tauri::command]
+fn workspace_remove_recent(
+  app: AppHandle,
+  store: State<'_, WorkspaceStore>,
+  request: WorkspaceRecentRequest
+) -> Result<WorkspaceState, String> {
+  ensure_not_closing(&app)?;
+  let _guard = store.write_lock.lock().map_err(|_| lock_error("workspace store"))?;
+  let path = persistence_file_path(&app, WORKSPACE_FILE_NAME)?;
+  let mut state = read_json_or_default(&path, default_workspace_state())?;
+  let removed = canonical_recent_path(&resolve_path(&request.path)?.to_string_lossy());
+  let is_removed = |path: &String| path == &request.path || canonical_recent_path(path) == removed;
+  state.recent_paths.retain(|path| !is_removed(path));
+  state.pinned_paths.retain(|path| !is_removed(path));
+  state.revision += 1;
+  state.updated_at = Utc::now().to_rfc3339();
+  write_json(&path, &state)?;
+  Ok(state)
+}
+
+/// Pins or unpins a recent path, adding it to the recents first if needed.
+#[tauri::command]
+fn workspace_pin_recent(
+  app: AppHandle,
+  settings_state: State<'_, SettingsState>,
+  store: State<'_, WorkspaceStore>,
+  request: WorkspacePinRecentRequest
+) -> Result<WorkspaceState, String> {
+  ensure_not_closing(&app)?;
+  let _guard = store.write_lock.lock().map_err(|_| lock_error("workspace store"))?;
+  let pinned_path = canonical_recent_path(&resolve_path(&request.path)?.to_string_lossy());
+  let path = persistence_file_path(&app, WORKSPACE_FILE_NAME)?;
+  let mut state = read_json_or_default(&path, default_workspace_state())?;
+  state.pinned_paths.retain(|path| canonical_recent_path(path) != pinned_path);
+  if request.pinned {
+    state.pinned_paths.push(pinned_path);
+  }
+  update_recent_paths(&mut state, None, recent_paths_max(&settings_state));
+  state.revision += 1;
+  state.updated_at = Utc::now().to_rfc3339();
+  write_json(&path, &state)?;
+  Ok(state)
+}
+
+fn load_app_settings(app: &AppHandle) -> Result<AppSettings, String> {
+  let path = persistence_file_path(app, SETTINGS_FILE_NAME)?;
+  read_json_or_default(&path, default_app_settings())
+}
+
+fn store_app_settings(app: &AppHandle, settings_state: &SettingsState, mut settings: AppSettings) -> Result<AppSettings, String> {
+  ensure_not_closing(app)?;
+  settings.recent_paths_max = settings.recent_paths_max.max(1);
+  settings.updated_at = Utc::now().to_rfc3339();
+  let path = persistence_file_path(app, SETTINGS_FILE_NAME)?;
+  write_json(&path, &settings)?;
+
+  let mut current = settings_state
+    .settings
+    .lock()
+    .map_err(|_| lock_error("app settings"))?;
+  *current = settings.clone();
+  Ok(settings)
+}
+
+#[tauri::command]
+fn settings_load(app: AppHandle, settings_state: State<'_, SettingsState>) -> Result<AppSettings, String> {
+  let settings = load_app_settings(&app)?;
+  let mut current = settings_state
+    .settings
+    .lock()
+    .map_err(|_| lock_error("app settings"))?;
+  *current = settings.clone();
+  Ok(settings)
+}
+
+#[tauri::command]
+fn settings_save(
+  app: AppHandle,
+  settings_state: State<'_, SettingsState>,
+  settings: AppSettings
+) -> Result<AppSettings, String> {
+  store_app_settings(&app, &settings_state, settings)
+}
+
+#[tauri::command]
+fn onboarding_complete(app: AppHandle, settings_state: State<'_, SettingsState>) -> Result<AppSettings, String> {
+  let mut settings = load_app_settings(&app)?;
+  settings.first_run = false;
+  store_app_settings(&app, &settings_state, settings)
+}
+
+#[tauri::command]
+fn sample_workspace_create(
+  app: AppHandle,
+  startup_context: State<'_, StartupContext>,
+  settings_state: State<'_, SettingsState>,
+  request: SampleWorkspaceRequest
+) -> Result<WorkspaceState, String> {
+  let target_path = resolve_path(&request.path)?;
+
+  if target_path.exists() {
+    if !target_path.is_dir() {
+      return Err(format!(
+        "sample workspace destination is not a directory: {}",
+        target_path.display()
+      ));
+    }
+
+    let mut entries = fs::read_dir(&target_path)
+      .map_err(|error| io_error(&format!("failed to list {}", target_path.display()), error))?;
+    if entries.next().is_some() {
+      return Err(format!(
+        "sample workspace destination must be empty: {}",
+        target_path.display()
+      ));
+    }
+  }
+
+  for directory in SAMPLE_WORKSPACE_DIRECTORIES {
+    let directory_path = target_path.join(directory);
+    fs::create_dir_all(&directory_path).map_err(|error| {
+      io_error(&format!("failed to create {}", directory_path.display()), error)
+    })?;
+  }
+
+  for (relative_path, content) in SAMPLE_WORKSPACE_FILES {
+    let file_path = target_path.join(relative_path);
+    fs::write(&file_path, content)
+      .map_err(|error| io_error(&format!("failed to write {}", file_path.display()), error))?;
+  }
+
+  let root = fs::canonicalize(&target_path)
+    .map_err(|error| io_error("failed to canonicalize sample workspace path", error))?
+    .to_string_lossy()
+    .into_owned();
+
+  set_workspace_root(&app, &startup_context, &settings_state, &root)
+}
+
+#[tauri::command]
+fn app_health(app: AppHandle) -> Result<AppHealth, String> {
+  app_health_snapshot(&app)
+}
+
+#[tauri::command]
+fn safe_mode_enable(
+  app: AppHandle,
+  safe_mode: State<'_, SafeModeState>,
+  request: SafeModeEnableRequest
+) -> Result<AppHealth, String> {
+  if !SAFE_MODE_SUBSYSTEMS.contains(&request.subsystem.as_str()) {
+    return Err(format!(
+      "unknown subsystem \"{}\"; expected one of: {}",
+      request.subsystem,
+      SAFE_MODE_SUBSYSTEMS.join(", ")
+    ));
+  }
+
+  safe_mode
+    .disabled_subsystems
+    .lock()
+    .map_err(|_| lock_error("safe mode subsystems"))?
+    .retain(|name| name != &request.subsystem);
+
+  app_health_snapshot(&app)
+}
+
+#[tauri::command]
+fn shells_list(cache: State<'_, ShellCache>, request: ShellsListRequest) -> Result<Vec<ShellInfo>, String> {
+  let mut shells = cache.shells.lock().map_err(|_| lock_error("shell cache"))?;
+  if request.refresh.unwrap_or(false) || shells.is_none() {
+    *shells = Some(scan_shells());
+  }
+  Ok(shells.clone().unwrap_or_default())
+}
+
+#[tauri::command]
+fn system_list_handlers(
+  cache: State<'_, DesktopEntryCache>,
+  request: SystemListHandlersRequest
+) -> Result<SystemListHandlersResponse, String> {
+  let target_path = resolve_path(&request.path)?;
+  let mime_type = detect_mime_type(&target_path);
+  let entries = cached_desktop_entries(&cache)?;
+  let associations = read_mime_associations();
+
+  let removed = associations.removed.get(&mime_type).cloned().unwrap_or_default();
+  let defaults = associations.defaults.get(&mime_type).cloned().unwrap_or_default();
+  let mut ordered_ids: Vec<String> = Vec::new();
+  let associated_ids = defaults
+    .iter()
+    .chain(associations.added.get(&mime_type).into_iter().flatten())
+    .cloned()
+    .chain(
+      entries
+        .iter()
+        .filter(|entry| entry.mime_types.contains(&mime_type))
+        .map(|entry| entry.id.clone())
+    );
+  for desktop_id in associated_ids {
+    if !removed.contains(&desktop_id) && !ordered_ids.contains(&desktop_id) {
+      ordered_ids.push(desktop_id);
+    }
+  }
+
+  let handlers = ordered_ids
+    .iter()
+    .filter_map(|desktop_id| entries.iter().find(|entry| &entry.id == desktop_id))
+    .map(|entry| SystemHandler {
+      desktop_id: entry.id.clone(),
+      name: entry.name.clone(),
+      icon: entry.icon.clone(),
+      is_default: defaults.first() == Some(&entry.id)
+    })
+    .collect();
+
+  Ok(SystemListHandlersResponse {
+    path: target_path.to_string_lossy().into_owned(),
+    mime_type,
+    handlers
+  })
+}
+
+#[tauri::command]
+fn system_open_with(
+  cache: State<'_, DesktopEntryCache>,
+  startup_context: State<'_, StartupContext>,
+  request: SystemOpenWithRequest
+) -> Result<(), String> {
+  let target_path = resolve_workspace_path(&startup_context, &request.path)?;
+  if !target_path.exists() {
+    return Err(format!("path does not exist: {}", target_path.display()));
+  }
+
+  let entries = cached_desktop_entries(&cache)?;
+  let entry = entries
+    .iter()
+    .find(|entry| entry.id == request.desktop_id)
+    .ok_or_else(|| message_error("NOT_FOUND", "application.notFound", &[("desktopId", request.desktop_id.clone())]))?;
+
+  let arguments = desktop_exec_arguments(entry, &target_path);
+  let (program, program_args) = arguments
+    .split_first()
+    .ok_or_else(|| format!("Application \"{}\" has an empty Exec line.", entry.id))?;
+
+  let mut command = std::process::Command::new(program);
+  command
+    .args(program_args)
+    .stdin(std::process::Stdio::null())
+    .stdout(std::process::Stdio::null())
+    .stderr(std::process::Stdio::null());
+
+  // A separate process group keeps the handler alive when OpenSpace exits.
+  #[cfg(unix)]
+  {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+  }
+
+  let mut child = command
+    .spawn()
+    .map_err(|error| io_error(&format!("failed to launch {}", entry.name), error))?;
+
+  std::thread::spawn(move || {
+    let _ = child.wait();
+  });
+  Ok(())
+}
+
+#[tauri::command]
+fn process_list(state: State<'_, AppState>) -> Result<Vec<ProcessEntry>, String> {
+  if !Path::new("/proc/self/stat").exists() {
+    return Err(String::from("process_list needs a Linux /proc filesystem"));
+  }
+
+  let mut sample = state
+    .process_sample
+    .lock()
+    .map_err(|_| lock_error("process sample"))?;
+
+  // CPU use is diffed against the previous call's scan; the first call, with nothing to diff
+  // against, reports lifetime averages rather than blocking to take a baseline.
+  let uptime_secs = system_uptime_secs();
+  let ticks_per_second = clock_ticks_per_second();
+  let mut user_names = HashMap::new();
+  let mut pids: Vec<u32> = fs::read_dir("/proc")
+    .map_err(|error| io_error("failed to list /proc", error))?
+    .filter_map(Result::ok)
+    .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+    .collect();
+  pids.sort_unstable();
+
+  let taken_at = Instant::now();
+  let mut ticks = HashMap::with_capacity(pids.len());
+  let processes: Vec<ProcessEntry> = pids
+    .into_iter()
+    .filter_map(|pid| read_process_entry(pid, sample.as_ref(), uptime_secs, ticks_per_second, &mut user_names))
+    .map(|(entry, process_ticks)| {
+      ticks.insert(entry.pid, process_ticks);
+      entry
+    })
+    .collect();
+
+  *sample = Some(ProcessSample { taken_at, ticks });
+  Ok(processes)
+}
+
+/// Sends `signal` (e.g. `TERM`, `SIGKILL` or `9`) to `pid`. Errors are prefixed with
+/// `PERMISSION_DENIED: ` or `NOT_FOUND: ` when the process is not ours or already gone.
+#[tauri::command]
+fn process_kill(request: ProcessKillRequest) -> Result<(), String> {
+  // pid 0 and anything past i32::MAX would address a process group rather than one process.
+  if request.pid <= 1 || request.pid > i32::MAX as u32 || request.pid == std::process::id() {
+    return Err(format!("refusing to signal process {}", request.pid));
+  }
+  send_process_signal(request.pid, &request.signal)
+}
+
+#[cfg(unix)]
+fn send_process_signal(pid: u32, name: &str) -> Result<(), String> {
+  use nix::errno::Errno;
+  use nix::sys::signal::{kill, Signal};
+  use nix::unistd::Pid;
+
+  let name = name.trim().to_ascii_uppercase();
+  let signal = match name.parse::<i32>() {
+    Ok(number) => Signal::try_from(number).ok(),
+    Err(_) if name.starts_with("SIG") => name.parse().ok(),
+    Err(_) => format!("SIG{name}").parse().ok()
+  }
+  .ok_or_else(|| format!("unknown signal: {name}"))?;
+
+  match kill(Pid::from_raw(pid as i32), signal) {
+    Ok(()) => Ok(()),
+    Err(Errno::EPERM) => Err(message_error(
+      "PERMISSION_DENIED",
+      "process.permissionDenied",
+      &[("pid", pid.to_string())]
+    )),
+    Err(Errno::ESRCH) => Err(message_error("NOT_FOUND", "process.notFound", &[("pid", pid.to_string())])),
+    Err(error) => Err(format!("failed to signal process {pid}: {error}"))
+  }
+}
+
+#[cfg(not(unix))]
+fn send_process_signal(_pid: u32, _name: &str) -> Result<(), String> {
+  Err(String::from("process_kill is only supported on Unix"))
+}
+
+fn with_clipboard<T>(
+  clipboard_state: &ClipboardState,
+  action: impl FnOnce(&mut arboard::Clipboard) -> Result<T, arboard::Error>
+) -> Result<T, String> {
+  let mut clipboard = clipboard_state
+    .clipboard
+    .lock()
+    .map_err(|_| lock_error("clipboard"))?;
+  let opened = match clipboard.take() {
+    Some(opened) => opened,
+    None => arboard::Clipboard::new().map_err(|error| format!("failed to open the clipboard: {error}"))?
+  };
+  let clipboard = clipboard.insert(opened);
+  action(clipboard).map_err(|error| format!("clipboard operation failed: {error}"))
+}
+
+/// Reads the clipboard as text; an empty or non-text clipboard reads as "".
+#[tauri::command]
+fn clipboard_read(clipboard_state: State<'_, ClipboardState>) -> Result<ClipboardReadResponse, String> {
+  let text = with_clipboard(&clipboard_state, |clipboard| match clipboard.get_text() {
+    Err(arboard::Error::ContentNotAvailable) => Ok(String::new()),
+    result => result
+  })?;
+  Ok(ClipboardReadResponse { text })
+}
+
+#[tauri::command]
+fn clipboard_write(clipboard_state: State<'_, ClipboardState>, request: ClipboardWriteRequest) -> Result<(), String> {
+  with_clipboard(&clipboard_state, |clipboard| clipboard.set_text(request.text))
+}
+
+/// The app's environment, which new terminals inherit. Non-UTF-8 values are decoded lossily.
+#[tauri::command]
+fn environment_list() -> HashMap<String, String> {
+  std::env::vars_os()
+    .map(|(key, value)| (key.to_string_lossy().into_owned(), value.to_string_lossy().into_owned()))
+    .collect()
+}
+
+#[tauri::command]
+fn environment_get(request: EnvironmentGetRequest) -> Result<Option<String>, String> {
+  if request.key.is_empty() || request.key.contains(['=', '\0']) {
+    return Err(format!("invalid environment variable name: {:?}", request.key));
+  }
+  Ok(std::env::var_os(&request.key).map(|value| value.to_string_lossy().into_owned()))
+}
+
+#[tauri::command]
+fn system_info() -> Result<SystemInfo, String> {
+  if !Path::new("/proc/meminfo").exists() {
+    return Err(String::from("system_info needs a Linux /proc filesystem"));
+  }
+
+  let read_trimmed = |path: &str| {
+    fs::read_to_string(path)
+      .map(|value| value.trim().to_string())
+      .unwrap_or_default()
+  };
+  let os_release = fs::read_to_string("/etc/os-release")
+    .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+    .unwrap_or_default();
+  let os_release_value = |key: &str| {
+    os_release
+      .lines()
+      .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+      .map(|value| value.trim().trim_matches('"').to_string())
+      .unwrap_or_default()
+  };
+
+  let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+  let cpu_field = |line: &str, key: &str| -> Option<String> {
+    let (name, value) = line.split_once(':')?;
+    (name.trim() == key).then(|| value.trim().to_string())
+  };
+  let cpu_count = cpuinfo
+    .lines()
+    .filter(|line| cpu_field(line, "processor").is_some())
+    .count()
+    .max(1);
+  let cpu_model = cpuinfo
+    .lines()
+    .find_map(|line| cpu_field(line, "model name").or_else(|| cpu_field(line, "Model")))
+    .unwrap_or_default();
+
+  let meminfo = fs::read_to_string("/proc/meminfo").map_err(|error| io_error("failed to read /proc/meminfo", error))?;
+  let meminfo_kb = |key: &str| {
+    meminfo
+      .lines()
+      .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+      .and_then(|value| value.split_whitespace().next()?.parse().ok())
+      .unwrap_or(0)
+  };
+
+  Ok(SystemInfo {
+    hostname: read_trimmed("/proc/sys/kernel/hostname"),
+    os_name: os_release_value("NAME"),
+    os_version: os_release_value("VERSION_ID"),
+    kernel_version: read_trimmed("/proc/sys/kernel/osrelease"),
+    cpu_count,
+    cpu_model,
+    total_memory_kb: meminfo_kb("MemTotal"),
+    available_memory_kb: meminfo_kb("MemAvailable"),
+    disk_entries: read_disk_entries()
+  })
+}
+
+/// Mounted filesystems with real capacity; pseudo filesystems such as proc report zero blocks
+/// and are skipped.
+fn read_disk_entries() -> Vec<DiskInfo> {
+  let mounts = fs::read_to_string("/proc/mounts").unwrap_or_default();
+  let mut entries: Vec<DiskInfo> = Vec::new();
+  for line in mounts.lines() {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [_, mount_point, filesystem_type, ..] = fields[..] else {
+      continue;
+    };
+    let mount_point = unescape_mount_field(mount_point);
+    if entries.iter().any(|entry| entry.mount_point == mount_point) {
+      continue;
+    }
+    let Some((total_bytes, free_bytes)) = disk_space(Path::new(&mount_point)) else {
+      continue;
+    };
+    if total_bytes == 0 {
+      continue;
+    }
+    entries.push(DiskInfo {
+      mount_point,
+      total_bytes,
+      free_bytes,
+      filesystem_type: filesystem_type.to_string()
+    });
+  }
+  entries
+}
+
+/// /proc/mounts writes spaces, tabs and backslashes in paths as octal escapes such as `\040`.
+fn unescape_mount_field(field: &str) -> String {
+  let bytes = field.as_bytes();
+  let mut unescaped = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+  while index < bytes.len() {
+    let octal = bytes
+      .get(index + 1..index + 4)
+      .filter(|digits| bytes[index] == b'\\' && digits.iter().all(|digit| (b'0'..=b'7').contains(digit)))
+      .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+    match octal {
+      Some(byte) => {
+        unescaped.push(byte);
+        index += 4;
+      }
+      None => {
+        unescaped.push(bytes[index]);
+        index += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// Total and available bytes of the filesystem holding `path`.
+#[cfg(unix)]
+fn disk_space(path: &Path) -> Option<(u64, u64)> {
+  let stats = nix::sys::statvfs::statvfs(path).ok()?;
+  let fragment_size = stats.fragment_size() as u64;
+  Some((
+    stats.blocks() as u64 * fragment_size,
+    stats.blocks_available() as u64 * fragment_size
+  ))
+}
+
+#[cfg(not(unix))]
+fn disk_space(_path: &Path) -> Option<(u64, u64)> {
+  None
+}
+
+#[tauri::command]
+fn workspace_activity(
+  cache: State<'_, ActivityCache>,
+  safe_mode: State<'_, SafeModeState>,
+  startup_context: State<'_, StartupContext>,
+  request: WorkspaceActivityRequest
+) -> Result<WorkspaceActivity, String> {
+  let root = fs::canonicalize(resolve_workspace_path(&startup_context, &request.root)?)
+    .map_err(|error| io_error("failed to resolve workspace root", error))?;
+  if !root.is_dir() {
+    return Err(format!("workspace root is not a directory: {}", root.display()));
+  }
+
+  let days = request
+    .days
+    .unwrap_or(ACTIVITY_DEFAULT_DAYS)
+    .clamp(1, ACTIVITY_MAX_DAYS);
+  let root_key = root.to_string_lossy().into_owned();
+  let cache_key = (root_key.clone(), days, Utc::now().format("%Y-%m-%d").to_string());
+
+  if let Some(activity) = cache
+    .entries
     .lock()
-    .map_err(|_| lock_error("terminal sessions"))?;
+    .map_err(|_| lock_error("activity cache"))?
+    .get(&cache_key)
+  {
+    return Ok(activity.1.clone());
+  }
+
+  let mut counts = HashMap::new();
+  let mut sources = Vec::new();
+  let git_result = if subsystem_enabled(&safe_mode, "git") {
+    collect_git_activity(&root, days, &mut counts)
+  } else {
+    None
+  };
+  let truncated = match git_result {
+    Some(truncated) => {
+      sources.push(String::from("git"));
+      truncated
+    }
+    None => {
+      sources.push(String::from("mtime"));
+      collect_mtime_activity(&root, days, &mut counts)
+    }
+  };
+
+  let mut buckets: Vec<ActivityBucket> = counts
+    .into_iter()
+    .map(|((date, directory), count)| ActivityBucket {
+      date,
+      directory,
+      count
+    })
+    .collect();
+  buckets.sort_by(|left, right| {
+    left
+      .date
+      .cmp(&right.date)
+      .then_with(|| left.directory.cmp(&right.directory))
+  });
+
+  let activity = WorkspaceActivity {
+    root: root_key,
+    days,
+    sources,
+    truncated,
+    buckets
+  };
+  cache_activity(
+    &mut *cache.entries.lock().map_err(|_| lock_error("activity cache"))?,
+    cache_key,
+    activity.clone()
+  );
+  Ok(activity)
+}
+
+fn main() {
+  if has_cli_flag(BUILTIN_SHELL_FLAG) {
+    std::process::exit(run_builtin_shell());
+  }
+
+  let (root_provenance, open_path) = match resolve_startup_from_args() {
+    Ok(Some((provenance, open_path))) => (Some(provenance), open_path),
+    Ok(None) => (None, None),
+    Err(message) => {
+      eprintln!("{message}");
+      (None, None)
+    }
+  };
+
+  let app = tauri::Builder::default()
+    .plugin(tauri_plugin_dialog::init())
+    .manage(AppState::default())
+    .manage(DesktopEntryCache::default())
+    .manage(ShellCache::default())
+    .manage(ActivityCache::default())
+    .manage(TaskStore::default())
+    .manage(WorkspaceStore::default())
+    .manage(TerminalLayoutStore::default())
+    .manage(ShutdownState::default())
+    .manage(EventReplayState::default())
+    .manage(ClipboardState::default())
+    .manage(OperationRegistry::default())
+    .manage(StartupContext {
+      root_path: Mutex::new(root_provenance.as_ref().map(|provenance| provenance.root_path.clone())),
+      root_provenance,
+      open_path
+    })
+    .setup(|app| {
+      let safe_mode = begin_startup(app.handle()).unwrap_or_else(|message| {
+        eprintln!("{message}");
+        SafeModeState::default()
+      });
+      app.manage(safe_mode);
+
+      let settings = load_app_settings(app.handle()).unwrap_or_else(|message| {
+        eprintln!("{message}");
+        default_app_settings()
+      });
+      let control_socket_enabled = settings.control_socket_enabled;
+      app.manage(SettingsState {
+        settings: Mutex::new(settings)
+      });
+
+      let sweeper_app = app.handle().clone();
+      std::thread::spawn(move || loop {
+        std::thread::sleep(TERMINAL_IDLE_SWEEP_INTERVAL);
+        sweep_idle_terminals(&sweeper_app);
+      });
+      let log_flusher_app = app.handle().clone();
+      std::thread::spawn(move || loop {
+        std::thread::sleep(TERMINAL_LOG_FLUSH_INTERVAL);
+        flush_terminal_logs(&log_flusher_app);
+      });
+
+      let mut control_socket = ControlSocketState::default();
+      if control_socket_enabled {
+        match start_control_socket(app.handle()) {
+          Ok(path) => control_socket.path = Some(path),
+          Err(message) => eprintln!("{message}")
+        }
+      }
+      app.manage(control_socket);
+      Ok(())
+    })
+    .on_window_event(|window, event| match event {
+      tauri::WindowEvent::CloseRequested { api, .. } => handle_close_request(window, api),
+      tauri::WindowEvent::Destroyed => {
+        let state = window.app_handle().state::<AppState>();
+        lock_recover(&state.watches).retain(|_, watch| watch.window_label != window.label());
+      }
+      _ => {}
+    })
+    .invoke_handler(tauri::generate_handler![
+      terminal_create,
+      terminal_write,
+      terminal_write_bytes,
+      terminal_paste,
+      terminal_broadcast,
+      terminal_send_control,
+      terminal_profile_get,
+      terminal_profile_set,
+      terminal_start_logging,
+      terminal_stop_logging,
+      terminal_get_stats,
+      terminal_stats,
+      terminal_info,
+      terminal_set_visibility,
+      terminal_set_priority,
+      terminal_install_shell_integration,
+      terminal_resize,
+      terminal_kill,
+      terminal_list,
+      terminal_dispose,
+      terminal_clear,
+      terminal_export_log,
+      terminal_duplicate,
+      terminal_adopt,
+      terminal_search_buffer,
+      filesystem_list,
+      filesystem_list_recursive,
+      fs_tree,
+      filesystem_get_home_dir,
+      filesystem_get_temp_dir,
+      filesystem_resolve,
+      filesystem_is_text,
+      fs_read_range,
+      fs_read_lines,
+      filesystem_hash,
+      filesystem_read,
+      filesystem_write,
+      fs_rename,
+      fs_delete,
+      fs_mkdir,
+      fs_stat,
+      fs_create_file,
+      tasks_load,
+      tasks_save,
+      tasks_create,
+      tasks_delete,
+      tasks_update_one,
+      tasks_reorder,
+      tasks_archive,
+      tasks_archive_load,
+      workspace_load,
+      workspace_save,
+      workspace_set_root,
+      workspace_validate_root,
+      workspace_add_recent,
+      workspace_remove_recent,
+      workspace_pin_recent,
+      settings_load,
+      settings_save,
+      onboarding_complete,
+      sample_workspace_create,
+      app_health,
+      safe_mode_enable,
+      shells_list,
+      system_list_handlers,
+      system_open_with,
+      workspace_activity,
+      process_list,
+      process_kill,
+      system_info,
+      environment_list,
+      environment_get,
+      events_replay,
+      clipboard_read,
+      clipboard_write,
+      messages_catalog,
+      git_status,
+      git_log,
+      git_diff,
+      git_branches,
+      git_checkout,
+      fs_watch_start,
+      fs_watch_stop,
+      filesystem_export,
+      fs_copy,
+      fs_search_filenames,
+      fs_search_content,
+      fs_replace_in_files,
+      filesystem_archive,
+      filesystem_extract,
+      operations_list,
+      operation_result,
+      operation_cancel,
+      workspace_export
+    ])
+    .build(tauri::generate_context!())
+    .expect("error while building tauri application");
+
+  app.run(|app_handle, event| match event {
+    RunEvent::Ready => finish_startup(app_handle),
+    RunEvent::Exit => {
+      if let Some(path) = app_handle.state::<ControlSocketState>().path.as_ref() {
+        let _ = fs::remove_file(path);
+      }
+    }
+    _ => {}
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A fresh directory under the system temp directory, removed again on drop.
+  struct TempDir(PathBuf);
+
+  impl TempDir {
+    fn new() -> Self {
+      let path = std::env::temp_dir().join(format!("openspace-test-{}", Uuid::new_v4()));
+      fs::create_dir_all(&path).expect("create temp dir");
+      Self(fs::canonicalize(&path).expect("canonicalize temp dir"))
+    }
+
+    fn child(&self, name: &str) -> PathBuf {
+      let path = self.0.join(name);
+      fs::create_dir_all(&path).expect("create child dir");
+      path
+    }
+  }
+
+  impl Drop for TempDir {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+    }
+  }
+
+  fn path_string(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+  }
+
+  fn workspace_state(recent_paths: &[String], pinned_paths: &[String]) -> WorkspaceState {
+    WorkspaceState {
+      recent_paths: recent_paths.to_vec(),
+      pinned_paths: pinned_paths.to_vec(),
+      ..default_workspace_state()
+    }
+  }
+
+  #[test]
+  fn recent_paths_fold_trailing_slash_spellings() {
+    let temp = TempDir::new();
+    let project = path_string(&temp.child("project"));
+    let mut state = workspace_state(&[format!("{project}/"), project.clone()], &[]);
+
+    update_recent_paths(&mut state, None, 6);
+
+    assert_eq!(state.recent_paths, vec![project]);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn recent_paths_fold_symlinked_spellings() {
+    let temp = TempDir::new();
+    let project = temp.child("project");
+    let link = temp.0.join("link");
+    std::os::unix::fs::symlink(&project, &link).expect("create symlink");
+    let mut state = workspace_state(&[path_string(&project)], &[]);
+
+    update_recent_paths(&mut state, Some(&path_string(&link)), 6);
+
+    assert_eq!(state.recent_paths, vec![path_string(&project)]);
+  }
+
+  #[test]
+  fn recent_paths_evict_the_oldest_unpinned_entry() {
+    let temp = TempDir::new();
+    let paths: Vec<String> = ["a", "b", "c"].iter().map(|name| path_string(&temp.child(name))).collect();
+    let mut state = workspace_state(&paths, &[paths[2].clone()]);
+
+    update_recent_paths(&mut state, None, 1);
+
+    assert_eq!(state.recent_paths, vec![paths[0].clone(), paths[2].clone()]);
+  }
+
+  #[test]
+  fn validate_recent_paths_rejects_trailing_slash_duplicates() {
+    let temp = TempDir::new();
+    let project = path_string(&temp.child("project"));
+    let state = workspace_state(&[project.clone(), format!("{project}/")], &[]);
+
+    let error = validate_recent_paths(&state, 6).expect_err("duplicate recents");
+
+    assert_eq!(error_code(&error).as_deref(), Some("INVALID_RECENTS"), "{error}");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn validate_recent_paths_rejects_symlinked_duplicates() {
+    let temp = TempDir::new();
+    let project = temp.child("project");
+    let link = temp.0.join("link");
+    std::os::unix::fs::symlink(&project, &link).expect("create symlink");
+    let state = workspace_state(&[path_string(&project), path_string(&link)], &[]);
+
+    assert!(validate_recent_paths(&state, 6).is_err());
+  }
+
+  #[test]
+  fn workspace_save_without_pins_keeps_the_stored_ones() {
+    let temp = TempDir::new();
+    let paths: Vec<String> = ["a", "b"].iter().map(|name| path_string(&temp.child(name))).collect();
+    let stored = workspace_state(&paths, &[paths[1].clone()]);
+    let save = WorkspaceSaveState {
+      root_path: Some(paths[0].clone()),
+      recent_paths: vec![paths[0].clone()],
+      pinned_paths: None,
+      revision: 0
+    };
+
+    let state = save.into_state(&stored, 6).expect("valid save");
+
+    assert_eq!(state.pinned_paths, vec![paths[1].clone()]);
+    assert_eq!(state.recent_paths, paths);
+  }
+
+  fn replace_pattern(pattern: &str, content: &str, replacement: &str) -> ReplacedContent {
+    let regex = regex::bytes::RegexBuilder::new(pattern)
+      .multi_line(true)
+      .crlf(true)
+      .build()
+      .expect("valid pattern");
+    replace_matches(&regex, content.as_bytes(), replacement.as_bytes(), false)
+  }
+
+  #[test]
+  fn replace_anchors_match_every_line_like_search() {
+    let replaced = replace_pattern("^foo", "foo\r\nfoo bar\nbar foo\n", "baz");
 
-  sessions
-    .get(session_id)
-    .cloned()
-    .ok_or_else(|| format!("Terminal session \"{session_id}\" was not found."))
-}
+    assert_eq!(replaced.content, b"baz\r\nbaz bar\nbar foo\n");
+    assert_eq!(replaced.matches, 2);
+    let previews: Vec<_> = replaced
+      .previews
+      .iter()
+      .map(|preview| (preview.line_number, preview.before.as_str(), preview.after.as_str()))
+      .collect();
+    assert_eq!(previews, vec![(1, "foo", "baz"), (2, "foo bar", "baz bar")]);
+  }
 
-#[tauri::command]
-fn terminal_create(
-  app: AppHandle,
-  state: State<'_, AppState>,
-  request: TerminalCreateRequest
-) -> Result<TerminalCreateResponse, String> {
-  let session_id = Uuid::new_v4().to_string();
-  let shell = request
-    .shell
-    .unwrap_or_else(|| std::env::var("SHELL").unwrap_or_else(|_| String::from("/bin/bash")));
-  let args = request.args.unwrap_or_else(|| {
-    if shell.ends_with("bash") {
-      vec![String::from("--login")]
-    } else {
-      Vec::new()
+  #[test]
+  fn replace_skips_empty_matches() {
+    let replaced = replace_pattern("a*", "xaay", "b");
+
+    assert_eq!(replaced.content, b"xby");
+    assert_eq!(replaced.matches, 1);
+  }
+
+  #[test]
+  fn replace_previews_show_multi_line_matches_once() {
+    let replaced = replace_pattern("b\nc", "a\nb\nc b\nc\nd", "${0}!");
+
+    assert_eq!(replaced.content, b"a\nb\nc! b\nc!\nd");
+    assert_eq!(replaced.matches, 2);
+    assert_eq!(replaced.previews.len(), 1);
+    assert_eq!(replaced.previews[0].line_number, 2);
+    assert_eq!(replaced.previews[0].before, "b\nc b\nc");
+    assert_eq!(replaced.previews[0].after, "b\nc! b\nc!");
+  }
+
+  #[test]
+  fn message_error_carries_the_key_params_and_text() {
+    let error = message_error("NOT_FOUND", "file.notFound", &[("path", String::from("/tmp/a"))]);
+    let parsed: MessageError = serde_json::from_str(&error).expect("structured error");
+
+    assert_eq!(parsed.code, "NOT_FOUND");
+    assert_eq!(parsed.message_key, "file.notFound");
+    assert_eq!(parsed.params.get("path").map(String::as_str), Some("/tmp/a"));
+    assert_eq!(parsed.message, "/tmp/a does not exist");
+    assert_eq!(error_code(&error).as_deref(), Some("NOT_FOUND"));
+  }
+
+  #[test]
+  fn error_code_reads_plain_prefixes() {
+    assert_eq!(error_code("CANCELLED: the copy was cancelled").as_deref(), Some("CANCELLED"));
+    assert_eq!(error_code("failed to read /tmp/a: denied"), None);
+  }
+
+  /// Every user-facing message goes through `message_error`, with a key the catalog has.
+  #[test]
+  fn user_facing_errors_use_catalog_keys() {
+    let source = include_str!("main.rs");
+    let bare = regex::Regex::new(r#"[^_a-z]message\(\s*""#).unwrap();
+    assert!(bare.find(source).is_none(), "message() called without message_error");
+
+    let keyed = regex::Regex::new(r#"(?:message_error(?:_with_details)?\(\s*"[A-Z_]+",\s*|invalid\()"([a-zA-Z]+\.[a-zA-Z]+)""#).unwrap();
+    let mut used = 0;
+    for captures in keyed.captures_iter(source) {
+      let key = &captures[1];
+      assert!(MESSAGE_CATALOG.iter().any(|(catalog_key, _)| *catalog_key == key), "{key} is not in the catalog");
+      used += 1;
     }
-  });
-  let cwd = request
-    .cwd
-    .as_deref()
-    .map(resolve_path)
-    .transpose()?
-    .unwrap_or(std::env::current_dir().map_err(|error| io_error("failed to resolve cwd", error))?);
+    assert!(used > 0);
 
-  let cols = request.cols.unwrap_or(120).max(1);
-  let rows = request.rows.unwrap_or(40).max(1);
+    let mut keys: Vec<&str> = MESSAGE_CATALOG.iter().map(|(key, _)| *key).collect();
+    keys.sort_unstable();
+    keys.dedup();
+    assert_eq!(keys.len(), MESSAGE_CATALOG.len(), "duplicate catalog keys");
+  }
 
-  let pty_system = native_pty_system();
-  let pty_pair = pty_system
-    .openpty(PtySize {
-      rows,
-      cols,
-      pixel_width: 0,
-      pixel_height: 0
+  #[test]
+  fn revisions_survive_a_backwards_clock_jump() {
+    let temp = TempDir::new();
+    let path = temp.0.join("tasks.json");
+    let saved = |revision: u64, updated_at: &str| TaskState {
+      tasks: Vec::new(),
+      revision,
+      updated_at: updated_at.to_string()
+    };
+    write_json(&path, &saved(1, "2030-01-01T00:00:00+00:00")).unwrap();
+
+    // The clock has jumped back years; the revision check does not look at it.
+    let revision = next_revision(&path, 1).expect("save on the loaded revision");
+    assert_eq!(revision, 2);
+    write_json(&path, &saved(revision, "2020-01-01T00:00:00+00:00")).unwrap();
+
+    let stale = next_revision(&path, 1).expect_err("save on a stale revision");
+    assert_eq!(error_code(&stale).as_deref(), Some("CONFLICT"));
+    assert_eq!(next_revision(&path, 2), Ok(3));
+  }
+
+  #[test]
+  fn files_without_a_revision_start_at_zero() {
+    let temp = TempDir::new();
+    let path = temp.0.join("tasks.json");
+    fs::write(&path, r#"{"tasks":[],"updatedAt":"2024-05-01T00:00:00Z"}"#).unwrap();
+
+    let state: TaskState = read_json_or_default(&path, default_task_state()).unwrap();
+    assert_eq!(state.revision, 0);
+    assert_eq!(next_revision(&path, 0), Ok(1));
+    assert_eq!(next_revision(&path, 7).map_err(|error| error_code(&error)), Err(Some(String::from("CONFLICT"))));
+  }
+
+  #[test]
+  fn cancelling_a_state_export_halfway_leaves_no_output_and_the_state_intact() {
+    let temp = TempDir::new();
+    let state_dir = temp.child("state");
+    let tasks = state_dir.join("tasks.json");
+    let workspace = state_dir.join("workspace.json");
+    fs::write(&tasks, r#"{"tasks":[],"revision":4}"#).unwrap();
+    fs::write(&workspace, r#"{"rootPath":null,"recentPaths":[]}"#).unwrap();
+    let output = temp.0.join("export").join("state.json");
+    let files = [("tasks.json", tasks.clone()), ("workspace.json", workspace.clone())];
+    let cancelled = AtomicBool::new(false);
+
+    let error = write_state_bundle(&files, &output, false, &cancelled, &mut |_, done, _, _| {
+      if done == 1 {
+        cancelled.store(true, Ordering::SeqCst);
+      }
     })
-    .map_err(|error| format!("failed to open PTY: {error}"))?;
+    .expect_err("cancelled export");
 
-  let mut command = CommandBuilder::new(shell.clone());
-  command.args(args);
-  command.cwd(cwd);
+    assert_eq!(error_code(&error).as_deref(), Some("CANCELLED"));
+    assert!(!output.exists());
+    assert!(!output.parent().unwrap().exists());
+    assert_eq!(fs::read_to_string(&tasks).unwrap(), r#"{"tasks":[],"revision":4}"#);
+    assert_eq!(fs::read_to_string(&workspace).unwrap(), r#"{"rootPath":null,"recentPaths":[]}"#);
+  }
 
-  for (key, value) in std::env::vars() {
-    command.env(key, value);
+  #[test]
+  fn state_export_bundles_the_files_that_exist() {
+    let temp = TempDir::new();
+    let tasks = temp.0.join("tasks.json");
+    fs::write(&tasks, r#"{"tasks":[],"revision":4}"#).unwrap();
+    let output = temp.0.join("state.json");
+    let files = [("tasks.json", tasks), ("settings.json", temp.0.join("settings.json"))];
+
+    let (bytes, names) = write_state_bundle(&files, &output, false, &AtomicBool::new(false), &mut |_, _, _, _| {}).unwrap();
+
+    assert_eq!(names, vec![String::from("tasks.json")]);
+    assert_eq!(bytes, fs::metadata(&output).unwrap().len());
+    let bundle: serde_json::Value = serde_json::from_slice(&fs::read(&output).unwrap()).unwrap();
+    assert_eq!(bundle["files"]["tasks.json"]["revision"], 4);
+    let again = write_state_bundle(&files, &output, false, &AtomicBool::new(false), &mut |_, _, _, _| {});
+    assert_eq!(again.map_err(|error| error_code(&error)), Err(Some(String::from("EXISTS"))));
   }
 
-  let master = pty_pair.master;
-  let mut reader = master
-    .try_clone_reader()
-    .map_err(|error| format!("failed to clone PTY reader: {error}"))?;
-  let writer = master
-    .take_writer()
-    .map_err(|error| format!("failed to take PTY writer: {error}"))?;
-  let child = pty_pair
-    .slave
-    .spawn_command(command)
-    .map_err(|error| format!("failed to spawn terminal process: {error}"))?;
-  let pid = child.process_id().unwrap_or_default();
+  #[test]
+  fn shutdown_steps_stop_waiting_at_the_deadline() {
+    let started = Instant::now();
+    let mut reports = Vec::new();
 
-  let session = TerminalSession {
-    master: Arc::new(Mutex::new(master)),
-    writer: Arc::new(Mutex::new(writer)),
-    child: Arc::new(Mutex::new(child))
-  };
+    let remaining = wait_for_shutdown_step(started + Duration::from_millis(200), || 2, |count| reports.push(count));
 
-  {
-    let mut sessions = state
-      .sessions
-      .lock()
-      .map_err(|_| lock_error("terminal sessions"))?;
-    sessions.insert(session_id.clone(), session);
+    assert_eq!(remaining, 2);
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(reports, vec![2]);
   }
 
-  let sessions_for_thread = state.sessions.clone();
-  let app_for_thread = app.clone();
-  let session_id_for_thread = session_id.clone();
+  #[test]
+  fn shutdown_steps_finish_as_soon_as_nothing_is_left() {
+    let started = Instant::now();
+    let mut outstanding = 3;
+    let mut reports = Vec::new();
 
-  std::thread::spawn(move || {
-    let mut buffer = [0_u8; 8192];
-    let mut exit_code = 0_i32;
+    let remaining = wait_for_shutdown_step(
+      started + Duration::from_secs(10),
+      || {
+        outstanding -= 1;
+        outstanding
+      },
+      |count| reports.push(count)
+    );
 
-    loop {
-      match reader.read(&mut buffer) {
-        Ok(0) => break,
-        Ok(bytes_read) => {
-          let payload = TerminalOutputEvent {
-            session_id: session_id_for_thread.clone(),
-            data: String::from_utf8_lossy(&buffer[..bytes_read]).into_owned()
-          };
-          let _ = app_for_thread.emit(TERMINAL_OUTPUT_EVENT, payload);
-        }
-        Err(error) => {
-          if error.kind() == ErrorKind::Interrupted {
-            continue;
-          }
-          exit_code = -1;
-          break;
-        }
+    assert_eq!(remaining, 0);
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(reports, vec![2, 1, 0]);
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn dropped_terminal_children_leave_no_zombies() {
+    use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+    let pty_system = native_pty_system();
+    let mut pids = Vec::new();
+    for index in 0..20 {
+      let pair = pty_system.openpty(PtySize::default()).expect("open pty");
+      let mut command = CommandBuilder::new("sh");
+      // Half exit on their own, half are still running when dropped.
+      command.args(["-c", if index % 2 == 0 { "exit 0" } else { "sleep 30" }]);
+      let child = ChildReaper(pair.slave.spawn_command(command).expect("spawn"));
+      pids.push(child.process_id().expect("pid"));
+      if index % 2 == 0 {
+        std::thread::sleep(Duration::from_millis(20));
       }
+      drop(child);
     }
 
-    if let Ok(mut sessions) = sessions_for_thread.lock() {
-      sessions.remove(&session_id_for_thread);
+    for pid in pids {
+      let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok().and_then(|raw| parse_process_stat(&raw));
+      assert!(stat.map_or(true, |stat| stat.state != "Z"), "{pid} is a zombie");
     }
+  }
 
-    let _ = app_for_thread.emit(
-      TERMINAL_EXIT_EVENT,
-      TerminalExitEvent {
-        session_id: session_id_for_thread,
-        exit_code,
-        signal: None
-      }
+  #[test]
+  fn watch_batches_coalesce_per_path() {
+    let mut changes = BTreeMap::new();
+    let path = |name: &str| PathBuf::from(format!("/w/{name}"));
+    record_fs_change(&mut changes, path("new"), "created");
+    record_fs_change(&mut changes, path("new"), "modified");
+    record_fs_change(&mut changes, path("temp"), "created");
+    record_fs_change(&mut changes, path("temp"), "modified");
+    record_fs_change(&mut changes, path("temp"), "removed");
+    record_fs_change(&mut changes, path("replaced"), "removed");
+    record_fs_change(&mut changes, path("replaced"), "created");
+    record_fs_change(&mut changes, path("edited"), "modified");
+    record_fs_change(&mut changes, path("edited"), "removed");
+
+    assert_eq!(
+      changes.into_iter().collect::<Vec<_>>(),
+      vec![(path("edited"), "removed"), (path("new"), "created"), (path("replaced"), "modified")]
     );
-  });
+  }
 
-  Ok(TerminalCreateResponse { session_id, pid })
-}
+  /// Accepts `capacity` bytes, then reports a full buffer forever.
+  struct FullPty {
+    capacity: usize
+  }
 
-#[tauri::command]
-fn terminal_write(state: State<'_, AppState>, request: TerminalWriteRequest) -> Result<(), String> {
-  let session = get_terminal_session(&state, &request.session_id)?;
-  let mut writer = session
-    .writer
-    .lock()
-    .map_err(|_| lock_error("terminal writer"))?;
+  impl Write for FullPty {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+      if self.capacity == 0 {
+        return Err(ErrorKind::WouldBlock.into());
+      }
+      let count = data.len().min(self.capacity);
+      self.capacity -= count;
+      Ok(count)
+    }
 
-  writer
-    .write_all(request.data.as_bytes())
-    .map_err(|error| io_error("failed to write to PTY", error))?;
-  writer
-    .flush()
-    .map_err(|error| io_error("failed to flush PTY writer", error))
-}
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
 
-#[tauri::command]
-fn terminal_resize(state: State<'_, AppState>, request: TerminalResizeRequest) -> Result<(), String> {
-  let session = get_terminal_session(&state, &request.session_id)?;
-  let master = session
-    .master
-    .lock()
-    .map_err(|_| lock_error("terminal master"))?;
+  #[test]
+  fn pty_writes_report_partial_progress() {
+    let data = [b'x'; 100];
+    let open = AtomicBool::new(false);
+    let mut written = 0;
+    let error = write_pty_data(&mut FullPty { capacity: 40 }, &data, Instant::now(), &open, &mut written).unwrap_err();
+    assert_eq!(written, 40);
+    assert_eq!(error_code(&error).as_deref(), Some("WRITE_TIMEOUT"));
+    assert!(error.contains("40 of 100 bytes"), "{error}");
 
-  let cols = request.cols.max(1);
-  let rows = request.rows.max(1);
+    let closed = AtomicBool::new(true);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut written = 0;
+    let error = write_pty_data(&mut FullPty { capacity: 10 }, &data, deadline, &closed, &mut written).unwrap_err();
+    assert_eq!(written, 10);
+    assert_eq!(error_code(&error).as_deref(), Some("EXITED"));
 
-  master
-    .resize(PtySize {
-      rows,
-      cols,
-      pixel_width: 0,
-      pixel_height: 0
-    })
-    .map_err(|error| format!("failed to resize PTY: {error}"))
-}
+    let mut written = 0;
+    write_pty_data(&mut FullPty { capacity: 100 }, &data, Instant::now(), &open, &mut written).expect("fits");
+    assert_eq!(written, 100);
+  }
 
-#[tauri::command]
-fn terminal_kill(state: State<'_, AppState>, request: TerminalKillRequest) -> Result<(), String> {
-  let _signal = request.signal;
-  let session = {
-    let mut sessions = state
-      .sessions
-      .lock()
-      .map_err(|_| lock_error("terminal sessions"))?;
-    sessions.remove(&request.session_id)
+  #[test]
+  fn capped_line_reads_skip_the_rest_of_long_lines() {
+    let mut reader = std::io::BufReader::with_capacity(4, &b"short\nmuch too long\nexact\nend"[..]);
+    let mut line = Vec::new();
+    let mut read = |max_bytes| {
+      line.clear();
+      let (consumed, cut) = read_line_capped(&mut reader, &mut line, max_bytes).expect("read");
+      (String::from_utf8_lossy(&line).into_owned(), consumed, cut)
+    };
+    assert_eq!(read(8), (String::from("short\n"), 6, false));
+    assert_eq!(read(8), (String::from("much too"), 14, true));
+    assert_eq!(read(5), (String::from("exact"), 6, false));
+    assert_eq!(read(8), (String::from("end"), 3, false));
+    assert_eq!(read(8), (String::new(), 0, false));
   }
-  .ok_or_else(|| format!("Terminal session \"{}\" was not found.", request.session_id))?;
 
-  let mut child = session
-    .child
-    .lock()
-    .map_err(|_| lock_error("terminal child process"))?;
+  #[test]
+  fn stat_of_a_missing_path_is_not_found() {
+    let dir = TempDir::new();
+    let missing = dir.0.join("gone.txt");
+    let error = stat_path(&missing).unwrap_err();
+    let parsed: MessageError = serde_json::from_str(&error).expect("structured error");
+    assert_eq!(parsed.code, "NOT_FOUND");
+    assert_eq!(parsed.message_key, "file.notFound");
+    assert_eq!(parsed.params.get("path"), Some(&path_string(&missing)));
 
-  child
-    .kill()
-    .map_err(|error| format!("failed to kill terminal process: {error}"))
-}
+    fs::write(&missing, "back").expect("write");
+    let stat = stat_path(&missing).expect("stat");
+    assert_eq!((stat.entry.size, stat.is_sparse), (Some(4), Some(false)));
+  }
 
-#[tauri::command]
-fn terminal_list(state: State<'_, AppState>) -> Result<Vec<String>, String> {
-  let sessions = state
-    .sessions
-    .lock()
-    .map_err(|_| lock_error("terminal sessions"))?;
-  Ok(sessions.keys().cloned().collect())
-}
+  #[test]
+  fn sparse_files_are_flagged() {
+    assert!(looks_sparse(10 * 1024 * 1024, 4096));
+    assert!(!looks_sparse(10 * 1024 * 1024, 10 * 1024 * 1024));
+    assert!(!looks_sparse(1024, 0));
 
-#[tauri::command]
-fn filesystem_list(request: FsListRequest) -> Result<Vec<FilesystemEntry>, String> {
-  let target_path = resolve_path(&request.path)?;
-  let entries = fs::read_dir(&target_path)
-    .map_err(|error| io_error(&format!("failed to list {}", target_path.display()), error))?;
+    let dir = TempDir::new();
+    let path = dir.0.join("holes.img");
+    fs::File::create(&path).expect("create").set_len(16 * 1024 * 1024).expect("extend");
+    let stat = stat_path(&path).expect("stat");
+    assert_eq!(stat.entry.size, Some(16 * 1024 * 1024));
+    if cfg!(unix) {
+      assert_eq!(stat.is_sparse, Some(true));
+    }
+    assert_eq!(stat_path(&dir.0).expect("stat dir").is_sparse, None);
+  }
 
-  let mut list: Vec<FilesystemEntry> = entries
-    .filter_map(Result::ok)
-    .filter_map(|entry| {
-      let entry_path = entry.path();
-      let metadata = entry.metadata().ok()?;
-
-      Some(FilesystemEntry {
-        name: entry.file_name().to_string_lossy().into_owned(),
-        path: entry_path.to_string_lossy().into_owned(),
-        is_directory: metadata.is_dir(),
-        size: metadata.len(),
-        mtime_ms: modified_time_ms(&metadata)
-      })
-    })
-    .collect();
+  #[test]
+  fn write_conflicts_carry_the_current_state() {
+    let dir = TempDir::new();
+    let path = dir.0.join("notes.txt");
+    fs::write(&path, "theirs").expect("write");
+    let mtime_ms = modified_time_ms(&fs::metadata(&path).expect("stat"));
+    check_write_conflict(&path, mtime_ms, Some(1024)).expect("unchanged file");
 
-  list.sort_by(|left, right| {
-    if left.is_directory != right.is_directory {
-      return right.is_directory.cmp(&left.is_directory);
-    }
+    let error = check_write_conflict(&path, mtime_ms.wrapping_sub(5_000), Some(1024)).unwrap_err();
+    let parsed: MessageError = serde_json::from_str(&error).expect("structured error");
+    assert_eq!(parsed.code, "CONFLICT");
+    let details = parsed.details.expect("details");
+    assert_eq!(details["currentMtimeMs"], mtime_ms);
+    assert_eq!(details["currentContent"], "theirs");
 
-    left
-      .name
-      .to_ascii_lowercase()
-      .cmp(&right.name.to_ascii_lowercase())
-  });
+    let error = check_write_conflict(&path, mtime_ms.wrapping_sub(5_000), None).unwrap_err();
+    let details = serde_json::from_str::<MessageError>(&error).unwrap().details.unwrap();
+    assert!(details.get("currentContent").is_none());
 
-  Ok(list)
-}
+    fs::remove_file(&path).expect("remove");
+    let error = check_write_conflict(&path, mtime_ms, Some(1024)).unwrap_err();
+    let parsed: MessageError = serde_json::from_str(&error).expect("structured error");
+    assert_eq!(parsed.message_key, "file.deletedOnDisk");
+    assert!(parsed.details.expect("details")["currentMtimeMs"].is_null());
+  }
 
-#[tauri::command]
-fn filesystem_read(request: FsReadRequest) -> Result<FsReadResponse, String> {
-  let target_path = resolve_path(&request.path)?;
-  let content = fs::read_to_string(&target_path)
-    .map_err(|error| io_error(&format!("failed to read {}", target_path.display()), error))?;
+  #[test]
+  fn atomic_writes_can_be_abandoned_before_the_rename() {
+    let dir = TempDir::new();
+    let path = dir.0.join("file.txt");
+    fs::write(&path, "original").expect("write");
 
-  Ok(FsReadResponse {
-    path: target_path.to_string_lossy().into_owned(),
-    content
-  })
-}
+    let error = write_file_atomically(
+      &path,
+      |file| file.write_all(b"replacement"),
+      || Err(std::io::Error::other("changed"))
+    )
+    .unwrap_err();
+    assert_eq!(error.to_string(), "changed");
+    assert_eq!(fs::read_to_string(&path).expect("read"), "original");
+    assert_eq!(fs::read_dir(&dir.0).expect("list").count(), 1, "temp file left behind");
 
-#[tauri::command]
-fn filesystem_write(request: FsWriteRequest) -> Result<FsReadResponse, String> {
-  let target_path = resolve_path(&request.path)?;
+    assert!(write_file_atomically(&path, |file| file.write_all(b"replacement"), || Ok(())).expect("write"));
+    assert_eq!(fs::read_to_string(&path).expect("read"), "replacement");
+  }
 
-  if let Some(parent) = target_path.parent() {
-    fs::create_dir_all(parent).map_err(|error| io_error("failed to create parent directory", error))?;
+  #[test]
+  fn cpu_use_is_measured_over_the_sample_window() {
+    assert_eq!(cpu_percent(150, Some((50, 2.0)), 100.0), 50.0);
+    assert_eq!(cpu_percent(400, Some((0, 2.0)), 100.0), 200.0);
+    assert_eq!(cpu_percent(40, Some((50, 2.0)), 100.0), 0.0);
+    assert_eq!(cpu_percent(150, Some((50, 0.0)), 100.0), 0.0);
+    assert_eq!(cpu_percent(150, None, 100.0), 0.0);
+
+    let stat = parse_process_stat("42 (a (b) c) S 1 42 42 0 -1 0 0 0 0 0 7 3 0 0 20 0 1 0 1234 0 0").expect("stat");
+    assert_eq!((stat.name.as_str(), stat.ticks, stat.start_ticks), ("a (b) c", 10, 1234));
   }
 
-  fs::write(&target_path, &request.content)
-    .map_err(|error| io_error(&format!("failed to write {}", target_path.display()), error))?;
+  #[test]
+  fn terminal_layouts_are_written_once_per_flush() {
+    let dir = TempDir::new();
+    let path = dir.0.join(TERMINAL_LAYOUTS_FILE_NAME);
+    let store = TerminalLayoutStore::default();
+    let layout = |cols| TerminalLayout { cols, rows: 30 };
 
-  Ok(FsReadResponse {
-    path: target_path.to_string_lossy().into_owned(),
-    content: request.content
-  })
-}
+    assert!(store_terminal_layout(&store, &path, String::from("/w"), layout(100)).expect("store"));
+    assert!(!store_terminal_layout(&store, &path, String::from("/w"), layout(101)).expect("store"));
+    assert!(!store_terminal_layout(&store, &path, String::from("/w"), layout(5)).expect("too small"));
+    assert!(!path.exists());
 
-#[tauri::command]
-fn tasks_load(app: AppHandle) -> Result<TaskState, String> {
-  let path = persistence_file_path(&app, TASKS_FILE_NAME)?;
-  read_json_or_default(&path, default_task_state())
-}
+    flush_terminal_layouts(&store, &path).expect("flush");
+    let written: HashMap<String, TerminalLayout> = read_json_or_default(&path, HashMap::new()).expect("read");
+    assert_eq!(written.get("/w"), Some(&layout(101)));
 
-#[tauri::command]
-fn tasks_save(app: AppHandle, mut state: TaskState) -> Result<TaskState, String> {
-  state.updated_at = Utc::now().to_rfc3339();
-  let path = persistence_file_path(&app, TASKS_FILE_NAME)?;
-  write_json(&path, &state)?;
-  Ok(state)
-}
+    fs::remove_file(&path).expect("remove");
+    flush_terminal_layouts(&store, &path).expect("nothing pending");
+    assert!(!path.exists());
+    assert!(!store_terminal_layout(&store, &path, String::from("/w"), layout(101)).expect("unchanged"));
+    assert!(store_terminal_layout(&store, &path, String::from("/w"), layout(120)).expect("store"));
+  }
 
-#[tauri::command]
-fn workspace_load(app: AppHandle, startup_context: State<'_, StartupContext>) -> Result<WorkspaceState, String> {
-  let path = persistence_file_path(&app, WORKSPACE_FILE_NAME)?;
-  let mut state = read_json_or_default(&path, default_workspace_state())?;
+  #[test]
+  fn listing_snapshots_are_reused_until_stale() {
+    let path = Path::new("/w");
+    let mut cache = HashMap::new();
+    assert!(cached_listing(&mut cache, "key", 1, None, path).expect("empty cache").is_none());
+    let error = cached_listing(&mut cache, "key", 1, Some("gone"), path).unwrap_err();
+    assert_eq!(error_code(&error).as_deref(), Some("STALE"));
 
-  if let Some(startup_root) = startup_context.root_path.as_ref() {
-    let mut next_recent_paths = vec![startup_root.clone()];
-    for recent_path in &state.recent_paths {
-      if recent_path != startup_root && !next_recent_paths.contains(recent_path) {
-        next_recent_paths.push(recent_path.clone());
+    let long_ago = Instant::now() - FILESYSTEM_LIST_CACHE_TTL * 2;
+    cache.insert(
+      String::from("key"),
+      FsListingSnapshot {
+        id: String::from("first"),
+        fetched_at: long_ago,
+        last_used: long_ago,
+        directory_mtime_ms: 1,
+        entries: Arc::new(Vec::new()),
+        warnings: Arc::new(Vec::new())
       }
+    );
+    // Too old to share with a fresh listing, but still valid for the caller paging through it.
+    assert!(cached_listing(&mut cache, "key", 1, None, path).expect("lookup").is_none());
+    let (id, _, _) = cached_listing(&mut cache, "key", 1, Some("first"), path).expect("lookup").expect("snapshot");
+    assert_eq!(id, "first");
 
-      if next_recent_paths.len() >= 6 {
-        break;
-      }
+    let error = cached_listing(&mut cache, "key", 2, Some("first"), path).unwrap_err();
+    assert_eq!(error_code(&error).as_deref(), Some("STALE"));
+    let error = cached_listing(&mut cache, "key", 1, Some("other"), path).unwrap_err();
+    assert_eq!(error_code(&error).as_deref(), Some("STALE"));
+  }
+
+  #[test]
+  fn replay_keeps_emission_order_and_window_targets() {
+    let mut buffer = EventReplayBuffer::default();
+    let ready = |subsystem: &str| serde_json::json!({ "subsystem": subsystem });
+    let cwd = |session: &str, cwd: &str| serde_json::json!({ "sessionId": session, "cwd": cwd });
+    let mut record = |target: Option<&str>, event: &str, payload: serde_json::Value| {
+      let policy = EVENT_REPLAY_POLICIES.iter().find(|(name, _)| *name == event).expect("replayable").1;
+      record_replayed_event(&mut buffer, target.map(String::from), event, payload, policy);
+    };
+    record(None, APP_READY_EVENT, ready("first"));
+    record(Some("main"), TERMINAL_CWD_CHANGED_EVENT, cwd("a", "/one"));
+    record(Some("other"), TERMINAL_CWD_CHANGED_EVENT, cwd("b", "/two"));
+    record(Some("main"), FS_CHANGED_EVENT, serde_json::json!({ "kind": "created" }));
+    record(Some("main"), TERMINAL_CWD_CHANGED_EVENT, cwd("a", "/three"));
+    record(None, APP_READY_EVENT, ready("second"));
+
+    let now_ms = unix_time_ms();
+    let replayed = replayable_events(&buffer, 0, "main", now_ms);
+    let summary: Vec<(u64, &str)> = replayed.iter().map(|event| (event.sequence, event.event.as_str())).collect();
+    assert_eq!(
+      summary,
+      vec![(4, FS_CHANGED_EVENT), (5, TERMINAL_CWD_CHANGED_EVENT), (6, APP_READY_EVENT)]
+    );
+    assert_eq!(replayed[1].payload["cwd"], "/three");
+    assert_eq!(replayed[2].payload["subsystem"], "second");
+    assert!(replayed.iter().all(|event| event.replayed));
+
+    assert_eq!(replayable_events(&buffer, 5, "main", now_ms).len(), 1);
+    let other: Vec<u64> = replayable_events(&buffer, 0, "other", now_ms).iter().map(|event| event.sequence).collect();
+    assert_eq!(other, vec![3, 6]);
+
+    // Watcher batches expire after their window, `Latest` entries after their TTL.
+    let later_ms = now_ms + FS_CHANGED_REPLAY_WINDOW.as_millis() as u64 + 1;
+    assert_eq!(replayable_events(&buffer, 0, "main", later_ms).len(), 2);
+    let much_later_ms = now_ms + EVENT_REPLAY_LATEST_TTL.as_millis() as u64 + 1;
+    assert!(replayable_events(&buffer, 0, "main", much_later_ms).is_empty());
+  }
+
+  #[test]
+  fn state_files_round_trip_in_both_formats() {
+    let dir = TempDir::new();
+    let state = TaskState {
+      tasks: (0..50).map(|index| serde_json::json!({ "id": index.to_string(), "title": "task" })).collect(),
+      revision: 3,
+      updated_at: String::from("2026-01-01T00:00:00Z")
+    };
+    let pretty = dir.0.join("pretty.json");
+    let compact = dir.0.join("compact.json");
+    write_json_with_threshold(&pretty, &state, u64::MAX).expect("write pretty");
+    write_json_with_threshold(&compact, &state, 0).expect("write compact");
+
+    let pretty_raw = fs::read_to_string(&pretty).expect("read");
+    let compact_raw = fs::read_to_string(&compact).expect("read");
+    assert!(pretty_raw.contains("\n  "));
+    assert!(!compact_raw.contains('\n'));
+    for path in [&pretty, &compact] {
+      let read: TaskState = read_json_or_default(path, default_task_state()).expect("parse");
+      assert_eq!((read.tasks, read.revision), (state.tasks.clone(), 3));
     }
+  }
 
-    let should_update = state.root_path.as_deref() != Some(startup_root)
-      || state.recent_paths != next_recent_paths;
+  #[test]
+  fn task_archive_reads_legacy_arrays_and_appends_lines() {
+    let dir = TempDir::new();
+    let path = dir.0.join(TASKS_ARCHIVE_FILE_NAME);
+    let task = |id: &str| serde_json::json!({ "id": id });
+    assert!(read_task_archive(&path).expect("missing archive").is_empty());
 
-    if should_update {
-      state.root_path = Some(startup_root.clone());
-      state.recent_paths = next_recent_paths;
-      state.updated_at = Utc::now().to_rfc3339();
-      write_json(&path, &state)?;
+    fs::write(&path, serde_json::to_string_pretty(&vec![task("1"), task("2")]).unwrap()).expect("write legacy");
+    assert_eq!(read_task_archive(&path).expect("legacy"), vec![task("1"), task("2")]);
+
+    append_task_archive(&path, &[task("3")]).expect("convert and append");
+    append_task_archive(&path, &[task("4"), task("5")]).expect("append");
+    assert_eq!(fs::read_to_string(&path).expect("read").lines().count(), 5);
+    let ids = |tasks: Vec<serde_json::Value>| {
+      tasks
+        .iter()
+        .map(|task| task["id"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(ids(read_task_archive(&path).expect("ndjson")), ["1", "2", "3", "4", "5"]);
+
+    // A torn append is dropped on read and cut off before the next one.
+    let mut file = OpenOptions::new().append(true).open(&path).expect("open");
+    file.write_all(b"{\"id\":\"6").expect("torn write");
+    assert_eq!(read_task_archive(&path).expect("torn").len(), 5);
+    append_task_archive(&path, &[task("7")]).expect("append after tear");
+    assert_eq!(ids(read_task_archive(&path).expect("repaired")), ["1", "2", "3", "4", "5", "7"]);
+  }
+
+  #[test]
+  fn search_scopes_are_exact_prefixes_under_the_root() {
+    let dir = TempDir::new();
+    let odd = dir.child("src [old] copy");
+    let nested = dir.child("src [old] copy/deeper");
+    dir.child("docs");
+    let scopes = vec![
+      String::from("docs"),
+      path_string(&nested),
+      String::from("src [old] copy")
+    ];
+    assert_eq!(resolve_search_scopes(&dir.0, &scopes).expect("scopes"), vec![dir.0.join("docs"), odd.clone()]);
+    assert!(resolve_search_scopes(&dir.0, &[]).expect("no scopes").is_empty());
+
+    let error = resolve_search_scopes(&dir.0, &[String::from("missing")]).unwrap_err();
+    assert_eq!(error_code(&error).as_deref(), Some("NOT_FOUND"));
+    let outside = resolve_search_scopes(&odd, &[path_string(&dir.0.join("docs"))]).unwrap_err();
+    assert_eq!(outside, PATH_ESCAPES_WORKSPACE_ERROR);
+  }
+
+  #[test]
+  fn activity_cache_drops_earlier_days_and_the_oldest_entries() {
+    let activity = |root: &str| WorkspaceActivity {
+      root: root.to_string(),
+      days: 30,
+      sources: Vec::new(),
+      truncated: false,
+      buckets: Vec::new()
+    };
+    let key = |root: &str, date: &str| (root.to_string(), 30, date.to_string());
+    let mut entries = ActivityCacheEntries::new();
+    cache_activity(&mut entries, key("/old", "2026-01-01"), activity("/old"));
+    for index in 0..ACTIVITY_CACHE_MAX_ENTRIES + 2 {
+      let root = format!("/root{index}");
+      cache_activity(&mut entries, key(&root, "2026-01-02"), activity(&root));
     }
+
+    assert_eq!(entries.len(), ACTIVITY_CACHE_MAX_ENTRIES);
+    assert!(!entries.contains_key(&key("/old", "2026-01-01")));
+    assert!(!entries.contains_key(&key("/root0", "2026-01-02")));
+    assert!(!entries.contains_key(&key("/root1", "2026-01-02")));
+    assert!(entries.contains_key(&key(&format!("/root{}", ACTIVITY_CACHE_MAX_ENTRIES + 1), "2026-01-02")));
   }
 
-  Ok(state)
-}
+  #[test]
+  fn bracketed_paste_end_markers_are_stripped_until_none_remain() {
+    assert_eq!(strip_bracketed_paste_end("plain text"), "plain text");
+    assert_eq!(strip_bracketed_paste_end("a\x1b[201~b"), "ab");
+    let nested = "echo \x1b[20\x1b[201~1~; rm -rf ~\n";
+    assert!(!strip_bracketed_paste_end(nested).contains(BRACKETED_PASTE_END));
+    assert_eq!(strip_bracketed_paste_end(nested), "echo ; rm -rf ~\n");
+  }
 
-#[tauri::command]
-fn workspace_save(app: AppHandle, mut state: WorkspaceState) -> Result<WorkspaceState, String> {
-  state.updated_at = Utc::now().to_rfc3339();
-  let path = persistence_file_path(&app, WORKSPACE_FILE_NAME)?;
-  write_json(&path, &state)?;
-  Ok(state)
-}
+  #[test]
+  fn catch_up_after_a_gap_resumes_at_a_boundary() {
+    let mut scrollback = ScrollbackBuffer::new(1024);
+    scrollback.append(b"first\n");
+    scrollback.append("\x1b[31mr\u{e9}d\x1b[0m\nnext line\n".as_bytes());
 
-fn main() {
-  if let Err(message) = ensure_linux_runtime() {
-    eprintln!("{message}");
-    std::process::exit(1);
+    let (data, skipped) = scrollback.catch_up_since(0, 20);
+    assert_eq!(data, b"next line\n");
+    assert_eq!(skipped, scrollback.total_bytes - 10);
+
+    let (data, skipped) = scrollback.catch_up_since(6, 64);
+    assert_eq!(data, "\x1b[31mr\u{e9}d\x1b[0m\nnext line\n".as_bytes());
+    assert_eq!(skipped, 0);
+
+    assert_eq!(catch_up_boundary(&"\u{e9}tat".as_bytes()[1..]), 1);
+    assert_eq!(catch_up_boundary(&[0xa9, 0x80, b'x']), 2);
   }
 
-  let startup_root_path = match resolve_startup_root_from_args() {
-    Ok(path) => path,
-    Err(message) => {
-      eprintln!("{message}");
-      None
+  #[test]
+  fn restart_attempts_reset_after_a_stable_run() {
+    let quick = Duration::from_secs(1);
+    assert_eq!(next_restart_attempt(0, quick), 1);
+    assert_eq!(next_restart_attempt(4, quick), 5);
+    assert_eq!(next_restart_attempt(4, TERMINAL_RESTART_STABLE_UPTIME), 1);
+    assert_eq!(next_restart_attempt(u32::MAX, quick), u32::MAX);
+  }
+
+  #[test]
+  fn terminal_log_output_is_flushed_once_due() {
+    let dir = TempDir::new();
+    let path = dir.0.join("session.log");
+    let mut log = TerminalLog::open(path.clone(), false).expect("open log");
+    log.append(b"quiet prompt $ ").expect("append");
+    assert_eq!(fs::read(&path).expect("read log"), b"");
+
+    log.flush_if_due().expect("early flush");
+    assert_eq!(fs::read(&path).expect("read log"), b"");
+    log.last_flush = Instant::now()
+      .checked_sub(TERMINAL_LOG_FLUSH_INTERVAL)
+      .expect("instant in the past");
+    log.flush_if_due().expect("due flush");
+    assert_eq!(fs::read(&path).expect("read log"), b"quiet prompt $ ");
+  }
+
+  #[test]
+  fn recursive_listing_stops_at_the_entry_cap() {
+    let dir = TempDir::new();
+    dir.child("a/deep");
+    fs::write(dir.0.join("a/deep/file.txt"), "x").expect("write");
+    fs::write(dir.0.join("b.txt"), "x").expect("write");
+    fs::write(dir.0.join(".hidden"), "x").expect("write");
+    let cancelled = AtomicBool::new(false);
+
+    let mut warnings = Vec::new();
+    let (entries, truncated) = list_recursive(&dir.0, 20, false, usize::MAX, &cancelled, &mut warnings).expect("list");
+    let names: Vec<(&str, Option<u32>)> = entries.iter().map(|entry| (entry.name.as_str(), entry.depth)).collect();
+    assert_eq!(names, vec![("a", Some(1)), ("deep", Some(2)), ("file.txt", Some(3)), ("b.txt", Some(1))]);
+    assert!(!truncated);
+    assert!(warnings.is_empty());
+
+    let (entries, truncated) = list_recursive(&dir.0, 20, true, 2, &cancelled, &mut warnings).expect("list");
+    assert_eq!(entries.len(), 2);
+    assert!(truncated);
+
+    cancelled.store(true, Ordering::SeqCst);
+    let error = list_recursive(&dir.0, 20, false, usize::MAX, &cancelled, &mut warnings).unwrap_err();
+    assert_eq!(error_code(&error).as_deref(), Some("CANCELLED"));
+  }
+
+  #[test]
+  fn file_hashes_match_known_digests_and_stop_when_cancelled() {
+    let dir = TempDir::new();
+    let path = dir.0.join("abc.txt");
+    fs::write(&path, "abc").expect("write");
+    let cancelled = AtomicBool::new(false);
+
+    assert_eq!(
+      hash_file(&path, HashAlgorithm::Sha256, &cancelled).expect("sha256"),
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(hash_file(&path, HashAlgorithm::Md5, &cancelled).expect("md5"), "900150983cd24fb0d6963f7d28e17f72");
+
+    cancelled.store(true, Ordering::SeqCst);
+    let error = hash_file(&path, HashAlgorithm::Sha256, &cancelled).unwrap_err();
+    assert_eq!(error_code(&error).as_deref(), Some("CANCELLED"));
+  }
+
+  #[test]
+  fn extract_stops_when_the_per_file_callback_fails() {
+    let dir = TempDir::new();
+    let archive = dir.0.join("two.zip");
+    {
+      let mut writer = zip::ZipWriter::new(fs::File::create(&archive).expect("create zip"));
+      for name in ["one.txt", "two.txt"] {
+        writer.start_file(name, zip::write::SimpleFileOptions::default()).expect("start file");
+        writer.write_all(name.as_bytes()).expect("write file");
+      }
+      writer.finish().expect("finish zip");
     }
-  };
+    let destination = dir.child("out");
 
-  tauri::Builder::default()
-    .plugin(tauri_plugin_dialog::init())
-    .manage(AppState::default())
-    .manage(StartupContext {
-      root_path: startup_root_path
-    })
-    .invoke_handler(tauri::generate_handler![
-      terminal_create,
-      terminal_write,
-      terminal_resize,
-      terminal_kill,
-      terminal_list,
-      filesystem_list,
-      filesystem_read,
-      filesystem_write,
-      tasks_load,
-      tasks_save,
-      workspace_load,
-      workspace_save
-    ])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
+    let mut calls = 0;
+    let result = extract_zip(&archive, &destination, &mut || {
+      calls += 1;
+      Err(String::from("CANCELLED: the extract was cancelled"))
+    });
+    assert!(result.is_err());
+    assert_eq!(calls, 1);
+    assert!(destination.join("one.txt").exists());
+    assert!(!destination.join("two.txt").exists());
+  }
+
+  #[test]
+  fn grep_matches_are_batched_until_due() {
+    let found = |line_number| FsGrepMatch {
+      path: String::from("/w/a.txt"),
+      relative_path: String::from("a.txt"),
+      line_number,
+      line: String::from("needle"),
+      submatches: Vec::new(),
+      truncated: false,
+      scope: None
+    };
+    let mut batch = GrepMatchBatch::new();
+    for line_number in 1..FS_GREP_MATCHES_PER_EVENT as u64 {
+      assert!(batch.push(found(line_number)).is_none());
+    }
+    let sent = batch.push(found(FS_GREP_MATCHES_PER_EVENT as u64)).expect("a full batch");
+    assert_eq!(sent.len(), FS_GREP_MATCHES_PER_EVENT);
+
+    assert!(batch.push(found(1)).is_none());
+    batch.last_sent = Instant::now().checked_sub(FS_GREP_MATCHES_INTERVAL).expect("instant in the past");
+    assert_eq!(batch.push(found(2)).expect("an interval batch").len(), 2);
+    assert!(batch.take().is_empty());
+  }
+
+  #[test]
+  fn poisoned_terminal_locks_are_recovered() {
+    let state = AppState::default();
+    let sessions = state.sessions.clone();
+    let poisoner = std::thread::spawn(move || {
+      let _guard = sessions.lock().expect("lock sessions");
+      panic!("reader thread panicked while holding the session map");
+    });
+    assert!(poisoner.join().is_err());
+    assert!(state.sessions.is_poisoned());
+
+    assert!(lock_recover(&state.sessions).is_empty());
+    let exit_code = Mutex::new(None::<i32>);
+    let _ = std::panic::catch_unwind(AssertUnwindSafe(|| {
+      let mut guard = exit_code.lock().expect("lock exit code");
+      *guard = Some(1);
+      panic!("panicked mid-update");
+    }));
+    assert_eq!(*lock_recover(&exit_code), Some(1));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn control_socket_directory_is_narrowed_and_must_be_real() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = TempDir::new();
+    let socket_dir = dir.child("openspace");
+    fs::set_permissions(&socket_dir, fs::Permissions::from_mode(0o755)).expect("loosen");
+    control_socket::secure_socket_dir(&socket_dir).expect("secure");
+    let mode = fs::metadata(&socket_dir).expect("stat").permissions().mode();
+    assert_eq!(mode & 0o777, 0o700);
+
+    let link = dir.0.join("linked");
+    std::os::unix::fs::symlink(&socket_dir, &link).expect("symlink");
+    assert!(control_socket::secure_socket_dir(&link).is_err());
+  }
 }
@@ -1435,18 +1435,22 @@ function App(): JSX.Element {
   }, [ensurePaneSession, updatePane]);
 
   const saveWorkspaceRoot = async (rootPath: string | null): Promise<void> => {
-    const nextRecent = rootPath
-      ? [rootPath, ...workspaceState.recentPaths.filter((item) => item !== rootPath)].slice(0, 6)
-      : workspaceState.recentPaths;
-
+    // The backend owns the recents: it dedups them by canonical path, applies the configured
+    // cap and keeps pinned entries, so a new root is recorded there rather than edited in here.
     const nextState: WorkspaceState = {
+      ...workspaceState,
       rootPath,
-      recentPaths: nextRecent,
       updatedAt: new Date().toISOString()
     };
     setWorkspaceState(nextState);
-    const persisted = await rendererBridge.saveWorkspaceState(nextState);
-    setWorkspaceState(persisted);
+    try {
+      const persisted = rootPath
+        ? await rendererBridge.setWorkspaceRoot(rootPath)
+        : await rendererBridge.saveWorkspaceState(nextState);
+      setWorkspaceState(persisted);
+    } catch {
+      setWorkspaceState(await rendererBridge.loadWorkspaceState());
+    }
   };
 
   const refreshTreeForRoot = async (rootPath: string): Promise<void> => {
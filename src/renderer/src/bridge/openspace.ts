@@ -1,4 +1,5 @@
 import type {
+  BackendErrorPayload,
   FilesystemEntry,
   FsReadResponse,
   TaskRecord,
@@ -6,6 +7,7 @@ import type {
   TerminalCreateRequest,
   TerminalCreateResponse,
   TerminalExitEvent,
+  TerminalInfo,
   TerminalKillRequest,
   TerminalOutputEvent,
   TerminalResizeRequest,
@@ -31,7 +33,7 @@ type TerminalExitListener = (payload: TerminalExitEvent) => void;
 
 /**
  * Expected Tauri backend contract (primary command names listed first):
- * - `workspace_load`, `workspace_save`
+ * - `workspace_load`, `workspace_save`, `workspace_set_root`
  * - `tasks_load`, `tasks_save`
  * - `filesystem_list`, `filesystem_read`, `filesystem_write`
  * - `terminal_create`, `terminal_write`, `terminal_resize`, `terminal_kill`, `terminal_list`
@@ -42,6 +44,7 @@ type TerminalExitListener = (payload: TerminalExitEvent) => void;
 const TAURI_COMMANDS = {
   workspaceLoad: ["workspace_load", "workspace:load"],
   workspaceSave: ["workspace_save", "workspace:save"],
+  workspaceSetRoot: ["workspace_set_root"],
   tasksLoad: ["tasks_load", "tasks:load"],
   tasksSave: ["tasks_save", "tasks:save"],
   filesystemList: ["filesystem_list", "fs:list"],
@@ -131,12 +134,38 @@ const loadListen = async (): Promise<ListenFn | null> => {
   return tauriListen as unknown as ListenFn;
 };
 
+type BackendError = Error & Omit<BackendErrorPayload, "message">;
+
+const parseBackendError = (error: unknown): unknown => {
+  if (typeof error !== "string" || !error.startsWith("{")) {
+    return error;
+  }
+  try {
+    const payload = JSON.parse(error) as Partial<BackendErrorPayload>;
+    if (typeof payload.messageKey !== "string" || typeof payload.message !== "string") {
+      return error;
+    }
+    return Object.assign(new Error(payload.message), {
+      code: payload.code ?? "",
+      messageKey: payload.messageKey,
+      params: payload.params ?? {},
+      details: payload.details
+    }) as BackendError;
+  } catch {
+    return error;
+  }
+};
+
 const invokeCommand = async <T>(command: string, args?: InvokeArgs): Promise<T> => {
   const invoke = await loadInvoke();
   if (!invoke) {
     throw new Error("Tauri invoke unavailable");
   }
-  return invoke<T>(command, args);
+  try {
+    return await invoke<T>(command, args);
+  } catch (error) {
+    throw parseBackendError(error);
+  }
 };
 
 const invokeWithFallbacks = async <T>(
@@ -246,6 +275,10 @@ const ensureTerminalEvents = async (): Promise<void> => {
   await terminalEventsSetupPromise;
 };
 
+// The tasks revision last loaded or saved, sent with the next save so the backend can refuse it
+// if another window wrote tasks.json in between.
+let tasksRevision = 0;
+
 const readTasksFromState = (state: TaskState): KanbanCard[] =>
   state.tasks.filter(isCardShape).map((task) => ({
     id: task.id,
@@ -261,6 +294,7 @@ export interface RendererBridge {
   pickFolder: () => Promise<string | null>;
   loadWorkspaceState: () => Promise<WorkspaceState>;
   saveWorkspaceState: (state: WorkspaceState) => Promise<WorkspaceState>;
+  setWorkspaceRoot: (path: string) => Promise<WorkspaceState>;
   loadKanbanCards: () => Promise<KanbanCard[]>;
   persistKanbanCards: (cards: KanbanCard[]) => Promise<void>;
   listFiles: (path: string) => Promise<FilesystemEntry[]>;
@@ -326,7 +360,23 @@ export const rendererBridge: RendererBridge = {
       ]);
     } catch (error) {
       console.error("workspace.save failed", error);
-      return state;
+      throw error;
+    }
+  },
+
+  setWorkspaceRoot: async (path) => {
+    if (!hasTauriRuntime()) {
+      placeholderLog("workspace.setRoot", path);
+      return { ...defaultWorkspaceState(), rootPath: path, recentPaths: [path] };
+    }
+
+    try {
+      return await invokeWithFallbacks<WorkspaceState>("workspace.setRoot", TAURI_COMMANDS.workspaceSetRoot, [
+        { request: { path } }
+      ]);
+    } catch (error) {
+      console.error("workspace.setRoot failed", error);
+      throw error;
     }
   },
 
@@ -338,6 +388,7 @@ export const rendererBridge: RendererBridge = {
 
     try {
       const state = await invokeWithFallbacks<TaskState>("tasks.load", TAURI_COMMANDS.tasksLoad, [undefined]);
+      tasksRevision = state.revision ?? 0;
       return readTasksFromState(state);
     } catch (error) {
       console.error("tasks.load failed", error);
@@ -348,7 +399,8 @@ export const rendererBridge: RendererBridge = {
   persistKanbanCards: async (cards) => {
     const payload: TaskState = {
       tasks: cards.map(toTaskRecord),
-      updatedAt: new Date().toISOString()
+      updatedAt: new Date().toISOString(),
+      revision: tasksRevision
     };
 
     if (!hasTauriRuntime()) {
@@ -357,10 +409,11 @@ export const rendererBridge: RendererBridge = {
     }
 
     try {
-      await invokeWithFallbacks<TaskState>("tasks.save", TAURI_COMMANDS.tasksSave, [
+      const saved = await invokeWithFallbacks<TaskState>("tasks.save", TAURI_COMMANDS.tasksSave, [
         { state: payload },
         toRecord(payload)
       ]);
+      tasksRevision = saved.revision ?? 0;
     } catch (error) {
       console.error("tasks.save failed", error);
     }
@@ -526,7 +579,8 @@ export const rendererBridge: RendererBridge = {
     }
 
     try {
-      return await invokeWithFallbacks<string[]>("terminal.list", TAURI_COMMANDS.terminalList, [undefined]);
+      const terminals = await invokeWithFallbacks<TerminalInfo[]>("terminal.list", TAURI_COMMANDS.terminalList, [undefined]);
+      return terminals.map((terminal) => terminal.sessionId);
     } catch (error) {
       console.error("terminal.list failed", error);
       return [];
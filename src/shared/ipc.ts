@@ -27,15 +27,31 @@ export const TAURI_COMMANDS = {
   tasksLoad: "tasks_load",
   tasksSave: "tasks_save",
   workspaceLoad: "workspace_load",
-  workspaceSave: "workspace_save"
+  workspaceSave: "workspace_save",
+  workspaceSetRoot: "workspace_set_root"
 } as const;
 
 export type Unsubscribe = () => void;
 
+/**
+ * A user-facing backend error. Commands reject with it serialized as a JSON string; the bridge
+ * turns it into an `Error` whose message is the English text and which carries these fields.
+ */
+export interface BackendErrorPayload {
+  code: string;
+  messageKey: string;
+  params: Record<string, string>;
+  message: string;
+  /** Typed data for recoverable errors, e.g. `FsWriteConflict` for a CONFLICT from `fs.write`. */
+  details?: unknown;
+}
+
 export interface TerminalCreateRequest {
   cwd?: string;
   cols?: number;
   rows?: number;
+  pixelWidth?: number;
+  pixelHeight?: number;
   shell?: string;
   args?: string[];
 }
@@ -54,6 +70,8 @@ export interface TerminalResizeRequest {
   sessionId: string;
   cols: number;
   rows: number;
+  pixelWidth?: number;
+  pixelHeight?: number;
 }
 
 export interface TerminalKillRequest {
@@ -61,6 +79,36 @@ export interface TerminalKillRequest {
   signal?: string;
 }
 
+export interface TerminalDuplicateRequest {
+  sessionId: string;
+}
+
+export interface TerminalExportLogRequest {
+  sessionId: string;
+  outputPath: string;
+  format: "text" | "ansi";
+  overwrite?: boolean;
+}
+
+export interface TerminalExportLogResponse {
+  outputPath: string;
+  sizeBytes: number;
+  truncated: boolean;
+}
+
+export interface TerminalInfo {
+  sessionId: string;
+  pid: number;
+  shell: string;
+  term: string;
+  visible: boolean;
+  builtin: boolean;
+  lastActivityMs: number;
+  exited: boolean;
+  exitCode: number | null;
+  windowLabel: string;
+}
+
 export interface TerminalOutputEvent {
   sessionId: string;
   data: string;
@@ -70,32 +118,267 @@ export interface TerminalExitEvent {
   sessionId: string;
   exitCode: number;
   signal?: number;
+  error?: string;
 }
 
 export interface FsListRequest {
   path: string;
+  sortBy?: "name" | "size" | "mtime" | "type" | "extension";
+  sortDesc?: boolean;
+  sortOrder?: "asc" | "desc";
+  directoriesFirst?: boolean;
+  naturalSort?: boolean;
+  filterGlob?: string;
+  includeHidden?: boolean;
+  excludeGlobs?: string[];
+  respectGitignore?: boolean;
+  hideIgnored?: boolean;
+  maxEntries?: number;
+  offset?: number;
+  /** From an earlier page; fails with STALE once that listing is gone. */
+  snapshotId?: string;
+}
+
+export interface FsListPage {
+  entries: FilesystemEntry[];
+  offset: number;
+  totalCount: number;
+  hasMore: boolean;
+  snapshotId: string;
+  /** Some entries could not be read and are missing; `warnings` says why. */
+  partial: boolean;
+  warnings: string[];
 }
 
 export interface FilesystemEntry {
   name: string;
   path: string;
   isDirectory: boolean;
-  size: number;
+  size: number | null;
+  sizeOnDisk: number | null;
   mtimeMs: number;
+  depth?: number;
+  ignored: boolean;
+  isSymlink: boolean;
+  symlinkTarget?: string;
+  broken: boolean;
+  error?: string;
+}
+
+export interface FsListRecursiveRequest {
+  path: string;
+  maxDepth: number;
+  includeHidden?: boolean;
+}
+
+/** Payload of `filesystem:list-recursive-finished`. */
+export interface FsListRecursiveFinishedEvent {
+  operationId: string;
+  path: string;
+  entries: FilesystemEntry[];
+  truncated: boolean;
+  warnings: string[];
+  error?: string;
+}
+
+export interface FilesystemHashRequest {
+  path: string;
+  algorithm: "sha256" | "md5";
+}
+
+/** Payload of `filesystem:hash-finished`; `hexDigest` is absent when `error` is set. */
+export interface FilesystemHashFinishedEvent {
+  operationId: string;
+  path: string;
+  algorithm: "sha256" | "md5";
+  hexDigest?: string;
+  error?: string;
+}
+
+export interface FilesystemExtractRequest {
+  archive: string;
+  destination: string;
+  overwrite?: boolean;
+}
+
+export interface FilesystemExtractStarted {
+  operationId: string;
+  destination: string;
+}
+
+/** Payload of `filesystem:extract_progress`. */
+export interface FilesystemExtractProgressEvent {
+  operationId: string;
+  archive: string;
+  filesDone: number;
+  filesTotal: number;
+}
+
+/** Payload of `filesystem:extract-finished`. */
+export interface FilesystemExtractFinishedEvent {
+  operationId: string;
+  archive: string;
+  destination: string;
+  fileCount: number;
+  error?: string;
+}
+
+export interface FsTreeRequest {
+  path: string;
+  maxDepth?: number;
+  maxEntriesPerDirectory?: number;
+  sortBy?: "name" | "size" | "mtime" | "type" | "extension";
+  sortOrder?: "asc" | "desc";
+  directoriesFirst?: boolean;
+  naturalSort?: boolean;
+  includeHidden?: boolean;
+  excludeGlobs?: string[];
+  respectGitignore?: boolean;
+  hideIgnored?: boolean;
+}
+
+export interface FsTreeNode extends FilesystemEntry {
+  children?: FsTreeNode[];
+  childrenTruncated: boolean;
+  notLoaded: boolean;
+  warnings?: string[];
 }
 
 export interface FsReadRequest {
   path: string;
+  maxBinaryBytes?: number;
 }
 
 export interface FsReadResponse {
   path: string;
   content: string;
+  encoding: string | null;
+  isBinary: boolean;
+  truncated: boolean;
+  mtimeMs: number;
+  atomic?: boolean;
 }
 
 export interface FsWriteRequest {
   path: string;
   content: string;
+  encoding?: string;
+  expectedMtimeMs?: number;
+  force?: boolean;
+  includeCurrentContent?: boolean;
+}
+
+/** `currentMtimeMs` is null when the file was deleted. */
+export interface FsWriteConflict {
+  path: string;
+  expectedMtimeMs: number;
+  currentMtimeMs: number | null;
+  currentContent?: string;
+  currentEncoding?: string;
+}
+
+export interface FsSearchFilenamesRequest {
+  root: string;
+  query: string;
+  maxResults?: number;
+  respectGitignore?: boolean;
+  skipDirs?: string[];
+}
+
+export interface FsSearchMatch {
+  path: string;
+  relativePath: string;
+  score: number;
+}
+
+export interface FsSearchResultsEvent {
+  searchId: string;
+  matches: FsSearchMatch[];
+  scannedFiles: number;
+}
+
+export interface FsSearchFinishedEvent extends FsSearchResultsEvent {
+  totalMatches: number;
+  error?: string;
+}
+
+export interface FsContentQuery {
+  root: string;
+  query: string;
+  isRegex?: boolean;
+  caseSensitive?: boolean;
+  wholeWord?: boolean;
+  includeGlobs?: string[];
+  excludeGlobs?: string[];
+  respectGitignore?: boolean;
+  skipDirs?: string[];
+  /** Paths under `root` to search instead of all of it; taken literally, not as globs. */
+  scopePaths?: string[];
+}
+
+export interface FsSearchContentRequest extends FsContentQuery {
+  maxMatches?: number;
+}
+
+export interface FsReplaceInFilesRequest extends FsContentQuery {
+  replacement: string;
+  dryRun?: boolean;
+  expectedMtimes?: Record<string, number>;
+}
+
+export interface FsReplacePreview {
+  lineNumber: number;
+  before: string;
+  after: string;
+}
+
+export interface FsReplaceFileResult {
+  path: string;
+  relativePath: string;
+  matches: number;
+  mtimeMs: number;
+  previews: FsReplacePreview[];
+}
+
+export interface FsReplaceStarted {
+  operationId: string;
+}
+
+export interface FsReplaceFinishedEvent {
+  operationId: string;
+  dryRun: boolean;
+  files: FsReplaceFileResult[];
+  filesChanged: number;
+  matchesReplaced: number;
+  skipped: { path: string; error: string }[];
+  error?: string;
+}
+
+export interface FsGrepMatch {
+  path: string;
+  relativePath: string;
+  lineNumber: number;
+  line: string;
+  submatches: { start: number; end: number }[];
+  truncated: boolean;
+  scope?: string;
+}
+
+/** Payload of `fs:grep-matches`: the lines found since the previous batch. */
+export interface FsGrepMatchesEvent {
+  searchId: string;
+  matches: FsGrepMatch[];
+}
+
+export interface FsGrepFinishedEvent {
+  searchId: string;
+  searchedFiles: number;
+  matchedFiles: number;
+  totalMatches: number;
+  limitReached: boolean;
+  binaryFilesSkipped: number;
+  binaryFiles: string[];
+  error?: string;
 }
 
 export type KanbanLane = "todo" | "in-progress" | "in-review" | "complete" | "cancelled";
@@ -110,15 +393,72 @@ export interface TaskRecord {
   priority: KanbanPriority;
 }
 
+/**
+ * `revision` is the backend's write counter. Sending back the revision that was loaded makes a
+ * save fail with CONFLICT if the file changed since; omitting it, or sending 0, overwrites.
+ * `updatedAt` is informational only.
+ */
 export interface TaskState {
   tasks: TaskRecord[];
   updatedAt: string;
+  revision?: number;
+}
+
+export interface RootProvenance {
+  rootPath: string;
+  source: "flag" | "directory" | "inferred" | "parent";
+  requestedPath: string;
+  marker?: string;
 }
 
 export interface WorkspaceState {
   rootPath: string | null;
   recentPaths: string[];
+  pinnedPaths?: string[];
   updatedAt: string;
+  /** See `TaskState.revision`. */
+  revision?: number;
+  rootProvenance?: RootProvenance;
+}
+
+export interface WorkspaceValidateRootRequest {
+  path: string;
+}
+
+export interface WorkspaceValidation {
+  exists: boolean;
+  isDirectory: boolean;
+  isReadable: boolean;
+  isGitRepo: boolean;
+  absolutePath: string;
+}
+
+export interface WorkspaceExportRequest {
+  outputPath: string;
+  overwrite?: boolean;
+}
+
+export interface StateOpProgressEvent {
+  operationId: string;
+  phase: "reading" | "writing";
+  itemsProcessed: number;
+  itemsTotal: number;
+  bytes: number;
+}
+
+export interface WorkspaceExportFinishedEvent {
+  operationId: string;
+  outputPath: string;
+  bytesWritten: number;
+  files: string[];
+  error?: string;
+}
+
+export interface OperationResult<T = unknown> {
+  operationId: string;
+  running: boolean;
+  event?: string;
+  payload?: T;
 }
 
 export interface OpenSpaceApi {
@@ -127,7 +467,7 @@ export interface OpenSpaceApi {
     write: (request: TerminalWriteRequest) => Promise<void>;
     resize: (request: TerminalResizeRequest) => Promise<void>;
     kill: (request: TerminalKillRequest) => Promise<void>;
-    list: () => Promise<string[]>;
+    list: () => Promise<TerminalInfo[]>;
     onOutput: (listener: (event: TerminalOutputEvent) => void) => Unsubscribe;
     onExit: (listener: (event: TerminalExitEvent) => void) => Unsubscribe;
   };